@@ -0,0 +1,210 @@
+use base64::Engine;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::check::server_address;
+use crate::sanitize::strip_userinfo;
+use crate::{provenance, KubeConfig, NamedExtension};
+
+/// Replaces identifying values with numbered placeholders. Equal inputs get
+/// equal placeholders, so names, servers and credentials that several
+/// entries share (or that differ) still do after anonymizing. Numbering is
+/// per pool of values rather than per field: a cluster, context and user
+/// all called `prod` become `cluster-1`, `context-1` and `user-1`.
+#[derive(Default)]
+pub struct Anonymizer {
+    seen: HashMap<(&'static str, String), usize>,
+    counts: HashMap<&'static str, usize>,
+}
+
+impl Anonymizer {
+    fn number(&mut self, pool: &'static str, value: &str) -> usize {
+        if let Some(n) = self.seen.get(&(pool, value.to_string())) {
+            return *n;
+        }
+        let count = self.counts.entry(pool).or_default();
+        *count += 1;
+        self.seen.insert((pool, value.to_string()), *count);
+        *count
+    }
+
+    fn name(&mut self, kind: &str, name: &str) -> String {
+        format!("{}-{}", kind, self.number("name", name))
+    }
+
+    fn host(&mut self, host: &str) -> String {
+        format!("host-{}.example", self.number("host", host))
+    }
+
+    fn url(&mut self, url: &str) -> String {
+        let url = strip_userinfo(url);
+        match server_address(&url) {
+            Some((host, _)) if !host.is_empty() => url.replacen(&host, &self.host(&host), 1),
+            _ => url,
+        }
+    }
+
+    fn secret(&mut self, kind: &str, value: &str) -> String {
+        format!("{}-{}", kind, self.number("secret", value))
+    }
+
+    /// Base64 fields stay valid base64
+    fn data(&mut self, kind: &str, value: &str) -> String {
+        base64::engine::general_purpose::STANDARD.encode(self.secret(kind, value))
+    }
+
+    fn path(&mut self, path: &str) -> String {
+        format!("/anonymized/file-{}", self.number("path", path))
+    }
+
+    fn value(&mut self, value: &str) -> String {
+        format!("value-{}", self.number("value", value))
+    }
+
+    /// Every string in an arbitrary extension value
+    fn yaml(&mut self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.value(s),
+            Value::Sequence(items) => items.iter_mut().for_each(|v| self.yaml(v)),
+            Value::Mapping(map) => map.values_mut().for_each(|v| self.yaml(v)),
+            Value::Tagged(tagged) => self.yaml(&mut tagged.value),
+            _ => {}
+        }
+    }
+
+    fn extensions(&mut self, extensions: &mut Option<Vec<NamedExtension>>) {
+        for extension in extensions.iter_mut().flatten() {
+            if extension.name != provenance::EXTENSION_NAME {
+                self.yaml(&mut extension.extension);
+            }
+        }
+    }
+
+    /// Anonymized copy of `config`; reuse the anonymizer for related files
+    /// so their placeholders line up
+    pub fn config(&mut self, config: &KubeConfig) -> KubeConfig {
+        let mut out = config.clone();
+        for cluster in &mut out.clusters {
+            cluster.name = self.name("cluster", &cluster.name);
+            let info = &mut cluster.cluster;
+            info.server = self.url(&info.server);
+            info.proxy_url = info.proxy_url.as_deref().map(|u| self.url(u));
+            info.tls_server_name = info.tls_server_name.as_deref().map(|h| self.host(h));
+            info.certificate_authority_data = info
+                .certificate_authority_data
+                .as_deref()
+                .map(|d| self.data("ca", d));
+            info.certificate_authority =
+                info.certificate_authority.as_deref().map(|p| self.path(p));
+        }
+        for context in &mut out.contexts {
+            context.name = self.name("context", &context.name);
+            let info = &mut context.context;
+            info.cluster = self.name("cluster", &info.cluster);
+            info.user = self.name("user", &info.user);
+            info.namespace = info
+                .namespace
+                .as_deref()
+                .map(|n| format!("namespace-{}", self.number("namespace", n)));
+            self.extensions(&mut info.extensions);
+            if let Some(mut record) = provenance::get(info) {
+                record.source = format!("source-{}", self.number("source", &record.source));
+                provenance::stamp(info, &record);
+            }
+        }
+        for user in &mut out.users {
+            user.name = self.name("user", &user.name);
+            let info = &mut user.user;
+            info.client_certificate_data = info
+                .client_certificate_data
+                .as_deref()
+                .map(|d| self.data("client-certificate", d));
+            info.client_key_data = info
+                .client_key_data
+                .as_deref()
+                .map(|d| self.data("client-key", d));
+            info.client_certificate = info.client_certificate.as_deref().map(|p| self.path(p));
+            info.client_key = info.client_key.as_deref().map(|p| self.path(p));
+            info.token = info.token.as_deref().map(|t| self.secret("token", t));
+            info.username = info.username.as_deref().map(|u| self.secret("username", u));
+            info.password = info.password.as_deref().map(|p| self.secret("password", p));
+            if let Some(exec) = &mut info.exec {
+                // Keep the program, not the directory it's installed in
+                if let Some(program) = Path::new(&exec.command).file_name() {
+                    exec.command = program.to_string_lossy().into_owned();
+                }
+                for arg in exec.args.iter_mut().flatten() {
+                    *arg = self.value(arg);
+                }
+                for var in exec.env.iter_mut().flatten() {
+                    var.value = self.value(&var.value);
+                }
+                exec.install_hint = None;
+            }
+            if let Some(provider) = &mut info.auth_provider {
+                for value in provider.config.iter_mut().flat_map(|c| c.values_mut()) {
+                    *value = self.value(value);
+                }
+            }
+        }
+        out.current_context = out
+            .current_context
+            .as_deref()
+            .map(|c| self.name("context", c));
+        if let Some(preferences) = &mut out.preferences {
+            self.extensions(&mut preferences.extensions);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_anonymize_preserves_sharing() {
+        let mut config = create_test_kubeconfig("prod");
+        config.clusters[0].cluster.server = "https://api.corp.example.com:6443".to_string();
+        config.users[0].user.token = Some("secret".to_string());
+        let mut admin = config.contexts[0].clone();
+        admin.name = "prod-admin".to_string();
+        config.contexts.push(admin);
+        config.current_context = Some("prod-admin".to_string());
+        let mut other = create_test_kubeconfig("staging");
+        other.clusters[0].cluster.server = config.clusters[0].cluster.server.clone();
+        other.users[0].user.token = Some("secret".to_string());
+
+        let mut anonymizer = Anonymizer::default();
+        let out = anonymizer.config(&config);
+        assert_eq!(out.clusters[0].name, "cluster-1");
+        assert_eq!(
+            out.clusters[0].cluster.server,
+            "https://host-1.example:6443"
+        );
+        assert_eq!(out.contexts[0].name, "context-2");
+        assert_eq!(out.contexts[1].name, "context-4");
+        assert_eq!(out.contexts[1].context.cluster, "cluster-1");
+        assert_eq!(out.contexts[1].context.user, "user-3");
+        assert_eq!(out.users[0].name, "user-3");
+        assert_eq!(out.current_context.as_deref(), Some("context-4"));
+        assert_eq!(out.users[0].user.token.as_deref(), Some("token-4"));
+
+        // A second file shares the numbering, so the reused server and
+        // token still show up as duplicates
+        let other = anonymizer.config(&other);
+        assert_eq!(
+            other.clusters[0].cluster.server,
+            out.clusters[0].cluster.server
+        );
+        assert_eq!(other.users[0].user.token, out.users[0].user.token);
+        assert_ne!(other.users[0].name, out.users[0].name);
+
+        let yaml = serde_yaml::to_string(&out).unwrap();
+        for leak in ["prod", "corp", "secret"] {
+            assert!(!yaml.contains(leak), "{}", leak);
+        }
+    }
+}
@@ -0,0 +1,663 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use serde::Deserialize;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::certs::load_cert_bytes;
+use crate::{ClusterInfo, ExecConfig, KconfError, KubeConfig, NamedCluster, UserInfo};
+
+/// What gets presented to the API server
+#[derive(Debug, Default, PartialEq)]
+pub struct Credentials {
+    pub bearer: Option<String>,
+    pub basic: Option<(String, String)>,
+    /// PEM client certificate and key
+    pub client_cert: Option<(Vec<u8>, Vec<u8>)>,
+    /// Where the credentials came from, for display
+    pub source: String,
+}
+
+impl Credentials {
+    /// Authorization header value: the bearer token, else basic auth
+    pub fn authorization(&self) -> Option<String> {
+        match (&self.bearer, &self.basic) {
+            (Some(token), _) => Some(format!("Bearer {}", token)),
+            (None, Some((username, password))) => Some(format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password))
+            )),
+            (None, None) => None,
+        }
+    }
+}
+
+/// `status` of an ExecCredential printed by a credential plugin
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecStatus {
+    token: Option<String>,
+    client_certificate_data: Option<String>,
+    client_key_data: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecCredential {
+    status: Option<ExecStatus>,
+}
+
+/// Run an exec credential plugin the way kubectl does and collect what it
+/// prints
+fn run_exec(exec: &ExecConfig, cluster: &ClusterInfo) -> Result<ExecStatus> {
+    let mut spec = serde_json::json!({ "interactive": false });
+    if exec.provide_cluster_info == Some(true) {
+        spec["cluster"] = serde_json::json!({
+            "server": cluster.server,
+            "certificate-authority-data": cluster.certificate_authority_data,
+            "insecure-skip-tls-verify": cluster.insecure_skip_tls_verify.unwrap_or(false),
+        });
+    }
+    let info = serde_json::json!({
+        "apiVersion": exec.api_version,
+        "kind": "ExecCredential",
+        "spec": spec,
+    });
+    let mut command = Command::new(&exec.command);
+    command
+        .args(exec.args.iter().flatten())
+        .env("KUBERNETES_EXEC_INFO", info.to_string())
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    for var in exec.env.iter().flatten() {
+        command.env(&var.name, &var.value);
+    }
+    let output = command.output().with_context(|| {
+        let mut message = format!("Failed to run credential plugin '{}'", exec.command);
+        if let Some(hint) = &exec.install_hint {
+            message.push_str(&format!(" ({})", hint.trim()));
+        }
+        message
+    })?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Credential plugin '{}' exited with {}",
+            exec.command,
+            output.status
+        );
+    }
+    let credential: ExecCredential = serde_json::from_slice(&output.stdout).with_context(|| {
+        format!(
+            "Credential plugin '{}' didn't print an ExecCredential",
+            exec.command
+        )
+    })?;
+    credential.status.context("ExecCredential has no status")
+}
+
+/// Gather the credentials a user would present, running its exec plugin if
+/// it has one
+pub fn credentials(user: &UserInfo, cluster: &ClusterInfo) -> Result<Credentials> {
+    let mut credentials = Credentials {
+        source: "static credentials".to_string(),
+        ..Credentials::default()
+    };
+    let cert = load_cert_bytes(
+        user.client_certificate_data.as_deref(),
+        user.client_certificate.as_deref(),
+    )?;
+    let key = load_cert_bytes(user.client_key_data.as_deref(), user.client_key.as_deref())?;
+    if let (Some((cert, _)), Some((key, _))) = (cert, key) {
+        credentials.client_cert = Some((cert, key));
+    }
+    credentials.bearer = user.token.clone();
+    if let (Some(username), Some(password)) = (&user.username, &user.password) {
+        credentials.basic = Some((username.clone(), password.clone()));
+    }
+
+    if let Some(exec) = &user.exec {
+        let status = run_exec(exec, cluster)?;
+        credentials.source = format!("exec plugin '{}'", exec.command);
+        if let Some(token) = status.token {
+            credentials.bearer = Some(token);
+        }
+        if let (Some(cert), Some(key)) = (status.client_certificate_data, status.client_key_data) {
+            credentials.client_cert = Some((cert.into_bytes(), key.into_bytes()));
+        }
+    }
+    Ok(credentials)
+}
+
+/// A context's cluster and the credentials its user presents
+pub fn context_credentials<'a>(
+    config: &'a KubeConfig,
+    context_name: &str,
+) -> Result<(&'a NamedCluster, Credentials)> {
+    let context = config
+        .contexts
+        .iter()
+        .find(|c| c.name == context_name)
+        .ok_or_else(|| KconfError::ContextNotFound(context_name.to_string()))?;
+    let cluster = config
+        .clusters
+        .iter()
+        .find(|c| c.name == context.context.cluster)
+        .with_context(|| format!("Cluster '{}' not found", context.context.cluster))?;
+    let user = config
+        .users
+        .iter()
+        .find(|u| u.name == context.context.user)
+        .with_context(|| format!("User '{}' not found", context.context.user))?;
+    Ok((cluster, credentials(&user.user, &cluster.cluster)?))
+}
+
+/// Accepts any server certificate, for clusters with insecure-skip-tls-verify
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// TLS settings trusting the cluster's CA (or the public roots when it has
+/// none) and presenting the client certificate, if any
+fn tls_config(
+    cluster: &ClusterInfo,
+    client_cert: Option<&(Vec<u8>, Vec<u8>)>,
+) -> Result<rustls::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("Failed to set up TLS")?;
+    let builder = if cluster.insecure_skip_tls_verify == Some(true) {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+    } else {
+        let mut roots = rustls::RootCertStore::empty();
+        match load_cert_bytes(
+            cluster.certificate_authority_data.as_deref(),
+            cluster.certificate_authority.as_deref(),
+        )? {
+            Some((ca, _)) => {
+                for cert in CertificateDer::pem_slice_iter(&ca) {
+                    roots
+                        .add(cert.context("Failed to parse the cluster's CA certificate")?)
+                        .context("Invalid CA certificate")?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        builder.with_root_certificates(roots)
+    };
+    match client_cert {
+        Some((cert, key)) => {
+            let chain = CertificateDer::pem_slice_iter(cert)
+                .collect::<Result<Vec<_>, _>>()
+                .context("Failed to parse the client certificate")?;
+            let key =
+                PrivateKeyDer::from_pem_slice(key).context("Failed to parse the client key")?;
+            builder
+                .with_client_auth_cert(chain, key)
+                .context("Client certificate and key don't match")
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionInfo {
+    git_version: String,
+}
+
+/// The Kubernetes version a cluster reports at `/version`. The endpoint is
+/// readable without credentials on standard clusters, so none are sent and
+/// no exec plugin runs.
+pub fn server_version(cluster: &ClusterInfo, timeout: Duration) -> Result<String> {
+    let mut agent = ureq::AgentBuilder::new()
+        .tls_config(Arc::new(tls_config(cluster, None)?))
+        .timeout(timeout);
+    if let Some(proxy) = &cluster.proxy_url {
+        agent = agent.proxy(ureq::Proxy::new(proxy).context("Invalid proxy-url")?);
+    }
+    let server = cluster.server.trim_end_matches('/');
+    let response = agent
+        .build()
+        .get(&format!("{}/version", server))
+        .set("Accept", "application/json")
+        .call()
+        .map_err(|e| explain(e, server))?;
+    let info: VersionInfo = parse_json(response).context("Unexpected /version response")?;
+    Ok(info.git_version)
+}
+
+/// Complete a TLS handshake with the cluster's API server, validating the
+/// certificate chain it presents against the CA in the kubeconfig (or the
+/// public roots when there's none) and the server name kubectl would check
+pub fn verify_server_certificate(cluster: &ClusterInfo, timeout: Duration) -> Result<()> {
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    let (host, port) = crate::check::server_address(&cluster.server)
+        .with_context(|| format!("Invalid server URL '{}'", cluster.server))?;
+    let name = cluster
+        .tls_server_name
+        .clone()
+        .unwrap_or_else(|| host.clone());
+    let server_name = ServerName::try_from(name.clone())
+        .with_context(|| format!("Invalid server name '{}'", name))?;
+    let mut connection =
+        rustls::ClientConnection::new(Arc::new(tls_config(cluster, None)?), server_name)
+            .context("Failed to set up TLS")?;
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("No addresses for {}", host))?;
+    let mut socket = TcpStream::connect_timeout(&addr, timeout)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut socket).map_err(|e| {
+            match e
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+            {
+                Some(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::NotValidForName
+                    | rustls::CertificateError::NotValidForNameContext { .. },
+                )) => anyhow::anyhow!("server certificate isn't valid for '{}'", name),
+                Some(rustls::Error::InvalidCertificate(reason)) => anyhow::anyhow!(
+                    "server certificate doesn't validate against the kubeconfig's CA ({:?})",
+                    reason
+                ),
+                _ => anyhow::Error::from(e).context("TLS handshake failed"),
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Who the API server says the credentials belong to
+#[derive(Debug)]
+pub struct Identity {
+    pub username: String,
+    pub uid: Option<String>,
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Review {
+    status: ReviewStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewStatus {
+    user_info: ReviewUser,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewUser {
+    #[serde(default)]
+    username: String,
+    uid: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+/// Outcome of an authentication test
+#[derive(Debug)]
+pub struct AuthReport {
+    pub context: String,
+    pub server: String,
+    pub credentials_source: String,
+    /// None when the cluster predates SelfSubjectReview but accepted the
+    /// credentials
+    pub identity: Option<Identity>,
+}
+
+/// An authenticated connection to a context's API server
+pub struct ApiClient {
+    agent: ureq::Agent,
+    pub server: String,
+    authorization: Option<String>,
+    /// Where the credentials came from, for display
+    pub credentials_source: String,
+}
+
+impl ApiClient {
+    /// Gather the context's credentials (running its exec plugin if needed)
+    /// and set up TLS for its cluster
+    pub fn connect(config: &KubeConfig, context_name: &str, timeout: Duration) -> Result<Self> {
+        let (cluster, credentials) = context_credentials(config, context_name)?;
+        let tls = tls_config(&cluster.cluster, credentials.client_cert.as_ref())?;
+        let mut agent = ureq::AgentBuilder::new()
+            .tls_config(Arc::new(tls))
+            .timeout(timeout);
+        if let Some(proxy) = &cluster.cluster.proxy_url {
+            agent = agent.proxy(ureq::Proxy::new(proxy).context("Invalid proxy-url")?);
+        }
+        Ok(Self {
+            agent: agent.build(),
+            server: cluster.cluster.server.trim_end_matches('/').to_string(),
+            authorization: credentials.authorization(),
+            credentials_source: credentials.source,
+        })
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}{}", self.server, path))
+            .set("Accept", "application/json");
+        match &self.authorization {
+            Some(value) => request.set("Authorization", value),
+            None => request,
+        }
+    }
+
+    /// GET an API path and parse the JSON response
+    pub fn get(&self, path: &str) -> Result<serde_json::Value> {
+        let response = self
+            .request("GET", path)
+            .call()
+            .map_err(|e| explain(e, &self.server))?;
+        parse_json(response)
+    }
+
+    /// HTTP status of a GET, for probing what exists and what's allowed
+    /// without reading the body
+    pub fn status(&self, path: &str) -> Result<u16> {
+        match self.request("GET", path).call() {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(401, response)) => {
+                Err(explain(ureq::Error::Status(401, response), &self.server))
+            }
+            Err(ureq::Error::Status(code, _)) => Ok(code),
+            Err(e) => Err(explain(e, &self.server)),
+        }
+    }
+
+    /// Names of the namespaces the credentials can list
+    pub fn namespaces(&self) -> Result<Vec<String>> {
+        Ok(namespace_names(&self.get("/api/v1/namespaces")?))
+    }
+}
+
+fn namespace_names(list: &serde_json::Value) -> Vec<String> {
+    let mut names: Vec<String> = list["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item["metadata"]["name"].as_str().map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(response: ureq::Response) -> Result<T> {
+    let body = response.into_string()?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// Authenticate as `context_name` and ask the API server who we are
+pub fn test(config: &KubeConfig, context_name: &str, timeout: Duration) -> Result<AuthReport> {
+    let client = ApiClient::connect(config, context_name, timeout)?;
+    let mut identity = None;
+    for version in ["v1", "v1beta1"] {
+        let body = serde_json::json!({
+            "apiVersion": format!("authentication.k8s.io/{}", version),
+            "kind": "SelfSubjectReview",
+        });
+        let request = client
+            .request(
+                "POST",
+                &format!("/apis/authentication.k8s.io/{}/selfsubjectreviews", version),
+            )
+            .set("Content-Type", "application/json");
+        match request.send_string(&body.to_string()) {
+            Ok(response) => {
+                let review: Review =
+                    parse_json(response).context("Unexpected SelfSubjectReview response")?;
+                let user = review.status.user_info;
+                identity = Some(Identity {
+                    username: user.username,
+                    uid: user.uid,
+                    groups: user.groups,
+                });
+                break;
+            }
+            Err(ureq::Error::Status(404, _)) => continue,
+            Err(e) => return Err(explain(e, &client.server)),
+        }
+    }
+    if identity.is_none() {
+        // Clusters older than 1.27 have no SelfSubjectReview; an
+        // authenticated discovery call at least proves the credentials work
+        client.get("/api")?;
+    }
+
+    Ok(AuthReport {
+        context: context_name.to_string(),
+        server: client.server,
+        credentials_source: client.credentials_source,
+        identity,
+    })
+}
+
+/// Turn a failed API call into a readable error, including the API server's
+/// own message when it sent one
+fn explain(error: ureq::Error, server: &str) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let message = response
+                .into_string()
+                .ok()
+                .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                .and_then(|v| v["message"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            let meaning = match code {
+                401 => "credentials rejected",
+                403 => "authenticated, but forbidden",
+                _ => "request failed",
+            };
+            let mut error = format!("{}: HTTP {} ({})", server, code, meaning);
+            if !message.is_empty() {
+                error.push_str(&format!(": {}", message));
+            }
+            anyhow::anyhow!(error)
+        }
+        other => anyhow::Error::from(other).context(format!("Failed to reach {}", server)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_credentials_from_exec_plugin() {
+        let mut config = create_test_kubeconfig("a");
+        let user = &mut config.users[0].user;
+        user.client_certificate_data = None;
+        user.exec = Some(ExecConfig {
+            api_version: "client.authentication.k8s.io/v1".to_string(),
+            command: "sh".to_string(),
+            args: Some(vec![
+                "-c".to_string(),
+                r#"echo "{\"status\":{\"token\":\"$TOKEN\"}}""#.to_string(),
+            ]),
+            env: Some(vec![crate::ExecEnvVar {
+                name: "TOKEN".to_string(),
+                value: "from-plugin".to_string(),
+            }]),
+            install_hint: None,
+            provide_cluster_info: None,
+            interactive_mode: None,
+        });
+        let credentials = credentials(&config.users[0].user, &config.clusters[0].cluster).unwrap();
+        assert_eq!(credentials.bearer.as_deref(), Some("from-plugin"));
+        assert_eq!(credentials.source, "exec plugin 'sh'");
+        assert_eq!(
+            credentials.authorization().as_deref(),
+            Some("Bearer from-plugin")
+        );
+    }
+
+    #[test]
+    fn test_authorization_header() {
+        let basic = Credentials {
+            basic: Some(("admin".to_string(), "secret".to_string())),
+            ..Credentials::default()
+        };
+        assert_eq!(
+            basic.authorization().as_deref(),
+            Some("Basic YWRtaW46c2VjcmV0")
+        );
+        assert_eq!(Credentials::default().authorization(), None);
+
+        let config = create_test_kubeconfig("a");
+        let (cluster, _) = context_credentials(&config, "a-context").unwrap();
+        assert_eq!(cluster.name, "a-cluster");
+        assert!(context_credentials(&config, "missing").is_err());
+    }
+
+    #[test]
+    fn test_unreachable_server() {
+        let mut config = create_test_kubeconfig("a");
+        config.clusters[0].cluster.server = "https://127.0.0.1:1".to_string();
+        config.clusters[0].cluster.certificate_authority_data = None;
+        config.users[0].user.client_certificate_data = None;
+        config.users[0].user.token = Some("t".to_string());
+        let error = test(&config, "a-context", Duration::from_secs(1)).unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to reach https://127.0.0.1:1"));
+        assert!(test(&config, "missing", Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_namespace_names() {
+        let list = serde_json::json!({
+            "kind": "NamespaceList",
+            "items": [
+                {"metadata": {"name": "kube-system"}},
+                {"metadata": {"name": "default"}},
+            ],
+        });
+        assert_eq!(namespace_names(&list), vec!["default", "kube-system"]);
+        assert!(namespace_names(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_verify_server_certificate() {
+        use rustls::pki_types::PrivateKeyDer;
+        use std::net::TcpListener;
+
+        let chain =
+            CertificateDer::pem_slice_iter(include_bytes!("../tests/fixtures/certs/server.crt"))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+        let key =
+            PrivateKeyDer::from_pem_slice(include_bytes!("../tests/fixtures/certs/server.key"))
+                .unwrap();
+        let server = Arc::new(
+            rustls::ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut connection = rustls::ServerConnection::new(server.clone()).unwrap();
+                while connection.is_handshaking() {
+                    if connection.complete_io(&mut stream).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut cluster = create_test_kubeconfig("a").clusters.remove(0).cluster;
+        cluster.server = format!("https://127.0.0.1:{}", port);
+        cluster.certificate_authority_data = Some(
+            base64::engine::general_purpose::STANDARD
+                .encode(include_str!("../tests/fixtures/certs/ca.crt")),
+        );
+        let timeout = Duration::from_secs(2);
+        verify_server_certificate(&cluster, timeout).unwrap();
+
+        // A stale CA: the public roots don't trust the test CA
+        cluster.certificate_authority_data = None;
+        let error = verify_server_certificate(&cluster, timeout).unwrap_err();
+        assert!(
+            error.to_string().contains("doesn't validate"),
+            "{:#}",
+            error
+        );
+
+        // The certificate isn't valid for this name
+        cluster.certificate_authority_data = Some(
+            base64::engine::general_purpose::STANDARD
+                .encode(include_str!("../tests/fixtures/certs/ca.crt")),
+        );
+        cluster.tls_server_name = Some("other.example.com".to_string());
+        let error = verify_server_certificate(&cluster, timeout).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "server certificate isn't valid for 'other.example.com'"
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::pem::Pem;
+
+use crate::{expand_tilde, jwt, KconfError, KubeConfig};
+
+/// Decoded details of a single X.509 certificate
+#[derive(Debug)]
+pub struct CertDetails {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// Expiry as seconds since the Unix epoch
+    pub not_after_timestamp: i64,
+    pub sans: Vec<String>,
+    pub key_usage: Vec<String>,
+    pub extended_key_usage: Vec<String>,
+    pub is_ca: bool,
+    pub sha256_fingerprint: String,
+    pub sha1_fingerprint: String,
+}
+
+/// Load certificate bytes from an embedded `-data` field or a file path.
+/// Returns a label describing where the certificate came from.
+pub fn load_cert_bytes(
+    data: Option<&str>,
+    path: Option<&str>,
+) -> Result<Option<(Vec<u8>, String)>> {
+    if let Some(data) = data {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(data.trim())
+            .context("Failed to decode base64 certificate data")?;
+        return Ok(Some((bytes, "embedded".to_string())));
+    }
+    if let Some(path) = path {
+        let path = expand_tilde(path);
+        let bytes = fs::read(&path)
+            .with_context(|| format!("Failed to read certificate file: {:?}", path))?;
+        return Ok(Some((bytes, format!("{:?}", path))));
+    }
+    Ok(None)
+}
+
+/// Parse every certificate in a PEM bundle (or a single DER certificate)
+pub fn parse_certificates(bytes: &[u8]) -> Result<Vec<CertDetails>> {
+    if !bytes.starts_with(b"-----BEGIN") {
+        let (_, cert) = x509_parser::parse_x509_certificate(bytes)
+            .map_err(|e| anyhow::anyhow!("Failed to parse DER certificate: {}", e))?;
+        return Ok(vec![describe(&cert, bytes)]);
+    }
+
+    let mut certs = Vec::new();
+    for pem in Pem::iter_from_buffer(bytes) {
+        let pem = pem.map_err(|e| anyhow::anyhow!("Failed to read PEM block: {}", e))?;
+        if pem.label != "CERTIFICATE" {
+            continue;
+        }
+        let cert = pem
+            .parse_x509()
+            .map_err(|e| anyhow::anyhow!("Failed to parse certificate: {}", e))?;
+        certs.push(describe(&cert, &pem.contents));
+    }
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in PEM data");
+    }
+    Ok(certs)
+}
+
+fn fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn describe(cert: &X509Certificate, der: &[u8]) -> CertDetails {
+    let validity = cert.validity();
+    let mut sans = Vec::new();
+    if let Ok(Some(ext)) = cert.subject_alternative_name() {
+        for name in &ext.value.general_names {
+            sans.push(match name {
+                GeneralName::DNSName(dns) => format!("DNS:{}", dns),
+                GeneralName::IPAddress(ip) => match ip.len() {
+                    4 => format!("IP:{}", std::net::Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])),
+                    16 => {
+                        let mut octets = [0u8; 16];
+                        octets.copy_from_slice(ip);
+                        format!("IP:{}", std::net::Ipv6Addr::from(octets))
+                    }
+                    _ => "IP:<invalid>".to_string(),
+                },
+                GeneralName::RFC822Name(email) => format!("email:{}", email),
+                GeneralName::URI(uri) => format!("URI:{}", uri),
+                other => format!("{:?}", other),
+            });
+        }
+    }
+
+    let mut key_usage = Vec::new();
+    if let Ok(Some(ext)) = cert.key_usage() {
+        let ku = ext.value;
+        let flags = [
+            (ku.digital_signature(), "Digital Signature"),
+            (ku.non_repudiation(), "Non Repudiation"),
+            (ku.key_encipherment(), "Key Encipherment"),
+            (ku.data_encipherment(), "Data Encipherment"),
+            (ku.key_agreement(), "Key Agreement"),
+            (ku.key_cert_sign(), "Certificate Sign"),
+            (ku.crl_sign(), "CRL Sign"),
+            (ku.encipher_only(), "Encipher Only"),
+            (ku.decipher_only(), "Decipher Only"),
+        ];
+        key_usage.extend(
+            flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, n)| n.to_string()),
+        );
+    }
+
+    let mut extended_key_usage = Vec::new();
+    if let Ok(Some(ext)) = cert.extended_key_usage() {
+        let eku = ext.value;
+        let flags = [
+            (eku.any, "Any"),
+            (eku.server_auth, "Server Auth"),
+            (eku.client_auth, "Client Auth"),
+            (eku.code_signing, "Code Signing"),
+            (eku.email_protection, "Email Protection"),
+            (eku.time_stamping, "Time Stamping"),
+            (eku.ocsp_signing, "OCSP Signing"),
+        ];
+        extended_key_usage.extend(
+            flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, n)| n.to_string()),
+        );
+        extended_key_usage.extend(eku.other.iter().map(|oid| oid.to_id_string()));
+    }
+
+    let is_ca = matches!(cert.basic_constraints(), Ok(Some(ext)) if ext.value.ca);
+
+    CertDetails {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        serial: cert.raw_serial_as_string(),
+        not_before: validity
+            .not_before
+            .to_rfc2822()
+            .unwrap_or_else(|_| validity.not_before.to_string()),
+        not_after: validity
+            .not_after
+            .to_rfc2822()
+            .unwrap_or_else(|_| validity.not_after.to_string()),
+        not_after_timestamp: validity.not_after.timestamp(),
+        sans,
+        key_usage,
+        extended_key_usage,
+        is_ca,
+        sha256_fingerprint: fingerprint(&Sha256::digest(der)),
+        sha1_fingerprint: fingerprint(&Sha1::digest(der)),
+    }
+}
+
+fn print_details(details: &CertDetails) {
+    let join = |items: &[String]| {
+        if items.is_empty() {
+            "(none)".to_string()
+        } else {
+            items.join(", ")
+        }
+    };
+    println!("  Subject:       {}", details.subject);
+    println!("  Issuer:        {}", details.issuer);
+    println!("  Serial:        {}", details.serial);
+    println!("  Not before:    {}", details.not_before);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    if details.not_after_timestamp < now {
+        println!("  Not after:     {} (EXPIRED)", details.not_after);
+    } else {
+        println!("  Not after:     {}", details.not_after);
+    }
+    println!("  SANs:          {}", join(&details.sans));
+    println!("  Key usage:     {}", join(&details.key_usage));
+    println!("  Ext key usage: {}", join(&details.extended_key_usage));
+    println!(
+        "  CA:            {}",
+        if details.is_ca { "yes" } else { "no" }
+    );
+    println!("  SHA-256:       {}", details.sha256_fingerprint);
+    println!("  SHA-1:         {}", details.sha1_fingerprint);
+}
+
+fn print_section(title: &str, loaded: Option<(Vec<u8>, String)>) -> Result<Vec<CertDetails>> {
+    match loaded {
+        Some((bytes, source)) => {
+            let certs = parse_certificates(&bytes)?;
+            for (i, details) in certs.iter().enumerate() {
+                if certs.len() > 1 {
+                    println!("{} ({}, {} of {}):", title, source, i + 1, certs.len());
+                } else {
+                    println!("{} ({}):", title, source);
+                }
+                print_details(details);
+                println!();
+            }
+            Ok(certs)
+        }
+        None => {
+            println!("{}: (none)", title);
+            println!();
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Print decoded client certificate and CA details for a context
+pub fn show(config: &KubeConfig, context_name: &str) -> Result<()> {
+    let context = config
+        .contexts
+        .iter()
+        .find(|c| c.name == context_name)
+        .ok_or_else(|| KconfError::ContextNotFound(context_name.to_string()))?;
+    let cluster = config
+        .clusters
+        .iter()
+        .find(|c| c.name == context.context.cluster);
+    let user = config.users.iter().find(|u| u.name == context.context.user);
+
+    println!("Context: {}", context.name);
+    match cluster {
+        Some(c) => println!("Cluster: {} ({})", c.name, c.cluster.server),
+        None => println!("Cluster: {} (missing)", context.context.cluster),
+    }
+    match user {
+        Some(u) => println!("User:    {}", u.name),
+        None => println!("User:    {} (missing)", context.context.user),
+    }
+    println!();
+
+    if let Some(claims) = user
+        .and_then(|u| u.user.token.as_deref())
+        .and_then(jwt::decode)
+    {
+        println!("Token (JWT):");
+        println!(
+            "  Subject:       {}",
+            claims.subject.as_deref().unwrap_or("(none)")
+        );
+        println!(
+            "  Issuer:        {}",
+            claims.issuer.as_deref().unwrap_or("(none)")
+        );
+        println!(
+            "  Expires:       {}",
+            claims
+                .expiry(jwt::now())
+                .unwrap_or_else(|| "never".to_string())
+        );
+        println!();
+    }
+
+    let client_certs = match user {
+        Some(u) => print_section(
+            "Client certificate",
+            load_cert_bytes(
+                u.user.client_certificate_data.as_deref(),
+                u.user.client_certificate.as_deref(),
+            )?,
+        )?,
+        None => Vec::new(),
+    };
+
+    let ca_certs = match cluster {
+        Some(c) => {
+            if c.cluster.insecure_skip_tls_verify == Some(true) {
+                println!("Note: cluster has insecure-skip-tls-verify enabled");
+                println!();
+            }
+            print_section(
+                "Certificate authority",
+                load_cert_bytes(
+                    c.cluster.certificate_authority_data.as_deref(),
+                    c.cluster.certificate_authority.as_deref(),
+                )?,
+            )?
+        }
+        None => Vec::new(),
+    };
+
+    // Help diagnose "signed by unknown authority" style errors
+    if let Some(client) = client_certs.first() {
+        if !ca_certs.is_empty() {
+            let matched = ca_certs.iter().any(|ca| ca.subject == client.issuer);
+            println!(
+                "Client certificate issuer matches a CA subject: {}",
+                if matched { "yes" } else { "no" }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA_PEM: &str = include_str!("../tests/fixtures/certs/ca.crt");
+    const CLIENT_PEM: &str = include_str!("../tests/fixtures/certs/client.crt");
+
+    #[test]
+    fn test_parse_ca_certificate() {
+        let certs = parse_certificates(CA_PEM.as_bytes()).unwrap();
+        assert_eq!(certs.len(), 1);
+        assert_eq!(certs[0].subject, "CN=kconf-test-ca");
+        assert!(certs[0].is_ca);
+        assert!(certs[0].key_usage.contains(&"Certificate Sign".to_string()));
+        assert_eq!(certs[0].sha256_fingerprint.split(':').count(), 32);
+    }
+
+    #[test]
+    fn test_parse_client_certificate() {
+        let certs = parse_certificates(CLIENT_PEM.as_bytes()).unwrap();
+        assert_eq!(certs[0].issuer, "CN=kconf-test-ca");
+        assert!(!certs[0].is_ca);
+        assert!(certs[0].sans.contains(&"DNS:admin.example.com".to_string()));
+        assert!(certs[0].sans.contains(&"IP:10.0.0.1".to_string()));
+        assert!(certs[0]
+            .extended_key_usage
+            .contains(&"Client Auth".to_string()));
+    }
+
+    #[test]
+    fn test_parse_certificate_chain() {
+        let bundle = format!("{}{}", CLIENT_PEM, CA_PEM);
+        let certs = parse_certificates(bundle.as_bytes()).unwrap();
+        assert_eq!(certs.len(), 2);
+    }
+
+    #[test]
+    fn test_load_cert_bytes_from_embedded_data() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(CA_PEM);
+        let (bytes, source) = load_cert_bytes(Some(&encoded), None).unwrap().unwrap();
+        assert_eq!(bytes, CA_PEM.as_bytes());
+        assert_eq!(source, "embedded");
+        assert!(load_cert_bytes(None, None).unwrap().is_none());
+    }
+}
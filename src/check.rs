@@ -0,0 +1,526 @@
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::certs::{load_cert_bytes, parse_certificates};
+use crate::progress::Progress;
+use crate::{kubens, ClusterInfo, KubeConfig, UserInfo};
+
+/// Certificates expiring within this many days are reported as warnings
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// Order of `kconf check` results; without one they follow the config
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortBy {
+    /// Context name
+    Name,
+    /// Fastest connection first, unreachable servers last
+    Latency,
+}
+
+/// Outcome of probing one context
+#[derive(Debug)]
+pub struct ContextCheck {
+    pub context: String,
+    pub server: String,
+    /// Time to open a TCP connection (one network round trip), or why it
+    /// failed
+    pub reachable: Result<Duration, String>,
+    /// Kubernetes version from `/version`, when the server answered
+    pub version: Option<String>,
+    /// Expired or unreadable certificates
+    pub problems: Vec<String>,
+    /// Certificates expiring soon
+    pub warnings: Vec<String>,
+}
+
+impl ContextCheck {
+    pub fn ok(&self) -> bool {
+        self.reachable.is_ok() && self.problems.is_empty()
+    }
+
+    pub fn status(&self) -> &'static str {
+        if self.reachable.is_err() {
+            "unreachable"
+        } else if !self.problems.is_empty() {
+            "failed"
+        } else if !self.warnings.is_empty() {
+            "warning"
+        } else {
+            "ok"
+        }
+    }
+
+    fn latency_ms(&self) -> Option<u128> {
+        self.reachable.as_ref().ok().map(Duration::as_millis)
+    }
+}
+
+/// Host and port from an API server URL, defaulting the port from the scheme
+pub fn server_address(server: &str) -> Option<(String, u16)> {
+    let (scheme, rest) = server.split_once("://").unwrap_or(("https", server));
+    let authority = rest.split('/').next()?;
+    let default_port = if scheme == "http" { 80 } else { 443 };
+    // [::1]:6443
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        let port = match after.strip_prefix(':') {
+            Some(port) => port.parse().ok()?,
+            None => default_port,
+        };
+        return Some((host.to_string(), port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None if !authority.is_empty() => Some((authority.to_string(), default_port)),
+        None => None,
+    }
+}
+
+fn probe(server: &str, timeout: Duration) -> Result<Duration, String> {
+    let (host, port) =
+        server_address(server).ok_or_else(|| format!("invalid server URL '{}'", server))?;
+    let addrs: Vec<_> = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+        .collect();
+    let start = Instant::now();
+    let mut last_error = format!("no addresses for {}", host);
+    for addr in addrs {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            return Err(format!("timed out after {}s", timeout.as_secs()));
+        }
+        match TcpStream::connect_timeout(&addr, remaining) {
+            Ok(_) => return Ok(start.elapsed()),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                last_error = format!("timed out after {}s", timeout.as_secs())
+            }
+            Err(e) => last_error = format!("{}: {}", addr, e),
+        }
+    }
+    Err(last_error)
+}
+
+/// Record expired, unreadable and soon-to-expire certificates for `check`
+fn check_certs(
+    check: &mut ContextCheck,
+    label: &str,
+    data: Option<&str>,
+    path: Option<&str>,
+    now: i64,
+) {
+    let certs = match load_cert_bytes(data, path).and_then(|loaded| match loaded {
+        Some((bytes, _)) => parse_certificates(&bytes),
+        None => Ok(Vec::new()),
+    }) {
+        Ok(certs) => certs,
+        Err(e) => return check.problems.push(format!("{}: {:#}", label, e)),
+    };
+    for cert in certs {
+        let days = (cert.not_after_timestamp - now) / 86_400;
+        if cert.not_after_timestamp < now {
+            check
+                .problems
+                .push(format!("{} expired {}", label, cert.not_after));
+        } else if days < EXPIRY_WARNING_DAYS {
+            check
+                .warnings
+                .push(format!("{} expires in {} day(s)", label, days));
+        }
+    }
+}
+
+/// Whether `namespace` still exists and the context's user can list pods in
+/// it, asking the API server with the user's credentials. Existence can only
+/// be confirmed when the user may read the namespace itself.
+#[cfg(feature = "remote")]
+fn check_namespace(
+    check: &mut ContextCheck,
+    config: &KubeConfig,
+    namespace: &str,
+    timeout: Duration,
+) {
+    use crate::auth::ApiClient;
+
+    let verified = ApiClient::connect(config, &check.context, timeout).and_then(|client| {
+        let exists = client.status(&format!("/api/v1/namespaces/{}", namespace))?;
+        let pods = client.status(&format!("/api/v1/namespaces/{}/pods?limit=1", namespace))?;
+        Ok((exists, pods))
+    });
+    match verified {
+        Ok((404, _)) => check
+            .problems
+            .push(format!("namespace '{}' doesn't exist", namespace)),
+        Ok((_, 403)) => check.warnings.push(format!(
+            "not allowed to list pods in namespace '{}'",
+            namespace
+        )),
+        Ok(_) => {}
+        Err(e) => check
+            .warnings
+            .push(format!("namespace '{}' not verified: {:#}", namespace, e)),
+    }
+}
+
+/// Whether the certificate the server presents validates against the CA
+/// stored for it, which goes stale when a cluster is rebuilt
+#[cfg(feature = "remote")]
+fn check_ca(check: &mut ContextCheck, cluster: &ClusterInfo, timeout: Duration) {
+    if cluster.insecure_skip_tls_verify == Some(true) {
+        check
+            .warnings
+            .push("CA not verified: insecure-skip-tls-verify is set".to_string());
+    } else if cluster.proxy_url.is_some() {
+        check
+            .warnings
+            .push("CA not verified: the cluster is reached through a proxy".to_string());
+    } else if let Err(e) = crate::auth::verify_server_certificate(cluster, timeout) {
+        check.problems.push(format!("{:#}", e));
+    }
+}
+
+#[cfg(not(feature = "remote"))]
+fn check_ca(check: &mut ContextCheck, _cluster: &ClusterInfo, _timeout: Duration) {
+    check
+        .warnings
+        .push("CA not verified: built without the remote feature".to_string());
+}
+
+#[cfg(not(feature = "remote"))]
+fn check_namespace(
+    check: &mut ContextCheck,
+    _config: &KubeConfig,
+    namespace: &str,
+    _timeout: Duration,
+) {
+    check.warnings.push(format!(
+        "namespace '{}' not verified: built without the remote feature",
+        namespace
+    ));
+}
+
+fn check_context(
+    name: &str,
+    cluster: Option<&ClusterInfo>,
+    user: Option<&UserInfo>,
+    timeout: Duration,
+    now: i64,
+) -> ContextCheck {
+    let mut check = ContextCheck {
+        context: name.to_string(),
+        server: String::new(),
+        reachable: Err("cluster is missing".to_string()),
+        version: None,
+        problems: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let Some(cluster) = cluster else {
+        return check;
+    };
+    check.server = cluster.server.clone();
+    check_certs(
+        &mut check,
+        "CA",
+        cluster.certificate_authority_data.as_deref(),
+        cluster.certificate_authority.as_deref(),
+        now,
+    );
+    if let Some(user) = user {
+        check_certs(
+            &mut check,
+            "client certificate",
+            user.client_certificate_data.as_deref(),
+            user.client_certificate.as_deref(),
+            now,
+        );
+    }
+    check.reachable = probe(&cluster.server, timeout);
+    if check.reachable.is_ok() {
+        check.version = server_version(cluster, timeout);
+    }
+    check
+}
+
+#[cfg(feature = "remote")]
+fn server_version(cluster: &ClusterInfo, timeout: Duration) -> Option<String> {
+    crate::auth::server_version(cluster, timeout).ok()
+}
+
+#[cfg(not(feature = "remote"))]
+fn server_version(_cluster: &ClusterInfo, _timeout: Duration) -> Option<String> {
+    None
+}
+
+/// Probe every context (or only `only`) using at most `concurrency` threads,
+/// giving each server `timeout` to accept a connection. With `namespaces`,
+/// contexts that set a namespace also have it verified with their
+/// credentials, and with `verify_ca` each server's certificate is validated
+/// against its CA. Results keep the order of the contexts in the config.
+pub fn run(
+    config: &KubeConfig,
+    only: &[String],
+    concurrency: usize,
+    timeout: Duration,
+    namespaces: bool,
+    verify_ca: bool,
+) -> Vec<ContextCheck> {
+    let contexts: Vec<_> = config
+        .contexts
+        .iter()
+        .filter(|c| only.is_empty() || only.contains(&c.name))
+        .collect();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<ContextCheck>>> =
+        Mutex::new(contexts.iter().map(|_| None).collect());
+    let progress = Progress::new(contexts.len());
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, contexts.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(context) = contexts.get(i) else {
+                    break;
+                };
+                let cluster = config
+                    .clusters
+                    .iter()
+                    .find(|c| c.name == context.context.cluster)
+                    .map(|c| &c.cluster);
+                let user = config
+                    .users
+                    .iter()
+                    .find(|u| u.name == context.context.user)
+                    .map(|u| &u.user);
+                let mut result = check_context(&context.name, cluster, user, timeout, now);
+                if let Some(cluster) = cluster.filter(|_| verify_ca && result.reachable.is_ok()) {
+                    check_ca(&mut result, cluster, timeout);
+                }
+                if let Some(namespace) = kubens::namespace(&context.context)
+                    .filter(|_| namespaces && result.reachable.is_ok())
+                {
+                    check_namespace(&mut result, config, &namespace, timeout);
+                }
+                progress.tick(&context.name);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Reorder results; sorting is stable, so ties keep the config's order
+pub fn sort(results: &mut [ContextCheck], by: SortBy) {
+    match by {
+        SortBy::Name => results.sort_by(|a, b| a.context.cmp(&b.context)),
+        SortBy::Latency => results.sort_by_key(|r| (r.reachable.is_err(), r.latency_ms())),
+    }
+}
+
+/// Table rows for the results, starting with a header row
+pub fn rows(results: &[ContextCheck]) -> Vec<Vec<String>> {
+    let mut rows = vec![[
+        "CONTEXT", "SERVER", "VERSION", "LATENCY", "STATUS", "DETAILS",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()];
+    for result in results {
+        let mut details: Vec<String> = result
+            .reachable
+            .as_ref()
+            .err()
+            .cloned()
+            .into_iter()
+            .collect();
+        details.extend(result.problems.iter().cloned());
+        details.extend(result.warnings.iter().cloned());
+        rows.push(vec![
+            result.context.clone(),
+            result.server.clone(),
+            result.version.clone().unwrap_or_else(|| "-".to_string()),
+            result
+                .latency_ms()
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_else(|| "-".to_string()),
+            result.status().to_string(),
+            details.join("; "),
+        ]);
+    }
+    rows
+}
+
+/// The results as a JSON array, for `--format json`
+pub fn json(results: &[ContextCheck]) -> Value {
+    results
+        .iter()
+        .map(|result| {
+            json!({
+                "context": result.context,
+                "server": result.server,
+                "version": result.version,
+                "latency_ms": result.latency_ms(),
+                "status": result.status(),
+                "error": result.reachable.as_ref().err(),
+                "problems": result.problems,
+                "warnings": result.warnings,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_server_address() {
+        assert_eq!(
+            server_address("https://api.example.com:6443/"),
+            Some(("api.example.com".to_string(), 6443))
+        );
+        assert_eq!(
+            server_address("https://api.example.com"),
+            Some(("api.example.com".to_string(), 443))
+        );
+        assert_eq!(
+            server_address("http://[::1]"),
+            Some(("::1".to_string(), 80))
+        );
+        assert_eq!(server_address("https://"), None);
+    }
+
+    /// A plain-HTTP stand-in for an API server. Namespace `gone` doesn't
+    /// exist and pods can't be listed in `locked`; the bare connectivity
+    /// probe just connects and hangs up.
+    fn fake_api_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap_or(0);
+                if read == 0 {
+                    continue;
+                }
+                let request = String::from_utf8_lossy(&request[..read]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match path {
+                    "/version" => (
+                        "200 OK",
+                        r#"{"major":"1","minor":"29","gitVersion":"v1.29.3"}"#,
+                    ),
+                    "/api/v1/namespaces/gone" => ("404 Not Found", "{}"),
+                    p if p.starts_with("/api/v1/namespaces/locked/") => ("403 Forbidden", "{}"),
+                    _ => ("200 OK", "{}"),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_run_probes_concurrently_in_order() {
+        let port = fake_api_server();
+        let mut config = create_test_kubeconfig("up");
+        config.clusters[0].cluster.server = format!("http://127.0.0.1:{}", port);
+        // The fixture's placeholder certificate data isn't parseable
+        config.clusters[0].cluster.certificate_authority_data = None;
+        config.users[0].user.client_certificate_data = None;
+        let mut down = create_test_kubeconfig("down");
+        down.clusters[0].cluster.server = "https://".to_string();
+        config.clusters.extend(down.clusters);
+        config.contexts.extend(down.contexts);
+        config.users.extend(down.users);
+
+        let results = run(&config, &[], 4, Duration::from_secs(2), false, false);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].context, "up-context");
+        assert!(results[0].ok());
+        #[cfg(feature = "remote")]
+        assert_eq!(results[0].version.as_deref(), Some("v1.29.3"));
+        assert_eq!(results[1].version, None);
+        assert_eq!(rows(&results)[2][2], "-");
+        assert_eq!(rows(&results)[2][3], "-");
+        assert!(rows(&results)[1][3].ends_with("ms"));
+        assert_eq!(results[1].context, "down-context");
+        assert!(results[1].reachable.is_err());
+
+        let only = run(
+            &config,
+            &["down-context".to_string()],
+            4,
+            Duration::from_secs(2),
+            false,
+            false,
+        );
+        assert_eq!(only.len(), 1);
+
+        let mut sorted = results;
+        sort(&mut sorted, SortBy::Name);
+        assert_eq!(sorted[0].context, "down-context");
+        sort(&mut sorted, SortBy::Latency);
+        assert_eq!(sorted[0].context, "up-context");
+        let json = json(&sorted);
+        assert!(json[0]["latency_ms"].is_u64());
+        assert_eq!(json[1]["status"], "unreachable");
+        assert!(json[1]["latency_ms"].is_null());
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn test_check_namespaces() {
+        let port = fake_api_server();
+        let mut config = KubeConfig {
+            clusters: Vec::new(),
+            contexts: Vec::new(),
+            users: Vec::new(),
+            ..create_test_kubeconfig("x")
+        };
+        for namespace in ["gone", "locked", "team"] {
+            let mut entry = create_test_kubeconfig(namespace);
+            entry.clusters[0].cluster.server = format!("http://127.0.0.1:{}", port);
+            entry.clusters[0].cluster.certificate_authority_data = None;
+            entry.users[0].user.client_certificate_data = None;
+            entry.contexts[0].context.namespace = Some(namespace.to_string());
+            config.clusters.extend(entry.clusters);
+            config.contexts.extend(entry.contexts);
+            config.users.extend(entry.users);
+        }
+
+        let unchecked = run(&config, &[], 4, Duration::from_secs(2), false, false);
+        assert!(unchecked.iter().all(ContextCheck::ok));
+
+        let results = run(&config, &[], 4, Duration::from_secs(2), true, false);
+        assert_eq!(results[0].problems, vec!["namespace 'gone' doesn't exist"]);
+        assert_eq!(
+            results[1].warnings,
+            vec!["not allowed to list pods in namespace 'locked'"]
+        );
+        assert_eq!(results[1].status(), "warning");
+        assert!(results[2].ok() && results[2].warnings.is_empty());
+    }
+}
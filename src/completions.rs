@@ -0,0 +1,135 @@
+use clap::ValueEnum;
+use clap_complete::{generate, Shell};
+use clap_complete_nushell::Nushell;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Nushell,
+}
+
+/// Subcommands whose first argument is a context name
+const CONTEXT_COMMANDS: &[&str] = &["use", "env", "which", "note", "tag", "pin", "unpin"];
+
+/// Start of the completer clap_complete emits for PowerShell
+const POWERSHELL_REGISTER: &str =
+    "Register-ArgumentCompleter -Native -CommandName 'kconf' -ScriptBlock {";
+
+/// Completes context names after the subcommands above and hands everything
+/// else to the generated completer, which is kept in `$global:_kconfStatic`
+const POWERSHELL_CONTEXTS: &str = r#"
+Register-ArgumentCompleter -Native -CommandName 'kconf' -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | ForEach-Object { $_.ToString() })
+    $position = if ($wordToComplete) { $words.Count - 1 } else { $words.Count }
+    if ($position -eq 2 -and @(__CONTEXT_COMMANDS__) -contains $words[1]) {
+        kconf completions --list-contexts |
+            Where-Object { $_ -like "$wordToComplete*" } |
+            ForEach-Object { [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_) }
+        return
+    }
+    & $global:_kconfStatic $wordToComplete $commandAst $cursorPosition
+}
+"#;
+
+/// Nushell custom completer listing the destination's contexts
+const NUSHELL_CONTEXTS: &str = r#"  def "nu-complete kconf contexts" [] {
+    ^kconf completions --list-contexts | lines
+  }
+
+"#;
+
+/// Completion script for `shell`. PowerShell and Nushell also complete the
+/// context names of the destination, by running `kconf completions
+/// --list-contexts` when completing.
+pub fn script(shell: CompletionShell, mut command: clap::Command) -> String {
+    let name = command.get_name().to_string();
+    let mut out = Vec::new();
+    let generator = match shell {
+        CompletionShell::Bash => Shell::Bash,
+        CompletionShell::Zsh => Shell::Zsh,
+        CompletionShell::Fish => Shell::Fish,
+        CompletionShell::Powershell => Shell::PowerShell,
+        CompletionShell::Nushell => {
+            generate(Nushell, &mut command, name, &mut out);
+            let script = String::from_utf8(out).expect("completion scripts are UTF-8");
+            return nushell_contexts(&script);
+        }
+    };
+    generate(generator, &mut command, name, &mut out);
+    let script = String::from_utf8(out).expect("completion scripts are UTF-8");
+    match shell {
+        CompletionShell::Powershell => powershell_contexts(&script),
+        _ => script,
+    }
+}
+
+fn powershell_contexts(script: &str) -> String {
+    let commands = CONTEXT_COMMANDS
+        .iter()
+        .map(|c| format!("'{}'", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let script = script.replacen(POWERSHELL_REGISTER, "$global:_kconfStatic = {", 1);
+    script + &POWERSHELL_CONTEXTS.replace("__CONTEXT_COMMANDS__", &commands)
+}
+
+fn nushell_contexts(script: &str) -> String {
+    let mut script = script.to_string();
+    for command in CONTEXT_COMMANDS {
+        let header = format!("export extern \"kconf {}\" [", command);
+        let Some(start) = script.find(&header) else {
+            continue;
+        };
+        let end = start + script[start..].find("\n  ]").unwrap_or(0);
+        let body = script[start..end]
+            .replacen(
+                "context?: string",
+                "context?: string@\"nu-complete kconf contexts\"",
+                1,
+            )
+            .replacen(
+                "context: string",
+                "context: string@\"nu-complete kconf contexts\"",
+                1,
+            );
+        script.replace_range(start..end, &body);
+    }
+    if let Some(at) = script.find("module completions {\n\n") {
+        script.insert_str(at + "module completions {\n\n".len(), NUSHELL_CONTEXTS);
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_dynamic_context_completion() {
+        let powershell = script(CompletionShell::Powershell, crate::Args::command());
+        assert!(powershell.contains("$global:_kconfStatic = {"));
+        assert_eq!(powershell.matches("Register-ArgumentCompleter").count(), 1);
+        assert!(powershell.contains("'use', 'env'"));
+
+        let nushell = script(CompletionShell::Nushell, crate::Args::command());
+        assert!(nushell.contains("def \"nu-complete kconf contexts\""));
+        for command in CONTEXT_COMMANDS {
+            let header = format!("export extern \"kconf {}\" [", command);
+            let start = nushell.find(&header).unwrap();
+            let end = start + nushell[start..].find("\n  ]").unwrap();
+            assert!(
+                nushell[start..end].contains("string@\"nu-complete kconf contexts\""),
+                "{}",
+                command
+            );
+        }
+
+        let bash = script(CompletionShell::Bash, crate::Args::command());
+        assert!(bash.contains("_kconf()"));
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::onepassword::OnePasswordConfig;
+use crate::vault::VaultConfig;
+use crate::{expand_tilde, write_private, ExecConfig, KubeConfig, NamedUser, UserInfo};
+
+/// apiVersion used for the exec stanzas and the ExecCredential output
+const EXEC_API_VERSION: &str = "client.authentication.k8s.io/v1";
+
+/// Credential store settings stored under `credentials:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CredentialsConfig {
+    /// Keep user credentials in ~/.k8sconf/credentials.yaml and reference them
+    /// from the destination through `kconf credential print` exec stanzas
+    #[serde(default)]
+    pub split: bool,
+    /// Keep tokens in HashiCorp Vault, fetched by `kconf credential vault`
+    #[serde(default)]
+    pub vault: VaultConfig,
+    /// Move tokens into 1Password, referenced as `op://` URIs
+    #[serde(default)]
+    pub onepassword: OnePasswordConfig,
+}
+
+/// Users whose credentials were moved out of the destination
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct CredentialStore {
+    #[serde(default)]
+    pub users: Vec<NamedUser>,
+}
+
+/// Load the credential store, or an empty one if it doesn't exist yet
+pub fn load_store(path: &Path) -> Result<CredentialStore> {
+    if !path.exists() {
+        return Ok(CredentialStore::default());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read credential store: {:?}", path))?;
+    serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse credential store: {:?}", path))
+}
+
+/// Write the credential store, readable by the owner only
+pub fn save_store(path: &Path, store: &CredentialStore) -> Result<()> {
+    let content = serde_yaml::to_string(store)?;
+    write_private(path, content.as_bytes()).context("Failed to save the credential store")
+}
+
+/// Name of the stored user an exec stanza written by [`split`] refers to
+pub fn referenced_user(user: &UserInfo) -> Option<&str> {
+    let args = user.exec.as_ref()?.args.as_ref()?;
+    match args.as_slice() {
+        [credential, print, name] if credential == "credential" && print == "print" => {
+            Some(name.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Whether a user's credentials can be served by `kconf credential print`.
+/// Exec users already keep their secrets elsewhere, and basic auth can't be
+/// returned by an exec plugin, so both stay inline.
+fn is_storable(user: &UserInfo) -> bool {
+    user.exec.is_none()
+        && user.username.is_none()
+        && user.password.is_none()
+        && (user.token.is_some()
+            || user.client_certificate_data.is_some()
+            || user.client_key_data.is_some()
+            || user.client_certificate.is_some()
+            || user.client_key.is_some())
+}
+
+/// A user whose credentials come from running `program` with `args`
+pub fn exec_stanza(program: &str, args: &[&str]) -> UserInfo {
+    UserInfo {
+        client_certificate_data: None,
+        client_key_data: None,
+        client_certificate: None,
+        client_key: None,
+        token: None,
+        username: None,
+        password: None,
+        auth_provider: None,
+        exec: Some(ExecConfig {
+            api_version: EXEC_API_VERSION.to_string(),
+            command: program.to_string(),
+            args: Some(args.iter().map(|a| a.to_string()).collect()),
+            env: None,
+            install_hint: None,
+            provide_cluster_info: None,
+            interactive_mode: Some("Never".to_string()),
+        }),
+    }
+}
+
+/// Move every storable user credential into `store` and return the config to
+/// write to the destination, whose users call `program credential print <user>`.
+/// Other stored users are left alone, since `config` needn't be the destination.
+pub fn split(config: &KubeConfig, store: &mut CredentialStore, program: &str) -> KubeConfig {
+    let mut public = config.clone();
+    for user in &mut public.users {
+        if !is_storable(&user.user) {
+            continue;
+        }
+        match store.users.iter_mut().find(|u| u.name == user.name) {
+            Some(stored) => *stored = user.clone(),
+            None => store.users.push(user.clone()),
+        }
+        user.user = exec_stanza(program, &["credential", "print", &user.name]);
+    }
+    public
+}
+
+/// Drop stored users that no longer exist in `config`. Only meant for the
+/// configured destination, which is the one config all stored users belong to.
+pub fn prune(store: &mut CredentialStore, config: &KubeConfig) {
+    store
+        .users
+        .retain(|stored| config.users.iter().any(|u| u.name == stored.name));
+}
+
+/// Replace exec stanzas written by [`split`] with the stored credentials
+pub fn hydrate(config: &mut KubeConfig, store: &CredentialStore) {
+    for user in &mut config.users {
+        let Some(name) = referenced_user(&user.user) else {
+            continue;
+        };
+        if let Some(stored) = store.users.iter().find(|u| u.name == name) {
+            user.user = stored.user.clone();
+        }
+    }
+}
+
+/// PEM text for a certificate or key given inline (base64) or as a file path
+fn pem(data: &Option<String>, path: &Option<String>) -> Result<Option<String>> {
+    if let Some(data) = data {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(data.trim())
+            .context("Invalid base64 in stored credential")?;
+        return Ok(Some(
+            String::from_utf8(decoded).context("Stored PEM is not UTF-8")?,
+        ));
+    }
+    if let Some(path) = path {
+        let path = expand_tilde(path);
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        return Ok(Some(content));
+    }
+    Ok(None)
+}
+
+/// Build the ExecCredential object kubectl expects from an exec plugin
+pub fn exec_credential(user: &UserInfo) -> Result<serde_json::Value> {
+    let mut status = serde_json::Map::new();
+    if let Some(token) = &user.token {
+        status.insert("token".to_string(), token.clone().into());
+    }
+    if let Some(cert) = pem(&user.client_certificate_data, &user.client_certificate)? {
+        status.insert("clientCertificateData".to_string(), cert.into());
+    }
+    if let Some(key) = pem(&user.client_key_data, &user.client_key)? {
+        status.insert("clientKeyData".to_string(), key.into());
+    }
+    Ok(credential(status.into()))
+}
+
+/// ExecCredential carrying just a bearer token
+pub fn token_credential(token: &str) -> serde_json::Value {
+    credential(serde_json::json!({ "token": token }))
+}
+
+fn credential(status: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "apiVersion": EXEC_API_VERSION,
+        "kind": "ExecCredential",
+        "status": status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_split_and_hydrate_roundtrip() {
+        let mut config = create_test_kubeconfig("split");
+        let basic = create_test_kubeconfig("basic");
+        config.users.extend(basic.users);
+        config.users[1].user.username = Some("admin".to_string());
+        config.users[1].user.password = Some("secret".to_string());
+
+        let mut store = CredentialStore::default();
+        let public = split(&config, &mut store, "kconf");
+        assert_eq!(store.users.len(), 1);
+        assert_eq!(referenced_user(&public.users[0].user), Some("split-user"));
+        assert!(public.users[0].user.client_key_data.is_none());
+        // Basic auth can't be served through exec, so it stays inline
+        assert_eq!(public.users[1], config.users[1]);
+
+        let mut hydrated = public.clone();
+        hydrate(&mut hydrated, &store);
+        assert_eq!(hydrated, config);
+    }
+
+    #[test]
+    fn test_split_keeps_other_users_until_pruned() {
+        let mut store = CredentialStore::default();
+        split(&create_test_kubeconfig("old"), &mut store, "kconf");
+        let new = create_test_kubeconfig("new");
+        split(&new, &mut store, "kconf");
+        assert_eq!(store.users.len(), 2);
+
+        prune(&mut store, &new);
+        assert_eq!(store.users.len(), 1);
+        assert_eq!(store.users[0].name, "new-user");
+    }
+
+    #[test]
+    fn test_exec_credential_decodes_data() {
+        let mut user = create_test_kubeconfig("test").users.remove(0).user;
+        user.token = Some("abc".to_string());
+        let credential = exec_credential(&user).unwrap();
+        assert_eq!(credential["kind"], "ExecCredential");
+        assert_eq!(credential["status"]["token"], "abc");
+        assert_eq!(
+            credential["status"]["clientCertificateData"],
+            "test-cert-data"
+        );
+        assert_eq!(credential["status"]["clientKeyData"], "test-key-data");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_store_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("credentials.yaml");
+        fs::write(&path, "users: []\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let mut store = CredentialStore::default();
+        split(&create_test_kubeconfig("test"), &mut store, "kconf");
+        save_store(&path, &store).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(load_store(&path).unwrap(), store);
+    }
+}
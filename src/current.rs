@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::{kubens, NamedContext};
+
+/// Just the parts of a kubeconfig needed to name the current context, so
+/// prompts don't pay for parsing clusters and users
+#[derive(Deserialize)]
+struct Minimal {
+    #[serde(rename = "current-context")]
+    current_context: Option<String>,
+    #[serde(default)]
+    contexts: Vec<NamedContext>,
+}
+
+/// The current context and its active namespace
+#[derive(Debug, PartialEq)]
+pub struct Current {
+    pub context: String,
+    pub namespace: String,
+}
+
+impl Current {
+    /// `context:namespace`, for shell prompts
+    pub fn short(&self) -> String {
+        format!("{}:{}", self.context, self.namespace)
+    }
+
+    /// Tab-separated context and namespace, for scripts
+    pub fn porcelain(&self) -> String {
+        format!("{}\t{}", self.context, self.namespace)
+    }
+}
+
+/// Read the current context of a kubeconfig, or None if it has none
+pub fn read(path: &Path) -> Result<Option<Current>> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let minimal: Minimal =
+        serde_yaml::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))?;
+    let Some(name) = minimal.current_context.filter(|c| !c.is_empty()) else {
+        return Ok(None);
+    };
+    let namespace = minimal
+        .contexts
+        .iter()
+        .find(|c| c.name == name)
+        .and_then(|c| kubens::namespace(&c.context))
+        .unwrap_or_else(|| "default".to_string());
+    Ok(Some(Current {
+        context: name,
+        namespace,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_current() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config");
+        let mut config = create_test_kubeconfig("a");
+        config.contexts[0].context.namespace = Some("team-a".to_string());
+        fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+
+        let current = read(&path).unwrap().unwrap();
+        assert_eq!(current.short(), "a-context:team-a");
+        assert_eq!(current.porcelain(), "a-context\tteam-a");
+
+        config.contexts[0].context.namespace = None;
+        fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(read(&path).unwrap().unwrap().namespace, "default");
+
+        config.current_context = None;
+        fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(read(&path).unwrap(), None);
+    }
+}
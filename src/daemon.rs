@@ -0,0 +1,262 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::KubeConfig;
+
+/// Refresh daemon settings stored under `daemon:` in the app config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DaemonConfig {
+    /// Cron expression (minute hour day-of-month month day-of-week)
+    #[serde(default = "default_schedule")]
+    pub schedule: String,
+    /// Show a desktop notification when contexts are added, credentials
+    /// rotate, or a refresh fails to merge a source
+    #[serde(default)]
+    pub notify: bool,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            schedule: default_schedule(),
+            notify: false,
+        }
+    }
+}
+
+fn default_schedule() -> String {
+    "0 * * * *".to_string()
+}
+
+/// Parsed five-field cron expression
+#[derive(Debug)]
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// Parse one cron field (`*`, `*/n`, `a-b`, `a-b/n` and comma lists)
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .with_context(|| format!("Invalid step in '{}'", part))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse()?, b.parse()?)
+        } else {
+            let value: u32 = range
+                .parse()
+                .with_context(|| format!("Invalid value '{}'", part))?;
+            (value, value)
+        };
+        if start < min || end > max || start > end {
+            anyhow::bail!("'{}' is outside {}-{}", part, min, max);
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            anyhow::bail!("Cron schedule must have five fields: '{}'", expression);
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)
+            .with_context(|| format!("Invalid cron schedule '{}'", expression))?;
+        // Both 0 and 7 mean Sunday
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        weekdays.truncate(7);
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        })
+    }
+
+    fn matches(&self, t: &DateTime<Local>) -> bool {
+        let day = self.days[t.day() as usize];
+        let weekday = self.weekdays[t.weekday().num_days_from_sunday() as usize];
+        // Like cron: when both day fields are restricted, either may match
+        let day_matches = if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        self.minutes[t.minute() as usize]
+            && self.hours[t.hour() as usize]
+            && self.months[t.month() as usize]
+            && day_matches
+    }
+
+    /// First matching minute strictly after `after`, searching up to a year ahead
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        for _ in 0..366 * 24 * 60 {
+            if self.matches(&t) {
+                return Some(t);
+            }
+            t += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+/// Contexts added and users whose credentials changed between two configs
+pub fn changes(before: &KubeConfig, after: &KubeConfig) -> (Vec<String>, Vec<String>) {
+    let added = after
+        .contexts
+        .iter()
+        .filter(|c| !before.contexts.iter().any(|b| b.name == c.name))
+        .map(|c| c.name.clone())
+        .collect();
+    let rotated = after
+        .users
+        .iter()
+        .filter(|u| {
+            before
+                .users
+                .iter()
+                .any(|b| b.name == u.name && b.user != u.user)
+        })
+        .map(|u| u.name.clone())
+        .collect();
+    (added, rotated)
+}
+
+/// Last known daemon state, written to ~/.k8sconf/daemon.json
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DaemonState {
+    pub pid: u32,
+    pub started_at: String,
+    pub last_run: Option<String>,
+    pub last_result: Option<String>,
+    pub next_run: Option<String>,
+}
+
+pub fn load_state(path: &Path) -> Result<Option<DaemonState>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(Some(serde_json::from_str(&content).with_context(|| {
+        format!("Failed to parse daemon state: {:?}", path)
+    })?))
+}
+
+pub fn save_state(path: &Path, state: &DaemonState) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("Failed to write daemon state: {:?}", path))
+}
+
+/// Whether a process with `pid` is still running
+pub fn is_running(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Append a timestamped line to the daemon log
+pub fn log(path: &Path, message: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log: {:?}", path))?;
+    writeln!(file, "{} {}", Local::now().to_rfc3339(), message)?;
+    Ok(())
+}
+
+/// Best-effort desktop notification (notify-send on Linux, osascript on macOS)
+pub fn notify(title: &str, body: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut c = Command::new("osascript");
+        c.arg("-e").arg(format!(
+            "display notification {:?} with title {:?}",
+            body, title
+        ));
+        c
+    } else {
+        let mut c = Command::new("notify-send");
+        c.arg(title).arg(body);
+        c
+    };
+    let _ = command.stdout(Stdio::null()).stderr(Stdio::null()).status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use chrono::TimeZone;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_schedule_next_after() {
+        let every_15 = Schedule::parse("*/15 * * * *").unwrap();
+        assert_eq!(
+            every_15.next_after(at(2026, 3, 2, 10, 7)),
+            Some(at(2026, 3, 2, 10, 15))
+        );
+
+        // 09:30 on weekdays; 2026-03-07 is a Saturday
+        let weekdays = Schedule::parse("30 9 * * 1-5").unwrap();
+        assert_eq!(
+            weekdays.next_after(at(2026, 3, 6, 9, 30)),
+            Some(at(2026, 3, 9, 9, 30))
+        );
+    }
+
+    #[test]
+    fn test_schedule_rejects_invalid() {
+        assert!(Schedule::parse("* * * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_changes_reports_added_and_rotated() {
+        let before = create_test_kubeconfig("a");
+        let mut after = before.clone();
+        after.users[0].user.token = Some("new".to_string());
+        after.contexts.extend(create_test_kubeconfig("b").contexts);
+        let (added, rotated) = changes(&before, &after);
+        assert_eq!(added, vec!["b-context"]);
+        assert_eq!(rotated, vec!["a-user"]);
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{server, KubeConfig, NamedContext};
+
+/// Settings for contexts written by local Kubernetes desktops, stored under
+/// `desktop:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DesktopConfig {
+    /// Leave Docker Desktop and Rancher Desktop contexts out of merged
+    /// sources; `kconf import desktop` still brings them in on demand
+    #[serde(default)]
+    pub exclude: bool,
+}
+
+/// A tool that writes its own context into ~/.kube/config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Desktop {
+    Docker,
+    Rancher,
+}
+
+impl fmt::Display for Desktop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Desktop::Docker => write!(f, "Docker Desktop"),
+            Desktop::Rancher => write!(f, "Rancher Desktop"),
+        }
+    }
+}
+
+/// Which desktop wrote a context, judging by the names these tools use
+/// and Docker Desktop's API server address
+pub fn detect(config: &KubeConfig, context: &NamedContext) -> Option<Desktop> {
+    match context.name.as_str() {
+        "docker-desktop" | "docker-for-desktop" => return Some(Desktop::Docker),
+        "rancher-desktop" => return Some(Desktop::Rancher),
+        _ => {}
+    }
+    let cluster = config
+        .clusters
+        .iter()
+        .find(|c| c.name == context.context.cluster)?;
+    match cluster.name.as_str() {
+        "docker-desktop" => Some(Desktop::Docker),
+        "rancher-desktop" => Some(Desktop::Rancher),
+        _ if server::canonical(&cluster.cluster.server)
+            .starts_with("https://kubernetes.docker.internal") =>
+        {
+            Some(Desktop::Docker)
+        }
+        _ => None,
+    }
+}
+
+/// Every desktop context in `config`, with the tool that wrote it
+pub fn contexts(config: &KubeConfig) -> Vec<(String, Desktop)> {
+    config
+        .contexts
+        .iter()
+        .filter_map(|c| detect(config, c).map(|d| (c.name.clone(), d)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_detect_desktops() {
+        let mut config = create_test_kubeconfig("docker-desktop");
+        config.contexts[0].name = "docker-desktop".to_string();
+        let mut rancher = create_test_kubeconfig("rancher");
+        rancher.contexts[0].name = "rancher-desktop".to_string();
+        let mut renamed = create_test_kubeconfig("laptop");
+        renamed.clusters[0].cluster.server = "https://kubernetes.docker.internal:6443".to_string();
+        for other in [rancher, renamed, create_test_kubeconfig("prod")] {
+            config.clusters.extend(other.clusters);
+            config.contexts.extend(other.contexts);
+        }
+
+        assert_eq!(
+            contexts(&config),
+            vec![
+                ("docker-desktop".to_string(), Desktop::Docker),
+                ("rancher-desktop".to_string(), Desktop::Rancher),
+                ("laptop-context".to_string(), Desktop::Docker),
+            ]
+        );
+    }
+}
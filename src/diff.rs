@@ -0,0 +1,461 @@
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::KubeConfig;
+
+/// Fields whose values are never printed
+pub const SECRET_FIELDS: &[&str] = &[
+    "token",
+    "password",
+    "client-key-data",
+    "client-key",
+    "id-token",
+    "refresh-token",
+    "access-token",
+    "client-secret",
+];
+
+/// Fields whose whole contents are never printed, as free-form values there
+/// often carry credentials
+const SECRET_SUBTREES: &[[&str; 2]] = &[
+    ["auth-provider", "config"],
+    ["exec", "env"],
+    ["exec", "args"],
+];
+
+/// Whether the field at the dotted `path` is or lies under a secret field
+pub fn is_secret_path(path: &str) -> bool {
+    let segments: Vec<&str> = path.split('.').collect();
+    segments.last().is_some_and(|f| SECRET_FIELDS.contains(f))
+        || segments
+            .windows(2)
+            .any(|pair| SECRET_SUBTREES.iter().any(|s| s[..] == *pair))
+}
+
+/// Whether `value`, found at `path`, is or contains a secret field
+fn contains_secret(path: &str, value: &Value) -> bool {
+    if is_secret_path(path) {
+        return true;
+    }
+    match value {
+        Value::Mapping(map) => map.iter().any(|(key, field)| {
+            key.as_str()
+                .is_some_and(|key| contains_secret(&format!("{}.{}", path, key), field))
+        }),
+        Value::Sequence(items) => items.iter().any(|item| contains_secret(path, item)),
+        _ => false,
+    }
+}
+
+/// A field that differs between two versions of an entry
+#[derive(Debug, PartialEq)]
+pub struct FieldChange {
+    /// Dotted path within the entry, e.g. `cluster.server`
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+impl FieldChange {
+    fn is_secret(&self) -> bool {
+        [&self.old, &self.new]
+            .into_iter()
+            .flatten()
+            .any(|value| contains_secret(&self.path, value))
+            || is_secret_path(&self.path)
+    }
+}
+
+/// Render a value for display: scalars as-is, embedded data summarized,
+/// anything structured as compact JSON
+fn render(path: &str, value: &Option<Value>) -> String {
+    let Some(value) = value else {
+        return "(unset)".to_string();
+    };
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Null => "null".to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    };
+    if path.ends_with("-data") {
+        return format!("({} chars of data)", text.len());
+    }
+    if text.chars().count() > 60 {
+        format!("{}...", text.chars().take(57).collect::<String>())
+    } else {
+        text
+    }
+}
+
+impl std::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_secret() {
+            let what = match (&self.old, &self.new) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                _ => "changed",
+            };
+            return write!(f, "{}: {}", self.path, what);
+        }
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            render(&self.path, &self.old),
+            render(&self.path, &self.new)
+        )
+    }
+}
+
+fn walk(path: &str, old: Option<&Value>, new: Option<&Value>, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Some(Value::Mapping(a)), Some(Value::Mapping(b))) => {
+            let mut keys: Vec<&Value> = a.keys().collect();
+            keys.extend(b.keys().filter(|k| !a.contains_key(*k)));
+            for key in keys {
+                let name = key.as_str().map(str::to_string).unwrap_or_else(|| {
+                    serde_yaml::to_string(key)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string()
+                });
+                let child = if path.is_empty() {
+                    name
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                walk(&child, a.get(key), b.get(key), changes);
+            }
+        }
+        (a, b) if a != b => changes.push(FieldChange {
+            path: path.to_string(),
+            old: a.cloned(),
+            new: b.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// Fields that differ between two versions of the same entry
+pub fn field_changes<T: Serialize>(old: &T, new: &T) -> Vec<FieldChange> {
+    let (Ok(old), Ok(new)) = (serde_yaml::to_value(old), serde_yaml::to_value(new)) else {
+        return Vec::new();
+    };
+    let mut changes = Vec::new();
+    walk("", Some(&old), Some(&new), &mut changes);
+    // The name is how entries are matched, so it never differs
+    changes.retain(|c| c.path != "name");
+    changes
+}
+
+/// Field changes of each entry in `updates` against the same-named entry in
+/// `existing`, in the order of `updates`
+pub fn updated_fields<T: Serialize>(
+    existing: &[T],
+    updates: &[T],
+    name: impl Fn(&T) -> &str,
+) -> Vec<(String, Vec<FieldChange>)> {
+    updates
+        .iter()
+        .map(|entry| {
+            let fields = existing
+                .iter()
+                .find(|e| name(e) == name(entry))
+                .map(|e| field_changes(e, entry))
+                .unwrap_or_default();
+            (name(entry).to_string(), fields)
+        })
+        .collect()
+}
+
+/// One difference between two kubeconfigs
+#[derive(Debug)]
+pub enum Change {
+    Added {
+        kind: &'static str,
+        name: String,
+    },
+    Removed {
+        kind: &'static str,
+        name: String,
+    },
+    Modified {
+        kind: &'static str,
+        name: String,
+        fields: Vec<FieldChange>,
+    },
+    CurrentContext {
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+fn diff_entries<T: Serialize>(
+    kind: &'static str,
+    old: &[T],
+    new: &[T],
+    name: impl Fn(&T) -> &str,
+    include_removed: bool,
+    changes: &mut Vec<Change>,
+) {
+    if include_removed {
+        for entry in old {
+            if !new.iter().any(|n| name(n) == name(entry)) {
+                changes.push(Change::Removed {
+                    kind,
+                    name: name(entry).to_string(),
+                });
+            }
+        }
+    }
+    for entry in new {
+        match old.iter().find(|o| name(o) == name(entry)) {
+            None => changes.push(Change::Added {
+                kind,
+                name: name(entry).to_string(),
+            }),
+            Some(existing) => {
+                let fields = field_changes(existing, entry);
+                if !fields.is_empty() {
+                    changes.push(Change::Modified {
+                        kind,
+                        name: name(entry).to_string(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Differences going from `old` to `new`. Without `include_removed`, entries
+/// missing from `new` are ignored, which suits comparing a partial source
+/// against a full destination.
+pub fn diff(old: &KubeConfig, new: &KubeConfig, include_removed: bool) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_entries(
+        "cluster",
+        &old.clusters,
+        &new.clusters,
+        |c| &c.name,
+        include_removed,
+        &mut changes,
+    );
+    diff_entries(
+        "context",
+        &old.contexts,
+        &new.contexts,
+        |c| &c.name,
+        include_removed,
+        &mut changes,
+    );
+    diff_entries(
+        "user",
+        &old.users,
+        &new.users,
+        |u| &u.name,
+        include_removed,
+        &mut changes,
+    );
+    if old.current_context != new.current_context
+        && (include_removed || new.current_context.is_some())
+    {
+        changes.push(Change::CurrentContext {
+            old: old.current_context.clone(),
+            new: new.current_context.clone(),
+        });
+    }
+    changes
+}
+
+/// Print changes in a `+`/`-`/`~` summary with field details indented
+pub fn print(changes: &[Change]) {
+    if changes.is_empty() {
+        println!("No differences");
+    }
+    for change in changes {
+        match change {
+            Change::Added { kind, name } => println!("+ {} '{}'", kind, name),
+            Change::Removed { kind, name } => println!("- {} '{}'", kind, name),
+            Change::Modified { kind, name, fields } => {
+                println!("~ {} '{}'", kind, name);
+                for field in fields {
+                    println!("    {}", field);
+                }
+            }
+            Change::CurrentContext { old, new } => println!(
+                "~ current-context: {} -> {}",
+                old.as_deref().unwrap_or("(unset)"),
+                new.as_deref().unwrap_or("(unset)")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_diff_structural_and_field_changes() {
+        let old = create_test_kubeconfig("a");
+        let mut new = create_test_kubeconfig("a");
+        new.clusters[0].cluster.server = "https://moved:6443".to_string();
+        new.users[0].user.token = Some("rotated".to_string());
+        new.contexts[0].name = "renamed".to_string();
+        new.current_context = Some("renamed".to_string());
+
+        let changes = diff(&old, &new, true);
+        let lines: Vec<String> = changes
+            .iter()
+            .map(|c| match c {
+                Change::Added { kind, name } => format!("+{} {}", kind, name),
+                Change::Removed { kind, name } => format!("-{} {}", kind, name),
+                Change::Modified { kind, name, fields } => format!(
+                    "~{} {} [{}]",
+                    kind,
+                    name,
+                    fields
+                        .iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+                Change::CurrentContext { .. } => "current".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "~cluster a-cluster [cluster.server: https://a.example.com:6443 -> https://moved:6443]",
+                "-context a-context",
+                "+context renamed",
+                "~user a-user [user.token: added]",
+                "current",
+            ]
+        );
+
+        // Without removals, only what the new side has is reported
+        assert_eq!(diff(&old, &new, false).len(), 4);
+        assert!(diff(&old, &old, true).is_empty());
+    }
+
+    #[test]
+    fn test_updated_fields() {
+        let old = create_test_kubeconfig("a");
+        let mut new = create_test_kubeconfig("a");
+        new.users[0].user.token = Some("rotated".to_string());
+        let mut other = create_test_kubeconfig("a");
+        other.users[0].user.token = Some("again".to_string());
+
+        let updates = updated_fields(&other.users, &new.users, |u| &u.name);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, "a-user");
+        assert_eq!(updates[0].1[0].to_string(), "user.token: changed");
+
+        let updates = updated_fields(&old.clusters, &new.clusters, |c| &c.name);
+        assert!(updates[0].1.is_empty());
+    }
+
+    #[test]
+    fn test_credentials_are_never_printed() {
+        let parse = |user: &str| {
+            crate::parse_kubeconfig(&format!(
+                "apiVersion: v1\nkind: Config\nclusters: []\ncontexts: []\nusers:\n{}",
+                user
+            ))
+            .unwrap()
+        };
+        let old = parse(
+            "- name: oidc
+  user:
+    auth-provider:
+      name: oidc
+      config:
+        id-token: old-id-token-value
+        client-id: kubernetes
+- name: exec
+  user:
+    exec:
+      apiVersion: client.authentication.k8s.io/v1
+      command: get-token
+      args: [--secret, old-arg-secret]
+      env:
+        - name: API_KEY
+          value: old-env-secret
+",
+        );
+        let new = parse(
+            "- name: oidc
+  user:
+    auth-provider:
+      name: oidc
+      config:
+        id-token: new-id-token-value
+        refresh-token: new-refresh-token-value
+        client-secret: new-client-secret-value
+        client-id: kubernetes
+- name: exec
+  user:
+    exec:
+      apiVersion: client.authentication.k8s.io/v1
+      command: get-token
+      args: [--secret, new-arg-secret]
+      env:
+        - name: API_KEY
+          value: new-env-secret
+- name: added
+  user:
+    auth-provider:
+      name: oidc
+      config:
+        access-token: new-access-token-value
+",
+        );
+        let mut existing = old.users.clone();
+        let mut added = new.users[2].clone();
+        added.user.auth_provider = None;
+        added.user.token = None;
+        existing.push(added);
+
+        let mut output = Vec::new();
+        for (_, fields) in updated_fields(&existing, &new.users, |u| &u.name) {
+            output.extend(fields.iter().map(|f| f.to_string()));
+        }
+        let output = output.join("\n");
+        assert!(output.contains("user.auth-provider.config.id-token: changed"));
+        assert!(output.contains("user.exec.env: changed"));
+        assert!(output.contains("user.auth-provider: added"));
+        for secret in [
+            "id-token-value",
+            "refresh-token-value",
+            "client-secret-value",
+            "access-token-value",
+            "arg-secret",
+            "env-secret",
+        ] {
+            assert!(
+                !output.contains(secret),
+                "{} leaked in:\n{}",
+                secret,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_field_change_rendering() {
+        let data = FieldChange {
+            path: "cluster.certificate-authority-data".to_string(),
+            old: Some(Value::from("abcd")),
+            new: None,
+        };
+        assert_eq!(
+            data.to_string(),
+            "cluster.certificate-authority-data: (4 chars of data) -> (unset)"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{
+    backup, check, credentials, daemon, expand_tilde, lint, load_kubeconfig, migrate, vault,
+    AppConfig,
+};
+
+/// How serious a doctor finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Status::Pass => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+/// Result of one doctor check, with a suggested fix for anything not passing
+#[derive(Debug)]
+pub struct Finding {
+    pub check: &'static str,
+    pub status: Status,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+fn finding(check: &'static str, status: Status, message: impl Into<String>) -> Finding {
+    Finding {
+        check,
+        status,
+        message: message.into(),
+        suggestion: None,
+    }
+}
+
+impl Finding {
+    fn suggest(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+/// Parse the app config in `config_dir`, falling back to defaults so the
+/// remaining checks can still run
+fn app_config(config_dir: &Path, findings: &mut Vec<Finding>) -> AppConfig {
+    let path = config_dir.join("config.yaml");
+    if !path.exists() {
+        findings.push(
+            finding(
+                "app config",
+                Status::Warn,
+                format!("{:?} does not exist", path),
+            )
+            .suggest("Run any kconf command once to create it with defaults"),
+        );
+        return AppConfig::default();
+    }
+    let parsed = fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| {
+            let mut value = serde_yaml::from_str(&content)?;
+            migrate::upgrade(&mut value)?;
+            Ok(serde_yaml::from_value::<AppConfig>(value)?)
+        });
+    let app_config = match parsed {
+        Ok(app_config) => app_config,
+        Err(e) => {
+            findings.push(
+                finding("app config", Status::Fail, format!("{:?}: {:#}", path, e))
+                    .suggest("Fix the YAML, or move the file aside to regenerate defaults"),
+            );
+            return AppConfig::default();
+        }
+    };
+
+    let mut problems = 0;
+    if let Err(e) = daemon::Schedule::parse(&app_config.daemon.schedule) {
+        problems += 1;
+        findings.push(
+            finding(
+                "app config",
+                Status::Fail,
+                format!("daemon.schedule: {:#}", e),
+            )
+            .suggest("Use a five-field cron expression such as \"0 * * * *\""),
+        );
+    }
+    for source in &app_config.sources {
+        if let Err(e) = source.location() {
+            problems += 1;
+            findings.push(
+                finding("app config", Status::Fail, format!("{:#}", e))
+                    .suggest("Give each source exactly one of file, url or command"),
+            );
+        }
+    }
+    if problems == 0 {
+        findings.push(finding(
+            "app config",
+            Status::Pass,
+            format!("{:?} is valid", path),
+        ));
+    }
+    app_config
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path, findings: &mut Vec<Finding>) {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        findings.push(
+            finding(
+                "permissions",
+                Status::Warn,
+                format!("{:?} is readable by other users (mode {:o})", path, mode),
+            )
+            .suggest(format!("chmod 600 {}", path.display())),
+        );
+    } else {
+        findings.push(finding(
+            "permissions",
+            Status::Pass,
+            format!("{:?} is private", path),
+        ));
+    }
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path, _findings: &mut Vec<Finding>) {}
+
+/// Run every check against the setup in `config_dir`. Connectivity and
+/// certificate expiry are only probed when `probe_timeout` is set.
+pub fn diagnose(config_dir: &Path, probe_timeout: Option<Duration>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let app_config = app_config(config_dir, &mut findings);
+
+    let dest_path = expand_tilde(&app_config.destination);
+    if !dest_path.exists() {
+        findings.push(
+            finding(
+                "destination",
+                Status::Warn,
+                format!("{:?} does not exist", dest_path),
+            )
+            .suggest("Merge a kubeconfig with `kconf <file>` to create it"),
+        );
+        return findings;
+    }
+    let mut config = match load_kubeconfig(&dest_path) {
+        Ok(config) => config,
+        Err(e) => {
+            findings.push(
+                finding("destination", Status::Fail, format!("{:#}", e))
+                    .suggest("Restore it from ~/.k8sconf/backups or `kconf backup pull`"),
+            );
+            return findings;
+        }
+    };
+    findings.push(finding(
+        "destination",
+        Status::Pass,
+        format!(
+            "{:?} parses ({} context(s), {} cluster(s), {} user(s))",
+            dest_path,
+            config.contexts.len(),
+            config.clusters.len(),
+            config.users.len()
+        ),
+    ));
+
+    check_permissions(&dest_path, &mut findings);
+    let store_path = config_dir.join("credentials.yaml");
+    if store_path.exists() {
+        check_permissions(&store_path, &mut findings);
+        match credentials::load_store(&store_path) {
+            Ok(store) => credentials::hydrate(&mut config, &store),
+            Err(e) => findings.push(
+                finding("credentials", Status::Fail, format!("{:#}", e))
+                    .suggest("Fix or remove the credential store and re-import affected users"),
+            ),
+        }
+    }
+
+    let backup_dir = match &app_config.backup.dir {
+        Some(dir) => expand_tilde(dir),
+        None => config_dir.join("backups"),
+    };
+    let local_backups = backup::list_local(&backup_dir)
+        .map(|b| b.len())
+        .unwrap_or(0);
+    if local_backups > 0 || app_config.backup.remote.is_some() {
+        findings.push(finding(
+            "backups",
+            Status::Pass,
+            format!(
+                "{} local backup(s){}",
+                local_backups,
+                if app_config.backup.remote.is_some() {
+                    ", remote configured"
+                } else {
+                    ""
+                }
+            ),
+        ));
+    } else {
+        findings.push(
+            finding("backups", Status::Warn, "no local or remote backups")
+                .suggest("Configure backup.remote and run `kconf backup push`"),
+        );
+    }
+
+    let orphans: Vec<_> = lint::lint(&config, &app_config.lint)
+        .into_iter()
+        .filter(|f| f.rule == lint::DANGLING_REF || f.rule == lint::ORPHAN_ENTRY)
+        .collect();
+    if orphans.is_empty() {
+        findings.push(finding(
+            "references",
+            Status::Pass,
+            "no dangling references or orphaned entries",
+        ));
+    }
+    for f in orphans {
+        let status = if f.rule == lint::DANGLING_REF {
+            Status::Fail
+        } else {
+            Status::Warn
+        };
+        findings.push(
+            finding(
+                "references",
+                status,
+                format!("{} '{}': {}", f.kind, f.name, f.message),
+            )
+            .suggest("Run `kconf fix` to clean up"),
+        );
+    }
+
+    let Some(timeout) = probe_timeout else {
+        return findings;
+    };
+    for user in &config.users {
+        let Some(path) = vault::referenced_path(&user.user) else {
+            continue;
+        };
+        if let Err(e) = app_config.credentials.vault.read_token(path) {
+            findings.push(
+                finding(
+                    "vault",
+                    Status::Fail,
+                    format!("user '{}': {:#}", user.name, e),
+                )
+                .suggest("Check VAULT_ADDR and VAULT_TOKEN, or store the token at that path"),
+            );
+        }
+    }
+    for result in check::run(&config, &[], 16, timeout, false, false) {
+        if let Err(e) = &result.reachable {
+            findings.push(
+                finding(
+                    "connectivity",
+                    Status::Warn,
+                    format!("context '{}': {}", result.context, e),
+                )
+                .suggest(format!(
+                    "Check VPN/network access, or remove it with `kconf --remove {}`",
+                    result.context
+                )),
+            );
+        }
+        for problem in &result.problems {
+            findings.push(
+                finding(
+                    "certificates",
+                    Status::Fail,
+                    format!("context '{}': {}", result.context, problem),
+                )
+                .suggest("Re-import the context to pick up fresh credentials"),
+            );
+        }
+        for warning in &result.warnings {
+            findings.push(
+                finding(
+                    "certificates",
+                    Status::Warn,
+                    format!("context '{}': {}", result.context, warning),
+                )
+                .suggest("Renew the certificate before it expires"),
+            );
+        }
+    }
+    if !findings
+        .iter()
+        .any(|f| f.check == "connectivity" || f.check == "certificates")
+    {
+        findings.push(finding(
+            "connectivity",
+            Status::Pass,
+            "every server reachable, no expired certificates",
+        ));
+    }
+    findings
+}
+
+/// Print findings, one per line with suggestions indented beneath
+pub fn print(findings: &[Finding]) {
+    for f in findings {
+        println!("[{:>4}] {}: {}", f.status, f.check, f.message);
+        if let Some(suggestion) = &f.suggestion {
+            println!("       -> {}", suggestion);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diagnose_reports_problems() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("kubeconfig");
+        let mut config = create_test_kubeconfig("a");
+        config.contexts[0].context.user = "missing".to_string();
+        fs::write(&dest, serde_yaml::to_string(&config).unwrap()).unwrap();
+        fs::write(
+            temp_dir.path().join("config.yaml"),
+            format!(
+                "destination: {}\ndaemon:\n  schedule: \"nope\"\n",
+                dest.display()
+            ),
+        )
+        .unwrap();
+
+        let findings = diagnose(temp_dir.path(), None);
+        let status = |check: &str| {
+            findings
+                .iter()
+                .filter(|f| f.check == check)
+                .map(|f| f.status)
+                .max()
+        };
+        assert_eq!(status("app config"), Some(Status::Fail));
+        assert_eq!(status("destination"), Some(Status::Pass));
+        assert_eq!(status("backups"), Some(Status::Warn));
+        assert_eq!(status("references"), Some(Status::Fail));
+        assert_eq!(status("connectivity"), None);
+    }
+
+    #[test]
+    fn test_diagnose_missing_app_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let findings = diagnose(temp_dir.path(), None);
+        assert_eq!(findings[0].check, "app config");
+        assert_eq!(findings[0].status, Status::Warn);
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{KconfError, KubeConfig};
+
+/// Replace `${VAR}` placeholders in `text` using `lookup`. `$$` produces a
+/// literal `$`. Fails on the first variable `lookup` can't resolve.
+pub fn expand_str(text: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(body) = after.strip_prefix('{') {
+            let end = body
+                .find('}')
+                .with_context(|| format!("Unterminated placeholder in '{}'", text))?;
+            let name = &body[..end];
+            let value =
+                lookup(name).ok_or_else(|| KconfError::UndefinedEnvVar(name.to_string()))?;
+            expanded.push_str(&value);
+            rest = &body[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = after;
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expand placeholders in every string scalar of a YAML document. Keys are
+/// left alone so placeholders can't change the document's structure.
+pub fn expand_value(
+    value: &mut serde_yaml::Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        serde_yaml::Value::String(s) => *s = expand_str(s, lookup)?,
+        serde_yaml::Value::Sequence(items) => {
+            for item in items {
+                expand_value(item, lookup)?;
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (_, item) in map.iter_mut() {
+                expand_value(item, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Load a kubeconfig, expanding `${VAR}` placeholders from the environment
+pub fn load_expanded(path: &PathBuf) -> Result<KubeConfig> {
+    if !path.exists() {
+        return Err(KconfError::ConfigNotFound(path.clone()).into());
+    }
+    let content = fs::read_to_string(path).map_err(|source| KconfError::Read {
+        path: path.clone(),
+        source,
+    })?;
+    let parse_failed = |e: serde_yaml::Error| KconfError::ParseFailed {
+        path: path.clone(),
+        message: e.to_string(),
+    };
+    let mut value: serde_yaml::Value = serde_yaml::from_str(&content).map_err(parse_failed)?;
+    value.apply_merge().map_err(parse_failed)?;
+    expand_value(&mut value, &|name| env::var(name).ok())
+        .with_context(|| format!("Failed to expand variables in {:?}", path))?;
+    let migrated = crate::legacy::migrate(&mut value);
+    crate::warn_migrated(path, &migrated);
+    Ok(serde_yaml::from_value(value).map_err(parse_failed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "REGION" => Some("eu-west-1".to_string()),
+            "TOKEN" => Some("s3cr3t".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_str() {
+        assert_eq!(
+            expand_str("https://api.${REGION}.example.com", &lookup).unwrap(),
+            "https://api.eu-west-1.example.com"
+        );
+        assert_eq!(
+            expand_str("cost: $$5 or $x", &lookup).unwrap(),
+            "cost: $5 or $x"
+        );
+        let err = expand_str("${MISSING}", &lookup).unwrap_err();
+        assert_eq!(err.to_string(), "Environment variable not set: MISSING");
+        assert!(expand_str("${REGION", &lookup).is_err());
+    }
+
+    #[test]
+    fn test_expand_value_only_touches_scalars() {
+        let yaml = "apiVersion: v1\nkind: Config\nclusters:\n- name: c\n  cluster:\n    server: https://${REGION}.example.com\nusers:\n- name: u\n  user:\n    token: ${TOKEN}\n";
+        let mut value: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        expand_value(&mut value, &lookup).unwrap();
+        let config: KubeConfig = serde_yaml::from_value(value).unwrap();
+        assert_eq!(
+            config.clusters[0].cluster.server,
+            "https://eu-west-1.example.com"
+        );
+        assert_eq!(config.users[0].user.token, Some("s3cr3t".to_string()));
+    }
+}
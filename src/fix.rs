@@ -0,0 +1,531 @@
+use std::collections::HashSet;
+
+use crate::lint::{self, LintConfig, Severity};
+use crate::{protect, KubeConfig, NamedCluster, NamedContext, NamedUser};
+
+/// Turn a name into the lowercase `[a-z0-9-._@]` convention used by lint
+pub fn conventional_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        let c = c.to_ascii_lowercase();
+        if c.is_ascii_lowercase() || c.is_ascii_digit() || "-._@".contains(c) {
+            normalized.push(c);
+        } else if !normalized.ends_with('-') {
+            normalized.push('-');
+        }
+    }
+    normalized.trim_matches('-').to_string()
+}
+
+/// Rename a cluster, context or user and rewrite every reference to it.
+/// `kind` is one of "cluster", "context" or "user".
+pub fn rename_entry(config: &mut KubeConfig, kind: &str, from: &str, to: &str) {
+    match kind {
+        "cluster" => {
+            for c in config.clusters.iter_mut().filter(|c| c.name == from) {
+                c.name = to.to_string();
+            }
+            for c in config
+                .contexts
+                .iter_mut()
+                .filter(|c| c.context.cluster == from)
+            {
+                c.context.cluster = to.to_string();
+            }
+        }
+        "context" => {
+            for c in config.contexts.iter_mut().filter(|c| c.name == from) {
+                c.name = to.to_string();
+            }
+            if config.current_context.as_deref() == Some(from) {
+                config.current_context = Some(to.to_string());
+            }
+        }
+        "user" => {
+            for u in config.users.iter_mut().filter(|u| u.name == from) {
+                u.name = to.to_string();
+            }
+            for c in config
+                .contexts
+                .iter_mut()
+                .filter(|c| c.context.user == from)
+            {
+                c.context.user = to.to_string();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Remove contexts whose cluster or user doesn't exist, except protected
+/// ones, and clear a current-context naming a context that doesn't exist
+fn remove_dangling(config: &mut KubeConfig, protected: &[String], changes: &mut Vec<String>) {
+    let clusters: HashSet<String> = config.clusters.iter().map(|c| c.name.clone()).collect();
+    let users: HashSet<String> = config.users.iter().map(|u| u.name.clone()).collect();
+    config.contexts.retain(|c| {
+        let missing = if !clusters.contains(&c.context.cluster) {
+            Some(("cluster", &c.context.cluster))
+        } else if !users.contains(&c.context.user) {
+            Some(("user", &c.context.user))
+        } else {
+            None
+        };
+        let Some((kind, name)) = missing else {
+            return true;
+        };
+        if protect::is_protected(protected, &c.name) {
+            eprintln!(
+                "Keeping protected context '{}' (references missing {} '{}')",
+                c.name, kind, name
+            );
+            return true;
+        }
+        changes.push(format!(
+            "Remove context '{}' (references missing {} '{}')",
+            c.name, kind, name
+        ));
+        false
+    });
+    if let Some(current) = &config.current_context {
+        if !config.contexts.iter().any(|c| &c.name == current) {
+            changes.push(format!(
+                "Clear current-context '{}' (does not exist)",
+                current
+            ));
+            config.current_context = None;
+        }
+    }
+}
+
+fn remove_orphans(config: &mut KubeConfig, changes: &mut Vec<String>) {
+    let used_clusters: HashSet<String> = config
+        .contexts
+        .iter()
+        .map(|c| c.context.cluster.clone())
+        .collect();
+    let used_users: HashSet<String> = config
+        .contexts
+        .iter()
+        .map(|c| c.context.user.clone())
+        .collect();
+
+    config.clusters.retain(|c| {
+        let keep = used_clusters.contains(&c.name);
+        if !keep {
+            changes.push(format!("Remove orphaned cluster '{}'", c.name));
+        }
+        keep
+    });
+    config.users.retain(|u| {
+        let keep = used_users.contains(&u.name);
+        if !keep {
+            changes.push(format!("Remove orphaned user '{}'", u.name));
+        }
+        keep
+    });
+}
+
+fn strip_basic_auth(config: &mut KubeConfig, changes: &mut Vec<String>) {
+    for user in &mut config.users {
+        let has_basic = user.user.username.is_some() || user.user.password.is_some();
+        if has_basic && user.user.token.is_some() {
+            user.user.username = None;
+            user.user.password = None;
+            changes.push(format!(
+                "Strip username/password from user '{}' (token present)",
+                user.name
+            ));
+        }
+    }
+}
+
+fn normalize_names(config: &mut KubeConfig, changes: &mut Vec<String>) {
+    let mut renames = Vec::new();
+    for (kind, names) in [
+        (
+            "cluster",
+            config
+                .clusters
+                .iter()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "context",
+            config.contexts.iter().map(|c| c.name.clone()).collect(),
+        ),
+        (
+            "user",
+            config.users.iter().map(|u| u.name.clone()).collect(),
+        ),
+    ] {
+        let mut taken: HashSet<String> = names.iter().cloned().collect();
+        for name in names {
+            if lint::is_conventional_name(&name) {
+                continue;
+            }
+            let normalized = conventional_name(&name);
+            if normalized.is_empty() || taken.contains(&normalized) {
+                changes.push(format!(
+                    "Cannot normalize {} '{}': '{}' is empty or already taken",
+                    kind, name, normalized
+                ));
+                continue;
+            }
+            taken.insert(normalized.clone());
+            renames.push((kind, name, normalized));
+        }
+    }
+
+    for (kind, from, to) in renames {
+        rename_entry(config, kind, &from, &to);
+        changes.push(format!("Rename {} '{}' to '{}'", kind, from, to));
+    }
+}
+
+/// Sort clusters, contexts and users by name
+pub fn sort_by_name(config: &mut KubeConfig) {
+    config.clusters.sort_by(|a, b| a.name.cmp(&b.name));
+    config.contexts.sort_by(|a, b| a.name.cmp(&b.name));
+    config.users.sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+fn sort_entries(config: &mut KubeConfig, changes: &mut Vec<String>) {
+    let before = (
+        config.clusters.clone(),
+        config.contexts.clone(),
+        config.users.clone(),
+    );
+    sort_by_name(config);
+    if before.0 != config.clusters || before.1 != config.contexts || before.2 != config.users {
+        changes.push("Sort clusters, contexts and users by name".to_string());
+    }
+}
+
+/// Name for an incoming entry whose name is taken: an earlier `<name>-N`
+/// holding the same entry (`same`) when there is one, so merging the same
+/// source again doesn't pile up copies, otherwise the first free `<name>-N`
+fn rename_target(name: &str, taken: &[&str], same: impl Fn(&str) -> bool) -> String {
+    let mut candidates = (2..).map(|n| format!("{}-{}", name, n));
+    loop {
+        let candidate = candidates.next().expect("some suffix is free");
+        if !taken.contains(&candidate.as_str()) || same(&candidate) {
+            return candidate;
+        }
+    }
+}
+
+/// Rename source entries whose names are taken in `dest` by a different entry,
+/// so both can be kept. Clusters and users go first, so contexts are compared
+/// after their references have been rewritten. Returns (kind, from, to) for
+/// each rename.
+pub fn rename_conflicts(
+    dest: &KubeConfig,
+    source: &mut KubeConfig,
+) -> Vec<(&'static str, String, String)> {
+    let mut renames = Vec::new();
+
+    let conflicts: Vec<NamedCluster> = source
+        .clusters
+        .iter()
+        .filter(|c| dest.clusters.iter().any(|d| d.name == c.name && d != *c))
+        .cloned()
+        .collect();
+    for incoming in conflicts {
+        let taken: Vec<&str> = dest
+            .clusters
+            .iter()
+            .chain(&source.clusters)
+            .map(|c| c.name.as_str())
+            .collect();
+        let to = rename_target(&incoming.name, &taken, |candidate| {
+            dest.clusters
+                .iter()
+                .any(|d| d.name == candidate && d.cluster == incoming.cluster)
+        });
+        rename_entry(source, "cluster", &incoming.name, &to);
+        renames.push(("cluster", incoming.name, to));
+    }
+
+    let conflicts: Vec<NamedUser> = source
+        .users
+        .iter()
+        .filter(|u| dest.users.iter().any(|d| d.name == u.name && d != *u))
+        .cloned()
+        .collect();
+    for incoming in conflicts {
+        let taken: Vec<&str> = dest
+            .users
+            .iter()
+            .chain(&source.users)
+            .map(|u| u.name.as_str())
+            .collect();
+        let to = rename_target(&incoming.name, &taken, |candidate| {
+            dest.users
+                .iter()
+                .any(|d| d.name == candidate && d.user == incoming.user)
+        });
+        rename_entry(source, "user", &incoming.name, &to);
+        renames.push(("user", incoming.name, to));
+    }
+
+    let conflicts: Vec<NamedContext> = source
+        .contexts
+        .iter()
+        .filter(|c| dest.contexts.iter().any(|d| d.name == c.name && d != *c))
+        .cloned()
+        .collect();
+    for incoming in conflicts {
+        let taken: Vec<&str> = dest
+            .contexts
+            .iter()
+            .chain(&source.contexts)
+            .map(|c| c.name.as_str())
+            .collect();
+        // Extensions such as provenance differ between imports, so compare
+        // only what the context points at
+        let to = rename_target(&incoming.name, &taken, |candidate| {
+            dest.contexts.iter().any(|d| {
+                d.name == candidate
+                    && d.context.cluster == incoming.context.cluster
+                    && d.context.user == incoming.context.user
+                    && d.context.namespace == incoming.context.namespace
+            })
+        });
+        rename_entry(source, "context", &incoming.name, &to);
+        renames.push(("context", incoming.name, to));
+    }
+
+    renames
+}
+
+/// Collapse users with identical credentials into the first of them,
+/// pointing their contexts at it
+fn collapse_duplicate_users(config: &mut KubeConfig, changes: &mut Vec<String>) {
+    for names in lint::duplicate_credentials(config) {
+        let keep = &names[0];
+        for name in &names[1..] {
+            for context in config
+                .contexts
+                .iter_mut()
+                .filter(|c| c.context.user == *name)
+            {
+                context.context.user = keep.clone();
+            }
+            config.users.retain(|u| u.name != *name);
+            changes.push(format!(
+                "Merge user '{}' into '{}' (identical credentials)",
+                name, keep
+            ));
+        }
+    }
+}
+
+/// Apply every mechanical fix whose lint rule is enabled, leaving contexts
+/// matching the `protected` patterns in place.
+/// Returns a description of each change made.
+pub fn apply_fixes(
+    config: &mut KubeConfig,
+    settings: &LintConfig,
+    protected: &[String],
+) -> Vec<String> {
+    let enabled = |rule: &str| settings.severity(rule) != Severity::Off;
+    let mut changes = Vec::new();
+
+    if enabled(lint::DANGLING_REF) {
+        remove_dangling(config, protected, &mut changes);
+    }
+    if enabled(lint::ORPHAN_ENTRY) {
+        remove_orphans(config, &mut changes);
+    }
+    if enabled(lint::BASIC_AUTH) {
+        strip_basic_auth(config, &mut changes);
+    }
+    if enabled(lint::DUPLICATE_CREDENTIALS) {
+        collapse_duplicate_users(config, &mut changes);
+    }
+    if enabled(lint::NAMING_CONVENTION) {
+        normalize_names(config, &mut changes);
+    }
+    if enabled(lint::UNSORTED_ENTRIES) {
+        sort_entries(config, &mut changes);
+    }
+
+    changes
+}
+
+/// Repair references in one pass, whatever the lint settings: drop dangling
+/// contexts and current-context first, then the clusters and users nothing
+/// uses any more, leaving protected contexts in place. Returns a
+/// description of each change made.
+pub fn repair_references(config: &mut KubeConfig, protected: &[String]) -> Vec<String> {
+    let mut changes = Vec::new();
+    remove_dangling(config, protected, &mut changes);
+    remove_orphans(config, &mut changes);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_conventional_name() {
+        assert_eq!(
+            conventional_name("arn:aws:eks:us-east-1:123:cluster/Prod"),
+            "arn-aws-eks-us-east-1-123-cluster-prod"
+        );
+        assert_eq!(conventional_name("My Cluster"), "my-cluster");
+        assert_eq!(conventional_name("admin@kind"), "admin@kind");
+    }
+
+    #[test]
+    fn test_fix_orphans_and_basic_auth() {
+        let mut config = create_test_kubeconfig("b");
+        let orphan = create_test_kubeconfig("a");
+        config.clusters.extend(orphan.clusters);
+        config.users[0].user.token = Some("token".to_string());
+        config.users[0].user.username = Some("admin".to_string());
+        config.users[0].user.password = Some("secret".to_string());
+
+        let changes = apply_fixes(&mut config, &LintConfig::default(), &[]);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(config.clusters.len(), 1);
+        assert_eq!(config.clusters[0].name, "b-cluster");
+        assert_eq!(config.users[0].user.password, None);
+        assert_eq!(config.users[0].user.token, Some("token".to_string()));
+    }
+
+    #[test]
+    fn test_repair_references() {
+        let mut config = create_test_kubeconfig("a");
+        let broken = create_test_kubeconfig("b");
+        config.contexts.extend(broken.contexts);
+        config.clusters.extend(broken.clusters);
+        config.contexts[1].context.user = "missing-user".to_string();
+        config.current_context = Some("gone".to_string());
+
+        let changes = repair_references(&mut config, &[]);
+        assert_eq!(
+            changes,
+            vec![
+                "Remove context 'b-context' (references missing user 'missing-user')",
+                "Clear current-context 'gone' (does not exist)",
+                "Remove orphaned cluster 'b-cluster'",
+            ]
+        );
+        assert_eq!(config.contexts.len(), 1);
+        assert_eq!(config.current_context, None);
+        assert!(repair_references(&mut config, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_repair_keeps_protected_contexts() {
+        let mut config = create_test_kubeconfig("prod");
+        config.clusters.clear();
+        let protected = vec!["prod-*".to_string()];
+
+        assert!(repair_references(&mut config, &protected).is_empty());
+        assert_eq!(config.contexts.len(), 1);
+        assert!(apply_fixes(&mut config, &LintConfig::default(), &protected)
+            .iter()
+            .all(|c| !c.contains("prod-context")));
+        assert_eq!(config.contexts.len(), 1);
+        // Unprotected, it goes
+        assert_eq!(
+            repair_references(&mut config, &[])[0],
+            "Remove context 'prod-context' (references missing cluster 'prod-cluster')"
+        );
+        assert!(config.contexts.is_empty());
+    }
+
+    #[test]
+    fn test_fix_normalizes_names_and_references() {
+        let mut config = create_test_kubeconfig("Team/Dev");
+        let changes = apply_fixes(&mut config, &LintConfig::default(), &[]);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(config.clusters[0].name, "team-dev-cluster");
+        assert_eq!(config.contexts[0].context.cluster, "team-dev-cluster");
+        assert_eq!(config.contexts[0].context.user, "team-dev-user");
+        assert_eq!(config.current_context, Some("team-dev-context".to_string()));
+        assert!(lint::lint(&config, &LintConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_fix_sorts_and_respects_disabled_rules() {
+        let mut config = create_test_kubeconfig("b");
+        let mut other = create_test_kubeconfig("a");
+        // The fixtures share credentials, which would be collapsed
+        other.users[0].user.token = Some("a-token".to_string());
+        config.clusters.extend(other.clusters);
+        config.contexts.extend(other.contexts);
+        config.users.extend(other.users);
+
+        let mut settings = LintConfig::default();
+        settings
+            .severities
+            .insert(lint::UNSORTED_ENTRIES.to_string(), Severity::Off);
+        assert!(apply_fixes(&mut config, &settings, &[]).is_empty());
+
+        let changes = apply_fixes(&mut config, &LintConfig::default(), &[]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(config.contexts[0].name, "a-context");
+    }
+
+    #[test]
+    fn test_rename_conflicts_keeps_both() {
+        let dest = create_test_kubeconfig("a");
+        let mut source = create_test_kubeconfig("a");
+        source.clusters[0].cluster.server = "https://other:6443".to_string();
+        let renames = rename_conflicts(&dest, &mut source);
+        assert_eq!(
+            renames,
+            vec![
+                (
+                    "cluster",
+                    "a-cluster".to_string(),
+                    "a-cluster-2".to_string()
+                ),
+                (
+                    "context",
+                    "a-context".to_string(),
+                    "a-context-2".to_string()
+                ),
+            ]
+        );
+        assert_eq!(source.contexts[0].context.cluster, "a-cluster-2");
+        // The identical user is shared rather than duplicated
+        assert_eq!(source.users[0].name, "a-user");
+
+        // Merging the same source again reuses the earlier renames
+        let mut merged = dest.clone();
+        merged.clusters.extend(source.clusters.clone());
+        merged.contexts.extend(source.contexts.clone());
+        let mut again = create_test_kubeconfig("a");
+        again.clusters[0].cluster.server = "https://other:6443".to_string();
+        let renames = rename_conflicts(&merged, &mut again);
+        assert_eq!(renames[0].2, "a-cluster-2");
+        assert_eq!(renames[1].2, "a-context-2");
+    }
+
+    #[test]
+    fn test_collapse_duplicate_credentials() {
+        let mut config = create_test_kubeconfig("a");
+        let mut second = create_test_kubeconfig("b");
+        second.users[0].user = config.users[0].user.clone();
+        config.clusters.extend(second.clusters);
+        config.contexts.extend(second.contexts);
+        config.users.extend(second.users);
+        let findings = lint::lint(&config, &LintConfig::default());
+        assert!(findings
+            .iter()
+            .any(|f| f.rule == lint::DUPLICATE_CREDENTIALS && f.name == "b-user"));
+
+        let changes = apply_fixes(&mut config, &LintConfig::default(), &[]);
+        assert!(changes
+            .contains(&"Merge user 'b-user' into 'a-user' (identical credentials)".to_string()));
+        assert_eq!(config.users.len(), 1);
+        assert_eq!(config.contexts[1].context.user, "a-user");
+    }
+}
@@ -0,0 +1,162 @@
+use clap::ValueEnum;
+
+use crate::KubeConfig;
+
+/// Output format of `kconf graph`
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT, e.g. for `dot -Tsvg`
+    Dot,
+    /// Mermaid flowchart, which GitHub and many wikis render inline
+    Mermaid,
+}
+
+/// A node of the graph; `shared` marks clusters and users that more than one
+/// context uses
+struct Node {
+    id: String,
+    label: String,
+    kind: &'static str,
+    shared: bool,
+}
+
+/// Nodes for every cluster, context and user (including names contexts
+/// reference but the config lacks), and the context edges to them
+fn topology(config: &KubeConfig) -> (Vec<Node>, Vec<(String, String)>) {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut edges = Vec::new();
+    let node = |nodes: &mut Vec<Node>, kind: &'static str, name: &str| -> String {
+        if let Some(existing) = nodes.iter().find(|n| n.kind == kind && n.label == name) {
+            return existing.id.clone();
+        }
+        let id = format!(
+            "{}{}",
+            kind,
+            nodes.iter().filter(|n| n.kind == kind).count()
+        );
+        let uses = config
+            .contexts
+            .iter()
+            .filter(|c| match kind {
+                "cluster" => c.context.cluster == name,
+                "user" => c.context.user == name,
+                _ => false,
+            })
+            .count();
+        nodes.push(Node {
+            id: id.clone(),
+            label: name.to_string(),
+            kind,
+            shared: uses > 1,
+        });
+        id
+    };
+    for context in &config.contexts {
+        let from = node(&mut nodes, "context", &context.name);
+        let cluster = node(&mut nodes, "cluster", &context.context.cluster);
+        let user = node(&mut nodes, "user", &context.context.user);
+        edges.push((from.clone(), cluster));
+        edges.push((from, user));
+    }
+    for cluster in &config.clusters {
+        node(&mut nodes, "cluster", &cluster.name);
+    }
+    for user in &config.users {
+        node(&mut nodes, "user", &user.name);
+    }
+    (nodes, edges)
+}
+
+fn dot(nodes: &[Node], edges: &[(String, String)]) -> String {
+    let mut out = String::from("digraph kubeconfig {\n  rankdir=LR;\n");
+    for node in nodes {
+        let shape = match node.kind {
+            "cluster" => "box3d",
+            "user" => "ellipse",
+            _ => "box",
+        };
+        let shared = if node.shared {
+            ", style=filled, fillcolor=orange"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "  {} [label=\"{}\", shape={}{}];\n",
+            node.id,
+            node.label.replace('\\', "\\\\").replace('"', "\\\""),
+            shape,
+            shared
+        ));
+    }
+    for (from, to) in edges {
+        out.push_str(&format!("  {} -> {};\n", from, to));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid(nodes: &[Node], edges: &[(String, String)]) -> String {
+    let mut out = String::from("flowchart LR\n");
+    for node in nodes {
+        let label = node.label.replace('"', "#quot;");
+        let shape = match node.kind {
+            "cluster" => format!("[(\"{}\")]", label),
+            "user" => format!("([\"{}\"])", label),
+            _ => format!("[\"{}\"]", label),
+        };
+        out.push_str(&format!("  {}{}\n", node.id, shape));
+    }
+    for (from, to) in edges {
+        out.push_str(&format!("  {} --> {}\n", from, to));
+    }
+    let shared: Vec<&str> = nodes
+        .iter()
+        .filter(|n| n.shared)
+        .map(|n| n.id.as_str())
+        .collect();
+    if !shared.is_empty() {
+        out.push_str("  classDef shared fill:orange\n");
+        out.push_str(&format!("  class {} shared\n", shared.join(",")));
+    }
+    out
+}
+
+/// The context → cluster and context → user graph, with clusters and users
+/// shared by several contexts highlighted
+pub fn render(config: &KubeConfig, format: GraphFormat) -> String {
+    let (nodes, edges) = topology(config);
+    match format {
+        GraphFormat::Dot => dot(&nodes, &edges),
+        GraphFormat::Mermaid => mermaid(&nodes, &edges),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_render_graph() {
+        let mut config = create_test_kubeconfig("a");
+        let mut second = create_test_kubeconfig("b");
+        second.contexts[0].context.user = "a-user".to_string();
+        config.contexts.extend(second.contexts);
+        config.clusters.extend(second.clusters);
+        config.users.extend(second.users);
+
+        let dot = render(&config, GraphFormat::Dot);
+        assert!(dot.starts_with("digraph kubeconfig {"));
+        assert!(dot.contains("  context0 -> cluster0;\n  context0 -> user0;"));
+        assert!(dot.contains(
+            "  user0 [label=\"a-user\", shape=ellipse, style=filled, fillcolor=orange];"
+        ));
+        // b-user has no context but is still drawn
+        assert!(dot.contains("  user1 [label=\"b-user\", shape=ellipse];"));
+
+        let mermaid = render(&config, GraphFormat::Mermaid);
+        assert!(mermaid.contains("  cluster1[(\"b-cluster\")]"));
+        assert!(mermaid.contains("  context1 --> user0"));
+        assert!(mermaid.ends_with("  class user0 shared\n"));
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::get_app_config_dir;
+
+/// Git history settings stored under `git:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct GitConfig {
+    /// Commit the destination after every write
+    #[serde(default)]
+    pub auto_commit: bool,
+}
+
+pub fn git(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir);
+    cmd
+}
+
+pub fn run_git(cmd: &mut Command) -> Result<String> {
+    let output = cmd.output().context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Top-level directory of the git repository containing `dir`, if any
+fn repo_root(dir: &Path) -> Option<PathBuf> {
+    run_git(git(dir).args(["rev-parse", "--show-toplevel"]))
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// Initialize a git repository in `dir` unless one already exists
+pub fn ensure_repo(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {:?}", dir))?;
+    if !dir.join(".git").exists() {
+        run_git(git(dir).arg("init").arg("--quiet"))?;
+    }
+    Ok(())
+}
+
+/// Commit a single file in `repo`. Returns false if the file had no changes.
+pub fn commit_file(repo: &Path, file: &Path, message: &str) -> Result<bool> {
+    run_git(git(repo).arg("add").arg("--").arg(file))?;
+    let staged = git(repo)
+        .args(["diff", "--cached", "--quiet", "--"])
+        .arg(file)
+        .status()
+        .context("Failed to run git")?;
+    if staged.success() {
+        return Ok(false);
+    }
+
+    // Fall back to a kconf identity when the user hasn't configured one
+    let mut cmd = git(repo);
+    if run_git(git(repo).args(["config", "user.email"])).is_err() {
+        cmd.args(["-c", "user.name=kconf", "-c", "user.email=kconf@localhost"]);
+    }
+    run_git(
+        cmd.args(["commit", "--quiet", "-m", message, "--"])
+            .arg(file),
+    )?;
+    Ok(true)
+}
+
+/// Commit the destination after a write. If the destination isn't inside a git
+/// repository, a copy is committed to a history repository in ~/.k8sconf/history.
+pub fn record(dest_path: &Path, message: &str) -> Result<()> {
+    let dest_path = &dest_path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve destination: {:?}", dest_path))?;
+    let dest_dir = dest_path
+        .parent()
+        .context("Destination has no parent directory")?;
+    match repo_root(dest_dir) {
+        Some(root) => {
+            commit_file(&root, dest_path, message)?;
+        }
+        None => {
+            let history = get_app_config_dir()?.join("history");
+            ensure_repo(&history)?;
+            let file_name = dest_path
+                .file_name()
+                .context("Destination has no file name")?;
+            let copy = history.join(file_name);
+            fs::copy(dest_path, &copy)
+                .with_context(|| format!("Failed to copy destination to {:?}", copy))?;
+            commit_file(&history, &copy, message)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn commit_count(repo: &Path) -> usize {
+        run_git(git(repo).args(["rev-list", "--count", "HEAD"]))
+            .map(|n| n.parse().unwrap())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_commit_file_only_when_changed() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        ensure_repo(repo).unwrap();
+        let file = repo.join("config");
+
+        fs::write(&file, "apiVersion: v1\n").unwrap();
+        assert!(commit_file(repo, &file, "kconf: first").unwrap());
+        assert!(!commit_file(repo, &file, "kconf: nothing").unwrap());
+
+        fs::write(&file, "apiVersion: v1\nkind: Config\n").unwrap();
+        assert!(commit_file(repo, &file, "kconf: second").unwrap());
+        assert_eq!(commit_count(repo), 2);
+        assert_eq!(
+            run_git(git(repo).args(["log", "-1", "--format=%s"])).unwrap(),
+            "kconf: second"
+        );
+    }
+
+    #[test]
+    fn test_record_in_existing_repo() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        ensure_repo(repo).unwrap();
+        let nested = repo.join("kube");
+        fs::create_dir_all(&nested).unwrap();
+        let file = nested.join("config");
+        fs::write(&file, "apiVersion: v1\n").unwrap();
+
+        record(&file, "kconf: merge").unwrap();
+        assert_eq!(commit_count(repo), 1);
+    }
+}
@@ -0,0 +1,530 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "importers")]
+use std::process::Command;
+
+#[cfg(feature = "importers")]
+use crate::fix::rename_entry;
+#[cfg(feature = "importers")]
+use crate::{load_kubeconfig, parse_kubeconfig};
+use crate::{
+    ClusterInfo, ContextInfo, KubeConfig, NamedCluster, NamedContext, NamedUser, UserInfo,
+};
+
+/// Where Kubernetes mounts a pod's ServiceAccount credentials
+pub const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Importer settings stored under `import:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ImportConfig {
+    #[serde(default)]
+    pub teleport: TeleportConfig,
+}
+
+/// Settings for `kconf import teleport`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TeleportConfig {
+    /// Context name template; `{teleport}` is the Teleport cluster and
+    /// `{cluster}` the Kubernetes cluster
+    #[serde(default = "default_teleport_template")]
+    pub context_template: String,
+}
+
+impl Default for TeleportConfig {
+    fn default() -> Self {
+        Self {
+            context_template: default_teleport_template(),
+        }
+    }
+}
+
+fn default_teleport_template() -> String {
+    "{teleport}-{cluster}".to_string()
+}
+
+/// Run an external CLI and return its stdout
+#[cfg(feature = "importers")]
+fn run_tool(program: &str, cmd: &mut Command) -> Result<String> {
+    let output = cmd
+        .output()
+        .with_context(|| format!("Failed to run {} (is it installed?)", program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("{} output is not UTF-8", program))
+}
+
+/// A user with only a bearer token
+fn token_user(token: String) -> UserInfo {
+    UserInfo {
+        client_certificate_data: None,
+        client_key_data: None,
+        client_certificate: None,
+        client_key: None,
+        token: Some(token),
+        username: None,
+        password: None,
+        auth_provider: None,
+        exec: None,
+    }
+}
+
+/// Kubeconfig with one cluster, context and user, all named `name`
+fn single_context(
+    name: &str,
+    cluster: ClusterInfo,
+    user: UserInfo,
+    namespace: Option<String>,
+) -> KubeConfig {
+    KubeConfig {
+        api_version: "v1".to_string(),
+        kind: "Config".to_string(),
+        clusters: vec![NamedCluster {
+            name: name.to_string(),
+            cluster,
+        }],
+        contexts: vec![NamedContext {
+            name: name.to_string(),
+            context: ContextInfo {
+                cluster: name.to_string(),
+                user: name.to_string(),
+                namespace,
+                extensions: None,
+            },
+        }],
+        users: vec![NamedUser {
+            name: name.to_string(),
+            user,
+        }],
+        current_context: Some(name.to_string()),
+        preferences: None,
+    }
+}
+
+/// Build a kubeconfig from a pod's mounted ServiceAccount (`token`, `ca.crt`,
+/// `namespace` in `dir`) and the API server address from the pod environment
+pub fn in_cluster(dir: &Path, host: &str, port: &str, name: &str) -> Result<KubeConfig> {
+    let read = |file: &str| {
+        let path = dir.join(file);
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))
+    };
+    let token = read("token")?.trim().to_string();
+    let ca = read("ca.crt")?;
+    let namespace = read("namespace").ok().map(|n| n.trim().to_string());
+
+    // IPv6 service hosts need brackets in a URL
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    let cluster = ClusterInfo {
+        server: format!("https://{}:{}", host, port),
+        certificate_authority_data: Some(base64::engine::general_purpose::STANDARD.encode(ca)),
+        certificate_authority: None,
+        insecure_skip_tls_verify: None,
+        proxy_url: None,
+        tls_server_name: None,
+    };
+    Ok(single_context(name, cluster, token_user(token), namespace))
+}
+
+/// Kubernetes cluster names from `tsh kube ls --format=json`
+#[cfg(feature = "importers")]
+fn teleport_clusters(json: &str) -> Result<Vec<String>> {
+    let clusters: Vec<serde_json::Value> =
+        serde_json::from_str(json).context("Unexpected output from tsh kube ls")?;
+    Ok(clusters
+        .iter()
+        .filter_map(|c| c["kube_cluster_name"].as_str().map(str::to_string))
+        .collect())
+}
+
+/// Value of a `--flag=value` argument in a user's exec args
+#[cfg(feature = "importers")]
+fn exec_flag(config: &KubeConfig, user: &str, flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+    config
+        .users
+        .iter()
+        .find(|u| u.name == user)?
+        .user
+        .exec
+        .as_ref()?
+        .args
+        .as_ref()?
+        .iter()
+        .find_map(|a| a.strip_prefix(&prefix).map(str::to_string))
+}
+
+/// Rename contexts written by `tsh kube login` using `template`
+#[cfg(feature = "importers")]
+fn apply_teleport_template(config: &mut KubeConfig, template: &str) {
+    let renames: Vec<(String, String)> = config
+        .contexts
+        .iter()
+        .filter_map(|c| {
+            let cluster = exec_flag(config, &c.context.user, "--kube-cluster")?;
+            let teleport = exec_flag(config, &c.context.user, "--teleport-cluster")?;
+            let name = template
+                .replace("{cluster}", &cluster)
+                .replace("{teleport}", &teleport);
+            (name != c.name).then(|| (c.name.clone(), name))
+        })
+        .collect();
+    for (from, to) in renames {
+        rename_entry(config, "context", &from, &to);
+    }
+}
+
+/// Enumerate Kubernetes clusters with `tsh kube ls` and let `tsh kube login`
+/// write exec-based entries for each into a scratch kubeconfig
+#[cfg(feature = "importers")]
+pub fn teleport(template: &str) -> Result<KubeConfig> {
+    let clusters = teleport_clusters(&run_tool(
+        "tsh",
+        Command::new("tsh").args(["kube", "ls", "--format=json"]),
+    )?)?;
+    if clusters.is_empty() {
+        anyhow::bail!("tsh kube ls returned no Kubernetes clusters");
+    }
+
+    let scratch = std::env::temp_dir().join(format!("kconf-tsh-{}.yaml", std::process::id()));
+    let result = clusters
+        .iter()
+        .try_for_each(|cluster| {
+            run_tool(
+                "tsh",
+                Command::new("tsh")
+                    .args(["kube", "login", cluster])
+                    .env("KUBECONFIG", &scratch),
+            )
+            .map(|_| ())
+        })
+        .and_then(|_| load_kubeconfig(&scratch));
+    let _ = fs::remove_file(&scratch);
+
+    let mut config = result?;
+    apply_teleport_template(&mut config, template);
+    Ok(config)
+}
+
+/// Server URL and token from an `oc login` command, e.g. the one copied from
+/// the OpenShift console: `oc login --token=sha256~... --server=https://api...`
+#[cfg(feature = "importers")]
+pub fn parse_oc_login(command: &str) -> (Option<String>, Option<String>) {
+    let (mut server, mut token) = (None, None);
+    let mut words = command
+        .split_whitespace()
+        .skip_while(|w| *w != "login")
+        .skip(1);
+    while let Some(word) = words.next() {
+        let (flag, inline) = match word.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (word, None),
+        };
+        match flag {
+            "--server" | "-s" => server = inline.or_else(|| words.next().map(str::to_string)),
+            "--token" => token = inline.or_else(|| words.next().map(str::to_string)),
+            _ if !word.starts_with('-') && server.is_none() => server = Some(word.to_string()),
+            _ => {}
+        }
+    }
+    (server, token)
+}
+
+/// Cluster name in `oc`'s style: the server host with dots as dashes, plus port
+#[cfg(feature = "importers")]
+pub fn openshift_cluster_name(server: &str) -> String {
+    let host = server
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(server)
+        .trim_end_matches('/');
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:443", host)
+    };
+    host.replace('.', "-")
+}
+
+/// Token-based kubeconfig for an OpenShift cluster, embedding the CA file if given
+#[cfg(feature = "importers")]
+pub fn openshift(
+    server: &str,
+    token: &str,
+    ca_file: Option<&Path>,
+    namespace: Option<String>,
+    name: &str,
+) -> Result<KubeConfig> {
+    let certificate_authority_data = match ca_file {
+        Some(path) => {
+            let ca = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+            Some(base64::engine::general_purpose::STANDARD.encode(ca))
+        }
+        None => None,
+    };
+    let cluster = ClusterInfo {
+        server: server.trim_end_matches('/').to_string(),
+        certificate_authority_data,
+        certificate_authority: None,
+        insecure_skip_tls_verify: None,
+        proxy_url: None,
+        tls_server_name: None,
+    };
+    Ok(single_context(
+        name,
+        cluster,
+        token_user(token.to_string()),
+        namespace,
+    ))
+}
+
+/// Server and token of the current `oc` session
+#[cfg(feature = "importers")]
+pub fn oc_session() -> Result<(String, String)> {
+    let server = run_tool("oc", Command::new("oc").args(["whoami", "--show-server"]))?;
+    let token = run_tool("oc", Command::new("oc").args(["whoami", "-t"]))?;
+    Ok((server.trim().to_string(), token.trim().to_string()))
+}
+
+/// DigitalOcean API base URL
+#[cfg(all(feature = "importers", feature = "remote"))]
+const DO_API: &str = "https://api.digitalocean.com/v2";
+
+/// A DOKS cluster from `doctl kubernetes cluster list` or the DO API
+#[cfg(feature = "importers")]
+#[derive(Debug, PartialEq)]
+struct DoksCluster {
+    id: String,
+    name: String,
+    region: String,
+}
+
+/// Clusters from doctl's JSON array or the API's `kubernetes_clusters` object
+#[cfg(feature = "importers")]
+fn doks_clusters(json: &str) -> Result<Vec<DoksCluster>> {
+    let value: serde_json::Value =
+        serde_json::from_str(json).context("Unexpected DOKS cluster list")?;
+    let list = value
+        .get("kubernetes_clusters")
+        .unwrap_or(&value)
+        .as_array()
+        .context("Unexpected DOKS cluster list")?;
+    Ok(list
+        .iter()
+        .filter_map(|c| {
+            Some(DoksCluster {
+                id: c["id"].as_str()?.to_string(),
+                name: c["name"].as_str()?.to_string(),
+                region: c["region"].as_str()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Rename a single-cluster DOKS kubeconfig to `do-<region>-<name>` (cluster and
+/// context) and `do-<region>-<name>-admin` (user)
+#[cfg(feature = "importers")]
+fn rename_doks(config: &mut KubeConfig, cluster: &DoksCluster) {
+    let base = format!("do-{}-{}", cluster.region, cluster.name);
+    let admin = format!("{}-admin", base);
+    for (kind, from, to) in [
+        (
+            "cluster",
+            config.clusters.first().map(|c| c.name.clone()),
+            &base,
+        ),
+        (
+            "context",
+            config.contexts.first().map(|c| c.name.clone()),
+            &base,
+        ),
+        ("user", config.users.first().map(|u| u.name.clone()), &admin),
+    ] {
+        if let Some(from) = from {
+            rename_entry(config, kind, &from, to);
+        }
+    }
+}
+
+#[cfg(all(feature = "importers", not(feature = "remote")))]
+fn do_api_get(_path: &str, _token: &str) -> Result<String> {
+    Err(crate::KconfError::FeatureDisabled("remote").into())
+}
+
+#[cfg(all(feature = "importers", feature = "remote"))]
+fn do_api_get(path: &str, token: &str) -> Result<String> {
+    let url = format!("{}{}", DO_API, path);
+    ureq::get(&url)
+        .set("authorization", &format!("Bearer {}", token))
+        .call()
+        .with_context(|| format!("DigitalOcean API request failed: {}", url))?
+        .into_string()
+        .context("Failed to read DigitalOcean API response")
+}
+
+/// Fetch kubeconfigs for every DOKS cluster, via the API when a token is
+/// given and through doctl otherwise
+#[cfg(feature = "importers")]
+pub fn doks(token: Option<&str>) -> Result<KubeConfig> {
+    let list = match token {
+        Some(token) => do_api_get("/kubernetes/clusters", token)?,
+        None => run_tool(
+            "doctl",
+            Command::new("doctl").args(["kubernetes", "cluster", "list", "--output", "json"]),
+        )?,
+    };
+    let clusters = doks_clusters(&list)?;
+    if clusters.is_empty() {
+        anyhow::bail!("No DOKS clusters found");
+    }
+
+    let mut combined = crate::create_empty_kubeconfig();
+    for cluster in &clusters {
+        let yaml = match token {
+            Some(token) => do_api_get(
+                &format!("/kubernetes/clusters/{}/kubeconfig", cluster.id),
+                token,
+            )?,
+            None => run_tool(
+                "doctl",
+                Command::new("doctl").args([
+                    "kubernetes",
+                    "cluster",
+                    "kubeconfig",
+                    "show",
+                    &cluster.id,
+                ]),
+            )?,
+        };
+        let mut config = parse_kubeconfig(&yaml)
+            .with_context(|| format!("Invalid kubeconfig for DOKS cluster {}", cluster.name))?;
+        rename_doks(&mut config, cluster);
+        combined.clusters.extend(config.clusters);
+        combined.contexts.extend(config.contexts);
+        combined.users.extend(config.users);
+    }
+    Ok(combined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_in_cluster() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("token"), "sa-token\n").unwrap();
+        fs::write(dir.join("ca.crt"), "-----BEGIN CERTIFICATE-----\n").unwrap();
+        fs::write(dir.join("namespace"), "apps").unwrap();
+
+        let config = in_cluster(dir, "10.96.0.1", "443", "in-cluster").unwrap();
+        assert_eq!(config.clusters[0].cluster.server, "https://10.96.0.1:443");
+        assert_eq!(config.users[0].user.token, Some("sa-token".to_string()));
+        assert_eq!(
+            config.contexts[0].context.namespace,
+            Some("apps".to_string())
+        );
+
+        let config = in_cluster(dir, "fd00::1", "443", "in-cluster").unwrap();
+        assert_eq!(config.clusters[0].cluster.server, "https://[fd00::1]:443");
+    }
+
+    #[cfg(feature = "importers")]
+    #[test]
+    fn test_teleport_template() {
+        let clusters = teleport_clusters(
+            r#"[{"kube_cluster_name":"prod","labels":{}},{"kube_cluster_name":"dev"}]"#,
+        )
+        .unwrap();
+        assert_eq!(clusters, vec!["prod", "dev"]);
+
+        let yaml = "apiVersion: v1\nkind: Config\nclusters:\n- name: tele.example.com\n  cluster:\n    server: https://tele.example.com:443\n    tls-server-name: kube-teleport-proxy-alpn.tele.example.com\ncontexts:\n- name: tele.example.com-prod\n  context:\n    cluster: tele.example.com\n    user: tele.example.com-prod\nusers:\n- name: tele.example.com-prod\n  user:\n    exec:\n      apiVersion: client.authentication.k8s.io/v1beta1\n      command: tsh\n      args: [kube, credentials, --kube-cluster=prod, --teleport-cluster=tele.example.com]\ncurrent-context: tele.example.com-prod\n";
+        let mut config: KubeConfig = serde_yaml::from_str(yaml).unwrap();
+        apply_teleport_template(&mut config, "tp-{cluster}");
+        assert_eq!(config.contexts[0].name, "tp-prod");
+        assert_eq!(config.current_context, Some("tp-prod".to_string()));
+        assert_eq!(
+            config.clusters[0].cluster.tls_server_name.as_deref(),
+            Some("kube-teleport-proxy-alpn.tele.example.com")
+        );
+    }
+
+    #[cfg(feature = "importers")]
+    #[test]
+    fn test_parse_oc_login() {
+        assert_eq!(
+            parse_oc_login("oc login --token=sha256~abc --server=https://api.ocp.example.com:6443"),
+            (
+                Some("https://api.ocp.example.com:6443".to_string()),
+                Some("sha256~abc".to_string())
+            )
+        );
+        assert_eq!(
+            parse_oc_login("oc login https://api.ocp:6443 --token sha256~xyz"),
+            (
+                Some("https://api.ocp:6443".to_string()),
+                Some("sha256~xyz".to_string())
+            )
+        );
+    }
+
+    #[cfg(feature = "importers")]
+    #[test]
+    fn test_openshift() {
+        let name = openshift_cluster_name("https://api.ocp.example.com:6443/");
+        assert_eq!(name, "api-ocp-example-com:6443");
+        assert_eq!(openshift_cluster_name("https://api.ocp"), "api-ocp:443");
+
+        let temp_dir = TempDir::new().unwrap();
+        let ca = temp_dir.path().join("ca.crt");
+        fs::write(&ca, "ca").unwrap();
+        let config =
+            openshift("https://api.ocp:6443", "sha256~abc", Some(&ca), None, &name).unwrap();
+        assert_eq!(
+            config.clusters[0].cluster.certificate_authority_data,
+            Some("Y2E=".to_string())
+        );
+        assert_eq!(config.users[0].user.token, Some("sha256~abc".to_string()));
+    }
+
+    #[cfg(feature = "importers")]
+    #[test]
+    fn test_doks_cluster_list_and_names() {
+        let doctl = r#"[{"id":"abc","name":"web","region":"fra1"}]"#;
+        let api =
+            r#"{"kubernetes_clusters":[{"id":"abc","name":"web","region":"fra1"}],"meta":{}}"#;
+        let expected = vec![DoksCluster {
+            id: "abc".to_string(),
+            name: "web".to_string(),
+            region: "fra1".to_string(),
+        }];
+        assert_eq!(doks_clusters(doctl).unwrap(), expected);
+        assert_eq!(doks_clusters(api).unwrap(), expected);
+
+        let mut config = crate::tests::create_test_kubeconfig("random");
+        rename_doks(&mut config, &expected[0]);
+        assert_eq!(config.clusters[0].name, "do-fra1-web");
+        assert_eq!(config.contexts[0].name, "do-fra1-web");
+        assert_eq!(config.contexts[0].context.cluster, "do-fra1-web");
+        assert_eq!(config.contexts[0].context.user, "do-fra1-web-admin");
+        assert_eq!(config.users[0].name, "do-fra1-web-admin");
+    }
+
+    #[test]
+    fn test_in_cluster_outside_pod() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(in_cluster(temp_dir.path(), "10.96.0.1", "443", "in-cluster").is_err());
+    }
+}
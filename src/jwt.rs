@@ -0,0 +1,99 @@
+use base64::Engine;
+use chrono::DateTime;
+use serde::Deserialize;
+
+/// The claims kconf cares about from a bearer token that is a JWT. The
+/// signature isn't checked: this is only for display and warnings.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Claims {
+    #[serde(rename = "sub")]
+    pub subject: Option<String>,
+    #[serde(rename = "iss")]
+    pub issuer: Option<String>,
+    /// Expiry as a Unix timestamp
+    #[serde(rename = "exp")]
+    pub expires_at: Option<i64>,
+}
+
+impl Claims {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|exp| exp <= now)
+    }
+
+    /// Expiry time, e.g. `2026-01-31 12:00 UTC`
+    pub fn expires(&self) -> Option<String> {
+        let exp = self.expires_at?;
+        Some(
+            DateTime::from_timestamp(exp, 0)?
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string(),
+        )
+    }
+
+    /// Expiry formatted for tables, e.g. `2026-01-31 12:00 UTC (expired)`
+    pub fn expiry(&self, now: i64) -> Option<String> {
+        let formatted = self.expires()?;
+        Some(if self.is_expired(now) {
+            format!("{} (expired)", formatted)
+        } else {
+            formatted
+        })
+    }
+}
+
+/// Decode a token's claims, or None if it isn't a JWT
+pub fn decode(token: &str) -> Option<Claims> {
+    let mut parts = token.trim().split('.');
+    let (_header, payload, _signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Current time as a Unix timestamp
+pub fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+#[cfg(test)]
+pub fn encode_for_test(payload: &str) -> String {
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    format!(
+        "{}.{}.sig",
+        engine.encode(r#"{"alg":"RS256"}"#),
+        engine.encode(payload)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_claims() {
+        let token =
+            encode_for_test(r#"{"sub":"system:serviceaccount:ci:deployer","exp":1700000000}"#);
+        let claims = decode(&token).unwrap();
+        assert_eq!(
+            claims.subject.as_deref(),
+            Some("system:serviceaccount:ci:deployer")
+        );
+        assert!(claims.is_expired(1700000000));
+        assert!(!claims.is_expired(1600000000));
+        assert_eq!(
+            claims.expiry(1800000000).unwrap(),
+            "2023-11-14 22:13 UTC (expired)"
+        );
+
+        assert_eq!(decode("not-a-jwt"), None);
+        assert_eq!(decode("a.b.c.d"), None);
+        assert!(decode(&encode_for_test(r#"{"sub":"x"}"#))
+            .unwrap()
+            .expiry(0)
+            .is_none());
+    }
+}
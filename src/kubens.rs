@@ -0,0 +1,201 @@
+use crate::{matches_pattern, ContextInfo, KubeConfig, NamedExtension};
+
+/// Name of the context extension some kubens setups keep the active namespace in
+pub const EXTENSION_NAME: &str = "kubens";
+
+fn extension(context: &ContextInfo) -> Option<&NamedExtension> {
+    context
+        .extensions
+        .as_ref()?
+        .iter()
+        .find(|e| e.name == EXTENSION_NAME)
+}
+
+/// Namespace recorded in the kubens extension, if the context has one
+fn extension_namespace(context: &ContextInfo) -> Option<String> {
+    extension(context)?
+        .extension
+        .get("namespace")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Active namespace of a context. kubens may update only its extension, so
+/// that wins over the plain `namespace` field.
+pub fn namespace(context: &ContextInfo) -> Option<String> {
+    extension_namespace(context).or_else(|| context.namespace.clone())
+}
+
+/// Set the active namespace, keeping an existing kubens extension in step
+pub fn set_namespace(context: &mut ContextInfo, namespace: Option<&str>) {
+    context.namespace = namespace.map(str::to_string);
+    if let Some(existing) = context
+        .extensions
+        .as_mut()
+        .and_then(|e| e.iter_mut().find(|e| e.name == EXTENSION_NAME))
+    {
+        existing.extension = extension_value(namespace);
+    }
+}
+
+fn extension_value(namespace: Option<&str>) -> serde_yaml::Value {
+    let mut mapping = serde_yaml::Mapping::new();
+    if let Some(namespace) = namespace {
+        mapping.insert("namespace".into(), namespace.into());
+    }
+    serde_yaml::Value::Mapping(mapping)
+}
+
+/// For source contexts replacing a destination context that uses the kubens
+/// extension: a namespace set by the source is written to both places, while
+/// a source without one keeps the namespace selected locally. Run after
+/// `provenance::carry_over` so the extension keeps its position.
+pub fn carry_over(dest: &KubeConfig, source: &mut KubeConfig) {
+    for context in &mut source.contexts {
+        let Some(existing) = dest.contexts.iter().find(|c| c.name == context.name) else {
+            continue;
+        };
+        let Some(position) = existing
+            .context
+            .extensions
+            .iter()
+            .flatten()
+            .position(|e| e.name == EXTENSION_NAME)
+        else {
+            continue;
+        };
+        let namespace = context
+            .context
+            .namespace
+            .clone()
+            .or_else(|| namespace(&existing.context));
+        let extensions = context.context.extensions.get_or_insert_with(Vec::new);
+        if !extensions.iter().any(|e| e.name == EXTENSION_NAME) {
+            extensions.insert(
+                position.min(extensions.len()),
+                NamedExtension {
+                    name: EXTENSION_NAME.to_string(),
+                    extension: extension_value(None),
+                },
+            );
+        }
+        set_namespace(&mut context.context, namespace.as_deref());
+    }
+}
+
+/// A context whose namespace `set_matching` changed
+#[derive(Debug, PartialEq)]
+pub struct NamespaceChange {
+    pub context: String,
+    pub old: Option<String>,
+}
+
+/// Set the namespace of every context whose name matches `pattern`,
+/// returning the contexts that actually changed
+pub fn set_matching(
+    config: &mut KubeConfig,
+    pattern: &str,
+    namespace_name: &str,
+) -> Vec<NamespaceChange> {
+    let mut changes = Vec::new();
+    for context in config
+        .contexts
+        .iter_mut()
+        .filter(|c| matches_pattern(pattern, &c.name))
+    {
+        let old = namespace(&context.context);
+        if old.as_deref() == Some(namespace_name) {
+            continue;
+        }
+        set_namespace(&mut context.context, Some(namespace_name));
+        changes.push(NamespaceChange {
+            context: context.name.clone(),
+            old,
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance;
+    use crate::tests::create_test_kubeconfig;
+
+    fn with_kubens(namespace: &str) -> KubeConfig {
+        let mut config = create_test_kubeconfig("a");
+        provenance::stamp(
+            &mut config.contexts[0].context,
+            &provenance::Provenance::now("/tmp/a.yaml"),
+        );
+        config.contexts[0]
+            .context
+            .extensions
+            .as_mut()
+            .unwrap()
+            .push(NamedExtension {
+                name: EXTENSION_NAME.to_string(),
+                extension: extension_value(Some(namespace)),
+            });
+        config
+    }
+
+    #[test]
+    fn test_extension_namespace_wins() {
+        let mut config = with_kubens("selected");
+        let context = &mut config.contexts[0].context;
+        context.namespace = Some("stale".to_string());
+        assert_eq!(namespace(context).as_deref(), Some("selected"));
+
+        set_namespace(context, Some("new"));
+        assert_eq!(context.namespace.as_deref(), Some("new"));
+        assert_eq!(extension_namespace(context).as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_carry_over_keeps_local_selection() {
+        let dest = with_kubens("selected");
+
+        // A source without a namespace keeps the one chosen with kubens
+        let mut source = create_test_kubeconfig("a");
+        provenance::carry_over(&dest, &mut source);
+        carry_over(&dest, &mut source);
+        let context = &source.contexts[0].context;
+        assert_eq!(context.namespace.as_deref(), Some("selected"));
+        assert_eq!(context.extensions, dest.contexts[0].context.extensions);
+
+        // A source that sets one updates the extension as well
+        let mut source = create_test_kubeconfig("a");
+        source.contexts[0].context.namespace = Some("upstream".to_string());
+        carry_over(&dest, &mut source);
+        assert_eq!(
+            namespace(&source.contexts[0].context).as_deref(),
+            Some("upstream")
+        );
+    }
+
+    #[test]
+    fn test_set_matching() {
+        let mut config = create_test_kubeconfig("team-a-dev");
+        let other = create_test_kubeconfig("team-a-prod");
+        config.contexts.extend(other.contexts);
+        config
+            .contexts
+            .extend(create_test_kubeconfig("team-b").contexts);
+        config.contexts[1].context.namespace = Some("team-a".to_string());
+
+        let changes = set_matching(&mut config, "team-a-*", "team-a");
+        assert_eq!(
+            changes,
+            vec![NamespaceChange {
+                context: "team-a-dev-context".to_string(),
+                old: None,
+            }]
+        );
+        assert_eq!(
+            config.contexts[0].context.namespace.as_deref(),
+            Some("team-a")
+        );
+        assert_eq!(config.contexts[2].context.namespace, None);
+    }
+}
@@ -0,0 +1,165 @@
+use serde_yaml::{Mapping, Value};
+
+/// apiVersion kubectl writes for kubeconfig files
+const API_VERSION: &str = "v1";
+
+/// Oldest exec plugin apiVersion current kubectl still runs; v1alpha1 was
+/// removed in Kubernetes 1.24
+const EXEC_API_VERSION: &str = "client.authentication.k8s.io/v1beta1";
+
+/// Older spellings of fields, with the current name, per section
+const CLUSTER_FIELDS: &[(&str, &str)] = &[
+    ("certificateAuthorityData", "certificate-authority-data"),
+    ("certificateAuthority", "certificate-authority"),
+    ("insecureSkipTLSVerify", "insecure-skip-tls-verify"),
+    ("proxyURL", "proxy-url"),
+    ("tlsServerName", "tls-server-name"),
+];
+const USER_FIELDS: &[(&str, &str)] = &[
+    ("clientCertificateData", "client-certificate-data"),
+    ("clientKeyData", "client-key-data"),
+    ("clientCertificate", "client-certificate"),
+    ("clientKey", "client-key"),
+];
+const TOP_LEVEL_FIELDS: &[(&str, &str)] = &[
+    ("currentContext", "current-context"),
+    ("current_context", "current-context"),
+];
+
+/// Rename legacy keys in `map`, unless the current key is also present
+fn rename_fields(map: &mut Mapping, fields: &[(&str, &str)], place: &str, notes: &mut Vec<String>) {
+    for (old, new) in fields {
+        if map.contains_key(*new) {
+            continue;
+        }
+        if let Some(value) = map.remove(*old) {
+            map.insert(Value::from(*new), value);
+            notes.push(format!("renamed {}{} to {}", place, old, new));
+        }
+    }
+}
+
+/// Normalize a parsed kubeconfig document to the current schema before it's
+/// deserialized: a missing or legacy apiVersion and kind, null lists, older
+/// field spellings and retired exec plugin versions. Returns a description
+/// of each change.
+pub fn migrate(document: &mut Value) -> Vec<String> {
+    let mut notes = Vec::new();
+    let Some(map) = document.as_mapping_mut() else {
+        return notes;
+    };
+
+    match map.get("apiVersion").and_then(Value::as_str) {
+        Some(API_VERSION) => {}
+        Some(other) => {
+            notes.push(format!("apiVersion {} changed to {}", other, API_VERSION));
+            map.insert(Value::from("apiVersion"), Value::from(API_VERSION));
+        }
+        None => {
+            notes.push(format!("missing apiVersion set to {}", API_VERSION));
+            map.insert(Value::from("apiVersion"), Value::from(API_VERSION));
+        }
+    }
+    if map.get("kind").and_then(Value::as_str).is_none() {
+        notes.push("missing kind set to Config".to_string());
+        map.insert(Value::from("kind"), Value::from("Config"));
+    }
+    rename_fields(map, TOP_LEVEL_FIELDS, "", &mut notes);
+
+    for (list, entry, fields) in [
+        ("clusters", "cluster", CLUSTER_FIELDS),
+        ("users", "user", USER_FIELDS),
+        ("contexts", "context", &[][..]),
+    ] {
+        if map.get(list).is_some_and(Value::is_null) {
+            map.remove(list);
+        }
+        let Some(items) = map.get_mut(list).and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for item in items {
+            let name = item["name"].as_str().unwrap_or_default().to_string();
+            let Some(section) = item.get_mut(entry).and_then(Value::as_mapping_mut) else {
+                continue;
+            };
+            rename_fields(
+                section,
+                fields,
+                &format!("{} '{}' ", entry, name),
+                &mut notes,
+            );
+            if entry != "user" {
+                continue;
+            }
+            let Some(exec) = section.get_mut("exec").and_then(Value::as_mapping_mut) else {
+                continue;
+            };
+            match exec.get("apiVersion").and_then(Value::as_str) {
+                Some("client.authentication.k8s.io/v1alpha1") | None => {
+                    notes.push(format!(
+                        "user '{}' exec apiVersion set to {}",
+                        name, EXEC_API_VERSION
+                    ));
+                    exec.insert(Value::from("apiVersion"), Value::from(EXEC_API_VERSION));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KubeConfig;
+
+    #[test]
+    fn test_migrate_legacy_document() {
+        let mut document: Value = serde_yaml::from_str(
+            r#"
+clusters:
+- name: old
+  cluster:
+    server: https://old.example.com
+    certificateAuthorityData: Y2E=
+users:
+- name: old
+  user:
+    clientKeyData: a2V5
+    exec:
+      apiVersion: client.authentication.k8s.io/v1alpha1
+      command: aws
+contexts: ~
+currentContext: old
+"#,
+        )
+        .unwrap();
+        let notes = migrate(&mut document);
+        assert_eq!(notes.len(), 6);
+        assert!(notes.contains(&"missing apiVersion set to v1".to_string()));
+        assert!(notes.contains(
+            &"renamed cluster 'old' certificateAuthorityData to certificate-authority-data"
+                .to_string()
+        ));
+
+        let config: KubeConfig = serde_yaml::from_value(document).unwrap();
+        assert_eq!(config.api_version, "v1");
+        assert_eq!(config.current_context.as_deref(), Some("old"));
+        assert_eq!(
+            config.clusters[0]
+                .cluster
+                .certificate_authority_data
+                .as_deref(),
+            Some("Y2E=")
+        );
+        let user = &config.users[0].user;
+        assert_eq!(user.client_key_data.as_deref(), Some("a2V5"));
+        assert_eq!(user.exec.as_ref().unwrap().api_version, EXEC_API_VERSION);
+        assert!(config.contexts.is_empty());
+
+        // A current document is left alone
+        let mut current = serde_yaml::to_value(&config).unwrap();
+        assert!(migrate(&mut current).is_empty());
+    }
+}
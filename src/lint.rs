@@ -0,0 +1,442 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::{kubens, matches_pattern, paths, server, KubeConfig};
+
+/// Dangling context or current-context references
+pub const DANGLING_REF: &str = "dangling-ref";
+/// Clusters or users not referenced by any context
+pub const ORPHAN_ENTRY: &str = "orphan-entry";
+/// Users authenticating with username/password
+pub const BASIC_AUTH: &str = "basic-auth";
+/// Oversized base64 blobs embedded in clusters or users
+pub const LARGE_EMBEDDED_DATA: &str = "large-embedded-data";
+/// Several clusters pointing at the same API server
+pub const DUPLICATE_SERVER: &str = "duplicate-server";
+/// Several users with byte-identical credentials
+pub const DUPLICATE_CREDENTIALS: &str = "duplicate-credentials";
+/// Production contexts without a namespace
+pub const PROD_MISSING_NAMESPACE: &str = "prod-missing-namespace";
+/// Names outside the lowercase `[a-z0-9-._@]` convention
+pub const NAMING_CONVENTION: &str = "naming-convention";
+/// Clusters, contexts or users not sorted by name
+pub const UNSORTED_ENTRIES: &str = "unsorted-entries";
+
+/// Every rule with its default severity
+pub const RULES: &[(&str, Severity)] = &[
+    (DANGLING_REF, Severity::Error),
+    (ORPHAN_ENTRY, Severity::Warning),
+    (BASIC_AUTH, Severity::Warning),
+    (LARGE_EMBEDDED_DATA, Severity::Warning),
+    (DUPLICATE_SERVER, Severity::Warning),
+    (DUPLICATE_CREDENTIALS, Severity::Warning),
+    (PROD_MISSING_NAMESPACE, Severity::Warning),
+    (NAMING_CONVENTION, Severity::Info),
+    (UNSORTED_ENTRIES, Severity::Info),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Severity::Off => "off",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Lint settings stored under `lint:` in the app config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LintConfig {
+    /// Per-rule severity overrides
+    #[serde(default)]
+    pub severities: HashMap<String, Severity>,
+    /// Context name patterns considered production
+    #[serde(default = "default_prod_patterns")]
+    pub prod_patterns: Vec<String>,
+    /// Largest embedded base64 value (in bytes) before it is flagged
+    #[serde(default = "default_max_embedded_bytes")]
+    pub max_embedded_bytes: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            severities: HashMap::new(),
+            prod_patterns: default_prod_patterns(),
+            max_embedded_bytes: default_max_embedded_bytes(),
+        }
+    }
+}
+
+fn default_prod_patterns() -> Vec<String> {
+    vec!["*prod*".to_string()]
+}
+
+fn default_max_embedded_bytes() -> usize {
+    16 * 1024
+}
+
+impl LintConfig {
+    pub fn severity(&self, rule: &str) -> Severity {
+        self.severities.get(rule).copied().unwrap_or_else(|| {
+            RULES
+                .iter()
+                .find(|(id, _)| *id == rule)
+                .map(|(_, s)| *s)
+                .unwrap_or(Severity::Warning)
+        })
+    }
+}
+
+/// A single lint finding
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    /// Kind of entry the finding is about (cluster, context, user, config)
+    pub kind: &'static str,
+    pub name: String,
+    pub message: String,
+}
+
+/// Whether a name follows the lowercase `[a-z0-9-._@]` convention
+pub fn is_conventional_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-._@".contains(c))
+}
+
+/// Names of users sharing byte-identical credentials, in config order, for
+/// every set of two or more. Users without any credentials aren't counted.
+pub fn duplicate_credentials(config: &KubeConfig) -> Vec<Vec<String>> {
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for user in &config.users {
+        let Ok(serde_yaml::Value::Mapping(fields)) = serde_yaml::to_value(&user.user) else {
+            continue;
+        };
+        if fields.is_empty() {
+            continue;
+        }
+        let key = serde_yaml::to_string(&fields).unwrap_or_default();
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, names)) => names.push(user.name.clone()),
+            None => groups.push((key, vec![user.name.clone()])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, names)| names)
+        .filter(|names| names.len() > 1)
+        .collect()
+}
+
+fn is_sorted<T>(items: &[T], name: impl Fn(&T) -> &str) -> bool {
+    items.windows(2).all(|w| name(&w[0]) <= name(&w[1]))
+}
+
+/// Run every enabled rule against a kubeconfig
+pub fn lint(config: &KubeConfig, settings: &LintConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut push = |rule: &'static str, kind: &'static str, name: &str, message: String| {
+        let severity = settings.severity(rule);
+        if severity != Severity::Off {
+            findings.push(Finding {
+                rule,
+                severity,
+                kind,
+                name: name.to_string(),
+                message,
+            });
+        }
+    };
+
+    let cluster_names: HashSet<&str> = config.clusters.iter().map(|c| c.name.as_str()).collect();
+    let user_names: HashSet<&str> = config.users.iter().map(|u| u.name.as_str()).collect();
+
+    // Dangling references
+    for ctx in &config.contexts {
+        if !cluster_names.contains(ctx.context.cluster.as_str()) {
+            push(
+                DANGLING_REF,
+                "context",
+                &ctx.name,
+                format!("references missing cluster '{}'", ctx.context.cluster),
+            );
+        }
+        if !user_names.contains(ctx.context.user.as_str()) {
+            push(
+                DANGLING_REF,
+                "context",
+                &ctx.name,
+                format!("references missing user '{}'", ctx.context.user),
+            );
+        }
+    }
+    if let Some(current) = &config.current_context {
+        if !config.contexts.iter().any(|c| &c.name == current) {
+            push(
+                DANGLING_REF,
+                "config",
+                "current-context",
+                format!("current-context '{}' does not exist", current),
+            );
+        }
+    }
+
+    // Orphans
+    for cluster in &config.clusters {
+        if !config
+            .contexts
+            .iter()
+            .any(|c| c.context.cluster == cluster.name)
+        {
+            push(
+                ORPHAN_ENTRY,
+                "cluster",
+                &cluster.name,
+                "is not referenced by any context".to_string(),
+            );
+        }
+    }
+    for user in &config.users {
+        if !config.contexts.iter().any(|c| c.context.user == user.name) {
+            push(
+                ORPHAN_ENTRY,
+                "user",
+                &user.name,
+                "is not referenced by any context".to_string(),
+            );
+        }
+    }
+
+    // Deprecated basic auth
+    for user in &config.users {
+        if user.user.username.is_some() || user.user.password.is_some() {
+            push(
+                BASIC_AUTH,
+                "user",
+                &user.name,
+                "uses deprecated basic authentication (username/password)".to_string(),
+            );
+        }
+    }
+
+    // Oversized embedded data
+    for data in paths::embedded(config) {
+        if data.bytes > settings.max_embedded_bytes {
+            push(
+                LARGE_EMBEDDED_DATA,
+                data.kind,
+                &data.name,
+                format!("{} is {} bytes", data.field, data.bytes),
+            );
+        }
+    }
+
+    // Duplicate servers, comparing canonical URLs
+    let by_server = server::by_server(config);
+    for cluster in &config.clusters {
+        let names = &by_server[&server::canonical(&cluster.cluster.server)];
+        if names.len() > 1 {
+            let others: Vec<&str> = names
+                .iter()
+                .map(String::as_str)
+                .filter(|n| *n != cluster.name)
+                .collect();
+            push(
+                DUPLICATE_SERVER,
+                "cluster",
+                &cluster.name,
+                format!(
+                    "shares server {} with {}",
+                    cluster.cluster.server,
+                    others.join(", ")
+                ),
+            );
+        }
+    }
+
+    // The same credentials under several user names
+    for names in duplicate_credentials(config) {
+        for name in &names[1..] {
+            push(
+                DUPLICATE_CREDENTIALS,
+                "user",
+                name,
+                format!("has the same credentials as user '{}'", names[0]),
+            );
+        }
+    }
+
+    // Production contexts without a namespace
+    for ctx in &config.contexts {
+        let is_prod = settings
+            .prod_patterns
+            .iter()
+            .any(|p| matches_pattern(p, &ctx.name));
+        if is_prod && kubens::namespace(&ctx.context).is_none() {
+            push(
+                PROD_MISSING_NAMESPACE,
+                "context",
+                &ctx.name,
+                "production context has no default namespace".to_string(),
+            );
+        }
+    }
+
+    // Naming conventions
+    let names = config
+        .clusters
+        .iter()
+        .map(|c| ("cluster", &c.name))
+        .chain(config.contexts.iter().map(|c| ("context", &c.name)))
+        .chain(config.users.iter().map(|u| ("user", &u.name)));
+    for (kind, name) in names {
+        if !is_conventional_name(name) {
+            push(
+                NAMING_CONVENTION,
+                kind,
+                name,
+                "name should only contain lowercase letters, digits, '-', '.', '_' or '@'"
+                    .to_string(),
+            );
+        }
+    }
+
+    // Sort order
+    for (kind, sorted) in [
+        ("cluster", is_sorted(&config.clusters, |c| &c.name)),
+        ("context", is_sorted(&config.contexts, |c| &c.name)),
+        ("user", is_sorted(&config.users, |u| &u.name)),
+    ] {
+        if !sorted {
+            push(
+                UNSORTED_ENTRIES,
+                "config",
+                kind,
+                format!("{} entries are not sorted by name", kind),
+            );
+        }
+    }
+
+    findings
+}
+
+/// Print findings in human-readable form
+pub fn print_text(findings: &[Finding]) {
+    for f in findings {
+        println!(
+            "{}[{}] {} '{}': {}",
+            f.severity, f.rule, f.kind, f.name, f.message
+        );
+    }
+    let count = |s: Severity| findings.iter().filter(|f| f.severity == s).count();
+    println!(
+        "{} finding(s): {} error(s), {} warning(s), {} info",
+        findings.len(),
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClusterInfo, NamedCluster};
+
+    fn config() -> KubeConfig {
+        crate::tests::create_test_kubeconfig("test")
+    }
+
+    fn rules(findings: &[Finding]) -> Vec<&'static str> {
+        findings.iter().map(|f| f.rule).collect()
+    }
+
+    #[test]
+    fn test_clean_config_has_no_findings() {
+        assert!(lint(&config(), &LintConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_dangling_refs_and_orphans() {
+        let mut config = config();
+        config.contexts[0].context.user = "missing-user".to_string();
+        config.current_context = Some("gone".to_string());
+
+        let findings = lint(&config, &LintConfig::default());
+        assert_eq!(
+            rules(&findings),
+            vec![DANGLING_REF, DANGLING_REF, ORPHAN_ENTRY]
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_duplicate_server_and_naming() {
+        let mut config = config();
+        config.clusters.push(NamedCluster {
+            name: "arn:aws:eks:Test".to_string(),
+            cluster: ClusterInfo {
+                // Same endpoint, spelled differently
+                server: format!("{}/", config.clusters[0].cluster.server.to_uppercase()),
+                certificate_authority_data: None,
+                certificate_authority: None,
+                insecure_skip_tls_verify: None,
+                proxy_url: None,
+                tls_server_name: None,
+            },
+        });
+        let findings = lint(&config, &LintConfig::default());
+        let rules = rules(&findings);
+        assert!(rules.contains(&DUPLICATE_SERVER));
+        assert!(rules.contains(&NAMING_CONVENTION));
+        assert!(rules.contains(&UNSORTED_ENTRIES));
+    }
+
+    #[test]
+    fn test_prod_namespace_and_severity_override() {
+        let mut config = crate::tests::create_test_kubeconfig("prod");
+        config.users[0].user.password = Some("secret".to_string());
+
+        let mut settings = LintConfig::default();
+        let findings = lint(&config, &settings);
+        assert_eq!(rules(&findings), vec![BASIC_AUTH, PROD_MISSING_NAMESPACE]);
+
+        settings
+            .severities
+            .insert(BASIC_AUTH.to_string(), Severity::Off);
+        settings
+            .severities
+            .insert(PROD_MISSING_NAMESPACE.to_string(), Severity::Error);
+        let findings = lint(&config, &settings);
+        assert_eq!(rules(&findings), vec![PROD_MISSING_NAMESPACE]);
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_large_embedded_data() {
+        let mut config = config();
+        let settings = LintConfig {
+            max_embedded_bytes: 8,
+            ..Default::default()
+        };
+        config.users[0].user.client_key_data = Some("A".repeat(9));
+        let findings = lint(&config, &settings);
+        assert!(findings.iter().all(|f| f.rule == LARGE_EMBEDDED_DATA));
+        assert_eq!(findings.len(), 3);
+    }
+}
@@ -0,0 +1,221 @@
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+use crate::state::AppState;
+use crate::{desktop, jwt, kubens, provenance, server, KubeConfig};
+
+/// How `kconf list --group-by` groups contexts
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    /// The cluster entry each context uses
+    Cluster,
+    /// The domain of the cluster's API server
+    Domain,
+    /// Tags set with `kconf tag`; a context with several tags is listed
+    /// under each
+    Tag,
+}
+
+/// Table rows describing each context, starting with a header row.
+/// `wide` adds the server URL, provenance and token expiry columns (contexts
+/// without provenance that a desktop wrote get its name as SOURCE); a NOTE
+/// column is added when any listed context has a note. Pinned contexts
+/// are listed first.
+pub fn rows(config: &KubeConfig, state: &AppState, wide: bool) -> Vec<Vec<String>> {
+    let mut header = vec!["CURRENT", "NAME", "CLUSTER", "USER", "NAMESPACE"];
+    if wide {
+        header.extend(["SERVER", "SOURCE", "IMPORTED", "TOKEN EXPIRES"]);
+    }
+    let notes = config
+        .contexts
+        .iter()
+        .any(|c| state.notes.contains_key(&c.name));
+    if notes {
+        header.push("NOTE");
+    }
+    let mut rows = vec![header.into_iter().map(str::to_string).collect()];
+
+    for context in state.order(&config.contexts) {
+        let current = config.current_context.as_deref() == Some(context.name.as_str());
+        let mut row = vec![
+            if current { "*" } else { "" }.to_string(),
+            context.name.clone(),
+            context.context.cluster.clone(),
+            context.context.user.clone(),
+            kubens::namespace(&context.context).unwrap_or_default(),
+        ];
+        if wide {
+            let server = config
+                .clusters
+                .iter()
+                .find(|c| c.name == context.context.cluster)
+                .map(|c| c.cluster.server.clone())
+                .unwrap_or_default();
+            let provenance = provenance::get(&context.context);
+            row.push(server);
+            row.push(
+                provenance
+                    .as_ref()
+                    .map(|p| p.source.clone())
+                    .or_else(|| desktop::detect(config, context).map(|d| d.to_string()))
+                    .unwrap_or_default(),
+            );
+            row.push(provenance.map(|p| p.imported_at).unwrap_or_default());
+            let token = config
+                .users
+                .iter()
+                .find(|u| u.name == context.context.user)
+                .and_then(|u| u.user.token.as_deref());
+            row.push(
+                token
+                    .and_then(jwt::decode)
+                    .and_then(|claims| claims.expiry(jwt::now()))
+                    .unwrap_or_default(),
+            );
+        }
+        if notes {
+            row.push(state.notes.get(&context.name).cloned().unwrap_or_default());
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+/// Context names by group, groups sorted by name and contexts in listing
+/// order. Contexts the grouping doesn't apply to are put in a group in
+/// parentheses, such as `(untagged)`.
+pub fn groups(config: &KubeConfig, state: &AppState, by: GroupBy) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for context in state.order(&config.contexts) {
+        let keys = match by {
+            GroupBy::Cluster => vec![context.context.cluster.clone()],
+            GroupBy::Domain => vec![config
+                .clusters
+                .iter()
+                .find(|c| c.name == context.context.cluster)
+                .map(|c| server::domain(&c.cluster.server))
+                .unwrap_or_else(|| "(no cluster)".to_string())],
+            GroupBy::Tag => match state.tags.get(&context.name) {
+                Some(tags) if !tags.is_empty() => tags.clone(),
+                _ => vec!["(untagged)".to_string()],
+            },
+        };
+        for key in keys {
+            groups.entry(key).or_default().push(context.name.clone());
+        }
+    }
+    groups
+}
+
+fn column_widths(rows: &[Vec<String>]) -> Vec<usize> {
+    let columns = rows.first().map(Vec::len).unwrap_or(0);
+    (0..columns)
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+        .collect()
+}
+
+fn format_row(row: &[String], widths: &[usize]) -> String {
+    let line: Vec<String> = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect();
+    line.join("   ").trim_end().to_string()
+}
+
+/// Print rows as left-aligned columns
+pub fn print_table(rows: &[Vec<String>]) {
+    let widths = column_widths(rows);
+    for row in rows {
+        println!("{}", format_row(row, &widths));
+    }
+}
+
+/// Print rows from [`rows`] under a line per group, indented, with columns
+/// aligned across groups
+pub fn print_grouped(rows: &[Vec<String>], groups: &BTreeMap<String, Vec<String>>) {
+    let widths = column_widths(rows);
+    let Some((header, rows)) = rows.split_first() else {
+        return;
+    };
+    println!("  {}", format_row(header, &widths));
+    for (group, names) in groups {
+        println!("{} ({})", group, names.len());
+        for name in names {
+            if let Some(row) = rows.iter().find(|r| &r[1] == name) {
+                println!("  {}", format_row(row, &widths));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::{stamp, Provenance};
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_rows_mark_current_context() {
+        let mut config = create_test_kubeconfig("a");
+        config.contexts.extend(create_test_kubeconfig("b").contexts);
+        let rows = rows(&config, &AppState::default(), false);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][..2], ["*", "a-context"]);
+        assert_eq!(rows[2][0], "");
+        assert_eq!(rows[0].len(), 5);
+    }
+
+    #[test]
+    fn test_wide_rows_include_provenance() {
+        let mut config = create_test_kubeconfig("a");
+        config.users[0].user.token = Some(crate::jwt::encode_for_test(r#"{"exp":1000}"#));
+        stamp(
+            &mut config.contexts[0].context,
+            &Provenance::now("/tmp/a.yaml"),
+        );
+        let rows = rows(&config, &AppState::default(), true);
+        assert_eq!(rows[0].len(), 9);
+        assert_eq!(rows[1][5], "https://a.example.com:6443");
+        assert_eq!(rows[1][6], "/tmp/a.yaml");
+        assert_eq!(rows[1][8], "1970-01-01 00:16 UTC (expired)");
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut config = create_test_kubeconfig("a");
+        let mut b = create_test_kubeconfig("b");
+        b.contexts[0].context.cluster = "a-cluster".to_string();
+        config.contexts.extend(b.contexts);
+        let mut state = AppState::default();
+        state.tags.insert(
+            "a-context".to_string(),
+            vec!["prod".to_string(), "eu".to_string()],
+        );
+
+        let by_cluster = groups(&config, &state, GroupBy::Cluster);
+        assert_eq!(by_cluster["a-cluster"], ["a-context", "b-context"]);
+        let by_domain = groups(&config, &state, GroupBy::Domain);
+        assert_eq!(by_domain["example.com"].len(), 2);
+        let by_tag = groups(&config, &state, GroupBy::Tag);
+        assert_eq!(
+            by_tag.keys().collect::<Vec<_>>(),
+            ["(untagged)", "eu", "prod"]
+        );
+        assert_eq!(by_tag["(untagged)"], ["b-context"]);
+    }
+
+    #[test]
+    fn test_rows_show_notes() {
+        let mut config = create_test_kubeconfig("a");
+        config.contexts.extend(create_test_kubeconfig("b").contexts);
+        let mut state = AppState::default();
+        state
+            .notes
+            .insert("b-context".to_string(), "platform team".to_string());
+        let rows = rows(&config, &state, false);
+        assert_eq!(rows[0][5], "NOTE");
+        assert_eq!(rows[1][5], "");
+        assert_eq!(rows[2][5], "platform team");
+    }
+}
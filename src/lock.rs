@@ -0,0 +1,98 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::KconfError;
+
+/// How long a write waits for another kconf process to finish its own
+const WAIT: Duration = Duration::from_secs(2);
+
+/// A write takes milliseconds, so an older lock was left by a process that
+/// died mid-write
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// Lock file held while a destination is written, so concurrent kconf
+/// processes (the daemon and a manual merge, say) don't interleave writes.
+/// Removed when dropped.
+#[derive(Debug)]
+pub struct DestinationLock {
+    path: PathBuf,
+}
+
+impl Drop for DestinationLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Lock file for a destination, next to it: `.config.kconf-lock`
+fn lock_path(dest_path: &Path) -> PathBuf {
+    let file_name = dest_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    dest_path.with_file_name(format!(".{}.kconf-lock", file_name))
+}
+
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > STALE_AFTER)
+}
+
+/// Lock a destination for writing, waiting briefly if another process holds it
+pub fn acquire(dest_path: &Path) -> Result<DestinationLock, KconfError> {
+    acquire_within(dest_path, WAIT)
+}
+
+fn acquire_within(dest_path: &Path, wait: Duration) -> Result<DestinationLock, KconfError> {
+    let path = lock_path(dest_path);
+    let start = Instant::now();
+    loop {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(mut file) => {
+                let _ = writeln!(file, "{}", std::process::id());
+                return Ok(DestinationLock { path });
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                if is_stale(&path) {
+                    let _ = fs::remove_file(&path);
+                } else if start.elapsed() >= wait {
+                    return Err(KconfError::Locked(path));
+                } else {
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+            Err(source) => return Err(KconfError::Write { path, source }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_is_exclusive_until_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+
+        let lock = acquire_within(&dest, Duration::ZERO).unwrap();
+        assert!(temp_dir.path().join(".config.kconf-lock").exists());
+        let error = acquire_within(&dest, Duration::ZERO).unwrap_err();
+        assert_eq!(error.code(), "locked");
+
+        drop(lock);
+        assert!(!temp_dir.path().join(".config.kconf-lock").exists());
+        assert!(acquire_within(&dest, Duration::ZERO).is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::diff::is_secret_path;
+use crate::open_private;
+
+/// The log is rotated once it grows past this size
+const MAX_BYTES: u64 = 1024 * 1024;
+/// Number of rotated logs kept next to the active one
+const KEEP: usize = 5;
+
+const REDACTED: &str = "<redacted>";
+
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// Open ~/.k8sconf/logs/kconf.log for this run, rotating it first if it's
+/// too large. Until this is called (as in tests) `record` does nothing.
+pub fn init(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let path = dir.join("kconf.log");
+    rotate(&path, MAX_BYTES, KEEP)?;
+    // Commands and parsed input can still name users and servers
+    let file = open_private(OpenOptions::new().create(true).append(true), &path)
+        .with_context(|| format!("Failed to open log: {:?}", path))?;
+    *LOG.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    Ok(())
+}
+
+/// Shift kconf.log to kconf.log.1, kconf.log.1 to kconf.log.2 and so on,
+/// dropping the oldest, once it reaches `max_bytes`
+fn rotate(path: &Path, max_bytes: u64, keep: usize) -> Result<()> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok(());
+    };
+    if metadata.len() < max_bytes {
+        return Ok(());
+    }
+    let numbered = |n: usize| path.with_extension(format!("log.{}", n));
+    for n in (1..keep).rev() {
+        if numbered(n).exists() {
+            fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(1)).with_context(|| format!("Failed to rotate {:?}", path))
+}
+
+/// Append a timestamped line to the log, if it's open. Logging never fails
+/// the command.
+pub fn record(message: &str) {
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(file) = log.as_mut() {
+        let _ = writeln!(
+            file,
+            "{} [{}] {}",
+            Local::now().to_rfc3339(),
+            std::process::id(),
+            message
+        );
+    }
+}
+
+/// Command-line arguments with the values of secret-looking flags replaced
+pub fn redact_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let is_secret = |flag: &str| {
+        let flag = flag.trim_start_matches('-').to_lowercase();
+        ["token", "password", "secret"]
+            .iter()
+            .any(|s| flag.contains(s))
+    };
+    let mut redacted = Vec::new();
+    let mut hide_next = false;
+    for arg in args {
+        if hide_next {
+            redacted.push(REDACTED.to_string());
+            hide_next = false;
+        } else if let Some((flag, _)) = arg.split_once('=').filter(|(f, _)| f.starts_with("--")) {
+            if is_secret(flag) {
+                redacted.push(format!("{}={}", flag, REDACTED));
+            } else {
+                redacted.push(arg);
+            }
+        } else {
+            hide_next = arg.starts_with("--") && is_secret(&arg);
+            redacted.push(arg);
+        }
+    }
+    redacted
+}
+
+/// A value as single-line JSON with credentials replaced, for logging
+/// parsed input
+pub fn redact<T: Serialize>(value: &T) -> String {
+    let mut value = serde_json::to_value(value).unwrap_or_default();
+    redact_value("", &mut value);
+    value.to_string()
+}
+
+/// Redact `value`, found at the dotted `path`, with the rules `diff` uses to
+/// mask field changes
+fn redact_value(path: &str, value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                if is_secret_path(&path) || key == "client-certificate-data" {
+                    *field = REDACTED.into();
+                } else if key.ends_with("-data") {
+                    let len = field.as_str().map(str::len).unwrap_or(0);
+                    *field = format!("<{} chars>", len).into();
+                } else {
+                    redact_value(&path, field);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                redact_value(path, item);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_redaction() {
+        let mut config = create_test_kubeconfig("a");
+        config.users[0].user.token = Some("s3cret-token".to_string());
+        let logged = redact(&config);
+        assert!(!logged.contains("s3cret-token"));
+        assert!(logged.contains("https://a.example.com:6443"));
+        assert!(logged.contains(r#""certificate-authority-data":"<16 chars>""#));
+
+        let mut exec = create_test_kubeconfig("exec");
+        exec.users[0].user = crate::credentials::exec_stanza("aws", &["--token=s3cret-arg"]);
+        exec.users[0].user.exec.as_mut().unwrap().env = Some(vec![crate::ExecEnvVar {
+            name: "AWS_SECRET_ACCESS_KEY".to_string(),
+            value: "s3cret-env".to_string(),
+        }]);
+        let logged = redact(&exec);
+        assert!(!logged.contains("s3cret-arg"));
+        assert!(!logged.contains("s3cret-env"));
+        assert!(logged.contains(r#""command":"aws""#));
+
+        let args = [
+            "kconf",
+            "import",
+            "doks",
+            "--token",
+            "t1",
+            "--password=p",
+            "x",
+        ]
+        .map(String::from);
+        assert_eq!(
+            redact_args(args),
+            [
+                "kconf",
+                "import",
+                "doks",
+                "--token",
+                REDACTED,
+                "--password=<redacted>",
+                "x"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rotation_keeps_newest_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("kconf.log");
+        for run in 1..=4 {
+            fs::write(&path, format!("run {}", run)).unwrap();
+            rotate(&path, 1, 2).unwrap();
+        }
+        assert!(!path.exists());
+        let read = |n| fs::read_to_string(temp_dir.path().join(format!("kconf.log.{}", n)));
+        assert_eq!(read(1).unwrap(), "run 4");
+        assert_eq!(read(2).unwrap(), "run 3");
+        assert!(read(3).is_err());
+    }
+}
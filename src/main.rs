@@ -1,16 +1,85 @@
+mod anonymize;
+#[cfg(feature = "remote")]
+mod auth;
+mod backup;
+mod certs;
+mod check;
+mod completions;
+mod credentials;
+mod current;
+mod daemon;
+mod desktop;
+mod diff;
+mod doctor;
+mod expand;
+mod fix;
+mod graph;
+mod history;
+mod import;
+mod jwt;
+mod kubens;
+mod legacy;
+mod lint;
+mod list;
+mod lock;
+mod logfile;
+mod manifest;
+mod migrate;
+mod names;
+mod normalize;
+mod oidc;
+mod onepassword;
+mod ops;
+mod outdated;
+mod paths;
+mod peek;
+mod plan;
+mod policy;
+mod preserve;
+mod progress;
+mod protect;
+mod provenance;
+mod sanitize;
+mod secrets;
+#[cfg(feature = "serve")]
+mod serve;
+mod server;
+mod shell;
+mod sources;
+mod state;
+mod stats;
+mod sync;
+mod team;
+mod tree;
+mod ttl;
+mod validate;
+mod vault;
+mod webhook;
+mod with;
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 /// CLI tool to join kubeconfig files together
 #[derive(Parser, Debug)]
 #[command(name = "kconf")]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// How a failure is reported on stderr: as text, or as a JSON object with
+    /// a stable error code for scripts
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    error_format: OutputFormat,
+
     /// Kubeconfig files to merge into the destination config
     configs: Vec<PathBuf>,
 
@@ -18,39 +87,979 @@ struct Args {
     #[arg(long)]
     remove: Option<String>,
 
+    /// Let --update and --remove change contexts matching the `protected`
+    /// patterns of the app config
+    #[arg(long)]
+    unprotect: bool,
+
+    /// Write to this file instead of the configured destination; repeat to
+    /// update several files at once (either all are written or none are)
+    #[arg(long, value_name = "PATH")]
+    into: Vec<PathBuf>,
+
+    /// If the destination can't be parsed, restore the most recent valid
+    /// backup first instead of failing
+    #[arg(long)]
+    auto_recover: bool,
+
     /// Update existing contexts instead of skipping them
+    #[arg(long, overrides_with = "no_update")]
+    update: bool,
+
+    /// For existing contexts, only refresh the user credentials from the source
+    #[arg(long, conflicts_with = "update")]
+    update_credentials_only: bool,
+
+    /// Exit with an error, without writing, if any entry was skipped because a
+    /// different entry with the same name already exists
+    #[arg(long)]
+    fail_on_skip: bool,
+
+    /// Only merge these contexts (comma-separated) from the sources
+    #[arg(long, value_delimiter = ',', value_name = "NAMES")]
+    contexts: Vec<String>,
+
+    /// Choose which contexts to import from each source interactively
+    #[arg(long)]
+    pick: bool,
+
+    /// Skip existing contexts even if defaults.update is set
+    #[arg(long, overrides_with = "update")]
+    no_update: bool,
+
+    /// With --update, only overwrite fields present in the source and keep the rest
+    #[arg(long)]
+    merge_fields: bool,
+
+    /// How to handle incoming entries whose name is taken by a different entry
+    /// (defaults to defaults.conflict_strategy)
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    on_conflict: Option<ConflictStrategy>,
+
+    /// When an incoming cluster has the same API server as an existing
+    /// cluster of another name, point its contexts at the existing cluster
+    /// without asking
+    #[arg(long)]
+    reuse_clusters: bool,
+
+    /// Write the destination as YAML or JSON (defaults to `format` in the
+    /// app config, or the format the destination already has)
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    output_format: Option<KubeconfigFormat>,
+
+    /// Make the last file's current-context the destination's, instead of
+    /// keeping the destination's own
+    #[arg(long)]
+    current_from_last: bool,
+
+    /// Remove the contexts this merge adds or updates once they are older than
+    /// this (e.g. 12h, 7d, 2w); see `kconf clean`
+    #[arg(long, value_name = "DURATION")]
+    ttl: Option<String>,
+
+    /// Copy the destination to ~/.k8sconf/backups before writing it
+    #[arg(long, overrides_with = "no_backup")]
+    backup: bool,
+
+    /// Don't back up the destination even if defaults.backup is set
+    #[arg(long, overrides_with = "backup")]
+    no_backup: bool,
+
+    /// Sort clusters, contexts and users by name when writing
+    #[arg(long, overrides_with = "no_sort")]
+    sort: bool,
+
+    /// Keep the existing order even if defaults.sort_output is set
+    #[arg(long, overrides_with = "sort")]
+    no_sort: bool,
+
+    /// Allow merging clusters with insecure-skip-tls-verify even when policy denies it
+    #[arg(long)]
+    allow_insecure: bool,
+
+    /// Fail instead of warning when a source violates a configured policy
+    #[arg(long)]
+    enforce_policy: bool,
+
+    /// Expand ${VAR} placeholders in source files from the environment
+    #[arg(long)]
+    expand_env: bool,
+
+    /// Inline certificate-authority, client-certificate and client-key files
+    /// as their -data fields
+    #[arg(long)]
+    embed: bool,
+
+    /// Bind contexts whose user isn't defined in the source to this local user
+    #[arg(long, value_name = "USER")]
+    bind_user: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect certificates referenced by the destination config
+    Certs {
+        #[command(subcommand)]
+        action: CertsCommand,
+    },
+    /// Probe API server connectivity, version and certificate expiry for each
+    /// context
+    Check {
+        /// Contexts to check (defaults to all)
+        contexts: Vec<String>,
+
+        /// Maximum number of clusters probed at once
+        #[arg(long, default_value_t = 16)]
+        concurrency: usize,
+
+        /// Seconds to wait for each server to accept a connection
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+
+        /// Also verify, with each context's credentials, that its namespace
+        /// exists and pods can be listed in it
+        #[arg(long)]
+        namespaces: bool,
+
+        /// Also check that the certificate each server presents validates
+        /// against the CA stored in the kubeconfig
+        #[arg(long)]
+        verify_ca: bool,
+
+        /// Order of the results (defaults to the config's order)
+        #[arg(long, value_enum)]
+        sort: Option<check::SortBy>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Check the whole setup and suggest fixes for anything wrong
+    Doctor {
+        /// Skip connectivity and certificate probes
+        #[arg(long)]
+        offline: bool,
+
+        /// Seconds to wait for each server to accept a connection
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+    },
+    /// Check a kubeconfig for common problems
+    Lint {
+        /// Kubeconfig to lint (defaults to the destination config)
+        file: Option<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// First remove dangling references, a nonexistent current-context
+        /// and orphaned clusters and users
+        #[arg(long)]
+        fix: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// List contexts in the destination config
+    List {
+        /// Also show server URLs and where each context was imported from
+        #[arg(long)]
+        wide: bool,
+
+        /// Group contexts under their cluster, server domain or tags
+        #[arg(long, value_enum, value_name = "GROUP")]
+        group_by: Option<list::GroupBy>,
+    },
+    /// Show clusters, their contexts and each context's user, marking
+    /// entries shared by several contexts
+    Tree,
+    /// Export the context, cluster and user graph for documentation
+    Graph {
+        /// Graphviz DOT or a Mermaid flowchart
+        #[arg(long, value_enum, default_value = "dot")]
+        format: graph::GraphFormat,
+    },
+    /// Summarize the destination's entries and the size of each cluster and
+    /// user, flagging large embedded certificates and keys
+    Stats {
+        /// Move embedded data over `lint.max_embedded_bytes` into files
+        /// under ~/.k8sconf/certs
+        #[arg(long)]
+        externalize: bool,
+
+        /// Externalize without asking for confirmation
+        #[arg(long, short, requires = "externalize")]
+        yes: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Print the destination with every credential removed, safe to attach
+    /// to a support ticket or commit as cluster inventory
+    Sanitize {
+        /// Contexts to include (defaults to all)
+        contexts: Vec<String>,
+
+        /// Also replace server, proxy and TLS server hostnames with hashes
+        #[arg(long)]
+        hash_hosts: bool,
+
+        /// Print YAML or JSON
+        #[arg(long, value_enum, default_value = "yaml")]
+        format: KubeconfigFormat,
+    },
+    /// Print kubeconfigs with names, servers and credentials replaced by
+    /// placeholders, keeping which entries share them, for bug reports
+    Anonymize {
+        /// Kubeconfig files (defaults to the destination config); several
+        /// files share placeholders and are printed as one YAML stream
+        files: Vec<PathBuf>,
+    },
+    /// Summarize a kubeconfig file and how it would merge, without merging
+    Peek {
+        /// Kubeconfig file to inspect
+        file: PathBuf,
+    },
+    /// Show differences between two kubeconfig files, or between the
+    /// destination and a file when only one is given
+    Diff {
+        /// Old side (or the file to compare against the destination)
+        a: PathBuf,
+        /// New side
+        b: Option<PathBuf>,
+    },
+    /// Work out what merging kubeconfig files would change and save it as a
+    /// plan for review, without touching the destination
+    Plan {
+        /// Kubeconfig files to merge
+        #[arg(required = true)]
+        configs: Vec<PathBuf>,
+        /// File to write the plan to
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+        /// Update existing entries instead of skipping them
+        #[arg(long)]
+        update: bool,
+    },
+    /// Apply a saved plan, refusing if the destination changed since
+    Apply {
+        /// Plan file written by `kconf plan`
+        plan: PathBuf,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// List the API servers clusters point at, comparing URLs in canonical
+    /// form (case, default ports and trailing slashes don't matter)
+    Servers {
+        /// Only show servers that several cluster entries point at
+        #[arg(long)]
+        duplicates: bool,
+    },
+    /// Run the operations in a file (merge, remove, set-namespace) in order,
+    /// writing the destination only if all of them succeed
+    ApplyOps {
+        /// YAML file with an `operations` list
+        file: PathBuf,
+
+        /// Run the operations and show the result without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Show where a context was imported from
+    Which {
+        /// Name of the context
+        context: String,
+    },
+    /// Show, set or clear the note attached to a context
+    Note {
+        /// Name of the context
+        context: String,
+
+        /// Note text, e.g. who owns the cluster or when not to touch it
+        text: Option<String>,
+
+        /// Remove the note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
+    /// Show, add or remove a context's tags (see `kconf list --group-by tag`)
+    Tag {
+        /// Name of the context
+        context: String,
+
+        /// Tags to add
+        tags: Vec<String>,
+
+        /// Remove the given tags instead
+        #[arg(long, requires = "tags")]
+        remove: bool,
+    },
+    /// Show the current context and namespace
+    Current {
+        /// Print `context:namespace` on one line, for shell prompts
+        #[arg(long)]
+        short: bool,
+
+        /// Stable output for scripts and prompts: tab-separated unless
+        /// --short, and empty rather than an error when nothing is current
+        #[arg(long)]
+        porcelain: bool,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum, required_unless_present = "list_contexts")]
+        shell: Option<completions::CompletionShell>,
+
+        /// Print the destination's context names, for dynamic completion
+        #[arg(long, hide = true)]
+        list_contexts: bool,
+    },
+    /// Print shell helper functions (`kc`, `kcl`, `kns`, `kconf_prompt`) to
+    /// evaluate from your shell's rc file
+    ShellInit {
+        #[arg(value_enum)]
+        shell: shell::Shell,
+    },
+    /// Merge kubeconfig files into a temporary file and run a command with
+    /// KUBECONFIG pointing at it, leaving the destination untouched
+    With {
+        /// Kubeconfig files to merge (the first to define a name wins)
+        #[arg(required = true)]
+        configs: Vec<PathBuf>,
+
+        /// Command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Write a kubeconfig holding only one context and print the command
+    /// pointing KUBECONFIG at it, for `eval "$(kconf env prod)"`
+    Env {
+        /// Name of the context
+        context: String,
+
+        /// Shell syntax to print
+        #[arg(long, value_enum, default_value = "bash")]
+        shell: shell::Shell,
+    },
+    /// Switch the current context, choosing interactively when no name is
+    /// given
+    Use {
+        /// Name of the context
+        context: Option<String>,
+    },
+    /// Switch back to the previously used context, like `kubectx -`
+    Last,
+    /// Pin a context so it's listed first, or show the pinned contexts
+    Pin {
+        /// Name of the context
+        context: Option<String>,
+
+        /// Place the context at this position among the pins (1 is first)
+        #[arg(long, requires = "context", value_parser = clap::value_parser!(u32).range(1..))]
+        position: Option<u32>,
+    },
+    /// Unpin a context
+    Unpin {
+        /// Name of the context
+        context: String,
+    },
+    /// Restore the destination from the most recent valid backup after it
+    /// became unparseable
+    Recover {
+        /// Restore without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Show or switch the namespace of the current context
+    Ns {
+        /// Namespace to switch to
+        namespace: Option<String>,
+
+        /// List the cluster's namespaces, marking the active one
+        #[arg(short, long, conflicts_with = "namespace")]
+        list: bool,
+
+        /// Seconds to wait for the API server when listing
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Set the namespace of every context matching a pattern
+    SetNamespace {
+        /// Context name pattern; `*` and `?` are wildcards
+        #[arg(long = "match", value_name = "PATTERN")]
+        pattern: String,
+
+        /// Namespace to set
+        #[arg(long)]
+        namespace: String,
+
+        /// Only preview the affected contexts without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Compare imported contexts with their original sources
+    Outdated,
+    /// Re-fetch every registered source and merge it into the destination
+    Refresh {
+        /// Only verify that the destination already matches the merged
+        /// sources; print the differences and fail if it doesn't
+        #[arg(long)]
+        check: bool,
+    },
+    /// Refresh registered sources on a schedule
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonCommand>,
+    },
+    /// Local and encrypted remote backups of the destination config
+    Backup {
+        #[command(subcommand)]
+        action: BackupCommand,
+    },
+    /// Merge the destination with a shared git remote and push the result,
+    /// or bring it to the state described by a manifest
+    Sync {
+        /// Manifest listing the sources and contexts that should exist
+        manifest: Option<PathBuf>,
+
+        /// Remove contexts the manifest doesn't account for
+        #[arg(long, requires = "manifest")]
+        prune: bool,
+
+        /// Show what would change without writing anything
+        #[arg(long, requires = "manifest")]
+        dry_run: bool,
+
+        /// Let the remote's version of entries replace local ones (defaults
+        /// to defaults.update)
+        #[arg(long, conflicts_with = "manifest")]
+        update: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Check that a context's credentials are accepted by its cluster
+    Auth {
+        #[command(subcommand)]
+        action: AuthCommand,
+    },
+    /// Print the bearer token a context authenticates with (running its exec
+    /// plugin if it has one), or `username:password` for basic auth, e.g.
+    /// for curl
+    Token {
+        /// Name of the context (defaults to the current context)
+        context: Option<String>,
+
+        /// Print a complete `Authorization:` header instead
+        #[arg(long)]
+        header: bool,
+    },
+    /// Store secrets in the OS keyring for use in the app config
+    Secret {
+        #[command(subcommand)]
+        action: SecretCommand,
+    },
+    /// Serve credentials moved into the credential store
+    Credential {
+        #[command(subcommand)]
+        action: CredentialCommand,
+    },
+    /// Serve a local REST API for listing, merging and removing contexts
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+        /// Host name clients use in their Host header; required when
+        /// listening on a wildcard or non-loopback address (repeatable)
+        #[arg(long = "allowed-host")]
+        allowed_hosts: Vec<String>,
+    },
+    /// Generate a kubeconfig from another tool or environment and merge it
+    Import {
+        #[command(subcommand)]
+        action: ImportCommand,
+    },
+    /// Remove contexts past the TTL they were merged with (--ttl)
+    Clean {
+        /// Only list the expired contexts without removing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Apply mechanical fixes for lint findings to the destination config
+    Fix {
+        /// Only preview the changes without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Apply without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Rewrite a context's legacy `auth-provider: oidc` user as a kubelogin
+    /// exec plugin, which current kubectl versions require
+    ConvertOidc {
+        /// Context whose user to convert
+        context: String,
+
+        /// Run the kubelogin binary instead of `kubectl oidc-login`
+        #[arg(long)]
+        standalone: bool,
+
+        /// Print the converted user without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't back up the destination even if defaults.backup is set
+        #[arg(long)]
+        no_backup: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupCommand {
+    /// List local backups, newest first
+    List,
+    /// Replace the destination with a local backup
+    Restore {
+        /// Timestamp of the backup (as shown by `backup list`); a unique prefix is enough
+        timestamp: String,
+
+        /// Replace the destination without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Print the key encrypted local backups use (backup.encrypt), to keep a
+    /// copy somewhere safe
+    Key,
+    /// Encrypt the destination config and upload it to the configured remote
+    Push,
+    /// Download and decrypt the remote backup, replacing the destination config
+    Pull {
+        /// Replace the destination without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommand {
+    /// Authenticate with a context's credentials (running its exec plugin if
+    /// needed) and show who the API server says you are
+    Test {
+        /// Context to test (defaults to the current context)
+        context: Option<String>,
+
+        /// Seconds to wait for the API server
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretCommand {
+    /// Prompt for a secret and store it under NAME (referenced as `keyring: NAME`)
+    Set {
+        /// Name of the keyring entry
+        name: String,
+    },
+    /// Remove a stored secret
+    Delete {
+        /// Name of the keyring entry
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ImportCommand {
+    /// Use the ServiceAccount mounted into the current pod
+    InCluster {
+        /// Name for the cluster, context and user
+        #[arg(long, default_value = "in-cluster")]
+        name: String,
+
+        #[command(flatten)]
+        output: ImportOutput,
+    },
+    /// Add a token-based context for an OpenShift cluster. Without --server and
+    /// --token, the current `oc` session is used.
+    Openshift {
+        /// API server URL
+        #[arg(long)]
+        server: Option<String>,
+
+        /// Bearer token (e.g. sha256~...)
+        #[arg(long)]
+        token: Option<String>,
+
+        /// An `oc login ...` command to take the server and token from
+        #[arg(long, value_name = "COMMAND", conflicts_with_all = ["server", "token"])]
+        login_command: Option<String>,
+
+        /// CA bundle to embed for the cluster
+        #[arg(long, value_name = "FILE")]
+        certificate_authority: Option<PathBuf>,
+
+        /// Default namespace for the context
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Name for the cluster, context and user (defaults to oc's cluster name)
+        #[arg(long)]
+        name: Option<String>,
+
+        #[command(flatten)]
+        output: ImportOutput,
+    },
+    /// Add every DigitalOcean Kubernetes (DOKS) cluster as do-<region>-<name>
+    Doks {
+        /// DigitalOcean API token (defaults to $DIGITALOCEAN_ACCESS_TOKEN;
+        /// doctl is used when neither is set)
+        #[arg(long)]
+        token: Option<String>,
+
+        #[command(flatten)]
+        output: ImportOutput,
+    },
+    /// Add every Kubernetes cluster available through Teleport (tsh)
+    Teleport {
+        /// Context name template (`{teleport}`, `{cluster}`); defaults to
+        /// import.teleport.context_template
+        #[arg(long)]
+        context_template: Option<String>,
+
+        #[command(flatten)]
+        output: ImportOutput,
+    },
+    /// Re-import the contexts Docker Desktop and Rancher Desktop write, even
+    /// when desktop.exclude leaves them out of merges
+    Desktop {
+        /// Kubeconfig the desktops write to (defaults to ~/.kube/config)
+        #[arg(long, value_name = "FILE")]
+        from: Option<PathBuf>,
+
+        #[command(flatten)]
+        output: ImportOutput,
+    },
+}
+
+/// Options shared by every importer
+#[derive(clap::Args, Debug)]
+struct ImportOutput {
+    /// Print the generated kubeconfig instead of merging it
+    #[arg(long)]
+    print: bool,
+
+    /// Replace existing entries with the same names
     #[arg(long)]
     update: bool,
+
+    /// Remove the imported contexts once they are older than this (e.g. 7d)
+    #[arg(long, value_name = "DURATION")]
+    ttl: Option<String>,
+
+    /// Don't back up the destination even if defaults.backup is set
+    #[arg(long)]
+    no_backup: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum DaemonCommand {
+    /// Show whether the daemon is running and its last refresh
+    Status,
+}
+
+#[derive(Subcommand, Debug)]
+enum CredentialCommand {
+    /// Print a stored user's credentials as an ExecCredential (used by kubectl)
+    Print {
+        /// Name of the user
+        user: String,
+    },
+    /// Print a token kept in HashiCorp Vault as an ExecCredential (used by kubectl)
+    Vault {
+        /// Secret path, e.g. secret/kubeconfig/prod-user
+        path: String,
+    },
+}
+
+/// File format of a written kubeconfig; kubectl reads both
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum KubeconfigFormat {
+    Yaml,
+    Json,
+}
+
+impl KubeconfigFormat {
+    /// The format of an existing kubeconfig file: JSON documents start with
+    /// `{`, which a YAML kubeconfig (a block mapping) never does
+    fn of(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        Some(if content.trim_start().starts_with('{') {
+            KubeconfigFormat::Json
+        } else {
+            KubeconfigFormat::Yaml
+        })
+    }
+
+    fn serialize(self, config: &KubeConfig) -> Result<String> {
+        Ok(match self {
+            KubeconfigFormat::Yaml => serde_yaml::to_string(config)?,
+            KubeconfigFormat::Json => serde_json::to_string_pretty(config)? + "\n",
+        })
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum CertsCommand {
+    /// Print decoded client certificate and CA details for a context
+    Show {
+        /// Name of the context to inspect
+        context: String,
+    },
 }
 
 /// Application configuration stored in ~/.k8sconf/config.yaml
 #[derive(Debug, Serialize, Deserialize)]
 struct AppConfig {
+    /// Schema version, upgraded automatically on load
+    #[serde(default)]
+    version: u32,
     /// Destination kubeconfig file path
     destination: String,
+    /// Format the destination is written in; unset keeps the format the
+    /// file already has (YAML for a new file)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<KubeconfigFormat>,
+    /// Defaults for merge flags
+    #[serde(default)]
+    defaults: Defaults,
+    /// Context name patterns (`*` and `?` wildcards) never merged from any source
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// Fields updates never overwrite once the destination sets them, as
+    /// `<cluster|context|user>.<field>`, e.g. `context.namespace`
+    #[serde(default)]
+    preserve_fields: Vec<String>,
+    /// Context name patterns (`*` and `?` wildcards) that updates and
+    /// removals refuse to touch without --unprotect
+    #[serde(default)]
+    protected: Vec<String>,
+    /// Naming rules applied to incoming context names
+    #[serde(default)]
+    normalize: normalize::NormalizeConfig,
+    /// Validation of incoming cluster, context and user names
+    #[serde(default)]
+    names: names::NamesConfig,
+    /// Handling of Docker Desktop and Rancher Desktop contexts
+    #[serde(default)]
+    desktop: desktop::DesktopConfig,
+    /// Policies applied to incoming kubeconfigs before merging
+    #[serde(default)]
+    policies: Policies,
+    /// Lint rule settings
+    #[serde(default)]
+    lint: lint::LintConfig,
+    /// Git history of destination writes
+    #[serde(default)]
+    git: history::GitConfig,
+    /// Backup settings
+    #[serde(default)]
+    backup: backup::BackupConfig,
+    /// Multi-machine sync via a git remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sync: Option<sync::SyncConfig>,
+    /// Team mode: bind shared cluster definitions to local credentials
+    #[serde(default)]
+    team: team::TeamConfig,
+    /// Keep user credentials out of the destination
+    #[serde(default)]
+    credentials: credentials::CredentialsConfig,
+    /// Registered sources re-merged by `kconf refresh`
+    #[serde(default)]
+    sources: Vec<sources::Source>,
+    /// Timeouts, retries and proxy for URL sources
+    #[serde(default)]
+    fetch: sources::FetchConfig,
+    /// Schedule and notifications for `kconf daemon`
+    #[serde(default)]
+    daemon: daemon::DaemonConfig,
+    /// Settings for `kconf import`
+    #[serde(default)]
+    import: import::ImportConfig,
+    /// Webhook notified after merges and removals
+    #[serde(default)]
+    webhook: webhook::WebhookConfig,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            version: migrate::CURRENT_VERSION,
             destination: "~/.kube/config".to_string(),
+            format: None,
+            defaults: Defaults::default(),
+            exclude: Vec::new(),
+            preserve_fields: Vec::new(),
+            protected: Vec::new(),
+            normalize: normalize::NormalizeConfig::default(),
+            names: names::NamesConfig::default(),
+            desktop: desktop::DesktopConfig::default(),
+            policies: Policies::default(),
+            lint: lint::LintConfig::default(),
+            git: history::GitConfig::default(),
+            backup: backup::BackupConfig::default(),
+            sync: None,
+            team: team::TeamConfig::default(),
+            credentials: credentials::CredentialsConfig::default(),
+            sources: Vec::new(),
+            fetch: sources::FetchConfig::default(),
+            daemon: daemon::DaemonConfig::default(),
+            import: import::ImportConfig::default(),
+            webhook: webhook::WebhookConfig::default(),
         }
     }
 }
 
+/// Defaults for merge flags so they don't have to be passed every time;
+/// command-line flags override them
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Defaults {
+    /// Behave as if --update was passed
+    #[serde(default)]
+    update: bool,
+    /// Back up the destination before writing it
+    #[serde(default)]
+    backup: bool,
+    /// Sort clusters, contexts and users by name when writing
+    #[serde(default)]
+    sort_output: bool,
+    /// What to do with incoming entries whose name is taken by a different entry
+    #[serde(default)]
+    conflict_strategy: ConflictStrategy,
+}
+
+/// Handling of name conflicts when existing entries aren't being updated
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+enum ConflictStrategy {
+    /// Keep the destination's entry and skip the incoming one
+    #[default]
+    Skip,
+    /// Keep both, renaming the incoming entry to <name>-2, <name>-3, ...
+    Rename,
+}
+
+/// Merge policies configured in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Policies {
+    /// Refuse to merge clusters with insecure-skip-tls-verify enabled
+    #[serde(default)]
+    deny_insecure_tls: bool,
+    /// Flag users that carry a plaintext password
+    #[serde(default)]
+    deny_plaintext_passwords: bool,
+    /// Flag clusters whose server uses plain http://
+    #[serde(default)]
+    deny_http_servers: bool,
+    /// Server URL patterns (`*` wildcards) whose users must authenticate via exec
+    #[serde(default)]
+    require_exec_for_servers: Vec<String>,
+}
+
 /// Kubeconfig structure
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 struct KubeConfig {
     #[serde(rename = "apiVersion")]
     api_version: String,
     kind: String,
+    #[serde(default)]
     clusters: Vec<NamedCluster>,
+    #[serde(default)]
     contexts: Vec<NamedContext>,
+    #[serde(default)]
     users: Vec<NamedUser>,
     #[serde(rename = "current-context", skip_serializing_if = "Option::is_none")]
     current_context: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    preferences: Option<HashMap<String, serde_yaml::Value>>,
+    preferences: Option<Preferences>,
+}
+
+/// Client preferences; fields kubectl doesn't define are kept as they are
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+struct Preferences {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    colors: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extensions: Option<Vec<NamedExtension>>,
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl Preferences {
+    fn is_empty(&self) -> bool {
+        self.colors.is_none()
+            && self.extensions.as_ref().is_none_or(Vec::is_empty)
+            && self.other.is_empty()
+    }
+
+    /// Take what `source` sets. Like clusters, contexts and users, values
+    /// already present are kept unless `replace` is set (for `--update`);
+    /// extensions are matched by name.
+    fn merge_from(&mut self, source: &Preferences, replace: bool) {
+        if source.colors.is_some() && (replace || self.colors.is_none()) {
+            self.colors = source.colors;
+        }
+        for extension in source.extensions.iter().flatten() {
+            let extensions = self.extensions.get_or_insert_with(Vec::new);
+            match extensions.iter_mut().find(|e| e.name == extension.name) {
+                Some(existing) if replace => *existing = extension.clone(),
+                Some(_) => {}
+                None => extensions.push(extension.clone()),
+            }
+        }
+        for (key, value) in &source.other {
+            if replace || !self.other.contains_key(key) {
+                self.other.insert(key.clone(), value.clone());
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -77,6 +1086,10 @@ struct ClusterInfo {
         skip_serializing_if = "Option::is_none"
     )]
     insecure_skip_tls_verify: Option<bool>,
+    #[serde(rename = "proxy-url", skip_serializing_if = "Option::is_none")]
+    proxy_url: Option<String>,
+    #[serde(rename = "tls-server-name", skip_serializing_if = "Option::is_none")]
+    tls_server_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -91,6 +1104,15 @@ struct ContextInfo {
     user: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extensions: Option<Vec<NamedExtension>>,
+}
+
+/// Named extension entry; kconf keeps its own metadata here
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct NamedExtension {
+    name: String,
+    extension: serde_yaml::Value,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -118,23 +1140,216 @@ struct UserInfo {
     username: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     password: Option<String>,
+    #[serde(rename = "auth-provider", skip_serializing_if = "Option::is_none")]
+    auth_provider: Option<AuthProviderConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exec: Option<ExecConfig>,
+}
+
+/// In-tree authentication plugin, removed in kubectl 1.26 in favour of exec
+/// credential plugins; kept so such users survive a merge
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct AuthProviderConfig {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<BTreeMap<String, String>>,
+}
+
+/// Exec credential plugin configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct ExecConfig {
+    #[serde(rename = "apiVersion")]
+    api_version: String,
+    command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<Vec<ExecEnvVar>>,
+    #[serde(rename = "installHint", skip_serializing_if = "Option::is_none")]
+    install_hint: Option<String>,
+    #[serde(rename = "provideClusterInfo", skip_serializing_if = "Option::is_none")]
+    provide_cluster_info: Option<bool>,
+    #[serde(rename = "interactiveMode", skip_serializing_if = "Option::is_none")]
+    interactive_mode: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct ExecEnvVar {
+    name: String,
+    value: String,
 }
 
 #[derive(Error, Debug)]
 enum KconfError {
     #[error("Kubeconfig file not found: {0}")]
     ConfigNotFound(PathBuf),
+    #[error("Context not found: {0}")]
+    ContextNotFound(String),
+    #[error(
+        "Refusing to merge cluster '{0}' with insecure-skip-tls-verify (use --allow-insecure to override)"
+    )]
+    InsecureCluster(String),
+    #[error("Source violates {0} policy rule(s) (see warnings above)")]
+    PolicyViolation(usize),
+    #[error("Refusing to change protected {0} (pass --unprotect to override)")]
+    Protected(String),
+    #[error("Lint found {0} error(s)")]
+    LintFailed(usize),
+    #[error("{0} context(s) failed checks")]
+    CheckFailed(usize),
+    #[error("Doctor found {0} problem(s)")]
+    DoctorFailed(usize),
+    #[error("{0} conflicting item(s) skipped; destination not written (--fail-on-skip)")]
+    SkippedConflicts(usize),
+    #[error("Destination differs from its registered sources in {0} place(s)")]
+    Drift(usize),
+    #[error("Destination changed since the plan was made; run `kconf plan` again")]
+    StalePlan,
+    #[error("No remote backup configured (set backup.remote in ~/.k8sconf/config.yaml)")]
+    NoRemoteBackup,
+    #[error("No sync remote configured (set sync.remote in ~/.k8sconf/config.yaml)")]
+    NoSyncRemote,
+    #[error("No stored credentials for user: {0}")]
+    CredentialNotFound(String),
+    #[error("Environment variable not set: {0}")]
+    UndefinedEnvVar(String),
+    #[error("Invalid {kind} name {name:?}: {problem} (set names.invalid: sanitize to fix names on import)")]
+    InvalidName {
+        kind: &'static str,
+        name: String,
+        problem: String,
+    },
+    #[error("Destination {0:?} can't be parsed; run `kconf recover` to restore the most recent valid backup")]
+    CorruptDestination(PathBuf),
+    #[error("Refusing to merge {name}, which isn't a valid kubeconfig:\n  - {}", .problems.join("\n  - "))]
+    InvalidSource { name: String, problems: Vec<String> },
+    #[error("Failed to parse kubeconfig: {path:?}: {message}")]
+    ParseFailed { path: PathBuf, message: String },
+    #[error("Failed to read {path:?}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {path:?}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Destination {path:?} is not writable: {reason}. Write somewhere else with --into <path>, or point `destination` in ~/.k8sconf/config.yaml at a writable file")]
+    ReadOnlyDestination { path: PathBuf, reason: String },
+    #[error("Another kconf process is writing the destination (lock file {0:?}; delete it if no kconf is running)")]
+    Locked(PathBuf),
+    #[error("This kconf was built without the `{0}` feature (rebuild with `--features {0}`)")]
+    #[cfg_attr(
+        all(
+            feature = "remote",
+            feature = "keyring",
+            feature = "importers",
+            feature = "serve"
+        ),
+        allow(dead_code)
+    )]
+    FeatureDisabled(&'static str),
+}
+
+impl KconfError {
+    /// Stable identifier for scripts, reported by `--error-format json`.
+    /// Never change one once released; add a new variant instead.
+    fn code(&self) -> &'static str {
+        match self {
+            KconfError::ConfigNotFound(_) => "config_not_found",
+            KconfError::ContextNotFound(_) => "context_not_found",
+            KconfError::InsecureCluster(_) => "insecure_cluster",
+            KconfError::PolicyViolation(_) => "policy_violation",
+            KconfError::Protected(_) => "protected",
+            KconfError::LintFailed(_) => "lint_failed",
+            KconfError::CheckFailed(_) => "check_failed",
+            KconfError::DoctorFailed(_) => "doctor_failed",
+            KconfError::SkippedConflicts(_) => "conflict",
+            KconfError::Drift(_) => "drift",
+            KconfError::StalePlan => "stale_plan",
+            KconfError::NoRemoteBackup => "no_remote_backup",
+            KconfError::NoSyncRemote => "no_sync_remote",
+            KconfError::CredentialNotFound(_) => "credential_not_found",
+            KconfError::UndefinedEnvVar(_) => "undefined_env_var",
+            KconfError::InvalidName { .. } => "invalid_name",
+            KconfError::InvalidSource { .. } => "invalid_source",
+            KconfError::CorruptDestination(_) => "corrupt_destination",
+            KconfError::ParseFailed { .. } => "parse_error",
+            KconfError::Read { .. } | KconfError::Write { .. } => "io_error",
+            KconfError::ReadOnlyDestination { .. } => "read_only_destination",
+            KconfError::Locked(_) => "locked",
+            KconfError::FeatureDisabled(_) => "feature_disabled",
+        }
+    }
+
+    /// Process exit code, shared by each class of failure (clap uses 2 for
+    /// usage errors, and 1 covers anything unclassified)
+    fn exit_code(&self) -> i32 {
+        match self {
+            KconfError::ConfigNotFound(_)
+            | KconfError::ContextNotFound(_)
+            | KconfError::CredentialNotFound(_)
+            | KconfError::UndefinedEnvVar(_) => 3,
+            KconfError::ParseFailed { .. }
+            | KconfError::CorruptDestination(_)
+            | KconfError::InvalidName { .. }
+            | KconfError::InvalidSource { .. } => 4,
+            KconfError::SkippedConflicts(_) | KconfError::StalePlan | KconfError::Drift(_) => 5,
+            KconfError::InsecureCluster(_)
+            | KconfError::PolicyViolation(_)
+            | KconfError::Protected(_) => 6,
+            KconfError::LintFailed(_)
+            | KconfError::CheckFailed(_)
+            | KconfError::DoctorFailed(_) => 7,
+            KconfError::NoRemoteBackup
+            | KconfError::NoSyncRemote
+            | KconfError::FeatureDisabled(_) => 8,
+            KconfError::Read { .. }
+            | KconfError::Write { .. }
+            | KconfError::ReadOnlyDestination { .. } => 9,
+            KconfError::Locked(_) => 10,
+        }
+    }
 }
 
 fn expand_tilde(path: &str) -> PathBuf {
-    if path.starts_with("~/") {
+    if let Some(rest) = path.strip_prefix("~/") {
         if let Some(home) = dirs::home_dir() {
-            return home.join(&path[2..]);
+            return home.join(rest);
         }
     }
     PathBuf::from(path)
 }
 
+/// Match `text` against a pattern where `*` matches any run of characters
+/// and `?` matches exactly one character
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 fn get_app_config_dir() -> Result<PathBuf> {
     let home = dirs::home_dir().context("Could not determine home directory")?;
     Ok(home.join(".k8sconf"))
@@ -147,8 +1362,26 @@ fn load_app_config() -> Result<AppConfig> {
     if config_path.exists() {
         let content = fs::read_to_string(&config_path)
             .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
-        let config: AppConfig =
+        let mut value: serde_yaml::Value =
             serde_yaml::from_str(&content).with_context(|| "Failed to parse config file")?;
+        let from = migrate::upgrade(&mut value)?;
+        let config: AppConfig =
+            serde_yaml::from_value(value.clone()).with_context(|| "Failed to parse config file")?;
+        if from < migrate::CURRENT_VERSION {
+            // Keep the original next to the upgraded file in case of surprises
+            let backup_path = config_dir.join(format!("config.v{}.yaml", from));
+            fs::copy(&config_path, &backup_path)
+                .with_context(|| format!("Failed to write backup: {:?}", backup_path))?;
+            fs::write(&config_path, serde_yaml::to_string(&value)?)
+                .with_context(|| format!("Failed to write config file: {:?}", config_path))?;
+            eprintln!(
+                "Upgraded {:?} from version {} to {} (previous copy in {:?})",
+                config_path,
+                from,
+                migrate::CURRENT_VERSION,
+                backup_path
+            );
+        }
         Ok(config)
     } else {
         // Create default config
@@ -162,77 +1395,817 @@ fn load_app_config() -> Result<AppConfig> {
     }
 }
 
-fn load_kubeconfig(path: &PathBuf) -> Result<KubeConfig> {
-    if !path.exists() {
-        return Err(KconfError::ConfigNotFound(path.clone()).into());
-    }
-    let content = fs::read_to_string(path)
-        .with_context(|| format!("Failed to read kubeconfig: {:?}", path))?;
-    let config: KubeConfig = serde_yaml::from_str(&content)
-        .with_context(|| format!("Failed to parse kubeconfig: {:?}", path))?;
-    Ok(config)
+/// Write a kubeconfig to disk atomically, in the format the file already
+/// has (YAML for a new file)
+fn write_kubeconfig(path: &Path, config: &KubeConfig) -> Result<()> {
+    let output = KubeconfigFormat::of(path)
+        .unwrap_or(KubeconfigFormat::Yaml)
+        .serialize(config)?;
+    replace_file(&resolve_symlinks(path), &output)
 }
 
-fn create_empty_kubeconfig() -> KubeConfig {
-    KubeConfig {
-        api_version: "v1".to_string(),
-        kind: "Config".to_string(),
-        clusters: Vec::new(),
-        contexts: Vec::new(),
-        users: Vec::new(),
-        current_context: None,
-        preferences: Some(HashMap::new()),
+/// Whether `path` is the configured destination, following symlinks
+fn is_configured_destination(app_config: &AppConfig, path: &Path) -> bool {
+    let configured = resolve_symlinks(&expand_tilde(&app_config.destination));
+    let path = resolve_symlinks(path);
+    match (configured.canonicalize(), path.canonicalize()) {
+        (Ok(configured), Ok(path)) => configured == path,
+        // A destination that doesn't exist yet can only match literally
+        _ => configured == path,
     }
 }
 
-/// Remove a context and its associated cluster and user from the kubeconfig.
-/// Returns the number of items removed.
-fn remove_context(config: &mut KubeConfig, context_name: &str) -> usize {
-    let mut removed = 0;
-
-    // Find the context to get the associated cluster and user names
-    let context_info = config
-        .contexts
-        .iter()
-        .find(|c| c.name == context_name)
-        .map(|c| (c.context.cluster.clone(), c.context.user.clone()));
+/// Open `path` with `options`, readable by the owner only. A file that
+/// already exists is tightened too, since the mode only applies to new ones.
+fn open_private(options: &mut fs::OpenOptions, path: &Path) -> io::Result<fs::File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
 
-    // Remove the context
-    let before = config.contexts.len();
-    config.contexts.retain(|c| c.name != context_name);
-    removed += before - config.contexts.len();
+/// Replace the contents of `path` with `bytes`, readable by the owner only
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    open_private(
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true),
+        path,
+    )
+    .and_then(|mut file| file.write_all(bytes))
+    .with_context(|| format!("Failed to write {:?}", path))
+}
 
-    if let Some((cluster_name, user_name)) = context_info {
-        // Only remove the cluster if no other context references it
-        let cluster_still_used = config
-            .contexts
-            .iter()
-            .any(|c| c.context.cluster == cluster_name);
-        if !cluster_still_used {
-            let before = config.clusters.len();
-            config.clusters.retain(|c| c.name != cluster_name);
-            removed += before - config.clusters.len();
+/// Serialize a config for writing to `path` in the destination format,
+/// moving tokens into 1Password or Vault when configured and other
+/// credentials into the store when credentials.split is enabled. The store
+/// is only pruned of users that are gone when `path` is the destination.
+fn render_destination(app_config: &AppConfig, path: &Path, config: &KubeConfig) -> Result<String> {
+    let format = app_config
+        .format
+        .or_else(|| KubeconfigFormat::of(path))
+        .unwrap_or(KubeconfigFormat::Yaml);
+    let credentials = &app_config.credentials;
+    let vault = &credentials.vault;
+    if !credentials.split && vault.path.is_none() && credentials.onepassword.vault.is_none() {
+        return format.serialize(config);
+    }
+    let program = std::env::current_exe()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "kconf".to_string());
+    let mut public = config.clone();
+    if let Some(op_vault) = &credentials.onepassword.vault {
+        for user in onepassword::offload(&mut public, op_vault)? {
+            println!("Moved the token of user '{}' to 1Password", user);
         }
-
-        // Only remove the user if no other context references it
-        let user_still_used = config.contexts.iter().any(|c| c.context.user == user_name);
-        if !user_still_used {
-            let before = config.users.len();
-            config.users.retain(|u| u.name != user_name);
-            removed += before - config.users.len();
+    }
+    if vault.path.is_some() {
+        public = vault::offload(&public, vault, &program)?;
+    }
+    if credentials.split {
+        let store_path = credential_store_path()?;
+        let mut store = credentials::load_store(&store_path)?;
+        public = credentials::split(&public, &mut store, &program);
+        if is_configured_destination(app_config, path) {
+            credentials::prune(&mut store, config);
         }
+        credentials::save_store(&store_path, &store)?;
     }
+    format.serialize(&public)
+}
 
-    // Clear current-context if it was the removed context
-    if config.current_context.as_deref() == Some(context_name) {
-        config.current_context = None;
+/// Commit a written destination when git.auto_commit is enabled, and tell
+/// the webhook about it
+fn record_history(app_config: &AppConfig, dest_path: &Path, operation: &str) {
+    logfile::record(&format!("wrote {:?}: {}", dest_path, operation));
+    // The write already succeeded, so failures here are only warnings
+    if app_config.git.auto_commit {
+        if let Err(e) = history::record(dest_path, &format!("kconf: {}", operation)) {
+            eprintln!("Warning: git auto-commit failed: {:#}", e);
+        }
     }
+    if let Err(e) = webhook::notify(&app_config.webhook, operation, dest_path) {
+        eprintln!("Warning: webhook notification failed: {:#}", e);
+    }
+}
 
-    removed
+/// The file a destination path refers to. Symlinks, as left by dotfile
+/// managers like stow or chezmoi, are followed so writes go through to the
+/// target and the link itself stays in place.
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    // Bounded so a symlink loop can't hang a write
+    for _ in 0..40 {
+        match fs::read_link(&path) {
+            Ok(target) => {
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    path
 }
 
-/// Result of checking for duplicates - contains lists of what can be merged
-struct MergeResult {
+/// Why a destination can't be written, if it can't: the file (or, for a new
+/// file, the directory it would go in) is read-only, or opening it for
+/// writing fails. Checked before any source is read, so a merge doesn't run
+/// to the end only to fail on the write.
+fn check_writable(dest_path: &Path) -> Result<(), KconfError> {
+    let target = resolve_symlinks(dest_path);
+    let unwritable = |reason: String| KconfError::ReadOnlyDestination {
+        path: dest_path.to_path_buf(),
+        reason,
+    };
+    if let Ok(metadata) = fs::metadata(&target) {
+        if metadata.permissions().readonly() {
+            return Err(unwritable("the file is read-only".to_string()));
+        }
+        if let Err(e) = fs::OpenOptions::new().append(true).open(&target) {
+            return Err(unwritable(e.to_string()));
+        }
+    }
+    // The lock and staging files go next to the destination, so the nearest
+    // existing directory has to be writable too
+    if let Some(dir) = target.ancestors().skip(1).find(|dir| dir.is_dir()) {
+        if fs::metadata(dir).is_ok_and(|m| m.permissions().readonly()) {
+            return Err(unwritable(format!("the directory {:?} is read-only", dir)));
+        }
+    }
+    Ok(())
+}
+
+/// Write the destination config and, when enabled, commit it to git.
+/// `operation` describes what changed and becomes the commit message.
+fn save_destination(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    config: &KubeConfig,
+    operation: &str,
+) -> Result<()> {
+    let target = resolve_symlinks(dest_path);
+    // Rendering updates the credential store, so it happens under the lock
+    let _lock = lock::acquire(&target)?;
+    let output = render_destination(app_config, dest_path, config)?;
+    replace_file(&target, &output)?;
+    record_history(app_config, dest_path, operation);
+    Ok(())
+}
+
+/// The staging file a write to `path` goes through before it's renamed into
+/// place
+fn staging_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path.file_name().context("Destination has no file name")?;
+    Ok(path.with_file_name(format!(".{}.kconf-tmp", file_name.to_string_lossy())))
+}
+
+/// Replace `path` with `output` by staging it next to the file and renaming
+/// it into place, so a crash mid-write never leaves a truncated file.
+/// `path` should already have its symlinks resolved.
+fn replace_file(path: &Path, output: &str) -> Result<()> {
+    let temp = staging_path(path)?;
+    write_staged(&temp, path, output)?;
+    fs::rename(&temp, path).map_err(|source| {
+        let _ = fs::remove_file(&temp);
+        KconfError::Write {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    Ok(())
+}
+
+/// Write `output` to the staging file `temp` with the permissions of the
+/// `path` it will replace, or readable by the owner only when `path` is
+/// new, so the rename doesn't widen access to the credentials
+fn write_staged(temp: &Path, path: &Path, output: &str) -> Result<()> {
+    let permissions = fs::metadata(path).ok().map(|m| m.permissions());
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(temp)
+        .and_then(|mut file| file.write_all(output.as_bytes()))
+        .with_context(|| format!("Failed to write {:?}", temp))?;
+    // A stale staging file keeps its old mode through `open`
+    #[cfg(unix)]
+    let permissions = permissions.or_else(|| {
+        use std::os::unix::fs::PermissionsExt;
+        Some(fs::Permissions::from_mode(0o600))
+    });
+    if let Some(permissions) = permissions {
+        fs::set_permissions(temp, permissions)
+            .with_context(|| format!("Failed to set permissions of {:?}", temp))?;
+    }
+    Ok(())
+}
+
+/// Write several destinations so that either all of them are updated or none
+/// are. Every file is staged next to its target first, then renamed into
+/// place; if a rename fails, the files already replaced are restored.
+/// A symlinked destination is staged and renamed next to its target, so the
+/// link survives.
+fn save_destinations(
+    app_config: &AppConfig,
+    writes: &[(&Path, &KubeConfig)],
+    operation: &str,
+) -> Result<()> {
+    let targets: Vec<PathBuf> = writes
+        .iter()
+        .map(|(path, _)| resolve_symlinks(path))
+        .collect();
+    let _locks = targets
+        .iter()
+        .map(|path| lock::acquire(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut staged: Vec<(PathBuf, &Path, Option<Vec<u8>>)> = Vec::new();
+    let discard = |staged: &[(PathBuf, &Path, Option<Vec<u8>>)]| {
+        for (temp, _, _) in staged {
+            let _ = fs::remove_file(temp);
+        }
+    };
+    for (path, (_, config)) in targets.iter().zip(writes) {
+        let path = path.as_path();
+        let temp = staging_path(path)?;
+        let result = render_destination(app_config, path, config).and_then(|output| {
+            let original = if path.exists() {
+                Some(fs::read(path).with_context(|| format!("Failed to read {:?}", path))?)
+            } else {
+                None
+            };
+            write_staged(&temp, path, &output)?;
+            Ok(original)
+        });
+        match result {
+            Ok(original) => staged.push((temp, path, original)),
+            Err(e) => {
+                discard(&staged);
+                return Err(e.context("No destination was written"));
+            }
+        }
+    }
+
+    for i in 0..staged.len() {
+        let (temp, path, _) = &staged[i];
+        if let Err(e) = fs::rename(temp, path) {
+            discard(&staged[i..]);
+            for (_, done, original) in &staged[..i] {
+                let restored = match original {
+                    Some(content) => fs::write(done, content),
+                    None => fs::remove_file(done),
+                };
+                if let Err(e) = restored {
+                    eprintln!("Warning: failed to restore {:?}: {}", done, e);
+                }
+            }
+            return Err(anyhow::Error::from(e).context(format!(
+                "Failed to write {:?}; no destination was changed",
+                path
+            )));
+        }
+    }
+
+    for (path, _) in writes {
+        if is_configured_destination(app_config, path) {
+            record_history(app_config, path, operation);
+        }
+    }
+    Ok(())
+}
+
+/// Directory holding local backups (backup.dir, or ~/.k8sconf/backups)
+fn backup_dir(app_config: &AppConfig) -> Result<PathBuf> {
+    match &app_config.backup.dir {
+        Some(dir) => Ok(expand_tilde(dir)),
+        None => Ok(get_app_config_dir()?.join("backups")),
+    }
+}
+
+/// Copy the destination config into the backup directory with a timestamped
+/// name, applying backup.compress and backup.keep. Any other file is backed
+/// up under `targets/` instead. Returns the backup path,
+/// or None if the destination doesn't exist yet.
+fn backup_destination(app_config: &AppConfig, dest_path: &Path) -> Result<Option<PathBuf>> {
+    if !dest_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read(dest_path).with_context(|| format!("Failed to read {:?}", dest_path))?;
+    let mut dir = backup_dir(app_config)?;
+    // Other files (`--into` targets) get a directory of their own, so backup
+    // restore and recover never mistake them for the destination
+    if !is_configured_destination(app_config, dest_path) {
+        let target = resolve_symlinks(dest_path);
+        let target = target.canonicalize().unwrap_or(target);
+        let name: String = target
+            .display()
+            .to_string()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        dir = dir.join("targets").join(name);
+    }
+    let path = backup::write_local(&app_config.backup, &dir, &content)?;
+    Ok(Some(path))
+}
+
+/// Back up the destination before a command rewrites it, when
+/// defaults.backup is set and `--no-backup` wasn't passed. Every command that
+/// edits the destination shares this; only merge's `--backup` and restores,
+/// which keep the file they replace, back up regardless.
+fn backup_before_write(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    no_backup: bool,
+) -> Result<Option<PathBuf>> {
+    if !app_config.defaults.backup || no_backup {
+        return Ok(None);
+    }
+    backup_destination(app_config, dest_path)
+}
+
+/// Best-effort name of this machine, used in sync commit messages
+fn machine_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown host".to_string())
+}
+
+/// Fuzzy-pick a context, pinned and recently used ones first
+fn select_context(config: &KubeConfig, state: &state::AppState) -> Result<String> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("Choosing a context needs an interactive terminal; pass its name instead");
+    }
+    let names: Vec<&str> = state
+        .by_recency(&config.contexts)
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect();
+    if names.is_empty() {
+        anyhow::bail!("The destination config has no contexts");
+    }
+    let chosen = dialoguer::FuzzySelect::new()
+        .with_prompt("Context")
+        .items(&names)
+        .default(0)
+        .interact()
+        .context("Failed to read selection")?;
+    Ok(names[chosen].to_string())
+}
+
+/// Make `context` current, record the switch for `kconf last` and the
+/// selector's ordering, and save the destination
+fn switch_context(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    config: &mut KubeConfig,
+    state_path: &Path,
+    state: &mut state::AppState,
+    context: &str,
+) -> Result<()> {
+    if !config.contexts.iter().any(|c| c.name == context) {
+        return Err(KconfError::ContextNotFound(context.to_string()).into());
+    }
+    let previous = config.current_context.replace(context.to_string());
+    if previous.as_deref() != Some(context) {
+        save_destination(
+            app_config,
+            dest_path,
+            config,
+            &format!("use context '{}'", context),
+        )?;
+    }
+    fs::create_dir_all(get_app_config_dir()?)?;
+    state.record_switch(
+        previous.as_deref(),
+        context,
+        &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    );
+    state::save(state_path, state)?;
+    println!("Switched to context '{}'", context);
+    Ok(())
+}
+
+/// Let the user choose which of a source's contexts to import, all selected
+/// to start with
+fn pick_contexts(config: &KubeConfig, source: &Path) -> Result<Vec<String>> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("--pick needs an interactive terminal");
+    }
+    let names: Vec<&str> = config.contexts.iter().map(|c| c.name.as_str()).collect();
+    let chosen = dialoguer::MultiSelect::new()
+        .with_prompt(format!(
+            "Contexts to import from {} (space toggles, enter confirms)",
+            source.display()
+        ))
+        .items(&names)
+        .defaults(&vec![true; names.len()])
+        .interact()
+        .context("Failed to read selection")?;
+    Ok(chosen.into_iter().map(|i| names[i].to_string()).collect())
+}
+
+/// Ask a yes/no question on stdin, defaulting to no
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn load_kubeconfig(path: &PathBuf) -> Result<KubeConfig> {
+    if !path.exists() {
+        return Err(KconfError::ConfigNotFound(path.clone()).into());
+    }
+    let content = fs::read_to_string(path).map_err(|source| KconfError::Read {
+        path: path.clone(),
+        source,
+    })?;
+    let (config, migrated) = parse_kubeconfig_migrating(&content).map_err(|e| {
+        anyhow::Error::from(KconfError::ParseFailed {
+            path: path.clone(),
+            message: format!("{:#}", e),
+        })
+    })?;
+    warn_migrated(path, &migrated);
+    Ok(config)
+}
+
+/// Parse kubeconfig YAML, resolving anchors, aliases and `<<` merge keys so
+/// entries that share data through them come out fully expanded
+fn parse_kubeconfig(content: &str) -> Result<KubeConfig> {
+    Ok(parse_kubeconfig_migrating(content)?.0)
+}
+
+/// Load a source kubeconfig, optionally expanding `${VAR}` placeholders,
+/// with its relative file references made absolute
+fn load_source(path: &PathBuf, expand_env: bool) -> Result<KubeConfig> {
+    let mut config = if expand_env {
+        expand::load_expanded(path)?
+    } else {
+        load_kubeconfig(path)?
+    };
+    absolutize_source(&mut config, path);
+    Ok(config)
+}
+
+/// Resolve relative file references against the directory of the source
+/// file they were read from, reporting each rewrite
+fn absolutize_source(config: &mut KubeConfig, path: &Path) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let Some(base) = path.parent() else {
+        return;
+    };
+    for resolved in paths::absolutize(config, base) {
+        println!(
+            "  Resolving {} of {} '{}' to {}",
+            resolved.field, resolved.kind, resolved.name, resolved.path
+        );
+    }
+}
+
+/// Parse a kubeconfig, normalizing legacy documents to the current schema.
+/// Also returns what was migrated, for callers that warn about it.
+fn parse_kubeconfig_migrating(content: &str) -> Result<(KubeConfig, Vec<String>)> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(content)?;
+    value.apply_merge()?;
+    let migrated = legacy::migrate(&mut value);
+    Ok((serde_yaml::from_value(value)?, migrated))
+}
+
+/// Warn about legacy parts of a kubeconfig that were migrated on read
+fn warn_migrated(path: &Path, migrated: &[String]) {
+    if migrated.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Warning: {:?} uses an older kubeconfig schema; migrated to the current one:",
+        path
+    );
+    for note in migrated {
+        eprintln!("  {}", note);
+    }
+}
+
+fn credential_store_path() -> Result<PathBuf> {
+    Ok(get_app_config_dir()?.join("credentials.yaml"))
+}
+
+/// Write `context` alone, as the current context, to
+/// `<app_dir>/env/<context>.yaml` (readable by the owner only) so a shell can
+/// point KUBECONFIG at it without other contexts being reachable. Split
+/// credentials are written inline rather than through the store, which
+/// belongs to the destination.
+fn write_env_file(
+    app_config: &AppConfig,
+    config: &KubeConfig,
+    context: &str,
+    app_dir: &Path,
+) -> Result<PathBuf> {
+    if !config.contexts.iter().any(|c| c.name == context) {
+        return Err(KconfError::ContextNotFound(context.to_string()).into());
+    }
+    let mut isolated = config.clone();
+    select_contexts(&mut isolated, &[context.to_string()]);
+    isolated.current_context = Some(context.to_string());
+    if app_config.credentials.split {
+        let store = credentials::load_store(&app_dir.join("credentials.yaml"))?;
+        credentials::hydrate(&mut isolated, &store);
+    }
+    onepassword::resolve(&mut isolated)?;
+
+    let dir = app_dir.join("env");
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create directory: {:?}", dir))?;
+    let file_name: String = context
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let path = dir.join(format!("{}.yaml", file_name));
+    let content = app_config
+        .format
+        .unwrap_or(KubeconfigFormat::Yaml)
+        .serialize(&isolated)?;
+    write_private(&path, content.as_bytes())?;
+    Ok(path)
+}
+
+/// Load the destination config with credentials from the credential store
+/// filled back in, so merges always compare the real user entries
+fn load_destination(dest_path: &PathBuf) -> Result<KubeConfig> {
+    if !dest_path.exists() {
+        return Err(KconfError::ConfigNotFound(dest_path.clone()).into());
+    }
+    let content = fs::read_to_string(dest_path).map_err(|source| KconfError::Read {
+        path: dest_path.clone(),
+        source,
+    })?;
+    let mut config = match parse_kubeconfig_migrating(&content) {
+        Ok((config, migrated)) => {
+            warn_migrated(dest_path, &migrated);
+            config
+        }
+        Err(e) => {
+            list_recovery_backups();
+            let parse = KconfError::ParseFailed {
+                path: dest_path.clone(),
+                message: format!("{:#}", e),
+            };
+            return Err(anyhow::Error::from(parse)
+                .context(KconfError::CorruptDestination(dest_path.clone())));
+        }
+    };
+    let store_path = credential_store_path()?;
+    if store_path.exists() {
+        credentials::hydrate(&mut config, &credentials::load_store(&store_path)?);
+    }
+    Ok(config)
+}
+
+/// Tell the user which backups `kconf recover` could restore from
+fn list_recovery_backups() {
+    let backups = load_app_config()
+        .and_then(|app_config| backup_dir(&app_config))
+        .and_then(|dir| backup::list_local(&dir))
+        .unwrap_or_default();
+    if backups.is_empty() {
+        eprintln!("No local backups are available to recover from");
+        return;
+    }
+    eprintln!("Local backups available to recover from (newest first):");
+    for b in backups.iter().take(5) {
+        eprintln!("  {}", b.timestamp);
+    }
+    if backups.len() > 5 {
+        eprintln!("  ... and {} older", backups.len() - 5);
+    }
+}
+
+/// Replace an unparseable destination with the newest backup that parses,
+/// after backing up the broken file. Returns None if the user declined.
+fn recover_destination(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    yes: bool,
+) -> Result<Option<KubeConfig>> {
+    let dir = backup_dir(app_config)?;
+    let recovery = backup::latest_valid(&dir)?;
+    for b in &recovery.invalid {
+        println!("Skipping backup {} (not a valid kubeconfig)", b.timestamp);
+    }
+    let Some((found, config)) = recovery.found else {
+        anyhow::bail!("No valid backup found in {:?}", dir);
+    };
+    println!(
+        "Backup {} is the most recent valid one: {} context(s), {} cluster(s), {} user(s)",
+        found.timestamp,
+        config.contexts.len(),
+        config.clusters.len(),
+        config.users.len()
+    );
+    if !yes && !confirm(&format!("Replace {:?} with it?", dest_path))? {
+        println!("Aborted");
+        return Ok(None);
+    }
+    // Keep the broken file too, in case it's worth salvaging by hand
+    if let Some(backup) = backup_destination(app_config, dest_path)? {
+        println!("Corrupt destination saved to {:?}", backup);
+    }
+    save_destination(
+        app_config,
+        dest_path,
+        &config,
+        &format!("recover from backup {}", found.timestamp),
+    )?;
+    println!("Recovered destination from backup {}", found.timestamp);
+    Ok(Some(config))
+}
+
+fn create_empty_kubeconfig() -> KubeConfig {
+    KubeConfig {
+        api_version: "v1".to_string(),
+        kind: "Config".to_string(),
+        clusters: Vec::new(),
+        contexts: Vec::new(),
+        users: Vec::new(),
+        current_context: None,
+        preferences: None,
+    }
+}
+
+/// Remove a context and its associated cluster and user from the kubeconfig.
+/// Returns the number of items removed.
+fn remove_context(config: &mut KubeConfig, context_name: &str) -> usize {
+    let mut removed = 0;
+
+    // Find the context to get the associated cluster and user names
+    let context_info = config
+        .contexts
+        .iter()
+        .find(|c| c.name == context_name)
+        .map(|c| (c.context.cluster.clone(), c.context.user.clone()));
+
+    // Remove the context
+    let before = config.contexts.len();
+    config.contexts.retain(|c| c.name != context_name);
+    removed += before - config.contexts.len();
+
+    if let Some((cluster_name, user_name)) = context_info {
+        // Only remove the cluster if no other context references it
+        let cluster_still_used = config
+            .contexts
+            .iter()
+            .any(|c| c.context.cluster == cluster_name);
+        if !cluster_still_used {
+            let before = config.clusters.len();
+            config.clusters.retain(|c| c.name != cluster_name);
+            removed += before - config.clusters.len();
+        }
+
+        // Only remove the user if no other context references it
+        let user_still_used = config.contexts.iter().any(|c| c.context.user == user_name);
+        if !user_still_used {
+            let before = config.users.len();
+            config.users.retain(|u| u.name != user_name);
+            removed += before - config.users.len();
+        }
+    }
+
+    // Clear current-context if it was the removed context
+    if config.current_context.as_deref() == Some(context_name) {
+        config.current_context = None;
+    }
+
+    removed
+}
+
+/// Drop contexts whose name matches one of `patterns`, along with the clusters
+/// and users only they referenced. Returns each dropped context with the
+/// pattern it matched.
+fn exclude_contexts(config: &mut KubeConfig, patterns: &[String]) -> Vec<(String, String)> {
+    let mut excluded = Vec::new();
+    let mut dropped = Vec::new();
+    config.contexts.retain(
+        |c| match patterns.iter().find(|p| matches_pattern(p, &c.name)) {
+            Some(pattern) => {
+                excluded.push((c.name.clone(), pattern.clone()));
+                dropped.push(c.context.clone());
+                false
+            }
+            None => true,
+        },
+    );
+    for context in &dropped {
+        if !config
+            .contexts
+            .iter()
+            .any(|c| c.context.cluster == context.cluster)
+        {
+            config.clusters.retain(|c| c.name != context.cluster);
+        }
+        if !config
+            .contexts
+            .iter()
+            .any(|c| c.context.user == context.user)
+        {
+            config.users.retain(|u| u.name != context.user);
+        }
+    }
+    if let Some(current) = &config.current_context {
+        if excluded.iter().any(|(name, _)| name == current) {
+            config.current_context = None;
+        }
+    }
+    excluded
+}
+
+/// Keep only the contexts named in `names`, plus the clusters and users they
+/// reference
+fn select_contexts(config: &mut KubeConfig, names: &[String]) {
+    config.contexts.retain(|c| names.contains(&c.name));
+    let contexts = &config.contexts;
+    config
+        .clusters
+        .retain(|cl| contexts.iter().any(|c| c.context.cluster == cl.name));
+    config
+        .users
+        .retain(|u| contexts.iter().any(|c| c.context.user == u.name));
+    if let Some(current) = &config.current_context {
+        if !names.contains(current) {
+            config.current_context = None;
+        }
+    }
+}
+
+/// Source entries whose name exists in `dest` with different content, as
+/// "kind 'name'". Contexts are compared by what they point at, ignoring
+/// extensions such as provenance.
+fn conflicting_entries(dest: &KubeConfig, source: &KubeConfig) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for c in &source.clusters {
+        if dest.clusters.iter().any(|d| d.name == c.name && d != c) {
+            conflicts.push(format!("cluster '{}'", c.name));
+        }
+    }
+    for c in &source.contexts {
+        if dest.contexts.iter().any(|d| {
+            d.name == c.name
+                && (d.context.cluster != c.context.cluster
+                    || d.context.user != c.context.user
+                    || d.context.namespace != c.context.namespace)
+        }) {
+            conflicts.push(format!("context '{}'", c.name));
+        }
+    }
+    for u in &source.users {
+        if dest.users.iter().any(|d| d.name == u.name && d != u) {
+            conflicts.push(format!("user '{}'", u.name));
+        }
+    }
+    conflicts
+}
+
+/// Names of clusters that disable TLS verification
+fn insecure_clusters(config: &KubeConfig) -> Vec<&str> {
+    config
+        .clusters
+        .iter()
+        .filter(|c| c.cluster.insecure_skip_tls_verify == Some(true))
+        .map(|c| c.name.as_str())
+        .collect()
+}
+
+/// Result of checking for duplicates - contains lists of what can be merged
+struct MergeResult {
     clusters_to_add: Vec<NamedCluster>,
     contexts_to_add: Vec<NamedContext>,
     users_to_add: Vec<NamedUser>,
@@ -242,9 +2215,64 @@ struct MergeResult {
     skipped_clusters: Vec<String>,
     skipped_contexts: Vec<String>,
     skipped_users: Vec<String>,
+    unchanged_clusters: Vec<String>,
+    unchanged_contexts: Vec<String>,
+    unchanged_users: Vec<String>,
+}
+
+impl MergeResult {
+    /// Number of existing entries whose incoming version was identical
+    fn unchanged_count(&self) -> usize {
+        self.unchanged_clusters.len() + self.unchanged_contexts.len() + self.unchanged_users.len()
+    }
+}
+
+/// How entries that already exist in the destination are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UpdateMode {
+    /// Leave existing entries untouched
+    Skip,
+    /// Replace existing clusters, contexts and users
+    Replace,
+    /// Overlay only the fields present in the source onto existing entries
+    MergeFields,
+    /// Only refresh user credentials behind existing contexts
+    CredentialsOnly,
+}
+
+/// Recursively overlay `overlay` onto `base`, keeping keys the overlay doesn't set
+fn overlay_value(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => overlay_value(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Field-level merge of an incoming entry onto an existing one: fields present in
+/// `incoming` win, optional fields it omits are preserved from `existing`
+fn merge_fields<T: Serialize + serde::de::DeserializeOwned>(existing: &T, incoming: T) -> T {
+    let (Ok(mut base), Ok(overlay)) = (
+        serde_yaml::to_value(existing),
+        serde_yaml::to_value(&incoming),
+    ) else {
+        return incoming;
+    };
+    overlay_value(&mut base, overlay);
+    serde_yaml::from_value(base).unwrap_or(incoming)
 }
 
-fn filter_duplicates(dest: &KubeConfig, source: KubeConfig, update: bool) -> MergeResult {
+fn filter_duplicates(dest: &KubeConfig, source: KubeConfig, mode: UpdateMode) -> MergeResult {
+    let update = matches!(mode, UpdateMode::Replace | UpdateMode::MergeFields);
+    let field_merge = mode == UpdateMode::MergeFields;
     let mut result = MergeResult {
         clusters_to_add: Vec::new(),
         contexts_to_add: Vec::new(),
@@ -255,13 +2283,25 @@ fn filter_duplicates(dest: &KubeConfig, source: KubeConfig, update: bool) -> Mer
         skipped_clusters: Vec::new(),
         skipped_contexts: Vec::new(),
         skipped_users: Vec::new(),
+        unchanged_clusters: Vec::new(),
+        unchanged_contexts: Vec::new(),
+        unchanged_users: Vec::new(),
     };
 
     // Filter clusters
     for cluster in source.clusters {
-        if dest.clusters.iter().any(|c| c.name == cluster.name) {
+        if let Some(existing) = dest.clusters.iter().find(|c| c.name == cluster.name) {
             if update {
-                result.clusters_to_update.push(cluster);
+                let cluster = if field_merge {
+                    merge_fields(existing, cluster)
+                } else {
+                    cluster
+                };
+                if &cluster == existing {
+                    result.unchanged_clusters.push(cluster.name);
+                } else {
+                    result.clusters_to_update.push(cluster);
+                }
             } else {
                 result.skipped_clusters.push(cluster.name.clone());
             }
@@ -270,11 +2310,27 @@ fn filter_duplicates(dest: &KubeConfig, source: KubeConfig, update: bool) -> Mer
         }
     }
 
+    // Source users behind existing contexts, mapped to the destination user they refresh
+    let mut credential_targets: HashMap<String, String> = HashMap::new();
+
     // Filter contexts
     for context in source.contexts {
-        if dest.contexts.iter().any(|c| c.name == context.name) {
+        if let Some(existing) = dest.contexts.iter().find(|c| c.name == context.name) {
+            if mode == UpdateMode::CredentialsOnly {
+                credential_targets
+                    .insert(context.context.user.clone(), existing.context.user.clone());
+            }
             if update {
-                result.contexts_to_update.push(context);
+                let context = if field_merge {
+                    merge_fields(existing, context)
+                } else {
+                    context
+                };
+                if &context == existing {
+                    result.unchanged_contexts.push(context.name);
+                } else {
+                    result.contexts_to_update.push(context);
+                }
             } else {
                 result.skipped_contexts.push(context.name.clone());
             }
@@ -284,10 +2340,30 @@ fn filter_duplicates(dest: &KubeConfig, source: KubeConfig, update: bool) -> Mer
     }
 
     // Filter users
-    for user in source.users {
-        if dest.users.iter().any(|u| u.name == user.name) {
+    for mut user in source.users {
+        if let Some(target) = credential_targets.get(&user.name) {
+            if let Some(existing) = dest.users.iter().find(|u| &u.name == target) {
+                user.name = target.clone();
+                if &user == existing {
+                    result.unchanged_users.push(user.name);
+                } else {
+                    result.users_to_update.push(user);
+                }
+                continue;
+            }
+        }
+        if let Some(existing) = dest.users.iter().find(|u| u.name == user.name) {
             if update {
-                result.users_to_update.push(user);
+                let user = if field_merge {
+                    merge_fields(existing, user)
+                } else {
+                    user
+                };
+                if &user == existing {
+                    result.unchanged_users.push(user.name);
+                } else {
+                    result.users_to_update.push(user);
+                }
             } else {
                 result.skipped_users.push(user.name.clone());
             }
@@ -338,22 +2414,2312 @@ fn merge_kubeconfigs(
             *existing = updated_context;
         }
     }
-    for updated_user in merge_result.users_to_update {
-        if let Some(existing) = dest.users.iter_mut().find(|u| u.name == updated_user.name) {
-            *existing = updated_user;
+    for updated_user in merge_result.users_to_update {
+        if let Some(existing) = dest.users.iter_mut().find(|u| u.name == updated_user.name) {
+            *existing = updated_user;
+        }
+    }
+
+    // Set current-context if destination doesn't have one
+    if dest.current_context.is_none() && source_current_context.is_some() {
+        dest.current_context = source_current_context;
+    }
+
+    (added, updated, skipped)
+}
+
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Certs { action } => match action {
+            CertsCommand::Show { context } => {
+                let app_config = load_app_config()?;
+                let dest_config = load_destination(&expand_tilde(&app_config.destination))?;
+                certs::show(&dest_config, &context)
+            }
+        },
+        Command::Check {
+            contexts,
+            concurrency,
+            timeout,
+            namespaces,
+            verify_ca,
+            sort,
+            format,
+        } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            for name in &contexts {
+                if !config.contexts.iter().any(|c| &c.name == name) {
+                    return Err(KconfError::ContextNotFound(name.clone()).into());
+                }
+            }
+            let mut results = check::run(
+                &config,
+                &contexts,
+                concurrency,
+                std::time::Duration::from_secs(timeout),
+                namespaces,
+                verify_ca,
+            );
+            if let Some(sort) = sort {
+                check::sort(&mut results, sort);
+            }
+            match format {
+                OutputFormat::Text => list::print_table(&check::rows(&results)),
+                OutputFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&check::json(&results))?)
+                }
+            }
+            let failed = results.iter().filter(|r| !r.ok()).count();
+            if failed > 0 {
+                return Err(KconfError::CheckFailed(failed).into());
+            }
+            Ok(())
+        }
+        Command::Doctor { offline, timeout } => {
+            let findings = doctor::diagnose(
+                &get_app_config_dir()?,
+                (!offline).then(|| std::time::Duration::from_secs(timeout)),
+            );
+            doctor::print(&findings);
+            let failed = findings
+                .iter()
+                .filter(|f| f.status == doctor::Status::Fail)
+                .count();
+            if failed > 0 {
+                return Err(KconfError::DoctorFailed(failed).into());
+            }
+            let warnings = findings
+                .iter()
+                .filter(|f| f.status == doctor::Status::Warn)
+                .count();
+            println!("No problems found ({} warning(s))", warnings);
+            Ok(())
+        }
+        Command::Lint {
+            file,
+            format,
+            fix,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let path = file.unwrap_or_else(|| expand_tilde(&app_config.destination));
+            // Other files are linted and fixed as they are, without the
+            // destination's credential store, backups or history
+            let is_destination = is_configured_destination(&app_config, &path);
+            let mut config = if is_destination {
+                load_destination(&path)?
+            } else {
+                load_kubeconfig(&path)?
+            };
+            if fix {
+                let changes = fix::repair_references(&mut config, &app_config.protected);
+                // JSON output stays parseable, so changes are reported on stderr
+                for change in &changes {
+                    eprintln!("  {}", change);
+                }
+                if !changes.is_empty() {
+                    if is_destination {
+                        if let Some(backup) = backup_before_write(&app_config, &path, no_backup)? {
+                            eprintln!("Backup written to {:?}", backup);
+                        }
+                        save_destination(
+                            &app_config,
+                            &path,
+                            &config,
+                            &format!("lint --fix ({} change(s))", changes.len()),
+                        )?;
+                    } else {
+                        let _lock = lock::acquire(&resolve_symlinks(&path))?;
+                        write_kubeconfig(&path, &config)?;
+                    }
+                    eprintln!("Repaired {} reference problem(s)", changes.len());
+                }
+            }
+            let findings = lint::lint(&config, &app_config.lint);
+            match format {
+                OutputFormat::Text => lint::print_text(&findings),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&findings)?),
+            }
+            let errors = findings
+                .iter()
+                .filter(|f| f.severity == lint::Severity::Error)
+                .count();
+            if errors > 0 {
+                return Err(KconfError::LintFailed(errors).into());
+            }
+            Ok(())
+        }
+        Command::List { wide, group_by } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            let state = state::load(&get_app_config_dir()?.join("state.json"))?;
+            let rows = list::rows(&config, &state, wide);
+            match group_by {
+                Some(by) => list::print_grouped(&rows, &list::groups(&config, &state, by)),
+                None => list::print_table(&rows),
+            }
+            Ok(())
+        }
+        Command::Tree => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            print!("{}", tree::render(&config));
+            Ok(())
+        }
+        Command::Graph { format } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            print!("{}", graph::render(&config, format));
+            Ok(())
+        }
+        Command::Stats {
+            externalize,
+            yes,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+            let limit = app_config.lint.max_embedded_bytes;
+            println!("{}", stats::summary(&config));
+            println!();
+            list::print_table(&stats::rows(&config, limit));
+            let large = paths::embedded(&config)
+                .into_iter()
+                .filter(|e| e.bytes > limit)
+                .count();
+            if large == 0 {
+                return Ok(());
+            }
+            if !externalize {
+                println!();
+                println!(
+                    "{} embedded value(s) over {}; move them to files with `kconf stats --externalize`",
+                    large,
+                    stats::format_size(limit)
+                );
+                return Ok(());
+            }
+            if !yes && !confirm(&format!("Move {} embedded value(s) to files?", large))? {
+                println!("Aborted");
+                return Ok(());
+            }
+
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            let moved =
+                paths::externalize(&mut config, &get_app_config_dir()?.join("certs"), limit)?;
+            for file in &moved {
+                println!(
+                    "Moved {} of {} '{}' to {}",
+                    file.field, file.kind, file.name, file.path
+                );
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!("stats --externalize ({} file(s))", moved.len()),
+            )?;
+            Ok(())
+        }
+        Command::Sanitize {
+            contexts,
+            hash_hosts,
+            format,
+        } => {
+            let app_config = load_app_config()?;
+            let mut config = load_destination(&expand_tilde(&app_config.destination))?;
+            if !contexts.is_empty() {
+                for name in &contexts {
+                    if !config.contexts.iter().any(|c| &c.name == name) {
+                        return Err(KconfError::ContextNotFound(name.clone()).into());
+                    }
+                }
+                select_contexts(&mut config, &contexts);
+            }
+            print!(
+                "{}",
+                format.serialize(&sanitize::sanitize(&config, hash_hosts))?
+            );
+            Ok(())
+        }
+        Command::Anonymize { files } => {
+            let configs = if files.is_empty() {
+                let app_config = load_app_config()?;
+                vec![load_destination(&expand_tilde(&app_config.destination))?]
+            } else {
+                files
+                    .iter()
+                    .map(load_kubeconfig)
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let mut anonymizer = anonymize::Anonymizer::default();
+            for (i, config) in configs.iter().enumerate() {
+                if i > 0 {
+                    println!("---");
+                }
+                print!("{}", serde_yaml::to_string(&anonymizer.config(config))?);
+            }
+            Ok(())
+        }
+        Command::Peek { file } => {
+            let source = load_kubeconfig(&file)?;
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let dest = if dest_path.exists() {
+                Some(load_destination(&dest_path)?)
+            } else {
+                None
+            };
+            println!("File: {}", file.display());
+            println!(
+                "{} context(s), {} cluster(s), {} user(s); current context: {}",
+                source.contexts.len(),
+                source.clusters.len(),
+                source.users.len(),
+                source.current_context.as_deref().unwrap_or("(none)")
+            );
+            println!();
+            list::print_table(&peek::rows(&source, dest.as_ref()));
+            let unreferenced = peek::unreferenced(&source);
+            if !unreferenced.is_empty() {
+                println!();
+                println!("Not used by any context: {}", unreferenced.join(", "));
+            }
+            if let Some(dest) = &dest {
+                let conflicts = conflicting_entries(dest, &source);
+                if !conflicts.is_empty() {
+                    println!();
+                    println!("Differs from the destination (skipped unless --update):");
+                    for conflict in conflicts {
+                        println!("  {}", conflict);
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::Diff { a, b } => {
+            let changes = match b {
+                Some(b) => diff::diff(&load_kubeconfig(&a)?, &load_kubeconfig(&b)?, true),
+                None => {
+                    let app_config = load_app_config()?;
+                    let dest = load_destination(&expand_tilde(&app_config.destination))?;
+                    let mut source = load_kubeconfig(&a)?;
+                    // Compare as a merge would see it, with kconf's own
+                    // extensions carried over from the destination
+                    provenance::carry_over(&dest, &mut source);
+                    kubens::carry_over(&dest, &mut source);
+                    diff::diff(&dest, &source, false)
+                }
+            };
+            diff::print(&changes);
+            Ok(())
+        }
+        Command::Plan {
+            configs,
+            output,
+            update,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let fingerprint = plan::fingerprint(&dest_path)?;
+            let original = if dest_path.exists() {
+                load_destination(&dest_path)?
+            } else {
+                create_empty_kubeconfig()
+            };
+            let mut config = original.clone();
+            let options = MergeOptions {
+                mode: if update || app_config.defaults.update {
+                    UpdateMode::Replace
+                } else {
+                    UpdateMode::Skip
+                },
+                conflict: app_config.defaults.conflict_strategy,
+                allow_insecure: false,
+                enforce_policy: false,
+                bind_user: None,
+                include_desktop: false,
+                expires_at: None,
+                reuse_clusters: ReuseClusters::Never,
+                unprotect: false,
+            };
+            let mut totals = MergeTotals::default();
+            let mut sources = Vec::new();
+            for path in &configs {
+                println!("Processing: {}", path.display());
+                let source = path.canonicalize().unwrap_or_else(|_| path.clone());
+                merge_source(
+                    &app_config,
+                    &mut config,
+                    load_source(path, false)?,
+                    &source.display().to_string(),
+                    &options,
+                    &mut totals,
+                )?;
+                sources.push(source.display().to_string());
+            }
+
+            let plan = plan::Plan {
+                version: plan::PLAN_VERSION,
+                created_at: chrono::Local::now().to_rfc3339(),
+                destination: dest_path.canonicalize().unwrap_or(dest_path),
+                destination_sha256: fingerprint,
+                sources,
+                changes: plan::changes(&original, &config),
+                current_context: if config.current_context != original.current_context {
+                    config.current_context.clone()
+                } else {
+                    None
+                },
+            };
+            println!();
+            diff::print(&diff::diff(&original, &config, true));
+            plan::save(&output, &plan)?;
+            println!();
+            println!(
+                "Plan with {} change(s) written to {:?}; apply it with `kconf apply {}`",
+                plan.changes.len() + usize::from(plan.current_context.is_some()),
+                output,
+                output.display()
+            );
+            Ok(())
+        }
+        Command::Apply {
+            plan: plan_path,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let plan = plan::load(&plan_path)?;
+            if plan::fingerprint(&plan.destination)? != plan.destination_sha256 {
+                return Err(KconfError::StalePlan.into());
+            }
+            if plan.is_empty() {
+                println!("Plan has no changes; destination left untouched");
+                return Ok(());
+            }
+            let dest_path = &plan.destination;
+            let mut config = if dest_path.exists() {
+                load_destination(dest_path)?
+            } else {
+                if let Some(parent) = dest_path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+                }
+                create_empty_kubeconfig()
+            };
+            plan::apply(&mut config, &plan);
+            if let Some(backup) = backup_before_write(&app_config, dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            save_destination(
+                &app_config,
+                dest_path,
+                &config,
+                &format!("apply plan {}", plan_path.display()),
+            )?;
+            for change in &plan.changes {
+                let verb = match change.action {
+                    plan::Action::Add => "Added",
+                    plan::Action::Update => "Updated",
+                };
+                println!("{} {} '{}'", verb, change.entry.kind(), change.entry.name());
+            }
+            if let Some(current) = &plan.current_context {
+                println!("Switched current context to '{}'", current);
+            }
+            println!("Applied plan to {:?}", dest_path);
+            Ok(())
+        }
+        #[cfg(not(feature = "remote"))]
+        Command::Auth { .. } | Command::Token { .. } => {
+            Err(KconfError::FeatureDisabled("remote").into())
+        }
+        #[cfg(feature = "remote")]
+        Command::Token { context, header } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            let context = context
+                .or_else(|| config.current_context.clone())
+                .context("No context given and no current context set")?;
+            if !config.contexts.iter().any(|c| c.name == context) {
+                return Err(KconfError::ContextNotFound(context).into());
+            }
+            // Only this context's 1Password references are read
+            let mut isolated = config.clone();
+            select_contexts(&mut isolated, std::slice::from_ref(&context));
+            onepassword::resolve(&mut isolated)?;
+            let (_, credentials) = auth::context_credentials(&isolated, &context)?;
+            let authorization = credentials.authorization().with_context(|| {
+                format!(
+                    "Context '{}' has no token or basic-auth credentials (it may use a client certificate)",
+                    context
+                )
+            })?;
+            if header {
+                println!("Authorization: {}", authorization);
+            } else {
+                match (credentials.bearer, credentials.basic) {
+                    (Some(token), _) => println!("{}", token),
+                    (None, Some((username, password))) => {
+                        println!("{}:{}", username, password)
+                    }
+                    (None, None) => {}
+                }
+            }
+            Ok(())
+        }
+        #[cfg(feature = "remote")]
+        Command::Auth {
+            action: AuthCommand::Test { context, timeout },
+        } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            let context = context
+                .or_else(|| config.current_context.clone())
+                .context("No context given and no current context set")?;
+            let report = auth::test(&config, &context, std::time::Duration::from_secs(timeout))?;
+            println!("Context:     {}", report.context);
+            println!("Server:      {}", report.server);
+            println!("Credentials: {}", report.credentials_source);
+            match report.identity {
+                Some(identity) => {
+                    println!("User:        {}", identity.username);
+                    if let Some(uid) = identity.uid {
+                        println!("UID:         {}", uid);
+                    }
+                    if !identity.groups.is_empty() {
+                        println!("Groups:      {}", identity.groups.join(", "));
+                    }
+                }
+                None => println!(
+                    "User:        (unknown: the cluster doesn't support SelfSubjectReview)"
+                ),
+            }
+            println!("Authentication succeeded");
+            Ok(())
+        }
+        Command::Secret { action } => match action {
+            SecretCommand::Set { name } => {
+                let secret = dialoguer::Password::new()
+                    .with_prompt(format!("Value for '{}'", name))
+                    .interact()
+                    .context("Failed to read secret")?;
+                secrets::set(&name, &secret)?;
+                println!("Stored '{}' in the OS keyring", name);
+                Ok(())
+            }
+            SecretCommand::Delete { name } => {
+                secrets::delete(&name)?;
+                println!("Deleted '{}' from the OS keyring", name);
+                Ok(())
+            }
+        },
+        Command::ApplyOps {
+            file,
+            dry_run,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let ops = ops::load(&file)?;
+            let dest_existed = dest_path.exists();
+            let original = if dest_existed {
+                load_destination(&dest_path)?
+            } else {
+                create_empty_kubeconfig()
+            };
+            let config = apply_operations(&app_config, original.clone(), &ops.operations)?;
+
+            let changes = diff::diff(&original, &config, true);
+            diff::print(&changes);
+            if dry_run {
+                println!("Dry run: destination not written");
+                return Ok(());
+            }
+            if !needs_write(dest_existed, &original, &config) {
+                return Ok(());
+            }
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!("apply operations from {}", file.display()),
+            )?;
+            println!(
+                "Applied {} operation(s) to {:?}",
+                ops.operations.len(),
+                dest_path
+            );
+            Ok(())
+        }
+        Command::Servers { duplicates } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            let servers = if duplicates {
+                server::duplicates(&config)
+            } else {
+                server::by_server(&config)
+            };
+            if servers.is_empty() {
+                println!(
+                    "{}",
+                    if duplicates {
+                        "No clusters share an API server"
+                    } else {
+                        "No clusters"
+                    }
+                );
+                return Ok(());
+            }
+            let mut rows = vec![vec!["SERVER".to_string(), "CLUSTERS".to_string()]];
+            for (url, names) in servers {
+                rows.push(vec![url, names.join(", ")]);
+            }
+            list::print_table(&rows);
+            Ok(())
+        }
+        Command::Which { context } => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            let entry = config
+                .contexts
+                .iter()
+                .find(|c| c.name == context)
+                .ok_or_else(|| KconfError::ContextNotFound(context.clone()))?;
+            match provenance::get(&entry.context) {
+                Some(p) => {
+                    println!("Source:      {}", p.source);
+                    println!("Imported at: {}", p.imported_at);
+                    println!("Imported by: kconf {}", p.kconf_version);
+                    if let Some(expires_at) = &p.expires_at {
+                        println!("Expires at:  {}", expires_at);
+                    }
+                }
+                None => println!("No provenance recorded for context '{}'", context),
+            }
+            Ok(())
+        }
+        Command::Note {
+            context,
+            text,
+            clear,
+        } => {
+            let state_path = get_app_config_dir()?.join("state.json");
+            let mut state = state::load(&state_path)?;
+            if clear {
+                if state.notes.remove(&context).is_none() {
+                    println!("Context '{}' has no note", context);
+                    return Ok(());
+                }
+                state::save(&state_path, &state)?;
+                println!("Removed note from '{}'", context);
+                return Ok(());
+            }
+            let Some(text) = text else {
+                match state.notes.get(&context) {
+                    Some(note) => println!("{}", note),
+                    None => println!("Context '{}' has no note", context),
+                }
+                return Ok(());
+            };
+
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            if !config.contexts.iter().any(|c| c.name == context) {
+                return Err(KconfError::ContextNotFound(context).into());
+            }
+            fs::create_dir_all(get_app_config_dir()?)?;
+            state.notes.insert(context.clone(), text);
+            state::save(&state_path, &state)?;
+            println!("Saved note for '{}'", context);
+            Ok(())
+        }
+        Command::Tag {
+            context,
+            tags,
+            remove,
+        } => {
+            let state_path = get_app_config_dir()?.join("state.json");
+            let mut state = state::load(&state_path)?;
+            if tags.is_empty() {
+                match state.tags.get(&context) {
+                    Some(tags) => println!("{}", tags.join(", ")),
+                    None => println!("Context '{}' has no tags", context),
+                }
+                return Ok(());
+            }
+            if remove {
+                if let Some(existing) = state.tags.get_mut(&context) {
+                    existing.retain(|t| !tags.contains(t));
+                    if existing.is_empty() {
+                        state.tags.remove(&context);
+                    }
+                }
+            } else {
+                let app_config = load_app_config()?;
+                let config = load_destination(&expand_tilde(&app_config.destination))?;
+                if !config.contexts.iter().any(|c| c.name == context) {
+                    return Err(KconfError::ContextNotFound(context).into());
+                }
+                let existing = state.tags.entry(context.clone()).or_default();
+                for tag in tags {
+                    if !existing.contains(&tag) {
+                        existing.push(tag);
+                    }
+                }
+            }
+            fs::create_dir_all(get_app_config_dir()?)?;
+            state::save(&state_path, &state)?;
+            match state.tags.get(&context) {
+                Some(tags) => println!("Tags for '{}': {}", context, tags.join(", ")),
+                None => println!("Context '{}' has no tags", context),
+            }
+            Ok(())
+        }
+        Command::Current { short, porcelain } => {
+            let current = load_app_config()
+                .and_then(|app_config| current::read(&expand_tilde(&app_config.destination)));
+            let current = match current {
+                Ok(current) => current,
+                Err(_) if porcelain => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            match current {
+                Some(current) if short => println!("{}", current.short()),
+                Some(current) if porcelain => println!("{}", current.porcelain()),
+                Some(current) => {
+                    println!("Context:   {}", current.context);
+                    println!("Namespace: {}", current.namespace);
+                }
+                None if porcelain => {}
+                None => println!("No current context set"),
+            }
+            Ok(())
+        }
+        Command::Completions {
+            shell,
+            list_contexts,
+        } => {
+            if list_contexts {
+                // Completion must never fail loudly; print nothing instead
+                let config = load_app_config().and_then(|app_config| {
+                    load_destination(&expand_tilde(&app_config.destination))
+                });
+                if let Ok(config) = config {
+                    for context in &config.contexts {
+                        println!("{}", context.name);
+                    }
+                }
+            } else if let Some(shell) = shell {
+                print!("{}", completions::script(shell, Args::command()));
+            }
+            Ok(())
+        }
+        Command::ShellInit { shell } => {
+            print!("{}", shell::init(shell));
+            Ok(())
+        }
+        Command::With { configs, command } => {
+            let mut config = with::merge(&configs)?;
+            onepassword::resolve(&mut config)?;
+            let code = with::run(&config, &command)?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+            Ok(())
+        }
+        Command::Env { context, shell } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let config = load_destination(&dest_path)?;
+            let path = write_env_file(&app_config, &config, &context, &get_app_config_dir()?)?;
+            println!(
+                "{}",
+                shell::export(shell, "KUBECONFIG", &path.display().to_string())
+            );
+            Ok(())
+        }
+        Command::Use { context } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+            let state_path = get_app_config_dir()?.join("state.json");
+            let mut state = state::load(&state_path)?;
+            let context = match context {
+                Some(context) => context,
+                None => select_context(&config, &state)?,
+            };
+            switch_context(
+                &app_config,
+                &dest_path,
+                &mut config,
+                &state_path,
+                &mut state,
+                &context,
+            )
+        }
+        Command::Last => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+            let state_path = get_app_config_dir()?.join("state.json");
+            let mut state = state::load(&state_path)?;
+            let previous = state
+                .previous
+                .clone()
+                .context("No previous context: switch with `kconf use` first")?;
+            switch_context(
+                &app_config,
+                &dest_path,
+                &mut config,
+                &state_path,
+                &mut state,
+                &previous,
+            )
+        }
+        Command::Pin { context, position } => {
+            let state_path = get_app_config_dir()?.join("state.json");
+            let mut state = state::load(&state_path)?;
+            let Some(context) = context else {
+                if state.pinned.is_empty() {
+                    println!("No pinned contexts");
+                }
+                for (i, name) in state.pinned.iter().enumerate() {
+                    println!("{}. {}", i + 1, name);
+                }
+                return Ok(());
+            };
+
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            if !config.contexts.iter().any(|c| c.name == context) {
+                return Err(KconfError::ContextNotFound(context).into());
+            }
+            fs::create_dir_all(get_app_config_dir()?)?;
+            state.pin(&context, position.map(|p| p as usize - 1));
+            state::save(&state_path, &state)?;
+            println!("Pinned '{}'", context);
+            Ok(())
+        }
+        Command::Unpin { context } => {
+            let state_path = get_app_config_dir()?.join("state.json");
+            let mut state = state::load(&state_path)?;
+            if !state.unpin(&context) {
+                println!("Context '{}' isn't pinned", context);
+                return Ok(());
+            }
+            state::save(&state_path, &state)?;
+            println!("Unpinned '{}'", context);
+            Ok(())
+        }
+        Command::Recover { yes } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            if dest_path.exists() {
+                let content = fs::read_to_string(&dest_path)
+                    .with_context(|| format!("Failed to read {:?}", dest_path))?;
+                if parse_kubeconfig(&content).is_ok() {
+                    println!("Destination {:?} is valid; nothing to recover", dest_path);
+                    return Ok(());
+                }
+            }
+            recover_destination(&app_config, &dest_path, yes)?;
+            Ok(())
+        }
+        Command::Ns {
+            namespace,
+            list,
+            timeout,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+            let current = config
+                .current_context
+                .clone()
+                .context("No current context set")?;
+            let context = config
+                .contexts
+                .iter_mut()
+                .find(|c| c.name == current)
+                .ok_or_else(|| KconfError::ContextNotFound(current.clone()))?;
+            let active = kubens::namespace(&context.context).unwrap_or_else(|| "default".into());
+
+            if list {
+                #[cfg(not(feature = "remote"))]
+                {
+                    let _ = timeout;
+                    return Err(KconfError::FeatureDisabled("remote").into());
+                }
+                #[cfg(feature = "remote")]
+                {
+                    let client = auth::ApiClient::connect(
+                        &config,
+                        &current,
+                        std::time::Duration::from_secs(timeout),
+                    )?;
+                    for name in client.namespaces()? {
+                        let marker = if name == active { "*" } else { " " };
+                        println!("{} {}", marker, name);
+                    }
+                    return Ok(());
+                }
+            }
+            let Some(namespace) = namespace else {
+                println!("{}", active);
+                return Ok(());
+            };
+            if namespace == active {
+                println!(
+                    "Context '{}' already uses namespace '{}'",
+                    current, namespace
+                );
+                return Ok(());
+            }
+
+            kubens::set_namespace(&mut context.context, Some(&namespace));
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!("switch '{}' to namespace '{}'", current, namespace),
+            )?;
+            println!(
+                "Switched context '{}' to namespace '{}'",
+                current, namespace
+            );
+            Ok(())
+        }
+        Command::SetNamespace {
+            pattern,
+            namespace,
+            dry_run,
+            yes,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+
+            let changes = kubens::set_matching(&mut config, &pattern, &namespace);
+            if changes.is_empty() {
+                println!(
+                    "No contexts matching '{}' need namespace '{}'",
+                    pattern, namespace
+                );
+                return Ok(());
+            }
+            for change in &changes {
+                println!(
+                    "  {}: {} -> {}",
+                    change.context,
+                    change.old.as_deref().unwrap_or("(none)"),
+                    namespace
+                );
+            }
+            if dry_run {
+                println!("Dry run: {} context(s) not changed", changes.len());
+                return Ok(());
+            }
+            if !yes
+                && !confirm(&format!(
+                    "Set namespace '{}' on {} context(s)?",
+                    namespace,
+                    changes.len()
+                ))?
+            {
+                println!("Aborted");
+                return Ok(());
+            }
+
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!(
+                    "set namespace '{}' on {} context(s) matching '{}'",
+                    namespace,
+                    changes.len(),
+                    pattern
+                ),
+            )?;
+            println!(
+                "Set namespace '{}' on {} context(s)",
+                namespace,
+                changes.len()
+            );
+            Ok(())
+        }
+        Command::Outdated => {
+            let app_config = load_app_config()?;
+            let config = load_destination(&expand_tilde(&app_config.destination))?;
+            let reports = outdated::check(&config, &app_config.sources, &app_config.fetch);
+            if reports.is_empty() {
+                println!("No contexts with recorded provenance");
+            }
+            for report in &reports {
+                println!("{}: {} ({})", report.context, report.status, report.source);
+            }
+            let stale = reports
+                .iter()
+                .filter(|r| {
+                    !matches!(
+                        r.status,
+                        outdated::Status::UpToDate | outdated::Status::NotComparable(_)
+                    )
+                })
+                .count();
+            if stale > 0 {
+                println!(
+                    "{} of {} context(s) differ from their source",
+                    stale,
+                    reports.len()
+                );
+            }
+            Ok(())
+        }
+        Command::Refresh { check } => {
+            let app_config = load_app_config()?;
+            if app_config.sources.is_empty() {
+                println!(
+                    "No sources registered (add them under sources: in ~/.k8sconf/config.yaml)"
+                );
+                return Ok(());
+            }
+            let report = refresh_sources(&app_config, !check)?;
+            if check {
+                if !report.failed.is_empty() {
+                    anyhow::bail!(
+                        "Failed to fetch {} source(s), can't verify the destination: {}",
+                        report.failed.len(),
+                        report.failed.join(", ")
+                    );
+                }
+                if report.drift.is_empty() {
+                    println!("Destination is up to date with its registered sources");
+                    return Ok(());
+                }
+                println!();
+                diff::print(&report.drift);
+                return Err(KconfError::Drift(report.drift.len()).into());
+            }
+            println!(
+                "Done: {} item(s) added, {} item(s) updated, {} item(s) unchanged, {} item(s) skipped",
+                report.totals.added,
+                report.totals.updated,
+                report.totals.unchanged,
+                report.totals.skipped
+            );
+            if !report.failed.is_empty() {
+                anyhow::bail!(
+                    "Failed to refresh {} source(s): {}",
+                    report.failed.len(),
+                    report.failed.join(", ")
+                );
+            }
+            Ok(())
+        }
+        Command::Daemon { action } => match action {
+            None => run_daemon(),
+            Some(DaemonCommand::Status) => {
+                let state = daemon::load_state(&get_app_config_dir()?.join("daemon.json"))?;
+                let Some(state) = state else {
+                    println!("Daemon has never run");
+                    return Ok(());
+                };
+                if daemon::is_running(state.pid) {
+                    println!("Running (pid {}) since {}", state.pid, state.started_at);
+                } else {
+                    println!("Not running (last pid {})", state.pid);
+                }
+                println!(
+                    "Last run:    {}",
+                    state.last_run.as_deref().unwrap_or("never")
+                );
+                if let Some(result) = &state.last_result {
+                    println!("Last result: {}", result);
+                }
+                if let Some(next) = &state.next_run {
+                    println!("Next run:    {}", next);
+                }
+                Ok(())
+            }
+        },
+        Command::Backup { action } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let remote = || {
+                app_config
+                    .backup
+                    .remote
+                    .as_ref()
+                    .ok_or(KconfError::NoRemoteBackup)
+            };
+            match action {
+                BackupCommand::Key => {
+                    println!("{}", backup::export_key()?);
+                }
+                BackupCommand::List => {
+                    let dir = backup_dir(&app_config)?;
+                    let backups = backup::list_local(&dir)?;
+                    if backups.is_empty() {
+                        println!("No backups in {:?}", dir);
+                    }
+                    for b in &backups {
+                        println!(
+                            "{}   {:>8} bytes   {}",
+                            b.timestamp,
+                            b.size,
+                            b.path.display()
+                        );
+                    }
+                }
+                BackupCommand::Restore { timestamp, yes } => {
+                    let found = backup::find_local(&backup_dir(&app_config)?, &timestamp)?;
+                    let content = backup::read_local(&found)?;
+                    let config = std::str::from_utf8(&content)
+                        .map_err(anyhow::Error::from)
+                        .and_then(parse_kubeconfig)
+                        .with_context(|| format!("{:?} is not a valid kubeconfig", found.path))?;
+                    println!(
+                        "Backup {} contains {} context(s), {} cluster(s), {} user(s)",
+                        found.timestamp,
+                        config.contexts.len(),
+                        config.clusters.len(),
+                        config.users.len()
+                    );
+                    if !yes && !confirm(&format!("Replace {:?} with it?", dest_path))? {
+                        println!("Aborted");
+                        return Ok(());
+                    }
+                    if let Some(backup) = backup_destination(&app_config, &dest_path)? {
+                        println!("Backup written to {:?}", backup);
+                    }
+                    if let Some(parent) = dest_path.parent() {
+                        fs::create_dir_all(parent)
+                            .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+                    }
+                    save_destination(
+                        &app_config,
+                        &dest_path,
+                        &config,
+                        &format!("restore backup {}", found.timestamp),
+                    )?;
+                    println!("Restored destination from backup {}", found.timestamp);
+                }
+                BackupCommand::Push => {
+                    let remote = remote()?;
+                    // Back up the full config, including credentials kept in the store
+                    let content = serde_yaml::to_string(&load_destination(&dest_path)?)?;
+                    let passphrase = backup::passphrase(true)?;
+                    let encrypted = backup::encrypt(content.as_bytes(), &passphrase)?;
+                    backup::push(remote, &encrypted)?;
+                    println!("Uploaded encrypted backup ({} bytes)", encrypted.len());
+                }
+                BackupCommand::Pull { yes } => {
+                    let encrypted = backup::pull(remote()?)?;
+                    let passphrase = backup::passphrase(false)?;
+                    let content = backup::decrypt(&encrypted, &passphrase)?;
+                    let config = std::str::from_utf8(&content)
+                        .map_err(anyhow::Error::from)
+                        .and_then(parse_kubeconfig)
+                        .context("Remote backup is not a valid kubeconfig")?;
+                    println!(
+                        "Remote backup contains {} context(s), {} cluster(s), {} user(s)",
+                        config.contexts.len(),
+                        config.clusters.len(),
+                        config.users.len()
+                    );
+                    if !yes && !confirm(&format!("Replace {:?} with it?", dest_path))? {
+                        println!("Aborted");
+                        return Ok(());
+                    }
+                    if let Some(backup) = backup_destination(&app_config, &dest_path)? {
+                        println!("Backup written to {:?}", backup);
+                    }
+                    if let Some(parent) = dest_path.parent() {
+                        fs::create_dir_all(parent)
+                            .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+                    }
+                    save_destination(&app_config, &dest_path, &config, "restore remote backup")?;
+                    println!("Restored destination from remote backup");
+                }
+            }
+            Ok(())
+        }
+        Command::Sync {
+            manifest: Some(manifest_path),
+            prune,
+            dry_run,
+            no_backup,
+            ..
+        } => {
+            let app_config = load_app_config()?;
+            let manifest = manifest::load(&manifest_path)?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let dest_existed = dest_path.exists();
+            let original = if dest_existed {
+                load_destination(&dest_path)?
+            } else {
+                create_empty_kubeconfig()
+            };
+            let config = sync_manifest(&app_config, original.clone(), &manifest, prune)?;
+
+            let changes = diff::diff(&original, &config, true);
+            diff::print(&changes);
+            if dry_run {
+                println!("Dry run: destination not written");
+                return Ok(());
+            }
+            if !needs_write(dest_existed, &original, &config) {
+                return Ok(());
+            }
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!("sync with manifest {}", manifest_path.display()),
+            )?;
+            println!("Destination matches {}", manifest_path.display());
+            Ok(())
+        }
+        Command::Sync {
+            manifest: None,
+            update,
+            no_backup,
+            ..
+        } => {
+            let app_config = load_app_config()?;
+            let settings = app_config.sync.as_ref().ok_or(KconfError::NoSyncRemote)?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = if dest_path.exists() {
+                load_destination(&dest_path)?
+            } else {
+                create_empty_kubeconfig()
+            };
+            let original = config.clone();
+
+            let workdir = get_app_config_dir()?.join("sync");
+            let mode = if update || app_config.defaults.update {
+                UpdateMode::Replace
+            } else {
+                UpdateMode::Skip
+            };
+            let report = sync::sync(
+                &mut config,
+                settings,
+                &workdir,
+                &machine_name(),
+                mode,
+                &app_config.protected,
+            )?;
+            println!(
+                "Pulled {} item(s) and updated {} from {}",
+                report.pulled, report.updated, settings.remote
+            );
+            for name in &report.removed {
+                println!("Removed context '{}' (removed from the remote)", name);
+            }
+            for user in &report.without_credentials {
+                println!(
+                    "Warning: user '{}' has no credentials (the remote is sanitized); add them locally",
+                    user
+                );
+            }
+            if report.pushed {
+                println!("Pushed local changes to {}", settings.remote);
+            } else {
+                println!("Remote already up to date");
+            }
+
+            if needs_write(dest_path.exists(), &original, &config) {
+                if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                    println!("Backup written to {:?}", backup);
+                }
+                if let Some(parent) = dest_path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+                }
+                save_destination(
+                    &app_config,
+                    &dest_path,
+                    &config,
+                    &format!("sync with {}", settings.remote),
+                )?;
+            }
+            Ok(())
+        }
+        Command::Credential { action } => match action {
+            CredentialCommand::Print { user } => {
+                let store = credentials::load_store(&credential_store_path()?)?;
+                let stored = store
+                    .users
+                    .into_iter()
+                    .find(|u| u.name == user)
+                    .ok_or(KconfError::CredentialNotFound(user))?;
+                let mut resolved = create_empty_kubeconfig();
+                resolved.users.push(stored);
+                onepassword::resolve(&mut resolved)?;
+                let credential = credentials::exec_credential(&resolved.users[0].user)?;
+                println!("{}", serde_json::to_string(&credential)?);
+                Ok(())
+            }
+            CredentialCommand::Vault { path } => {
+                let app_config = load_app_config()?;
+                let token = app_config.credentials.vault.read_token(&path)?;
+                let credential = credentials::token_credential(&token);
+                println!("{}", serde_json::to_string(&credential)?);
+                Ok(())
+            }
+        },
+        #[cfg(not(feature = "serve"))]
+        Command::Serve { .. } => Err(KconfError::FeatureDisabled("serve").into()),
+        #[cfg(feature = "serve")]
+        Command::Serve {
+            listen,
+            allowed_hosts,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            if !serve::is_loopback(&listen) {
+                eprintln!("Warning: {} may be reachable from other machines", listen);
+            }
+            serve::serve(&app_config, &dest_path, &listen, &allowed_hosts)
+        }
+        Command::Import { action } => match action {
+            ImportCommand::InCluster { name, output } => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST")
+                    .context("KUBERNETES_SERVICE_HOST is not set (not running in a pod?)")?;
+                let port =
+                    std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+                let imported = import::in_cluster(
+                    Path::new(import::SERVICE_ACCOUNT_DIR),
+                    &host,
+                    &port,
+                    &name,
+                )?;
+                finish_import(imported, "in-cluster service account", &output)
+            }
+            #[cfg(feature = "importers")]
+            ImportCommand::Openshift {
+                server,
+                token,
+                login_command,
+                certificate_authority,
+                namespace,
+                name,
+                output,
+            } => {
+                let (server, token) = match (login_command, server, token) {
+                    (Some(command), _, _) => import::parse_oc_login(&command),
+                    (None, None, None) => {
+                        let (server, token) = import::oc_session()?;
+                        (Some(server), Some(token))
+                    }
+                    (None, server, token) => (server, token),
+                };
+                let server = server.context("No OpenShift server given (use --server)")?;
+                let token = token.context("No token given (use --token)")?;
+                if certificate_authority.is_none() {
+                    eprintln!("Note: no --certificate-authority given; the system trust store will be used");
+                }
+                let name = name.unwrap_or_else(|| import::openshift_cluster_name(&server));
+                let imported = import::openshift(
+                    &server,
+                    &token,
+                    certificate_authority.as_deref(),
+                    namespace,
+                    &name,
+                )?;
+                finish_import(imported, &format!("openshift {}", server), &output)
+            }
+            #[cfg(feature = "importers")]
+            ImportCommand::Doks { token, output } => {
+                let token = token.or_else(|| std::env::var("DIGITALOCEAN_ACCESS_TOKEN").ok());
+                let imported = import::doks(token.as_deref())?;
+                finish_import(imported, "digitalocean (doks)", &output)
+            }
+            #[cfg(feature = "importers")]
+            ImportCommand::Teleport {
+                context_template,
+                output,
+            } => {
+                let app_config = load_app_config()?;
+                let template = context_template
+                    .unwrap_or_else(|| app_config.import.teleport.context_template.clone());
+                let imported = import::teleport(&template)?;
+                finish_import(imported, "teleport (tsh)", &output)
+            }
+            #[cfg(not(feature = "importers"))]
+            ImportCommand::Openshift { .. }
+            | ImportCommand::Doks { .. }
+            | ImportCommand::Teleport { .. } => {
+                Err(KconfError::FeatureDisabled("importers").into())
+            }
+            ImportCommand::Desktop { from, output } => {
+                let from = from.unwrap_or_else(|| expand_tilde("~/.kube/config"));
+                let mut source = load_kubeconfig(&from)?;
+                let found = desktop::contexts(&source);
+                if found.is_empty() {
+                    println!(
+                        "No Docker Desktop or Rancher Desktop contexts in {:?}",
+                        from
+                    );
+                    return Ok(());
+                }
+                for (context, tool) in &found {
+                    println!("Found context '{}' ({})", context, tool);
+                }
+                let names: Vec<String> = found.into_iter().map(|(name, _)| name).collect();
+                select_contexts(&mut source, &names);
+                finish_import(source, &format!("desktop contexts in {:?}", from), &output)
+            }
+        },
+        Command::Clean { dry_run, no_backup } => {
+            let app_config = load_app_config()?;
+            let expired = clean_expired(&app_config, dry_run, no_backup)?;
+            if expired.is_empty() {
+                println!("No expired contexts");
+                return Ok(());
+            }
+            for name in &expired {
+                println!("  {}", name);
+            }
+            if dry_run {
+                println!("Dry run: {} expired context(s) not removed", expired.len());
+            } else {
+                println!("Removed {} expired context(s)", expired.len());
+            }
+            Ok(())
+        }
+        Command::Fix {
+            dry_run,
+            yes,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+
+            let changes = fix::apply_fixes(&mut config, &app_config.lint, &app_config.protected);
+            if changes.is_empty() {
+                println!("Nothing to fix");
+                return Ok(());
+            }
+            for change in &changes {
+                println!("  {}", change);
+            }
+            if dry_run {
+                println!("Dry run: {} fix(es) not applied", changes.len());
+                return Ok(());
+            }
+            if !yes && !confirm(&format!("Apply {} fix(es)?", changes.len()))? {
+                println!("Aborted");
+                return Ok(());
+            }
+
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!("fix ({} change(s))", changes.len()),
+            )?;
+            println!("Applied {} fix(es)", changes.len());
+            Ok(())
+        }
+        Command::ConvertOidc {
+            context,
+            standalone,
+            dry_run,
+            no_backup,
+        } => {
+            let app_config = load_app_config()?;
+            let dest_path = expand_tilde(&app_config.destination);
+            let mut config = load_destination(&dest_path)?;
+            let user_name = config
+                .contexts
+                .iter()
+                .find(|c| c.name == context)
+                .map(|c| c.context.user.clone())
+                .ok_or_else(|| KconfError::ContextNotFound(context.clone()))?;
+            let user = config
+                .users
+                .iter_mut()
+                .find(|u| u.name == user_name)
+                .with_context(|| format!("User '{}' not found", user_name))?;
+            let notes = oidc::convert(&mut user.user, standalone)
+                .with_context(|| format!("Cannot convert user '{}'", user_name))?;
+            for note in &notes {
+                println!("  {}", note);
+            }
+            if dry_run {
+                print!("{}", serde_yaml::to_string(&*user)?);
+                return Ok(());
+            }
+
+            if let Some(backup) = backup_before_write(&app_config, &dest_path, no_backup)? {
+                println!("Backup written to {:?}", backup);
+            }
+            save_destination(
+                &app_config,
+                &dest_path,
+                &config,
+                &format!("convert-oidc user '{}'", user_name),
+            )?;
+            println!(
+                "Converted user '{}' to a kubelogin exec plugin; the first kubectl call will open a browser to log in",
+                user_name
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Remove contexts past their TTL, with the clusters and users only they
+/// used. Returns the expired contexts; nothing is written with `dry_run`.
+fn clean_expired(app_config: &AppConfig, dry_run: bool, no_backup: bool) -> Result<Vec<String>> {
+    let dest_path = expand_tilde(&app_config.destination);
+    if !dest_path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut config = load_destination(&dest_path)?;
+    let (kept, expired): (Vec<String>, Vec<String>) = ttl::expired(&config, chrono::Utc::now())
+        .into_iter()
+        .partition(|name| protect::is_protected(&app_config.protected, name));
+    for name in &kept {
+        println!("Keeping protected context '{}' (expired)", name);
+    }
+    if expired.is_empty() || dry_run {
+        return Ok(expired);
+    }
+    for name in &expired {
+        remove_context(&mut config, name);
+    }
+    backup_before_write(app_config, &dest_path, no_backup)?;
+    save_destination(
+        app_config,
+        &dest_path,
+        &config,
+        &format!("clean (removed {})", expired.join(", ")),
+    )?;
+    Ok(expired)
+}
+
+/// Options controlling how each source is merged into the destination
+struct MergeOptions<'a> {
+    mode: UpdateMode,
+    conflict: ConflictStrategy,
+    allow_insecure: bool,
+    enforce_policy: bool,
+    bind_user: Option<&'a str>,
+    /// Keep desktop contexts even when desktop.exclude is set
+    include_desktop: bool,
+    /// Expiry recorded on the contexts this merge adds or updates (--ttl)
+    expires_at: Option<String>,
+    /// Whether incoming clusters that duplicate a destination cluster's
+    /// server under another name are replaced by the existing one
+    reuse_clusters: ReuseClusters,
+    /// Allow updates to protected contexts and their clusters and users
+    unprotect: bool,
+}
+
+/// What to do with an incoming cluster whose API server a differently named
+/// destination cluster already uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReuseClusters {
+    /// Only warn
+    Never,
+    /// Ask for each such cluster
+    Ask,
+    /// Point the incoming contexts at the existing cluster
+    Always,
+}
+
+/// Item counts accumulated across merged sources
+#[derive(Debug, Default)]
+struct MergeTotals {
+    added: usize,
+    updated: usize,
+    skipped: usize,
+    unchanged: usize,
+    /// Skipped entries that differ from the destination's, as "kind 'name'"
+    conflicts: Vec<String>,
+}
+
+/// A destination being merged into, with its state before the merge
+struct MergeTarget {
+    path: PathBuf,
+    config: KubeConfig,
+    original: KubeConfig,
+    existed: bool,
+    totals: MergeTotals,
+}
+
+/// Bind users, check policies, then merge one source into the destination,
+/// reporting each step. `source` is recorded as the contexts' provenance.
+fn merge_source(
+    app_config: &AppConfig,
+    dest_config: &mut KubeConfig,
+    mut source_config: KubeConfig,
+    source: &str,
+    options: &MergeOptions,
+    totals: &mut MergeTotals,
+) -> Result<()> {
+    logfile::record(&format!(
+        "parsed {}: {}",
+        source,
+        logfile::redact(&source_config)
+    ));
+    for (from, to) in normalize::normalize_contexts(&mut source_config, &app_config.normalize) {
+        println!("  Renaming context '{}' to '{}'", from, to);
+    }
+    for (context, pattern) in exclude_contexts(&mut source_config, &app_config.exclude) {
+        println!("  Excluding context '{}' (matches '{}')", context, pattern);
+    }
+    if app_config.desktop.exclude && !options.include_desktop {
+        let found = desktop::contexts(&source_config);
+        let names: Vec<String> = found.iter().map(|(name, _)| name.clone()).collect();
+        for (context, _) in exclude_contexts(&mut source_config, &names) {
+            let tool = found.iter().find(|(name, _)| *name == context).map(|f| f.1);
+            println!(
+                "  Excluding context '{}' ({}; see desktop.exclude)",
+                context,
+                tool.map(|t| t.to_string()).unwrap_or_default()
+            );
+        }
+    }
+    for (kind, from, to) in names::validate(&mut source_config, &app_config.names)? {
+        println!("  Renaming {} {:?} to '{}' (invalid name)", kind, from, to);
+    }
+    for data in paths::embedded(&source_config) {
+        if data.bytes > app_config.lint.max_embedded_bytes {
+            println!(
+                "  Warning: {} '{}' embeds {} of {} (see `kconf stats --externalize`)",
+                data.kind,
+                data.name,
+                stats::format_size(data.bytes),
+                data.field
+            );
+        }
+    }
+    let source_current_context = source_config.current_context.clone();
+    let source_preferences = source_config.preferences.take();
+
+    // Team mode: bind credential-less contexts to local users
+    let bound = team::bind_users(
+        &mut source_config,
+        &app_config.team.bindings,
+        options.bind_user,
+    );
+    for (context, user) in &bound {
+        println!("  Binding context '{}' to local user '{}'", context, user);
+        if !dest_config.users.iter().any(|u| &u.name == user) {
+            println!(
+                "  Warning: local user '{}' does not exist in the destination",
+                user
+            );
+        }
+    }
+
+    let problems = validate::problems(&source_config, &bound);
+    if !problems.is_empty() {
+        return Err(KconfError::InvalidSource {
+            name: source.to_string(),
+            problems,
+        }
+        .into());
+    }
+
+    // Offer the existing cluster for incoming near-duplicates
+    for (incoming, existing) in server::near_duplicates(dest_config, &source_config) {
+        println!(
+            "  Cluster '{}' has the same API server as existing cluster '{}'",
+            incoming, existing
+        );
+        let reuse = match options.reuse_clusters {
+            ReuseClusters::Always => true,
+            ReuseClusters::Ask => confirm(&format!(
+                "  Use '{}' for its contexts instead of adding '{}'?",
+                existing, incoming
+            ))?,
+            ReuseClusters::Never => {
+                println!(
+                    "  (merge with --reuse-clusters to use '{}' instead)",
+                    existing
+                );
+                false
+            }
+        };
+        if reuse {
+            server::reuse(&mut source_config, &incoming, &existing);
+            println!(
+                "  Using cluster '{}' for contexts of '{}'",
+                existing, incoming
+            );
+        }
+    }
+
+    // Flag clusters that disable TLS verification
+    for name in insecure_clusters(&source_config) {
+        if app_config.policies.deny_insecure_tls && !options.allow_insecure {
+            return Err(KconfError::InsecureCluster(name.to_string()).into());
+        }
+        println!(
+            "  Warning: cluster '{}' has insecure-skip-tls-verify enabled",
+            name
+        );
+    }
+
+    // Tokens that are already expired won't get anyone in
+    let now = jwt::now();
+    for user in &source_config.users {
+        let claims = user.user.token.as_deref().and_then(jwt::decode);
+        if let Some(expires) = claims
+            .filter(|c| c.is_expired(now))
+            .and_then(|c| c.expires())
+        {
+            println!(
+                "  Warning: token for user '{}' expired at {}",
+                user.name, expires
+            );
+        }
+    }
+
+    // Evaluate the remaining policies before merging anything
+    let violations = policy::evaluate(&app_config.policies, &source_config);
+    for violation in &violations {
+        println!(
+            "  Policy violation [{}]: {}",
+            violation.rule, violation.message
+        );
+    }
+    if options.enforce_policy && !violations.is_empty() {
+        return Err(KconfError::PolicyViolation(violations.len()).into());
+    }
+
+    // Filter out duplicates and get what can be merged
+    provenance::carry_over(dest_config, &mut source_config);
+    // Before kubens, so a kept namespace also lands in the kubens extension
+    for (kind, name, field) in
+        preserve::carry_over(dest_config, &mut source_config, &app_config.preserve_fields)?
+    {
+        println!("  Keeping local {} of {} '{}'", field, kind, name);
+    }
+    kubens::carry_over(dest_config, &mut source_config);
+    if options.mode == UpdateMode::Skip && options.conflict == ConflictStrategy::Rename {
+        for (kind, from, to) in fix::rename_conflicts(dest_config, &mut source_config) {
+            println!(
+                "  Renaming {} '{}' to '{}' (name already taken)",
+                kind, from, to
+            );
+        }
+    }
+    if options.mode == UpdateMode::Skip {
+        totals
+            .conflicts
+            .extend(conflicting_entries(dest_config, &source_config));
+    }
+    let mut merge_result = filter_duplicates(dest_config, source_config, options.mode);
+    if !options.unprotect {
+        let touched = protect::touched(dest_config, &app_config.protected, &merge_result);
+        if !touched.is_empty() {
+            return Err(KconfError::Protected(touched.join(", ")).into());
+        }
+    }
+
+    // What each update changes, taken before the new provenance is stamped
+    let updated_fields = [
+        (
+            "cluster",
+            diff::updated_fields(
+                &dest_config.clusters,
+                &merge_result.clusters_to_update,
+                |c| &c.name,
+            ),
+        ),
+        (
+            "context",
+            diff::updated_fields(
+                &dest_config.contexts,
+                &merge_result.contexts_to_update,
+                |c| &c.name,
+            ),
+        ),
+        (
+            "user",
+            diff::updated_fields(&dest_config.users, &merge_result.users_to_update, |u| {
+                &u.name
+            }),
+        ),
+    ];
+
+    // Record where imported contexts came from, and when they expire. An
+    // update without --ttl keeps the expiry the context already had.
+    let stamp = provenance::Provenance {
+        expires_at: options.expires_at.clone(),
+        ..provenance::Provenance::now(source)
+    };
+    provenance::stamp_all(&mut merge_result.contexts_to_add, &stamp);
+    for context in &mut merge_result.contexts_to_update {
+        let expires_at = options.expires_at.clone().or_else(|| {
+            dest_config
+                .contexts
+                .iter()
+                .find(|c| c.name == context.name)
+                .and_then(|c| provenance::get(&c.context))
+                .and_then(|p| p.expires_at)
+        });
+        let stamp = provenance::Provenance {
+            expires_at,
+            ..stamp.clone()
+        };
+        provenance::stamp(&mut context.context, &stamp);
+    }
+
+    // Report skipped items
+    for name in &merge_result.skipped_clusters {
+        println!("  Skipping cluster '{}' (already exists)", name);
+    }
+    for name in &merge_result.skipped_contexts {
+        println!("  Skipping context '{}' (already exists)", name);
+    }
+    for name in &merge_result.skipped_users {
+        println!("  Skipping user '{}' (already exists)", name);
+    }
+
+    // Report unchanged items
+    for name in &merge_result.unchanged_clusters {
+        println!("  Cluster '{}' is unchanged", name);
+    }
+    for name in &merge_result.unchanged_contexts {
+        println!("  Context '{}' is unchanged", name);
+    }
+    for name in &merge_result.unchanged_users {
+        println!("  User '{}' is unchanged", name);
+    }
+
+    // Report updated items with the fields that changed; secrets only say so
+    for (kind, updates) in &updated_fields {
+        for (name, fields) in updates {
+            println!("  Updating {} '{}'", kind, name);
+            for field in fields {
+                println!("    {}", field);
+            }
+        }
+    }
+
+    logfile::record(&format!(
+        "decisions for {} ({:?}, {:?}): add {:?}, update {:?}, skip {:?}, unchanged {:?}",
+        source,
+        options.mode,
+        options.conflict,
+        merge_result
+            .clusters_to_add
+            .iter()
+            .map(|c| &c.name)
+            .chain(merge_result.contexts_to_add.iter().map(|c| &c.name))
+            .chain(merge_result.users_to_add.iter().map(|u| &u.name))
+            .collect::<Vec<_>>(),
+        merge_result
+            .clusters_to_update
+            .iter()
+            .map(|c| &c.name)
+            .chain(merge_result.contexts_to_update.iter().map(|c| &c.name))
+            .chain(merge_result.users_to_update.iter().map(|u| &u.name))
+            .collect::<Vec<_>>(),
+        [
+            merge_result.skipped_clusters.as_slice(),
+            merge_result.skipped_contexts.as_slice(),
+            merge_result.skipped_users.as_slice(),
+        ]
+        .concat(),
+        [
+            merge_result.unchanged_clusters.as_slice(),
+            merge_result.unchanged_contexts.as_slice(),
+            merge_result.unchanged_users.as_slice(),
+        ]
+        .concat(),
+    ));
+
+    // Merge configs
+    let unchanged = merge_result.unchanged_count();
+    let (added, updated, skipped) =
+        merge_kubeconfigs(dest_config, merge_result, source_current_context);
+    if let Some(preferences) = source_preferences.filter(|p| !p.is_empty()) {
+        dest_config
+            .preferences
+            .get_or_insert_with(Preferences::default)
+            .merge_from(
+                &preferences,
+                matches!(options.mode, UpdateMode::Replace | UpdateMode::MergeFields),
+            );
+    }
+    totals.added += added;
+    totals.updated += updated;
+    totals.skipped += skipped;
+    totals.unchanged += unchanged;
+
+    if added > 0 {
+        println!("  Merged {} item(s)", added);
+    }
+    if updated > 0 {
+        println!("  Updated {} item(s)", updated);
+    }
+    if (skipped > 0 || unchanged > 0) && added == 0 && updated == 0 {
+        println!("  Nothing new to merge");
+    }
+
+    Ok(())
+}
+
+/// Print an imported kubeconfig, or merge it into the destination
+fn finish_import(imported: KubeConfig, source: &str, output: &ImportOutput) -> Result<()> {
+    if output.print {
+        print!("{}", serde_yaml::to_string(&imported)?);
+        return Ok(());
+    }
+
+    let app_config = load_app_config()?;
+    let dest_path = expand_tilde(&app_config.destination);
+    let dest_existed = dest_path.exists();
+    let mut config = if dest_existed {
+        load_destination(&dest_path)?
+    } else {
+        create_empty_kubeconfig()
+    };
+    let original = config.clone();
+
+    let options = MergeOptions {
+        mode: if output.update {
+            UpdateMode::Replace
+        } else {
+            UpdateMode::Skip
+        },
+        conflict: ConflictStrategy::Skip,
+        allow_insecure: false,
+        enforce_policy: false,
+        bind_user: None,
+        // An import is an explicit request, so desktop.exclude doesn't apply
+        include_desktop: true,
+        expires_at: match &output.ttl {
+            Some(duration) => Some(ttl::expiry(chrono::Utc::now(), ttl::parse(duration)?)),
+            None => None,
+        },
+        reuse_clusters: ReuseClusters::Never,
+        unprotect: false,
+    };
+    let mut totals = MergeTotals::default();
+    println!("Importing from {}", source);
+    merge_source(
+        &app_config,
+        &mut config,
+        imported,
+        source,
+        &options,
+        &mut totals,
+    )?;
+
+    if needs_write(dest_existed, &original, &config) {
+        if let Some(backup) = backup_before_write(&app_config, &dest_path, output.no_backup)? {
+            println!("Backup written to {:?}", backup);
+        }
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        save_destination(
+            &app_config,
+            &dest_path,
+            &config,
+            &format!(
+                "import {} ({} added, {} updated)",
+                source, totals.added, totals.updated
+            ),
+        )?;
+    }
+    println!(
+        "Done: {} item(s) added, {} item(s) updated, {} item(s) unchanged, {} item(s) skipped",
+        totals.added, totals.updated, totals.unchanged, totals.skipped
+    );
+    Ok(())
+}
+
+/// Outcome of refreshing every registered source
+struct RefreshReport {
+    totals: MergeTotals,
+    /// Names of sources that couldn't be fetched or merged
+    failed: Vec<String>,
+    /// Contexts that didn't exist before the refresh
+    added_contexts: Vec<String>,
+    /// Users whose credentials changed
+    rotated_users: Vec<String>,
+    /// Everything the merge changed in the destination
+    drift: Vec<diff::Change>,
+}
+
+/// Re-fetch every registered source, merge it with its strategy and write the
+/// destination if anything changed (and `write` is set). A failing source
+/// doesn't stop the others.
+fn refresh_sources(app_config: &AppConfig, write: bool) -> Result<RefreshReport> {
+    let dest_path = expand_tilde(&app_config.destination);
+    let dest_existed = dest_path.exists();
+    let mut config = if dest_existed {
+        load_destination(&dest_path)?
+    } else {
+        create_empty_kubeconfig()
+    };
+    let original = config.clone();
+
+    let mut totals = MergeTotals::default();
+    let mut failed = Vec::new();
+    let mut progress = progress::Progress::new(app_config.sources.len());
+    for source in &app_config.sources {
+        let options = MergeOptions {
+            mode: source.strategy.into(),
+            conflict: ConflictStrategy::Skip,
+            allow_insecure: false,
+            enforce_policy: false,
+            bind_user: None,
+            include_desktop: false,
+            expires_at: None,
+            reuse_clusters: ReuseClusters::Never,
+            unprotect: false,
+        };
+        progress.run("Refreshing", &source.name, || {
+            let result = source.location().and_then(|location| {
+                let fetched = source.fetch(&app_config.fetch)?;
+                merge_source(
+                    app_config,
+                    &mut config,
+                    fetched,
+                    &location,
+                    &options,
+                    &mut totals,
+                )
+            });
+            if let Err(e) = result {
+                println!("  Error: {:#}", e);
+                failed.push(source.name.clone());
+            }
+        });
+    }
+    drop(progress);
+
+    if write && needs_write(dest_existed, &original, &config) {
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        save_destination(
+            app_config,
+            &dest_path,
+            &config,
+            &format!(
+                "refresh sources ({} added, {} updated)",
+                totals.added, totals.updated
+            ),
+        )?;
+    }
+
+    let (added_contexts, rotated_users) = daemon::changes(&original, &config);
+    Ok(RefreshReport {
+        totals,
+        failed,
+        added_contexts,
+        rotated_users,
+        drift: diff::diff(&original, &config, true),
+    })
+}
+
+/// Refresh registered sources on the configured schedule until killed
+fn run_daemon() -> Result<()> {
+    let config_dir = get_app_config_dir()?;
+    let state_path = config_dir.join("daemon.json");
+    let log_path = config_dir.join("daemon.log");
+    let mut state = daemon::DaemonState {
+        pid: std::process::id(),
+        started_at: chrono::Local::now().to_rfc3339(),
+        last_run: None,
+        last_result: None,
+        next_run: None,
+    };
+    daemon::log(&log_path, "daemon started")?;
+
+    loop {
+        // Re-read the app config every cycle so edits apply without a restart
+        let app_config = load_app_config()?;
+        let schedule = daemon::Schedule::parse(&app_config.daemon.schedule)?;
+        let now = chrono::Local::now();
+        let next = schedule
+            .next_after(now)
+            .context("Daemon schedule never fires")?;
+        state.next_run = Some(next.to_rfc3339());
+        daemon::save_state(&state_path, &state)?;
+        println!("Next refresh at {}", next.format("%Y-%m-%d %H:%M"));
+        std::thread::sleep((next - now).to_std().unwrap_or_default());
+
+        let result = match refresh_sources(&app_config, true) {
+            Ok(report) => {
+                let mut summary = format!(
+                    "{} added, {} updated",
+                    report.totals.added, report.totals.updated
+                );
+                if !report.failed.is_empty() {
+                    summary.push_str(&format!("; failed: {}", report.failed.join(", ")));
+                }
+                let mut events = Vec::new();
+                if !report.added_contexts.is_empty() {
+                    events.push(format!(
+                        "New contexts: {}",
+                        report.added_contexts.join(", ")
+                    ));
+                }
+                if !report.rotated_users.is_empty() {
+                    events.push(format!(
+                        "Rotated credentials: {}",
+                        report.rotated_users.join(", ")
+                    ));
+                }
+                for event in &events {
+                    daemon::log(&log_path, event)?;
+                }
+                if app_config.daemon.notify && !events.is_empty() {
+                    daemon::notify("kconf refresh", &events.join("\n"));
+                }
+                if app_config.daemon.notify && !report.failed.is_empty() {
+                    daemon::notify(
+                        "kconf refresh failed",
+                        &format!("Could not merge: {}", report.failed.join(", ")),
+                    );
+                }
+                summary
+            }
+            Err(e) => {
+                if app_config.daemon.notify {
+                    daemon::notify("kconf refresh failed", &format!("{:#}", e));
+                }
+                format!("error: {:#}", e)
+            }
+        };
+        daemon::log(&log_path, &format!("refresh: {}", result))?;
+        match clean_expired(&app_config, false, false) {
+            Ok(expired) if expired.is_empty() => {}
+            Ok(expired) => daemon::log(
+                &log_path,
+                &format!("clean: removed expired {}", expired.join(", ")),
+            )?,
+            Err(e) => daemon::log(&log_path, &format!("clean: error: {:#}", e))?,
+        }
+        state.last_run = Some(chrono::Local::now().to_rfc3339());
+        state.last_result = Some(result);
+    }
+}
+
+/// Merge every manifest source (only the contexts it wants) and, with
+/// `prune`, remove contexts that don't match the manifest's patterns (or,
+/// without patterns, that didn't come from one of its sources). Any failing
+/// source aborts the sync, since pruning without it would drop its contexts.
+fn sync_manifest(
+    app_config: &AppConfig,
+    mut config: KubeConfig,
+    manifest: &manifest::Manifest,
+    prune: bool,
+) -> Result<KubeConfig> {
+    let mut totals = MergeTotals::default();
+    let mut locations = Vec::new();
+    for source in &manifest.sources {
+        println!("Processing: {}", source.name);
+        let location = source.location()?;
+        let mut fetched = source.fetch(&app_config.fetch).with_context(|| {
+            format!(
+                "Source '{}' failed; destination left untouched",
+                source.name
+            )
+        })?;
+        let wanted: Vec<String> = fetched
+            .contexts
+            .iter()
+            .filter(|c| manifest.wants(&c.name))
+            .map(|c| c.name.clone())
+            .collect();
+        select_contexts(&mut fetched, &wanted);
+        let options = MergeOptions {
+            mode: source.strategy.into(),
+            conflict: ConflictStrategy::Skip,
+            allow_insecure: false,
+            enforce_policy: false,
+            bind_user: None,
+            include_desktop: false,
+            expires_at: None,
+            reuse_clusters: ReuseClusters::Never,
+            unprotect: false,
+        };
+        merge_source(
+            app_config,
+            &mut config,
+            fetched,
+            &location,
+            &options,
+            &mut totals,
+        )?;
+        locations.push(location);
+    }
+
+    if prune {
+        let unwanted: Vec<String> = config
+            .contexts
+            .iter()
+            .filter(|c| {
+                if manifest.contexts.is_empty() {
+                    !provenance::get(&c.context).is_some_and(|p| locations.contains(&p.source))
+                } else {
+                    !manifest.wants(&c.name)
+                }
+            })
+            .map(|c| c.name.clone())
+            .collect();
+        for name in unwanted {
+            if protect::is_protected(&app_config.protected, &name) {
+                println!("Keeping protected context '{}' (not in manifest)", name);
+                continue;
+            }
+            let removed = remove_context(&mut config, &name);
+            println!(
+                "Pruning context '{}' and {} associated item(s) (not in manifest)",
+                name,
+                removed - 1
+            );
         }
     }
+    Ok(config)
+}
 
-    // Set current-context if destination doesn't have one
-    if dest.current_context.is_none() && source_current_context.is_some() {
-        dest.current_context = source_current_context;
+/// Run operations from an operations file against `config` in order. The
+/// first failure aborts the whole run, so nothing is written.
+fn apply_operations(
+    app_config: &AppConfig,
+    mut config: KubeConfig,
+    operations: &[ops::Operation],
+) -> Result<KubeConfig> {
+    let mut totals = MergeTotals::default();
+    for (i, op) in operations.iter().enumerate() {
+        println!("[{}/{}] {}", i + 1, operations.len(), op);
+        let result = match op {
+            ops::Operation::Merge {
+                file,
+                prefix,
+                strategy,
+            } => load_source(file, false).and_then(|mut source| {
+                if let Some(prefix) = prefix {
+                    ops::add_prefix(&mut source, prefix);
+                }
+                let mode = match strategy {
+                    Some(strategy) => (*strategy).into(),
+                    None if app_config.defaults.update => UpdateMode::Replace,
+                    None => UpdateMode::Skip,
+                };
+                let options = MergeOptions {
+                    mode,
+                    conflict: app_config.defaults.conflict_strategy,
+                    allow_insecure: false,
+                    enforce_policy: false,
+                    bind_user: None,
+                    include_desktop: false,
+                    expires_at: None,
+                    reuse_clusters: ReuseClusters::Never,
+                    unprotect: false,
+                };
+                merge_source(
+                    app_config,
+                    &mut config,
+                    source,
+                    &file.display().to_string(),
+                    &options,
+                    &mut totals,
+                )
+            }),
+            ops::Operation::Remove { pattern } => {
+                let matching: Vec<String> = config
+                    .contexts
+                    .iter()
+                    .filter(|c| matches_pattern(pattern, &c.name))
+                    .map(|c| c.name.clone())
+                    .collect();
+                if matching.is_empty() {
+                    println!("  No contexts match '{}'", pattern);
+                }
+                for name in matching {
+                    protect::guard(&app_config.protected, &name, false)?;
+                    let removed = remove_context(&mut config, &name);
+                    println!(
+                        "  Removed context '{}' and {} associated item(s)",
+                        name,
+                        removed - 1
+                    );
+                }
+                Ok(())
+            }
+            ops::Operation::SetNamespace { pattern, namespace } => {
+                let changes = kubens::set_matching(&mut config, pattern, namespace);
+                for change in &changes {
+                    println!(
+                        "  {}: {} -> {}",
+                        change.context,
+                        change.old.as_deref().unwrap_or("(none)"),
+                        namespace
+                    );
+                }
+                Ok(())
+            }
+        };
+        result.with_context(|| {
+            format!(
+                "Operation {} ({}) failed; destination left untouched",
+                i + 1,
+                op
+            )
+        })?;
     }
+    Ok(config)
+}
 
-    (added, updated, skipped)
+/// Whether the destination has to be (re)written after processing
+fn needs_write(dest_existed: bool, original: &KubeConfig, updated: &KubeConfig) -> bool {
+    !dest_existed || original != updated
 }
 
-fn run() -> Result<()> {
-    let args = Args::parse();
+fn run(args: Args) -> Result<()> {
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
 
     // Validate: at least one of configs or --remove must be provided
     if args.configs.is_empty() && args.remove.is_none() {
@@ -361,110 +4727,319 @@ fn run() -> Result<()> {
             "Either provide kubeconfig files to merge or use --remove to remove a context"
         );
     }
+    let expires_at = match &args.ttl {
+        Some(duration) => Some(ttl::expiry(chrono::Utc::now(), ttl::parse(duration)?)),
+        None => None,
+    };
 
     // Load application config
-    let app_config = load_app_config()?;
-    let dest_path = expand_tilde(&app_config.destination);
-
-    println!("Destination kubeconfig: {:?}", dest_path);
-
-    // Load or create destination kubeconfig
-    let mut dest_config = if dest_path.exists() {
-        load_kubeconfig(&dest_path)?
+    let mut app_config = load_app_config()?;
+    if args.output_format.is_some() {
+        app_config.format = args.output_format;
+    }
+    let dest_paths = if args.into.is_empty() {
+        vec![expand_tilde(&app_config.destination)]
     } else {
-        // Ensure parent directory exists
-        if let Some(parent) = dest_path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
-        }
-        create_empty_kubeconfig()
+        args.into.clone()
     };
 
+    // Load or create each destination kubeconfig
+    let mut targets = Vec::new();
+    for dest_path in &dest_paths {
+        check_writable(dest_path)?;
+    }
+    for dest_path in dest_paths {
+        println!("Destination kubeconfig: {:?}", dest_path);
+        let config = if dest_path.exists() {
+            match load_destination(&dest_path) {
+                Err(e)
+                    if args.auto_recover
+                        && matches!(
+                            e.downcast_ref::<KconfError>(),
+                            Some(KconfError::CorruptDestination(_))
+                        ) =>
+                {
+                    eprintln!("Warning: {:#}", e);
+                    recover_destination(&app_config, &dest_path, true)?
+                        .context("Recovery aborted")?
+                }
+                result => result?,
+            }
+        } else {
+            // Ensure parent directory exists
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+            }
+            create_empty_kubeconfig()
+        };
+        targets.push(MergeTarget {
+            existed: dest_path.exists(),
+            path: dest_path,
+            original: config.clone(),
+            config,
+            totals: MergeTotals::default(),
+        });
+    }
+    let fan_out = targets.len() > 1;
+
     // Handle --remove flag
     if let Some(ref context_name) = args.remove {
-        let removed = remove_context(&mut dest_config, context_name);
-        if removed > 0 {
-            println!(
-                "Removed context '{}' and {} associated item(s)",
-                context_name,
-                removed - 1
-            );
-        } else {
-            println!("Context '{}' not found in destination config", context_name);
+        protect::guard(&app_config.protected, context_name, args.unprotect)?;
+        for target in &mut targets {
+            let removed = remove_context(&mut target.config, context_name);
+            if removed > 0 {
+                println!(
+                    "Removed context '{}' and {} associated item(s)",
+                    context_name,
+                    removed - 1
+                );
+            } else {
+                println!("Context '{}' not found in destination config", context_name);
+            }
         }
     }
 
-    let mut total_added = 0;
-    let mut total_updated = 0;
-    let mut total_skipped = 0;
+    // Command-line flags win over defaults from the app config
+    let defaults = &app_config.defaults;
+    let update = args.update || (defaults.update && !args.no_update);
+    if args.merge_fields && !update {
+        anyhow::bail!("--merge-fields requires --update (or defaults.update)");
+    }
+    let options = MergeOptions {
+        mode: if args.update_credentials_only {
+            UpdateMode::CredentialsOnly
+        } else if update && args.merge_fields {
+            UpdateMode::MergeFields
+        } else if update {
+            UpdateMode::Replace
+        } else {
+            UpdateMode::Skip
+        },
+        conflict: args.on_conflict.unwrap_or(defaults.conflict_strategy),
+        allow_insecure: args.allow_insecure,
+        enforce_policy: args.enforce_policy,
+        bind_user: args.bind_user.as_deref(),
+        include_desktop: false,
+        expires_at,
+        reuse_clusters: if args.reuse_clusters {
+            ReuseClusters::Always
+        } else if io::stdin().is_terminal() {
+            ReuseClusters::Ask
+        } else {
+            ReuseClusters::Never
+        },
+        unprotect: args.unprotect,
+    };
 
     // Process each source kubeconfig
+    let mut last_current = None;
+    let mut selected: HashSet<String> = HashSet::new();
+    let mut progress = progress::Progress::new(args.configs.len());
     for config_path in &args.configs {
-        println!("Processing: {:?}", config_path);
-
-        let source_config = load_kubeconfig(config_path)?;
-        let source_current_context = source_config.current_context.clone();
-
-        // Filter out duplicates and get what can be merged
-        let merge_result = filter_duplicates(&dest_config, source_config, args.update);
+        progress.run("Processing", &config_path.display().to_string(), || {
+            let mut source_config = load_source(config_path, args.expand_env)?;
+            if args.embed {
+                for embedded in paths::embed(&mut source_config)? {
+                    println!(
+                        "  Embedding {} of {} '{}' from {}",
+                        embedded.field, embedded.kind, embedded.name, embedded.path
+                    );
+                }
+            }
+            if !args.contexts.is_empty() {
+                selected.extend(
+                    source_config
+                        .contexts
+                        .iter()
+                        .filter(|c| args.contexts.contains(&c.name))
+                        .map(|c| c.name.clone()),
+                );
+                select_contexts(&mut source_config, &args.contexts);
+            }
+            if args.pick && source_config.contexts.len() > 1 {
+                let picked = pick_contexts(&source_config, config_path)?;
+                select_contexts(&mut source_config, &picked);
+            }
+            let source = config_path
+                .canonicalize()
+                .unwrap_or_else(|_| config_path.clone());
+            last_current = source_config.current_context.clone();
+            for target in &mut targets {
+                if fan_out {
+                    println!(" Into {:?}:", target.path);
+                }
+                merge_source(
+                    &app_config,
+                    &mut target.config,
+                    source_config.clone(),
+                    &source.display().to_string(),
+                    &options,
+                    &mut target.totals,
+                )?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })?;
+    }
+    drop(progress);
 
-        // Report skipped items
-        for name in &merge_result.skipped_clusters {
-            println!("  Skipping cluster '{}' (already exists)", name);
-        }
-        for name in &merge_result.skipped_contexts {
-            println!("  Skipping context '{}' (already exists)", name);
-        }
-        for name in &merge_result.skipped_users {
-            println!("  Skipping user '{}' (already exists)", name);
+    if args.current_from_last {
+        match &last_current {
+            Some(name) => {
+                for target in &mut targets {
+                    if target.config.contexts.iter().any(|c| &c.name == name) {
+                        target.config.current_context = Some(name.clone());
+                    } else {
+                        eprintln!(
+                            "Warning: context '{}' from the last file isn't in {:?} (excluded or renamed); current-context unchanged",
+                            name, target.path
+                        );
+                    }
+                }
+            }
+            None => eprintln!(
+                "Warning: the last file has no current-context; current-context unchanged"
+            ),
         }
+    }
 
-        // Report updated items
-        for name in &merge_result.clusters_to_update {
-            println!("  Updating cluster '{}'", name.name);
-        }
-        for name in &merge_result.contexts_to_update {
-            println!("  Updating context '{}'", name.name);
+    let missing: Vec<&str> = args
+        .contexts
+        .iter()
+        .filter(|name| !selected.contains(*name))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "Context(s) not found in any source: {}; destination not written",
+            missing.join(", ")
+        );
+    }
+
+    if args.fail_on_skip {
+        let mut conflicts = 0;
+        for target in &targets {
+            for conflict in &target.totals.conflicts {
+                if fan_out {
+                    eprintln!("Conflict: {} differs from {:?}", conflict, target.path);
+                } else {
+                    eprintln!("Conflict: {} differs from the destination", conflict);
+                }
+            }
+            conflicts += target.totals.conflicts.len();
         }
-        for name in &merge_result.users_to_update {
-            println!("  Updating user '{}'", name.name);
+        if conflicts > 0 {
+            return Err(KconfError::SkippedConflicts(conflicts).into());
         }
+    }
 
-        // Merge configs
-        let (added, updated, skipped) =
-            merge_kubeconfigs(&mut dest_config, merge_result, source_current_context);
-        total_added += added;
-        total_updated += updated;
-        total_skipped += skipped;
+    let mut operations = Vec::new();
+    if let Some(ref context_name) = args.remove {
+        operations.push(format!("remove context '{}'", context_name));
+    }
+    let files: Vec<String> = args
+        .configs
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect();
 
-        if added > 0 {
-            println!("  Merged {} item(s)", added);
+    // Only write merged configs when something actually differs, so the
+    // file's mtime is preserved and watchers aren't triggered needlessly
+    if args.sort || (defaults.sort_output && !args.no_sort) {
+        for target in &mut targets {
+            fix::sort_by_name(&mut target.config);
         }
-        if updated > 0 {
-            println!("  Updated {} item(s)", updated);
+    }
+    let mut writes = Vec::new();
+    for target in &targets {
+        // --output-format converts the file even when nothing else changed
+        let converts = args
+            .output_format
+            .is_some_and(|f| target.existed && KubeconfigFormat::of(&target.path) != Some(f));
+        if !converts && !needs_write(target.existed, &target.original, &target.config) {
+            continue;
         }
-        if skipped > 0 && added == 0 && updated == 0 {
-            println!("  Nothing new to merge");
+        if args.backup || (defaults.backup && !args.no_backup) {
+            if let Some(backup) = backup_destination(&app_config, &target.path)? {
+                println!("Backup written to {:?}", backup);
+            }
         }
+        writes.push((target.path.as_path(), &target.config));
+    }
+    if !files.is_empty() {
+        let (added, updated) = targets.iter().fold((0, 0), |(a, u), t| {
+            (a + t.totals.added, u + t.totals.updated)
+        });
+        operations.push(format!(
+            "merge {} ({} added, {} updated)",
+            files.join(", "),
+            added,
+            updated
+        ));
+    }
+    let operation = operations.join("; ");
+    match writes.as_slice() {
+        [] => {}
+        [(path, config)] if args.into.is_empty() => {
+            save_destination(&app_config, path, config, &operation)?
+        }
+        _ => save_destinations(&app_config, &writes, &operation)?,
     }
 
-    // Write the merged config
-    let output = serde_yaml::to_string(&dest_config)?;
-    fs::write(&dest_path, &output)
-        .with_context(|| format!("Failed to write destination config: {:?}", dest_path))?;
-
-    println!(
-        "Done: {} item(s) added, {} item(s) updated, {} item(s) skipped",
-        total_added, total_updated, total_skipped
-    );
+    for target in &targets {
+        let totals = &target.totals;
+        let prefix = if fan_out {
+            format!("{}: ", target.path.display())
+        } else {
+            String::new()
+        };
+        println!(
+            "{}Done: {} item(s) added, {} item(s) updated, {} item(s) unchanged, {} item(s) skipped",
+            prefix, totals.added, totals.updated, totals.unchanged, totals.skipped
+        );
+        if !writes
+            .iter()
+            .any(|(path, _)| *path == target.path.as_path())
+        {
+            println!("{}No changes; destination left untouched", prefix);
+        }
+    }
 
     Ok(())
 }
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {:#}", e);
-        std::process::exit(1);
+    if std::env::var("KCONF_LOG").as_deref() != Ok("off") {
+        if let Ok(dir) = get_app_config_dir() {
+            // A missing log shouldn't stop kconf from working
+            let _ = logfile::init(&dir.join("logs"));
+        }
+    }
+    logfile::record(&format!(
+        "kconf {}: {}",
+        env!("CARGO_PKG_VERSION"),
+        logfile::redact_args(std::env::args()).join(" ")
+    ));
+    let args = Args::parse();
+    let error_format = args.error_format;
+    if let Err(e) = run(args) {
+        logfile::record(&format!("error: {:#}", e));
+        let kconf_error = e.chain().find_map(|c| c.downcast_ref::<KconfError>());
+        let exit_code = kconf_error.map_or(1, KconfError::exit_code);
+        match error_format {
+            OutputFormat::Text => eprintln!("Error: {:#}", e),
+            OutputFormat::Json => eprintln!(
+                "{}",
+                serde_json::json!({
+                    "error": {
+                        "code": kconf_error.map_or("error", KconfError::code),
+                        "message": format!("{:#}", e),
+                        "exit_code": exit_code,
+                    }
+                })
+            ),
+        }
+        std::process::exit(exit_code);
     }
 }
 
@@ -473,7 +5048,7 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
-    fn create_test_kubeconfig(name: &str) -> KubeConfig {
+    pub(crate) fn create_test_kubeconfig(name: &str) -> KubeConfig {
         KubeConfig {
             api_version: "v1".to_string(),
             kind: "Config".to_string(),
@@ -484,6 +5059,8 @@ mod tests {
                     certificate_authority_data: Some("dGVzdC1jYS1kYXRh".to_string()),
                     certificate_authority: None,
                     insecure_skip_tls_verify: None,
+                    proxy_url: None,
+                    tls_server_name: None,
                 },
             }],
             contexts: vec![NamedContext {
@@ -492,6 +5069,7 @@ mod tests {
                     cluster: format!("{}-cluster", name),
                     user: format!("{}-user", name),
                     namespace: None,
+                    extensions: None,
                 },
             }],
             users: vec![NamedUser {
@@ -504,20 +5082,50 @@ mod tests {
                     token: None,
                     username: None,
                     password: None,
+                    auth_provider: None,
+                    exec: None,
                 },
             }],
             current_context: Some(format!("{}-context", name)),
-            preferences: Some(HashMap::new()),
+            preferences: None,
         }
     }
 
+    #[test]
+    fn test_preferences_round_trip_and_merge() {
+        let yaml = "colors: true\nextensions:\n- name: a\n  extension: {x: 1}\nfuture: keep\n";
+        let mut dest: Preferences = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(dest.colors, Some(true));
+        assert_eq!(dest.other["future"], serde_yaml::Value::from("keep"));
+        let written = serde_yaml::to_string(&dest).unwrap();
+        assert_eq!(serde_yaml::from_str::<Preferences>(&written).unwrap(), dest);
+        assert!(Preferences::default().is_empty());
+
+        let source: Preferences = serde_yaml::from_str(
+            "colors: false\nextensions:\n- name: a\n  extension: {x: 2}\n- name: b\n  extension: {}\nfuture: new\nother: 1\n",
+        )
+        .unwrap();
+        let mut kept = dest.clone();
+        kept.merge_from(&source, false);
+        assert_eq!(kept.colors, Some(true));
+        assert_eq!(kept.extensions.as_ref().unwrap().len(), 2);
+        assert_eq!(kept.extensions.as_ref().unwrap()[0].extension["x"], 1);
+        assert_eq!(kept.other["future"], serde_yaml::Value::from("keep"));
+        assert_eq!(kept.other["other"], 1);
+
+        dest.merge_from(&source, true);
+        assert_eq!(dest.colors, Some(false));
+        assert_eq!(dest.extensions.as_ref().unwrap()[0].extension["x"], 2);
+        assert_eq!(dest.other["future"], serde_yaml::Value::from("new"));
+    }
+
     #[test]
     fn test_merge_kubeconfigs() {
         let mut dest = create_empty_kubeconfig();
         let source = create_test_kubeconfig("test1");
         let source_ctx = source.current_context.clone();
 
-        let merge_result = filter_duplicates(&dest, source, false);
+        let merge_result = filter_duplicates(&dest, source, UpdateMode::Skip);
         merge_kubeconfigs(&mut dest, merge_result, source_ctx);
 
         assert_eq!(dest.clusters.len(), 1);
@@ -531,7 +5139,7 @@ mod tests {
         let dest = create_test_kubeconfig("dest");
         let source = create_test_kubeconfig("source");
 
-        let result = filter_duplicates(&dest, source, false);
+        let result = filter_duplicates(&dest, source, UpdateMode::Skip);
         assert_eq!(result.clusters_to_add.len(), 1);
         assert_eq!(result.skipped_clusters.len(), 0);
     }
@@ -541,7 +5149,7 @@ mod tests {
         let dest = create_test_kubeconfig("test");
         let source = create_test_kubeconfig("test");
 
-        let result = filter_duplicates(&dest, source, false);
+        let result = filter_duplicates(&dest, source, UpdateMode::Skip);
         assert_eq!(result.clusters_to_add.len(), 0);
         assert_eq!(result.skipped_clusters.len(), 1);
         assert_eq!(result.skipped_clusters[0], "test-cluster");
@@ -564,6 +5172,45 @@ mod tests {
         assert_eq!(parsed.users.len(), 1);
     }
 
+    #[test]
+    fn test_parse_kubeconfig_without_users() {
+        let yaml = "apiVersion: v1\nkind: Config\nclusters:\n- name: c\n  cluster:\n    server: https://c:6443\ncontexts:\n- name: ctx\n  context:\n    cluster: c\n    user: team-user\n";
+        let parsed: KubeConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(parsed.contexts.len(), 1);
+        assert!(parsed.users.is_empty());
+    }
+
+    #[test]
+    fn test_parse_kubeconfig_resolves_anchors() {
+        let yaml = r#"
+apiVersion: v1
+kind: Config
+clusters:
+- name: a
+  cluster: &shared
+    server: https://a:6443
+    certificate-authority-data: c2hhcmVk
+- name: b
+  cluster:
+    <<: *shared
+    server: https://b:6443
+- name: c
+  cluster: *shared
+contexts: []
+users: []
+"#;
+        let parsed = parse_kubeconfig(yaml).unwrap();
+        let b = &parsed.clusters[1].cluster;
+        assert_eq!(b.server, "https://b:6443");
+        assert_eq!(b.certificate_authority_data.as_deref(), Some("c2hhcmVk"));
+        assert_eq!(parsed.clusters[2].cluster.server, "https://a:6443");
+
+        // Output carries every value inline rather than anchors
+        let out = serde_yaml::to_string(&parsed).unwrap();
+        assert!(!out.contains('&') && !out.contains('*'));
+        assert_eq!(out.matches("c2hhcmVk").count(), 3);
+    }
+
     #[test]
     fn test_load_kubeconfig_from_file() {
         let temp_dir = TempDir::new().unwrap();
@@ -585,10 +5232,10 @@ mod tests {
         let ctx1 = source1.current_context.clone();
         let ctx2 = source2.current_context.clone();
 
-        let merge_result1 = filter_duplicates(&dest, source1, false);
+        let merge_result1 = filter_duplicates(&dest, source1, UpdateMode::Skip);
         merge_kubeconfigs(&mut dest, merge_result1, ctx1);
 
-        let merge_result2 = filter_duplicates(&dest, source2, false);
+        let merge_result2 = filter_duplicates(&dest, source2, UpdateMode::Skip);
         merge_kubeconfigs(&mut dest, merge_result2, ctx2);
 
         assert_eq!(dest.clusters.len(), 2);
@@ -609,6 +5256,8 @@ mod tests {
                 certificate_authority_data: Some("bmV3LWNh".to_string()),
                 certificate_authority: None,
                 insecure_skip_tls_verify: None,
+                proxy_url: None,
+                tls_server_name: None,
             },
         };
         let new_context = NamedContext {
@@ -617,6 +5266,7 @@ mod tests {
                 cluster: "new-cluster".to_string(),
                 user: "new-user".to_string(),
                 namespace: None,
+                extensions: None,
             },
         };
         let new_user = NamedUser {
@@ -629,13 +5279,15 @@ mod tests {
                 token: Some("new-token".to_string()),
                 username: None,
                 password: None,
+                auth_provider: None,
+                exec: None,
             },
         };
         source.clusters.push(new_cluster);
         source.contexts.push(new_context);
         source.users.push(new_user);
 
-        let merge_result = filter_duplicates(&dest, source, false);
+        let merge_result = filter_duplicates(&dest, source, UpdateMode::Skip);
 
         // Should skip the existing ones
         assert_eq!(merge_result.skipped_clusters.len(), 1);
@@ -693,6 +5345,7 @@ mod tests {
                 cluster: "test-cluster".to_string(),
                 user: "other-user".to_string(),
                 namespace: None,
+                extensions: None,
             },
         });
         config.users.push(NamedUser {
@@ -705,6 +5358,8 @@ mod tests {
                 token: Some("other-token".to_string()),
                 username: None,
                 password: None,
+                auth_provider: None,
+                exec: None,
             },
         });
 
@@ -717,15 +5372,431 @@ mod tests {
         assert_eq!(config.contexts[0].name, "other-context");
     }
 
+    #[test]
+    fn test_update_credentials_only() {
+        let mut dest = create_test_kubeconfig("test");
+        dest.contexts[0].context.namespace = Some("local-ns".to_string());
+
+        // Source renames the user and changes the server and credentials
+        let mut source = create_test_kubeconfig("test");
+        source.clusters[0].cluster.server = "https://changed.example.com:6443".to_string();
+        source.contexts[0].context.user = "oidc-user".to_string();
+        source.users[0].name = "oidc-user".to_string();
+        source.users[0].user.token = Some("rotated-token".to_string());
+
+        let merge_result = filter_duplicates(&dest, source, UpdateMode::CredentialsOnly);
+        assert_eq!(merge_result.skipped_clusters.len(), 1);
+        assert_eq!(merge_result.skipped_contexts.len(), 1);
+        assert_eq!(merge_result.users_to_update.len(), 1);
+        assert_eq!(merge_result.users_to_add.len(), 0);
+
+        let (added, updated, skipped) = merge_kubeconfigs(&mut dest, merge_result, None);
+        assert_eq!((added, updated, skipped), (0, 1, 2));
+        assert_eq!(dest.users[0].name, "test-user");
+        assert_eq!(dest.users[0].user.token, Some("rotated-token".to_string()));
+        assert_eq!(
+            dest.clusters[0].cluster.server,
+            "https://test.example.com:6443"
+        );
+        assert_eq!(
+            dest.contexts[0].context.namespace,
+            Some("local-ns".to_string())
+        );
+    }
+
+    #[test]
+    fn test_update_merge_fields_preserves_local_fields() {
+        let mut dest = create_test_kubeconfig("test");
+        dest.contexts[0].context.namespace = Some("local-ns".to_string());
+        dest.clusters[0].cluster.proxy_url = Some("socks5://localhost:1080".to_string());
+
+        let mut source = create_test_kubeconfig("test");
+        source.clusters[0].cluster.server = "https://updated.example.com:6443".to_string();
+        source.users[0].user.client_key_data = None;
+
+        let merge_result = filter_duplicates(&dest, source, UpdateMode::MergeFields);
+        // Only the cluster differs once local fields are preserved
+        assert_eq!(merge_result.unchanged_count(), 2);
+        let (_, updated, _) = merge_kubeconfigs(&mut dest, merge_result, None);
+        assert_eq!(updated, 1);
+        assert_eq!(
+            dest.clusters[0].cluster.server,
+            "https://updated.example.com:6443"
+        );
+        assert_eq!(
+            dest.clusters[0].cluster.proxy_url,
+            Some("socks5://localhost:1080".to_string())
+        );
+        assert_eq!(
+            dest.contexts[0].context.namespace,
+            Some("local-ns".to_string())
+        );
+        // Fields absent from the source are kept rather than cleared
+        assert!(dest.users[0].user.client_key_data.is_some());
+    }
+
+    #[test]
+    fn test_update_identical_entries_are_unchanged() {
+        let mut dest = create_test_kubeconfig("test");
+        let mut source = create_test_kubeconfig("test");
+        source.users[0].user.token = Some("new-token".to_string());
+
+        let merge_result = filter_duplicates(&dest, source, UpdateMode::Replace);
+        assert_eq!(merge_result.unchanged_clusters, vec!["test-cluster"]);
+        assert_eq!(merge_result.unchanged_contexts, vec!["test-context"]);
+        assert!(merge_result.unchanged_users.is_empty());
+        assert_eq!(merge_result.unchanged_count(), 2);
+
+        let (added, updated, skipped) = merge_kubeconfigs(&mut dest, merge_result, None);
+        assert_eq!((added, updated, skipped), (0, 1, 0));
+    }
+
+    #[test]
+    fn test_needs_write_only_on_effective_changes() {
+        let mut dest = create_test_kubeconfig("test");
+        let original = dest.clone();
+        let source = create_test_kubeconfig("test");
+
+        let merge_result = filter_duplicates(&dest, source.clone(), UpdateMode::Replace);
+        merge_kubeconfigs(&mut dest, merge_result, source.current_context.clone());
+        assert!(!needs_write(true, &original, &dest));
+        // A missing destination is always created
+        assert!(needs_write(false, &original, &dest));
+
+        remove_context(&mut dest, "nonexistent-context");
+        assert!(!needs_write(true, &original, &dest));
+
+        remove_context(&mut dest, "test-context");
+        assert!(needs_write(true, &original, &dest));
+    }
+
+    #[test]
+    fn test_backup_before_write_follows_defaults() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+        let mut app_config = AppConfig {
+            destination: dest.display().to_string(),
+            ..AppConfig::default()
+        };
+        app_config.backup.dir = Some(temp_dir.path().join("backups").display().to_string());
+        write_kubeconfig(&dest, &create_test_kubeconfig("dest")).unwrap();
+
+        assert!(backup_before_write(&app_config, &dest, false)
+            .unwrap()
+            .is_none());
+        app_config.defaults.backup = true;
+        assert!(backup_before_write(&app_config, &dest, true)
+            .unwrap()
+            .is_none());
+        assert!(backup_before_write(&app_config, &dest, false)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_backups_of_other_targets_stay_apart() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+        let work = temp_dir.path().join("work.yaml");
+        let backups = temp_dir.path().join("backups");
+        let mut app_config = AppConfig {
+            destination: dest.display().to_string(),
+            ..AppConfig::default()
+        };
+        app_config.backup.dir = Some(backups.display().to_string());
+        write_kubeconfig(&dest, &create_test_kubeconfig("dest")).unwrap();
+        write_kubeconfig(&work, &create_test_kubeconfig("work")).unwrap();
+
+        backup_destination(&app_config, &dest).unwrap().unwrap();
+        let other = backup_destination(&app_config, &work).unwrap().unwrap();
+        assert!(other.starts_with(backups.join("targets")));
+        assert_eq!(backup::list_local(&backups).unwrap().len(), 1);
+        let (_, recovered) = backup::latest_valid(&backups).unwrap().found.unwrap();
+        assert_eq!(recovered, create_test_kubeconfig("dest"));
+    }
+
+    #[test]
+    fn test_save_destinations_is_all_or_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let work = temp_dir.path().join("work.yaml");
+        let spare = temp_dir.path().join("spare.yaml");
+        write_kubeconfig(&work, &create_test_kubeconfig("old")).unwrap();
+        let config = create_test_kubeconfig("new");
+        let app_config = AppConfig::default();
+
+        save_destinations(&app_config, &[(&work, &config), (&spare, &config)], "merge").unwrap();
+        assert_eq!(load_kubeconfig(&work).unwrap(), config);
+        assert_eq!(load_kubeconfig(&spare).unwrap(), config);
+
+        // The second rename fails because the target is a directory, so the
+        // first file is put back the way it was
+        write_kubeconfig(&work, &create_test_kubeconfig("old")).unwrap();
+        let blocked = temp_dir.path().join("blocked");
+        fs::create_dir(&blocked).unwrap();
+        fs::write(blocked.join("keep"), "").unwrap();
+        assert!(save_destinations(
+            &app_config,
+            &[(&work, &config), (&blocked, &config)],
+            "merge"
+        )
+        .is_err());
+        assert_eq!(
+            load_kubeconfig(&work).unwrap(),
+            create_test_kubeconfig("old")
+        );
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_clean_keeps_protected_contexts() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+        let mut app_config = AppConfig {
+            destination: dest.display().to_string(),
+            protected: vec!["prod-*".to_string()],
+            ..AppConfig::default()
+        };
+        app_config.backup.dir = Some(temp_dir.path().join("backups").display().to_string());
+        let mut config = create_test_kubeconfig("prod");
+        config
+            .contexts
+            .extend(create_test_kubeconfig("sandbox").contexts);
+        let expired = provenance::Provenance {
+            expires_at: Some(ttl::expiry(chrono::Utc::now(), -chrono::Duration::days(1))),
+            ..provenance::Provenance::now("/tmp/a.yaml")
+        };
+        for context in &mut config.contexts {
+            provenance::stamp(&mut context.context, &expired);
+        }
+        write_kubeconfig(&dest, &config).unwrap();
+
+        assert_eq!(
+            clean_expired(&app_config, false, false).unwrap(),
+            vec!["sandbox-context"]
+        );
+        let names: Vec<String> = load_kubeconfig(&dest)
+            .unwrap()
+            .contexts
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, vec!["prod-context"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_destinations_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let work = temp_dir.path().join("work.yaml");
+        let shared = temp_dir.path().join("shared.yaml");
+        let spare = temp_dir.path().join("spare.yaml");
+        write_kubeconfig(&work, &create_test_kubeconfig("old")).unwrap();
+        fs::set_permissions(&work, fs::Permissions::from_mode(0o600)).unwrap();
+        write_kubeconfig(&shared, &create_test_kubeconfig("old")).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o640)).unwrap();
+        let config = create_test_kubeconfig("new");
+
+        save_destinations(
+            &AppConfig::default(),
+            &[(&work, &config), (&shared, &config), (&spare, &config)],
+            "merge",
+        )
+        .unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&work), 0o600);
+        assert_eq!(mode(&shared), 0o640);
+        assert_eq!(mode(&spare), 0o600);
+        assert_eq!(load_kubeconfig(&work).unwrap(), config);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_destinations_writes_through_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("dotfiles").join("config");
+        fs::create_dir(target.parent().unwrap()).unwrap();
+        write_kubeconfig(&target, &create_test_kubeconfig("old")).unwrap();
+        let link = temp_dir.path().join("config");
+        std::os::unix::fs::symlink("dotfiles/config", &link).unwrap();
+        let config = create_test_kubeconfig("new");
+
+        save_destinations(&AppConfig::default(), &[(&link, &config)], "merge").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(load_kubeconfig(&target).unwrap(), config);
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+        assert!(check_writable(&dest).is_ok());
+        write_kubeconfig(&dest, &create_test_kubeconfig("a")).unwrap();
+        assert!(check_writable(&dest).is_ok());
+
+        let mut permissions = fs::metadata(&dest).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&dest, permissions.clone()).unwrap();
+        let error = check_writable(&dest).unwrap_err();
+        assert_eq!(error.code(), "read_only_destination");
+        assert!(error.to_string().contains("--into"));
+
+        let locked_dir = temp_dir.path().join("locked");
+        fs::create_dir(&locked_dir).unwrap();
+        fs::set_permissions(&locked_dir, permissions).unwrap();
+        let error = check_writable(&locked_dir.join("new").join("config")).unwrap_err();
+        assert!(error.to_string().contains("directory"));
+
+        // Let TempDir clean up
+        let mut permissions = fs::metadata(&locked_dir).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&locked_dir, permissions).unwrap();
+    }
+
+    #[test]
+    fn test_json_destination_round_trips() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config");
+        let mut config = create_test_kubeconfig("a");
+        provenance::stamp(
+            &mut config.contexts[0].context,
+            &provenance::Provenance::now("/tmp/a.yaml"),
+        );
+        let app_config = AppConfig {
+            format: Some(KubeconfigFormat::Json),
+            ..AppConfig::default()
+        };
+        fs::write(
+            &path,
+            render_destination(&app_config, &path, &config).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(KubeconfigFormat::of(&path), Some(KubeconfigFormat::Json));
+        assert_eq!(load_kubeconfig(&path).unwrap(), config);
+
+        // Without a configured format, the file keeps the one it has
+        let output = render_destination(&AppConfig::default(), &path, &config).unwrap();
+        assert!(output.starts_with('{'));
+        let new_file = temp_dir.path().join("new");
+        let output = render_destination(&AppConfig::default(), &new_file, &config).unwrap();
+        assert!(output.starts_with("apiVersion"));
+
+        // Plain writes (lint --fix on another file) keep the format too
+        write_kubeconfig(&path, &create_test_kubeconfig("b")).unwrap();
+        assert_eq!(KubeconfigFormat::of(&path), Some(KubeconfigFormat::Json));
+        assert_eq!(load_kubeconfig(&path).unwrap(), create_test_kubeconfig("b"));
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_env_file_keeps_credential_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app_config = AppConfig::default();
+        app_config.credentials.split = true;
+        let mut config = create_test_kubeconfig("prod");
+        let staging = create_test_kubeconfig("staging");
+        config.clusters.extend(staging.clusters);
+        config.contexts.extend(staging.contexts);
+        config.users.extend(staging.users);
+        let store_path = temp_dir.path().join("credentials.yaml");
+        let mut store = credentials::CredentialStore::default();
+        let public = credentials::split(&config, &mut store, "kconf");
+        credentials::save_store(&store_path, &store).unwrap();
+
+        let path = write_env_file(&app_config, &public, "prod-context", temp_dir.path()).unwrap();
+        assert_eq!(credentials::load_store(&store_path).unwrap(), store);
+        let isolated = load_kubeconfig(&path).unwrap();
+        assert_eq!(isolated.users, create_test_kubeconfig("prod").users);
+        assert_eq!(isolated.current_context.as_deref(), Some("prod-context"));
+    }
+
+    #[test]
+    fn test_insecure_clusters() {
+        let mut config = create_test_kubeconfig("test");
+        assert!(insecure_clusters(&config).is_empty());
+
+        config.clusters[0].cluster.insecure_skip_tls_verify = Some(true);
+        assert_eq!(insecure_clusters(&config), vec!["test-cluster"]);
+
+        config.clusters[0].cluster.insecure_skip_tls_verify = Some(false);
+        assert!(insecure_clusters(&config).is_empty());
+    }
+
+    #[test]
+    fn test_select_contexts() {
+        let mut config = create_test_kubeconfig("dev");
+        let other = create_test_kubeconfig("prod");
+        config.clusters.extend(other.clusters);
+        config.contexts.extend(other.contexts);
+        config.users.extend(other.users);
+
+        select_contexts(&mut config, &["prod-context".to_string()]);
+        assert_eq!(config.contexts.len(), 1);
+        assert_eq!(config.clusters[0].name, "prod-cluster");
+        assert_eq!(config.users[0].name, "prod-user");
+        assert_eq!(config.current_context, None);
+    }
+
+    #[test]
+    fn test_conflicting_entries() {
+        let dest = create_test_kubeconfig("a");
+        let mut source = create_test_kubeconfig("a");
+        assert!(conflicting_entries(&dest, &source).is_empty());
+
+        source.clusters[0].cluster.server = "https://elsewhere:6443".to_string();
+        source.contexts[0].context.namespace = Some("other".to_string());
+        assert_eq!(
+            conflicting_entries(&dest, &source),
+            vec!["cluster 'a-cluster'", "context 'a-context'"]
+        );
+    }
+
+    #[test]
+    fn test_exclude_contexts() {
+        let mut config = create_test_kubeconfig("minikube");
+        let kept = create_test_kubeconfig("prod");
+        config.clusters.extend(kept.clusters);
+        config.contexts.extend(kept.contexts);
+        config.users.extend(kept.users);
+
+        let excluded = exclude_contexts(
+            &mut config,
+            &["docker-desktop".to_string(), "minikube*".to_string()],
+        );
+        assert_eq!(
+            excluded,
+            vec![("minikube-context".to_string(), "minikube*".to_string())]
+        );
+        assert_eq!(config.contexts.len(), 1);
+        assert_eq!(config.clusters[0].name, "prod-cluster");
+        assert_eq!(config.users[0].name, "prod-user");
+        assert_eq!(config.current_context, None);
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("team-a-*", "team-a-dev"));
+        assert!(matches_pattern(
+            "*.prod.example.com*",
+            "https://api.prod.example.com:6443"
+        ));
+        assert!(matches_pattern("ctx-?", "ctx-1"));
+        assert!(matches_pattern("*", ""));
+        assert!(!matches_pattern("team-a-*", "team-b-dev"));
+        assert!(!matches_pattern("ctx-?", "ctx-10"));
+    }
+
     #[test]
     fn test_update_duplicates() {
         let mut dest = create_test_kubeconfig("test");
-        // Source has same names but different server URL
+        // Source has same names but different server URL, namespace and token
         let mut source = create_test_kubeconfig("test");
         source.clusters[0].cluster.server = "https://updated.example.com:6443".to_string();
+        source.contexts[0].context.namespace = Some("updated-ns".to_string());
         source.users[0].user.token = Some("updated-token".to_string());
 
-        let merge_result = filter_duplicates(&dest, source, true);
+        let merge_result = filter_duplicates(&dest, source, UpdateMode::Replace);
 
         // With update=true, duplicates go to update lists, not skip lists
         assert_eq!(merge_result.clusters_to_update.len(), 1);
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::matches_pattern;
+use crate::sources::Source;
+
+/// Desired state of the destination, applied by `kconf sync <manifest>`
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    /// Sources whose contexts should exist, in the same format as `sources:`
+    /// in the app config
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    /// Context name patterns (`*` and `?` wildcards) that should exist.
+    /// Source contexts not matching any are left out, and with --prune so
+    /// are destination contexts. Empty means every context the sources have.
+    #[serde(default)]
+    pub contexts: Vec<String>,
+}
+
+impl Manifest {
+    /// Whether a context of this name belongs in the destination
+    pub fn wants(&self, name: &str) -> bool {
+        self.contexts.is_empty() || self.contexts.iter().any(|p| matches_pattern(p, name))
+    }
+}
+
+/// Read a manifest, resolving relative source files against its directory
+pub fn load(path: &Path) -> Result<Manifest> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let mut manifest: Manifest = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse manifest: {:?}", path))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    for source in &mut manifest.sources {
+        if let Some(file) = &mut source.file {
+            if !file.starts_with('~') && Path::new(file.as_str()).is_relative() {
+                *file = base.join(&*file).display().to_string();
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("manifest.yaml");
+        fs::write(
+            &path,
+            "sources:\n  - name: team\n    file: team.yaml\n  - name: home\n    file: ~/k.yaml\ncontexts:\n  - \"team-*\"\n",
+        )
+        .unwrap();
+        let manifest = load(&path).unwrap();
+        assert_eq!(
+            manifest.sources[0].file.as_deref(),
+            Some(temp_dir.path().join("team.yaml").to_str().unwrap())
+        );
+        assert_eq!(manifest.sources[1].file.as_deref(), Some("~/k.yaml"));
+        assert!(manifest.wants("team-dev"));
+        assert!(!manifest.wants("scratch"));
+
+        let everything = Manifest {
+            sources: Vec::new(),
+            contexts: Vec::new(),
+        };
+        assert!(everything.wants("scratch"));
+    }
+}
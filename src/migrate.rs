@@ -0,0 +1,104 @@
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+/// Upgrades the app config from version `i` to `i + 1`. Files written before
+/// versioning existed are version 0.
+type Migration = fn(&mut Mapping) -> Result<()>;
+
+const MIGRATIONS: &[Migration] = &[
+    // 0 -> 1: introduces the `version` key itself; no other changes
+    |_| Ok(()),
+    // 1 -> 2: backup.compress changes from a bool to none | gzip | zstd
+    |config| {
+        if let Some(Value::Mapping(backup)) = config.get_mut("backup") {
+            if let Some(Value::Bool(compress)) = backup.get("compress") {
+                let format = if *compress { "gzip" } else { "none" };
+                backup.insert("compress".into(), format.into());
+            }
+        }
+        Ok(())
+    },
+];
+
+/// Schema version written by this build
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+fn version_of(config: &Mapping) -> Result<u32> {
+    match config.get("version") {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid app config version: {:?}", v)),
+    }
+}
+
+/// Bring a parsed app config up to `CURRENT_VERSION`. Returns the version the
+/// file had, so callers can tell whether anything changed.
+pub fn upgrade(config: &mut Value) -> Result<u32> {
+    // An empty file parses as null; treat it like an empty mapping
+    if config.is_null() {
+        *config = Value::Mapping(Mapping::new());
+    }
+    let Some(mapping) = config.as_mapping_mut() else {
+        anyhow::bail!("App config must be a YAML mapping");
+    };
+    let from = version_of(mapping)?;
+    if from > CURRENT_VERSION {
+        anyhow::bail!(
+            "App config is version {}, but this kconf only understands up to {}; upgrade kconf",
+            from,
+            CURRENT_VERSION
+        );
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(mapping).map_err(|e| {
+            e.context(format!(
+                "Failed to migrate app config from version {}",
+                version
+            ))
+        })?;
+        mapping.insert("version".into(), (version as u64 + 1).into());
+    }
+    // Keep the version at the top of the file where people will see it
+    if from < CURRENT_VERSION {
+        let rest = std::mem::take(mapping);
+        mapping.insert("version".into(), CURRENT_VERSION.into());
+        for (key, value) in rest {
+            if key != "version" {
+                mapping.insert(key, value);
+            }
+        }
+    }
+    Ok(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_unversioned_config() {
+        let mut config: Value = serde_yaml::from_str("destination: ~/.kube/config\n").unwrap();
+        assert_eq!(upgrade(&mut config).unwrap(), 0);
+        assert_eq!(config["version"].as_u64(), Some(CURRENT_VERSION as u64));
+        assert_eq!(config["destination"].as_str(), Some("~/.kube/config"));
+
+        // Already current: nothing to do
+        assert_eq!(upgrade(&mut config).unwrap(), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_upgrade_backup_compress_flag() {
+        let mut config: Value =
+            serde_yaml::from_str("version: 1\nbackup:\n  compress: true\n").unwrap();
+        assert_eq!(upgrade(&mut config).unwrap(), 1);
+        assert_eq!(config["backup"]["compress"].as_str(), Some("gzip"));
+    }
+
+    #[test]
+    fn test_upgrade_rejects_newer_config() {
+        let mut config: Value = serde_yaml::from_str("version: 999\n").unwrap();
+        assert!(upgrade(&mut config).is_err());
+    }
+}
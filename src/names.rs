@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fix::rename_entry;
+use crate::{KconfError, KubeConfig};
+
+/// Characters that need quoting when a name is passed on a shell command
+/// line (`kubectl --context ...`), besides whitespace and control characters
+const SHELL_SPECIAL: &str = "'\"`$;&|<>()\\*?!";
+
+/// How incoming names are checked, stored under `names:` in the app config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamesConfig {
+    /// What to do with a name that fails validation
+    #[serde(default)]
+    pub invalid: InvalidNames,
+    /// Replaces each run of offending characters when sanitizing
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+impl Default for NamesConfig {
+    fn default() -> Self {
+        Self {
+            invalid: InvalidNames::default(),
+            replacement: default_replacement(),
+        }
+    }
+}
+
+fn default_replacement() -> String {
+    "-".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidNames {
+    /// Refuse to merge the source
+    #[default]
+    Reject,
+    /// Replace offending characters and keep going
+    Sanitize,
+}
+
+/// Why a name is unusable, or None if it's fine
+pub fn problem(name: &str) -> Option<String> {
+    if name.trim().is_empty() {
+        return Some("it is empty".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Some("it contains a control character".to_string());
+    }
+    if name.chars().any(char::is_whitespace) {
+        return Some("it contains whitespace".to_string());
+    }
+    name.chars()
+        .find(|c| SHELL_SPECIAL.contains(*c))
+        .map(|c| format!("'{}' needs quoting in a shell", c))
+}
+
+/// Replace each run of offending characters with `replacement`
+pub fn sanitize(name: &str, replacement: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    let mut replaced = false;
+    for c in name.chars() {
+        if c.is_control() || c.is_whitespace() || SHELL_SPECIAL.contains(c) {
+            if !replaced {
+                sanitized.push_str(replacement);
+                replaced = true;
+            }
+        } else {
+            sanitized.push(c);
+            replaced = false;
+        }
+    }
+    let trimmed = sanitized.trim_matches(|c| replacement.contains(c));
+    if trimmed.is_empty() {
+        "unnamed".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Check every cluster, context and user name in an incoming source.
+/// Rejects the first invalid name, or sanitizes them all (adding `-2`, `-3`,
+/// ... where sanitized names collide) and returns (kind, from, to) for each.
+pub fn validate(
+    config: &mut KubeConfig,
+    settings: &NamesConfig,
+) -> Result<Vec<(&'static str, String, String)>, KconfError> {
+    let names = |config: &KubeConfig, kind: &str| -> Vec<String> {
+        match kind {
+            "cluster" => config.clusters.iter().map(|c| c.name.clone()).collect(),
+            "context" => config.contexts.iter().map(|c| c.name.clone()).collect(),
+            _ => config.users.iter().map(|u| u.name.clone()).collect(),
+        }
+    };
+    let mut renames = Vec::new();
+    for kind in ["cluster", "user", "context"] {
+        for name in names(config, kind) {
+            let Some(problem) = problem(&name) else {
+                continue;
+            };
+            if settings.invalid == InvalidNames::Reject {
+                return Err(KconfError::InvalidName {
+                    kind,
+                    name,
+                    problem,
+                });
+            }
+            let base = sanitize(&name, &settings.replacement);
+            let taken = names(config, kind);
+            let mut target = base.clone();
+            let mut n = 2;
+            while taken.contains(&target) {
+                target = format!("{}-{}", base, n);
+                n += 1;
+            }
+            rename_entry(config, kind, &name, &target);
+            renames.push((kind, name, target));
+        }
+    }
+    Ok(renames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_problem_and_sanitize() {
+        assert_eq!(problem("arn:aws:eks:us-east-1:1:cluster/prod"), None);
+        assert_eq!(problem(" ").unwrap(), "it is empty");
+        assert_eq!(
+            problem("prod\u{1b}[31m").unwrap(),
+            "it contains a control character"
+        );
+        assert_eq!(problem("my cluster").unwrap(), "it contains whitespace");
+        assert_eq!(problem("prod$1").unwrap(), "'$' needs quoting in a shell");
+
+        assert_eq!(sanitize("my  cluster (eu)", "-"), "my-cluster-eu");
+        assert_eq!(sanitize("$$", "-"), "unnamed");
+    }
+
+    #[test]
+    fn test_validate_rejects_or_sanitizes() {
+        let mut config = create_test_kubeconfig("a");
+        let mut other = create_test_kubeconfig("b");
+        other.contexts[0].name = "a context".to_string();
+        config.contexts[0].name = "a-context".to_string();
+        config.clusters[0].name = "team a".to_string();
+        config.contexts[0].context.cluster = "team a".to_string();
+        config.contexts.extend(other.contexts);
+
+        let mut rejected = config.clone();
+        let error = validate(&mut rejected, &NamesConfig::default()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid cluster name \"team a\": it contains whitespace"));
+
+        let settings = NamesConfig {
+            invalid: InvalidNames::Sanitize,
+            ..Default::default()
+        };
+        let renames = validate(&mut config, &settings).unwrap();
+        assert_eq!(
+            renames,
+            vec![
+                ("cluster", "team a".to_string(), "team-a".to_string()),
+                (
+                    "context",
+                    "a context".to_string(),
+                    "a-context-2".to_string()
+                ),
+            ]
+        );
+        assert_eq!(config.contexts[0].context.cluster, "team-a");
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::KubeConfig;
+
+/// Context name normalization applied to every incoming source, stored under
+/// `normalize:` in the app config. Everything is off by default.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct NormalizeConfig {
+    /// Prefixes removed from the start of names (e.g. `arn:aws:eks:`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip_prefixes: Vec<String>,
+    /// Substrings replaced in names, e.g. `"/": "-"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub replace: BTreeMap<String, String>,
+    /// Lowercase names
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Truncate names to at most this many characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+}
+
+impl NormalizeConfig {
+    /// Apply the rules to one name: strip prefixes, replace, lowercase, truncate
+    pub fn apply(&self, name: &str) -> String {
+        let mut name = name;
+        for prefix in &self.strip_prefixes {
+            if let Some(rest) = name.strip_prefix(prefix.as_str()) {
+                name = rest;
+                break;
+            }
+        }
+        let mut name = name.to_string();
+        for (from, to) in &self.replace {
+            if !from.is_empty() {
+                name = name.replace(from.as_str(), to);
+            }
+        }
+        if self.lowercase {
+            name = name.to_lowercase();
+        }
+        match self.max_length {
+            Some(max) => truncate(&name, max),
+            None => name,
+        }
+    }
+}
+
+fn truncate(name: &str, max: usize) -> String {
+    let truncated: String = name.chars().take(max).collect();
+    // Don't leave a dangling separator where the cut landed
+    match truncated.trim_end_matches(['-', '_', '.']) {
+        "" => truncated,
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Rename every context in `config` according to `rules`. Names that collide
+/// after normalization get a `-2`, `-3`, ... suffix (still within
+/// `max_length`). Returns (from, to) for each rename.
+pub fn normalize_contexts(
+    config: &mut KubeConfig,
+    rules: &NormalizeConfig,
+) -> Vec<(String, String)> {
+    let mut renames = Vec::new();
+    let mut used: Vec<String> = Vec::new();
+    let current = config
+        .contexts
+        .iter()
+        .position(|c| config.current_context.as_deref() == Some(c.name.as_str()));
+    for i in 0..config.contexts.len() {
+        let name = config.contexts[i].name.clone();
+        let base = rules.apply(&name);
+        let mut target = base.clone();
+        let mut n = 2;
+        while used.contains(&target) {
+            let suffix = format!("-{}", n);
+            target = match rules.max_length {
+                Some(max) => format!(
+                    "{}{}",
+                    truncate(&base, max.saturating_sub(suffix.len())),
+                    suffix
+                ),
+                None => format!("{}{}", base, suffix),
+            };
+            n += 1;
+        }
+        used.push(target.clone());
+        if target != name {
+            // Rename by position: another context may still hold `target`
+            // until its own turn comes
+            config.contexts[i].name = target.clone();
+            if current == Some(i) {
+                config.current_context = Some(target.clone());
+            }
+            renames.push((name, target));
+        }
+    }
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    fn eks_rules() -> NormalizeConfig {
+        NormalizeConfig {
+            strip_prefixes: vec!["arn:aws:eks:".to_string()],
+            replace: [("/", "-"), (":", "-")]
+                .into_iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect(),
+            lowercase: true,
+            max_length: Some(40),
+        }
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let rules = eks_rules();
+        assert_eq!(
+            rules.apply("arn:aws:eks:us-east-1:123456789012:cluster/Prod"),
+            "us-east-1-123456789012-cluster-prod"
+        );
+        assert_eq!(rules.apply("Team/Dev"), "team-dev");
+        assert_eq!(NormalizeConfig::default().apply("Team/Dev"), "Team/Dev");
+    }
+
+    #[test]
+    fn test_normalize_contexts_resolves_collisions() {
+        let mut config = create_test_kubeconfig("a");
+        config.contexts[0].name = "Team/Dev".to_string();
+        config.current_context = Some("Team/Dev".to_string());
+        config.contexts.extend(create_test_kubeconfig("b").contexts);
+        config.contexts[1].name = "team-dev".to_string();
+
+        let renames = normalize_contexts(&mut config, &eks_rules());
+        assert_eq!(renames.len(), 2);
+        assert_eq!(config.contexts[0].name, "team-dev");
+        assert_eq!(config.contexts[1].name, "team-dev-2");
+        assert_eq!(config.current_context.as_deref(), Some("team-dev"));
+    }
+}
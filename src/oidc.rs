@@ -0,0 +1,138 @@
+use anyhow::{bail, Result};
+
+use crate::{ExecConfig, UserInfo};
+
+/// kubelogin prints ExecCredentials of this version
+const EXEC_API_VERSION: &str = "client.authentication.k8s.io/v1beta1";
+
+const INSTALL_HINT: &str =
+    "kubelogin is required: https://github.com/int128/kubelogin#setup (e.g. kubectl krew install oidc-login)";
+
+/// auth-provider config keys and the kubelogin flag each becomes
+const FLAGS: &[(&str, &str)] = &[
+    ("idp-issuer-url", "--oidc-issuer-url"),
+    ("client-id", "--oidc-client-id"),
+    ("client-secret", "--oidc-client-secret"),
+    ("idp-certificate-authority", "--certificate-authority"),
+    (
+        "idp-certificate-authority-data",
+        "--certificate-authority-data",
+    ),
+];
+
+/// Tokens kubectl cached in the kubeconfig; kubelogin keeps its own cache
+const CACHED: &[&str] = &["id-token", "refresh-token"];
+
+/// Replace a user's `auth-provider: oidc` with the equivalent kubelogin exec
+/// stanza, running `kubectl oidc-login` or, with `standalone`, the
+/// `kubelogin` binary. Returns notes on settings that weren't carried over.
+pub fn convert(user: &mut UserInfo, standalone: bool) -> Result<Vec<String>> {
+    let Some(provider) = &user.auth_provider else {
+        bail!("User has no auth-provider to convert");
+    };
+    if provider.name != "oidc" {
+        bail!(
+            "Only the oidc auth-provider can be converted, not '{}'",
+            provider.name
+        );
+    }
+    let config = provider.config.clone().unwrap_or_default();
+    for required in ["idp-issuer-url", "client-id"] {
+        if config.get(required).is_none_or(|v| v.is_empty()) {
+            bail!("The oidc auth-provider has no {}", required);
+        }
+    }
+
+    let mut args: Vec<String> = if standalone {
+        vec!["get-token".to_string()]
+    } else {
+        vec!["oidc-login".to_string(), "get-token".to_string()]
+    };
+    let mut notes = Vec::new();
+    for (key, value) in &config {
+        if let Some((_, flag)) = FLAGS.iter().find(|(k, _)| k == key) {
+            args.push(format!("{}={}", flag, value));
+        } else if key == "extra-scopes" {
+            args.extend(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(|scope| format!("--oidc-extra-scope={}", scope)),
+            );
+        } else if CACHED.contains(&key.as_str()) {
+            notes.push(format!("dropped the cached {}", key));
+        } else {
+            notes.push(format!(
+                "'{}' has no kubelogin equivalent and was dropped",
+                key
+            ));
+        }
+    }
+
+    user.auth_provider = None;
+    user.exec = Some(ExecConfig {
+        api_version: EXEC_API_VERSION.to_string(),
+        command: if standalone { "kubelogin" } else { "kubectl" }.to_string(),
+        args: Some(args),
+        env: None,
+        install_hint: Some(INSTALL_HINT.to_string()),
+        provide_cluster_info: None,
+        interactive_mode: Some("IfAvailable".to_string()),
+    });
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use crate::AuthProviderConfig;
+
+    #[test]
+    fn test_convert_oidc_auth_provider() {
+        let mut user = create_test_kubeconfig("a").users.remove(0).user;
+        user.client_certificate_data = None;
+        user.auth_provider = Some(AuthProviderConfig {
+            name: "oidc".to_string(),
+            config: Some(
+                [
+                    ("idp-issuer-url", "https://dex.example.com"),
+                    ("client-id", "kubernetes"),
+                    ("client-secret", "s3cret"),
+                    ("extra-scopes", "groups,email"),
+                    ("id-token", "eyJ..."),
+                ]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ),
+        });
+
+        let notes = convert(&mut user, false).unwrap();
+        assert_eq!(notes, vec!["dropped the cached id-token"]);
+        assert_eq!(user.auth_provider, None);
+        let exec = user.exec.as_ref().unwrap();
+        assert_eq!(exec.command, "kubectl");
+        assert_eq!(
+            exec.args.as_deref().unwrap(),
+            [
+                "oidc-login",
+                "get-token",
+                "--oidc-client-id=kubernetes",
+                "--oidc-client-secret=s3cret",
+                "--oidc-extra-scope=groups",
+                "--oidc-extra-scope=email",
+                "--oidc-issuer-url=https://dex.example.com",
+            ]
+        );
+
+        // Already converted
+        assert!(convert(&mut user, true).is_err());
+        user.auth_provider = Some(AuthProviderConfig {
+            name: "gcp".to_string(),
+            config: None,
+        });
+        assert!(convert(&mut user, true).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::KubeConfig;
+
+/// 1Password settings stored under `credentials.onepassword:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct OnePasswordConfig {
+    /// Vault to move plaintext tokens into when the destination is written;
+    /// the destination keeps `op://` references instead
+    #[serde(default)]
+    pub vault: Option<String>,
+}
+
+/// Field of the API Credential items kconf creates
+const FIELD: &str = "credential";
+
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with("op://")
+}
+
+/// Run the 1Password CLI, feeding `input` on stdin, and return its stdout
+fn op(args: &[&str], input: Option<&str>) -> Result<String> {
+    let mut child = Command::new("op")
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run op (is the 1Password CLI installed?)")?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "op {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).context("op output is not UTF-8")
+}
+
+/// Replace every `op://` reference in user credentials with the secret it
+/// names. Each reference is read once. Returns how many fields were resolved.
+pub fn resolve(config: &mut KubeConfig) -> Result<usize> {
+    let mut secrets: HashMap<String, String> = HashMap::new();
+    let mut resolved = 0;
+    for user in &mut config.users {
+        let info = &mut user.user;
+        for field in [
+            &mut info.token,
+            &mut info.password,
+            &mut info.client_certificate_data,
+            &mut info.client_key_data,
+        ] {
+            let Some(reference) = field.as_ref().filter(|v| is_reference(v)) else {
+                continue;
+            };
+            let secret = match secrets.get(reference) {
+                Some(secret) => secret.clone(),
+                None => {
+                    let secret =
+                        op(&["read", "--no-newline", reference], None).with_context(|| {
+                            format!("Failed to resolve {} for user '{}'", reference, user.name)
+                        })?;
+                    secrets.insert(reference.clone(), secret.clone());
+                    secret
+                }
+            };
+            *field = Some(secret);
+            resolved += 1;
+        }
+    }
+    Ok(resolved)
+}
+
+/// Item template for a token, as `op item create` reads it from stdin
+fn item_template(title: &str, token: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "category": "API_CREDENTIAL",
+        "fields": [{
+            "id": FIELD,
+            "label": FIELD,
+            "type": "CONCEALED",
+            "value": token,
+        }],
+    })
+}
+
+/// The item kconf created earlier under `title`, if there is exactly one
+fn find_item(vault: &str, title: &str) -> Option<serde_json::Value> {
+    let found = op(
+        &["item", "get", title, "--vault", vault, "--format", "json"],
+        None,
+    )
+    .ok()?;
+    serde_json::from_str(&found).ok()
+}
+
+/// Store every plaintext token in `vault` and replace it with an `op://`
+/// reference. An item from an earlier write is reused when it holds the same
+/// token and replaced otherwise, so refreshing a source doesn't pile up
+/// copies. Returns the names of the users changed.
+pub fn offload(config: &mut KubeConfig, vault: &str) -> Result<Vec<String>> {
+    let mut moved = Vec::new();
+    for user in &mut config.users {
+        let Some(token) = user.user.token.as_ref().filter(|t| !is_reference(t)) else {
+            continue;
+        };
+        let title = format!("kubeconfig {}", user.name);
+        let mut id = None;
+        if let Some(item) = find_item(vault, &title) {
+            let existing = item["id"].as_str().unwrap_or_default().to_string();
+            let same = item["fields"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|f| f["id"] == FIELD && f["value"].as_str() == Some(token.as_str()));
+            if same {
+                id = Some(existing);
+            } else {
+                op(&["item", "delete", &existing, "--vault", vault], None)
+                    .with_context(|| format!("Failed to replace 1Password item '{}'", title))?;
+            }
+        }
+        let id = match id {
+            Some(id) => id,
+            None => {
+                let created = op(
+                    &["item", "create", "--vault", vault, "--format", "json"],
+                    Some(&item_template(&title, token).to_string()),
+                )
+                .with_context(|| {
+                    format!(
+                        "Failed to store the token of user '{}' in 1Password",
+                        user.name
+                    )
+                })?;
+                let item: serde_json::Value = serde_json::from_str(&created)
+                    .context("Unexpected output from op item create")?;
+                item["id"]
+                    .as_str()
+                    .context("op item create returned no item id")?
+                    .to_string()
+            }
+        };
+        user.user.token = Some(format!("op://{}/{}/{}", vault, id, FIELD));
+        moved.push(user.name.clone());
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_references_and_templates() {
+        assert!(is_reference("op://Private/kubeconfig/credential"));
+        assert!(!is_reference("sha256~abc"));
+
+        let template = item_template("kubeconfig prod-user", "abc");
+        assert_eq!(template["category"], "API_CREDENTIAL");
+        assert_eq!(template["fields"][0]["value"], "abc");
+
+        // Nothing to resolve, so op is never run
+        let mut config = create_test_kubeconfig("a");
+        assert_eq!(resolve(&mut config).unwrap(), 0);
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fix::rename_entry;
+use crate::sources::Strategy;
+use crate::KubeConfig;
+
+/// Operations run in order by `kconf apply-ops`
+#[derive(Debug, Deserialize)]
+pub struct OpsFile {
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Operation {
+    /// Merge a kubeconfig file, relative to the operations file
+    Merge {
+        file: PathBuf,
+        /// Prepended to every cluster, context and user name from the file
+        #[serde(default)]
+        prefix: Option<String>,
+        /// How existing entries are handled (defaults to defaults.update)
+        #[serde(default)]
+        strategy: Option<Strategy>,
+    },
+    /// Remove contexts matching a pattern, with clusters and users nothing
+    /// else uses
+    Remove { pattern: String },
+    /// Set the namespace of contexts matching a pattern
+    SetNamespace { pattern: String, namespace: String },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Merge { file, prefix, .. } => {
+                write!(f, "merge {}", file.display())?;
+                match prefix {
+                    Some(prefix) => write!(f, " with prefix '{}'", prefix),
+                    None => Ok(()),
+                }
+            }
+            Operation::Remove { pattern } => write!(f, "remove '{}'", pattern),
+            Operation::SetNamespace { pattern, namespace } => {
+                write!(f, "set namespace '{}' on '{}'", namespace, pattern)
+            }
+        }
+    }
+}
+
+/// Read an operations file, resolving merge paths against its directory
+pub fn load(path: &Path) -> Result<OpsFile> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let mut ops: OpsFile = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse operations file: {:?}", path))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    for op in &mut ops.operations {
+        if let Operation::Merge { file, .. } = op {
+            *file = base.join(&*file);
+        }
+    }
+    Ok(ops)
+}
+
+/// Prepend `prefix` to every cluster, context and user name, rewriting
+/// references
+pub fn add_prefix(config: &mut KubeConfig, prefix: &str) {
+    let renames: Vec<(&str, String)> = config
+        .clusters
+        .iter()
+        .map(|c| ("cluster", c.name.clone()))
+        .chain(config.users.iter().map(|u| ("user", u.name.clone())))
+        .chain(config.contexts.iter().map(|c| ("context", c.name.clone())))
+        .collect();
+    for (kind, name) in renames {
+        rename_entry(config, kind, &name, &format!("{}{}", prefix, name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_operations() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("ops.yaml");
+        fs::write(
+            &path,
+            r#"
+operations:
+  - op: merge
+    file: team-a.yaml
+    prefix: team-a-
+  - op: remove
+    pattern: "old-*"
+  - op: set-namespace
+    pattern: "team-a-*"
+    namespace: team-a
+"#,
+        )
+        .unwrap();
+        let ops = load(&path).unwrap().operations;
+        assert_eq!(
+            ops[0],
+            Operation::Merge {
+                file: temp_dir.path().join("team-a.yaml"),
+                prefix: Some("team-a-".to_string()),
+                strategy: None,
+            }
+        );
+        assert_eq!(ops[1].to_string(), "remove 'old-*'");
+        assert_eq!(ops[2].to_string(), "set namespace 'team-a' on 'team-a-*'");
+
+        fs::write(&path, "operations:\n  - op: explode\n").unwrap();
+        assert!(load(&path).is_err());
+    }
+
+    #[test]
+    fn test_add_prefix() {
+        let mut config = create_test_kubeconfig("a");
+        add_prefix(&mut config, "team-");
+        assert_eq!(config.clusters[0].name, "team-a-cluster");
+        assert_eq!(config.users[0].name, "team-a-user");
+        let context = &config.contexts[0];
+        assert_eq!(context.name, "team-a-context");
+        assert_eq!(context.context.cluster, "team-a-cluster");
+        assert_eq!(context.context.user, "team-a-user");
+        assert_eq!(config.current_context.as_deref(), Some("team-a-context"));
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::sources::{FetchConfig, Source, Strategy};
+use crate::{load_kubeconfig, provenance, KubeConfig};
+
+/// How a destination context compares to its recorded source
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// Identical to the source
+    UpToDate,
+    /// The source changed after the context was imported
+    UpdatedUpstream,
+    /// The context differs but the source hasn't changed since the import
+    DriftedLocally,
+    /// The context differs from a source that has no modification time, so
+    /// which side changed is unknown
+    Differs,
+    /// The source no longer defines the context
+    RemovedUpstream,
+    /// The source can't be read
+    SourceUnavailable(String),
+    /// The source can't be read again to compare with, e.g. command output
+    NotComparable(&'static str),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::UpToDate => write!(f, "up to date"),
+            Status::UpdatedUpstream => write!(f, "newer version available upstream"),
+            Status::DriftedLocally => write!(f, "drifted from source"),
+            Status::Differs => write!(f, "differs from source"),
+            Status::RemovedUpstream => write!(f, "removed from source"),
+            Status::SourceUnavailable(reason) => write!(f, "source unavailable ({})", reason),
+            Status::NotComparable(reason) => write!(f, "not comparable ({})", reason),
+        }
+    }
+}
+
+/// Result for one context with recorded provenance
+#[derive(Debug)]
+pub struct Report {
+    pub context: String,
+    pub source: String,
+    pub status: Status,
+}
+
+/// Whether the destination's context, cluster and user match the source's.
+/// Names of the referenced cluster/user may differ (team bindings,
+/// credential-only updates), so only their contents are compared.
+fn matches_source(dest: &KubeConfig, source: &KubeConfig, name: &str) -> Option<bool> {
+    let dest_ctx = dest.contexts.iter().find(|c| c.name == name)?;
+    let source_ctx = source.contexts.iter().find(|c| c.name == name)?;
+
+    let cluster = |config: &KubeConfig, cluster: &str| {
+        config
+            .clusters
+            .iter()
+            .find(|c| c.name == cluster)
+            .map(|c| c.cluster.clone())
+    };
+    let user = |config: &KubeConfig, user: &str| {
+        config
+            .users
+            .iter()
+            .find(|u| u.name == user)
+            .map(|u| u.user.clone())
+    };
+
+    let source_user = user(source, &source_ctx.context.user);
+    Some(
+        dest_ctx.context.namespace == source_ctx.context.namespace
+            && cluster(dest, &dest_ctx.context.cluster)
+                == cluster(source, &source_ctx.context.cluster)
+            && (source_user.is_none() || user(dest, &dest_ctx.context.user) == source_user),
+    )
+}
+
+/// Where a recorded provenance source can be read again
+enum Origin {
+    File(PathBuf),
+    Url,
+    Other(&'static str),
+}
+
+/// Classify a provenance source: a file path, a URL, or the label of a
+/// command or importer, whose output can't be reproduced here
+fn origin(source: &str) -> Origin {
+    if source.starts_with("https://") || source.starts_with("http://") {
+        Origin::Url
+    } else if source.starts_with("command: ") {
+        Origin::Other("generated by a command")
+    } else if Path::new(source).is_absolute() {
+        Origin::File(PathBuf::from(source))
+    } else {
+        Origin::Other("generated by an importer")
+    }
+}
+
+/// Fetch a URL source the way `kconf refresh` does, with the auth, pin and
+/// signature of the registered source for that URL if there is one
+fn fetch_url(
+    url: &str,
+    registered: &[Source],
+    settings: &FetchConfig,
+) -> anyhow::Result<KubeConfig> {
+    let source = registered
+        .iter()
+        .find(|s| s.url.as_deref() == Some(url))
+        .cloned()
+        .unwrap_or_else(|| Source {
+            name: url.to_string(),
+            file: None,
+            url: Some(url.to_string()),
+            command: None,
+            strategy: Strategy::default(),
+            sha256: None,
+            minisign_key: None,
+            signature: None,
+            auth: None,
+        });
+    source.fetch(settings)
+}
+
+/// Compare every context that has provenance with its original source file
+/// or URL. `registered` are the sources under `sources:`, whose settings are
+/// used to fetch their URLs.
+pub fn check(config: &KubeConfig, registered: &[Source], settings: &FetchConfig) -> Vec<Report> {
+    let mut sources: HashMap<String, Result<KubeConfig, String>> = HashMap::new();
+    let mut reports = Vec::new();
+
+    for context in &config.contexts {
+        let Some(provenance) = provenance::get(&context.context) else {
+            continue;
+        };
+        let path = match origin(&provenance.source) {
+            Origin::File(path) => Some(path),
+            Origin::Url => None,
+            Origin::Other(reason) => {
+                reports.push(Report {
+                    context: context.name.clone(),
+                    source: provenance.source,
+                    status: Status::NotComparable(reason),
+                });
+                continue;
+            }
+        };
+        let source = sources.entry(provenance.source.clone()).or_insert_with(|| {
+            match &path {
+                Some(path) => load_kubeconfig(path),
+                None => fetch_url(&provenance.source, registered, settings),
+            }
+            .map_err(|e| format!("{:#}", e))
+        });
+
+        let status = match source {
+            Err(reason) => Status::SourceUnavailable(reason.clone()),
+            Ok(source) => match matches_source(config, source, &context.name) {
+                None => Status::RemovedUpstream,
+                Some(true) => Status::UpToDate,
+                Some(false) => {
+                    let Some(path) = &path else {
+                        reports.push(Report {
+                            context: context.name.clone(),
+                            source: provenance.source,
+                            status: Status::Differs,
+                        });
+                        continue;
+                    };
+                    let modified = fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .map(chrono::DateTime::<chrono::Utc>::from)
+                        .ok();
+                    let imported = chrono::DateTime::parse_from_rfc3339(&provenance.imported_at)
+                        .ok()
+                        .map(|t| t.with_timezone(&chrono::Utc));
+                    match (modified, imported) {
+                        (Some(modified), Some(imported)) if modified > imported => {
+                            Status::UpdatedUpstream
+                        }
+                        _ => Status::DriftedLocally,
+                    }
+                }
+            },
+        };
+        reports.push(Report {
+            context: context.name.clone(),
+            source: provenance.source,
+            status,
+        });
+    }
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::{stamp, Provenance};
+    use crate::tests::create_test_kubeconfig;
+    use crate::write_kubeconfig;
+    use tempfile::TempDir;
+
+    fn imported(source: &std::path::Path, imported_at: &str) -> KubeConfig {
+        let mut config = create_test_kubeconfig("test");
+        let mut provenance = Provenance::now(&source.display().to_string());
+        provenance.imported_at = imported_at.to_string();
+        stamp(&mut config.contexts[0].context, &provenance);
+        config
+    }
+
+    #[test]
+    fn test_outdated_statuses() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("source.yaml");
+        write_kubeconfig(&path, &create_test_kubeconfig("test")).unwrap();
+
+        let dest = imported(&path, "2100-01-01T00:00:00Z");
+        assert_eq!(
+            check(&dest, &[], &FetchConfig::default())[0].status,
+            Status::UpToDate
+        );
+
+        // Changed locally, source untouched since the (future) import time
+        let mut drifted = dest.clone();
+        drifted.clusters[0].cluster.server = "https://other:6443".to_string();
+        assert_eq!(
+            check(&drifted, &[], &FetchConfig::default())[0].status,
+            Status::DriftedLocally
+        );
+
+        // The source file is newer than the import
+        let mut stale = imported(&path, "2000-01-01T00:00:00Z");
+        stale.contexts[0].context.namespace = Some("old".to_string());
+        assert_eq!(
+            check(&stale, &[], &FetchConfig::default())[0].status,
+            Status::UpdatedUpstream
+        );
+
+        write_kubeconfig(&path, &create_test_kubeconfig("other")).unwrap();
+        assert_eq!(
+            check(&dest, &[], &FetchConfig::default())[0].status,
+            Status::RemovedUpstream
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            check(&dest, &[], &FetchConfig::default())[0].status,
+            Status::SourceUnavailable(_)
+        ));
+    }
+
+    #[test]
+    fn test_generated_sources_are_not_comparable() {
+        for source in [
+            "command: vault read -field=config k8s/prod",
+            "teleport (tsh)",
+            "kconf serve",
+        ] {
+            let mut config = create_test_kubeconfig("test");
+            stamp(&mut config.contexts[0].context, &Provenance::now(source));
+            assert!(matches!(
+                check(&config, &[], &FetchConfig::default())[0].status,
+                Status::NotComparable(_)
+            ));
+        }
+    }
+
+    /// Serve `body` over plain HTTP to every request
+    #[cfg(feature = "remote")]
+    fn serve_kubeconfig(body: String) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/kubeconfig.yaml", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn test_url_sources() {
+        let url = serve_kubeconfig(serde_yaml::to_string(&create_test_kubeconfig("test")).unwrap());
+        let mut dest = create_test_kubeconfig("test");
+        stamp(&mut dest.contexts[0].context, &Provenance::now(&url));
+        let settings = FetchConfig::default();
+        assert_eq!(check(&dest, &[], &settings)[0].status, Status::UpToDate);
+
+        dest.clusters[0].cluster.server = "https://other:6443".to_string();
+        assert_eq!(check(&dest, &[], &settings)[0].status, Status::Differs);
+
+        // A registered source's pin applies to its URL
+        let registered: Source =
+            serde_yaml::from_str(&format!("name: team\nurl: {}\nsha256: '00'\n", url)).unwrap();
+        assert!(matches!(
+            check(&dest, &[registered], &settings)[0].status,
+            Status::SourceUnavailable(_)
+        ));
+    }
+}
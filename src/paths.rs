@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::fs;
+use std::path::Path;
+
+use crate::{write_private, KubeConfig};
+
+/// A file reference that was rewritten or embedded
+#[derive(Debug, PartialEq)]
+pub struct FileReference {
+    pub kind: &'static str,
+    pub name: String,
+    pub field: &'static str,
+    pub path: String,
+}
+
+/// Rewrite `value` as `base/value` when it's a relative path
+fn resolve(value: &mut Option<String>, base: &Path) -> Option<String> {
+    let path = value.as_deref().filter(|p| !p.is_empty())?;
+    if Path::new(path).is_absolute() {
+        return None;
+    }
+    let absolute = base.join(path.strip_prefix("./").unwrap_or(path));
+    let absolute = absolute.display().to_string();
+    *value = Some(absolute.clone());
+    Some(absolute)
+}
+
+/// Make the file references of a kubeconfig absolute. kubectl reads relative
+/// paths against the kubeconfig's own directory, so they break once entries
+/// are merged into a file elsewhere. `base` is the source file's directory.
+pub fn absolutize(config: &mut KubeConfig, base: &Path) -> Vec<FileReference> {
+    let mut resolved = Vec::new();
+    for cluster in &mut config.clusters {
+        if let Some(path) = resolve(&mut cluster.cluster.certificate_authority, base) {
+            resolved.push(FileReference {
+                kind: "cluster",
+                name: cluster.name.clone(),
+                field: "certificate-authority",
+                path,
+            });
+        }
+    }
+    for user in &mut config.users {
+        for (field, value) in [
+            ("client-certificate", &mut user.user.client_certificate),
+            ("client-key", &mut user.user.client_key),
+        ] {
+            if let Some(path) = resolve(value, base) {
+                resolved.push(FileReference {
+                    kind: "user",
+                    name: user.name.clone(),
+                    field,
+                    path,
+                });
+            }
+        }
+    }
+    resolved
+}
+
+/// Move the contents of `path` into `data` as base64
+fn embed_file(path: &mut Option<String>, data: &mut Option<String>) -> Result<Option<String>> {
+    let Some(file) = path.take().filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    let bytes = fs::read(&file).with_context(|| format!("Failed to read {} to embed it", file))?;
+    *data = Some(base64::engine::general_purpose::STANDARD.encode(bytes));
+    Ok(Some(file))
+}
+
+/// Inline every certificate and key file as its `-data` field, so the
+/// config no longer depends on files on this machine
+pub fn embed(config: &mut KubeConfig) -> Result<Vec<FileReference>> {
+    let mut embedded = Vec::new();
+    for cluster in &mut config.clusters {
+        let info = &mut cluster.cluster;
+        if let Some(path) = embed_file(
+            &mut info.certificate_authority,
+            &mut info.certificate_authority_data,
+        )? {
+            embedded.push(FileReference {
+                kind: "cluster",
+                name: cluster.name.clone(),
+                field: "certificate-authority",
+                path,
+            });
+        }
+    }
+    for user in &mut config.users {
+        let info = &mut user.user;
+        for (field, path, data) in [
+            (
+                "client-certificate",
+                &mut info.client_certificate,
+                &mut info.client_certificate_data,
+            ),
+            (
+                "client-key",
+                &mut info.client_key,
+                &mut info.client_key_data,
+            ),
+        ] {
+            if let Some(path) = embed_file(path, data)? {
+                embedded.push(FileReference {
+                    kind: "user",
+                    name: user.name.clone(),
+                    field,
+                    path,
+                });
+            }
+        }
+    }
+    Ok(embedded)
+}
+
+/// An embedded `-data` field and the size of its base64 text
+#[derive(Debug, PartialEq)]
+pub struct EmbeddedData {
+    pub kind: &'static str,
+    pub name: String,
+    pub field: &'static str,
+    pub bytes: usize,
+}
+
+/// Every certificate and key embedded in the config
+pub fn embedded(config: &KubeConfig) -> Vec<EmbeddedData> {
+    let mut found = Vec::new();
+    for cluster in &config.clusters {
+        if let Some(data) = &cluster.cluster.certificate_authority_data {
+            found.push(EmbeddedData {
+                kind: "cluster",
+                name: cluster.name.clone(),
+                field: "certificate-authority-data",
+                bytes: data.len(),
+            });
+        }
+    }
+    for user in &config.users {
+        for (field, data) in [
+            (
+                "client-certificate-data",
+                &user.user.client_certificate_data,
+            ),
+            ("client-key-data", &user.user.client_key_data),
+        ] {
+            if let Some(data) = data {
+                found.push(EmbeddedData {
+                    kind: "user",
+                    name: user.name.clone(),
+                    field,
+                    bytes: data.len(),
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Decode `data` into `dir/file_name`, readable by the owner only, and
+/// point `path` at it
+fn externalize_field(
+    data: &mut Option<String>,
+    path: &mut Option<String>,
+    dir: &Path,
+    file_name: &str,
+) -> Result<String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.as_deref().unwrap_or_default().trim())
+        .with_context(|| format!("{} isn't valid base64", file_name))?;
+    let target = dir.join(file_name);
+    write_private(&target, &bytes)?;
+    let target = target.display().to_string();
+    *data = None;
+    *path = Some(target.clone());
+    Ok(target)
+}
+
+/// Move embedded data larger than `limit` bytes into files under `dir`, the
+/// inverse of `embed`. Files are named after the entry, e.g.
+/// `user-admin.client-key`.
+pub fn externalize(
+    config: &mut KubeConfig,
+    dir: &Path,
+    limit: usize,
+) -> Result<Vec<FileReference>> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {:?}", dir))?;
+    let file_name = |kind: &str, name: &str, field: &str| -> String {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{}-{}.{}", kind, name, field.trim_end_matches("-data"))
+    };
+    let large = |data: &Option<String>| data.as_ref().is_some_and(|d| d.len() > limit);
+    let mut moved = Vec::new();
+    for cluster in &mut config.clusters {
+        let info = &mut cluster.cluster;
+        if large(&info.certificate_authority_data) {
+            let path = externalize_field(
+                &mut info.certificate_authority_data,
+                &mut info.certificate_authority,
+                dir,
+                &file_name("cluster", &cluster.name, "certificate-authority"),
+            )?;
+            moved.push(FileReference {
+                kind: "cluster",
+                name: cluster.name.clone(),
+                field: "certificate-authority",
+                path,
+            });
+        }
+    }
+    for user in &mut config.users {
+        let info = &mut user.user;
+        for (field, data, path) in [
+            (
+                "client-certificate",
+                &mut info.client_certificate_data,
+                &mut info.client_certificate,
+            ),
+            (
+                "client-key",
+                &mut info.client_key_data,
+                &mut info.client_key,
+            ),
+        ] {
+            if large(data) {
+                let path =
+                    externalize_field(data, path, dir, &file_name("user", &user.name, field))?;
+                moved.push(FileReference {
+                    kind: "user",
+                    name: user.name.clone(),
+                    field,
+                    path,
+                });
+            }
+        }
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_absolutize_relative_references() {
+        let mut config = create_test_kubeconfig("a");
+        config.clusters[0].cluster.certificate_authority = Some("./ca.crt".to_string());
+        config.users[0].user.client_certificate = Some("certs/client.crt".to_string());
+        config.users[0].user.client_key = Some("/etc/kube/client.key".to_string());
+
+        let resolved = absolutize(&mut config, Path::new("/home/me/vendor"));
+        assert_eq!(
+            resolved
+                .iter()
+                .map(|r| (r.kind, r.field))
+                .collect::<Vec<_>>(),
+            vec![
+                ("cluster", "certificate-authority"),
+                ("user", "client-certificate")
+            ]
+        );
+        assert_eq!(
+            config.clusters[0].cluster.certificate_authority.as_deref(),
+            Some("/home/me/vendor/ca.crt")
+        );
+        assert_eq!(
+            config.users[0].user.client_certificate.as_deref(),
+            Some("/home/me/vendor/certs/client.crt")
+        );
+        assert_eq!(
+            config.users[0].user.client_key.as_deref(),
+            Some("/etc/kube/client.key")
+        );
+        assert!(absolutize(&mut config, Path::new("/elsewhere")).is_empty());
+    }
+
+    #[test]
+    fn test_embed_file_references() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let ca = temp_dir.path().join("ca.crt");
+        fs::write(&ca, "ca").unwrap();
+        let mut config = create_test_kubeconfig("a");
+        config.clusters[0].cluster.certificate_authority = Some(ca.display().to_string());
+
+        let embedded = embed(&mut config).unwrap();
+        assert_eq!(embedded.len(), 1);
+        assert_eq!(embedded[0].path, ca.display().to_string());
+        let cluster = &config.clusters[0].cluster;
+        assert_eq!(cluster.certificate_authority, None);
+        assert_eq!(cluster.certificate_authority_data.as_deref(), Some("Y2E="));
+
+        config.users[0].user.client_key = Some("/nonexistent/client.key".to_string());
+        assert!(embed(&mut config).is_err());
+    }
+
+    #[test]
+    fn test_externalize_large_data() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = create_test_kubeconfig("a");
+        config.users[0].user.client_key_data = Some("a2V5".to_string());
+        let large = base64::engine::general_purpose::STANDARD.encode(vec![b'x'; 64]);
+        config.users[0].user.client_certificate_data = Some(large.clone());
+
+        let sizes = embedded(&config);
+        assert!(sizes
+            .iter()
+            .any(|e| e.field == "client-certificate-data" && e.bytes == large.len()));
+
+        let moved = externalize(&mut config, temp_dir.path(), 16).unwrap();
+        assert_eq!(moved.len(), 1);
+        let user = &config.users[0].user;
+        assert_eq!(user.client_certificate_data, None);
+        let path = user.client_certificate.as_deref().unwrap();
+        assert!(path.ends_with("user-a-user.client-certificate"));
+        assert_eq!(fs::read(path).unwrap(), vec![b'x'; 64]);
+        // Small enough to stay embedded
+        assert_eq!(user.client_key_data.as_deref(), Some("a2V5"));
+
+        // Round trip
+        embed(&mut config).unwrap();
+        assert_eq!(config.users[0].user.client_certificate_data, Some(large));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{conflicting_entries, KubeConfig, UserInfo};
+
+/// Short description of how a user authenticates
+pub fn auth_type(user: &UserInfo) -> String {
+    let mut kinds = Vec::new();
+    if let Some(exec) = &user.exec {
+        kinds.push(format!("exec ({})", exec.command));
+    }
+    if let Some(provider) = &user.auth_provider {
+        kinds.push(format!("auth-provider ({})", provider.name));
+    }
+    if user.client_certificate_data.is_some() || user.client_certificate.is_some() {
+        kinds.push("client certificate".to_string());
+    }
+    if user.token.is_some() {
+        kinds.push("token".to_string());
+    }
+    if user.username.is_some() || user.password.is_some() {
+        kinds.push("basic auth".to_string());
+    }
+    if kinds.is_empty() {
+        "none".to_string()
+    } else {
+        kinds.join(" + ")
+    }
+}
+
+/// Table rows describing each context in `source`, starting with a header row.
+/// With a destination, the last column says whether merging would add the
+/// context, find it already present, or conflict with a different entry.
+pub fn rows(source: &KubeConfig, dest: Option<&KubeConfig>) -> Vec<Vec<String>> {
+    let mut header = vec!["NAME", "CLUSTER", "SERVER", "USER", "AUTH", "NAMESPACE"];
+    if dest.is_some() {
+        header.push("DESTINATION");
+    }
+    let mut rows = vec![header.into_iter().map(str::to_string).collect()];
+    let conflicts = dest
+        .map(|d| conflicting_entries(d, source))
+        .unwrap_or_default();
+
+    for context in &source.contexts {
+        let cluster = source
+            .clusters
+            .iter()
+            .find(|c| c.name == context.context.cluster);
+        let user = source.users.iter().find(|u| u.name == context.context.user);
+        let mut row = vec![
+            context.name.clone(),
+            context.context.cluster.clone(),
+            cluster
+                .map(|c| c.cluster.server.clone())
+                .unwrap_or_else(|| "(missing)".to_string()),
+            context.context.user.clone(),
+            user.map(|u| auth_type(&u.user))
+                .unwrap_or_else(|| "(missing)".to_string()),
+            context.context.namespace.clone().unwrap_or_default(),
+        ];
+        if let Some(dest) = dest {
+            let conflicted = [
+                format!("context '{}'", context.name),
+                format!("cluster '{}'", context.context.cluster),
+                format!("user '{}'", context.context.user),
+            ]
+            .iter()
+            .any(|c| conflicts.contains(c));
+            let status = if conflicted {
+                "conflict"
+            } else if dest.contexts.iter().any(|c| c.name == context.name) {
+                "exists"
+            } else {
+                "new"
+            };
+            row.push(status.to_string());
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+/// Clusters and users that no context in `config` references
+pub fn unreferenced(config: &KubeConfig) -> Vec<String> {
+    let mut names = Vec::new();
+    for cluster in &config.clusters {
+        if !config
+            .contexts
+            .iter()
+            .any(|c| c.context.cluster == cluster.name)
+        {
+            names.push(format!("cluster '{}'", cluster.name));
+        }
+    }
+    for user in &config.users {
+        if !config.contexts.iter().any(|c| c.context.user == user.name) {
+            names.push(format!("user '{}'", user.name));
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_rows_mark_destination_status() {
+        let dest = create_test_kubeconfig("a");
+        let mut source = create_test_kubeconfig("a");
+        source.users[0].user.token = Some("rotated".to_string());
+        let other = create_test_kubeconfig("b");
+        source.clusters.extend(other.clusters);
+        source.contexts.extend(other.contexts);
+        source.users.extend(other.users);
+
+        let rows = rows(&source, Some(&dest));
+        assert_eq!(rows[0].last().unwrap(), "DESTINATION");
+        assert_eq!(rows[1][0], "a-context");
+        assert_eq!(rows[1][4], "client certificate + token");
+        assert_eq!(rows[1][6], "conflict");
+        assert_eq!(rows[2][6], "new");
+        assert_eq!(super::rows(&dest, Some(&dest))[1][6], "exists");
+    }
+
+    #[test]
+    fn test_unreferenced() {
+        let mut config = create_test_kubeconfig("a");
+        config.clusters.extend(create_test_kubeconfig("b").clusters);
+        assert_eq!(unreferenced(&config), vec!["cluster 'b-cluster'"]);
+    }
+}
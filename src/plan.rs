@@ -0,0 +1,250 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{backup, write_private, KubeConfig, NamedCluster, NamedContext, NamedUser};
+
+/// Format version of plan files written by this build
+pub const PLAN_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Add,
+    Update,
+}
+
+/// A complete entry as it will be written to the destination
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+#[allow(clippy::large_enum_variant)]
+pub enum Entry {
+    Cluster(NamedCluster),
+    Context(NamedContext),
+    User(NamedUser),
+}
+
+impl Entry {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Entry::Cluster(_) => "cluster",
+            Entry::Context(_) => "context",
+            Entry::User(_) => "user",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Entry::Cluster(c) => &c.name,
+            Entry::Context(c) => &c.name,
+            Entry::User(u) => &u.name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedChange {
+    pub action: Action,
+    pub entry: Entry,
+}
+
+/// A reviewed set of changes to apply to one destination, valid only while
+/// the destination is byte-for-byte what it was when the plan was made
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub created_at: String,
+    pub destination: PathBuf,
+    /// SHA-256 of the destination file, or None if it didn't exist
+    pub destination_sha256: Option<String>,
+    pub sources: Vec<String>,
+    pub changes: Vec<PlannedChange>,
+    /// New current context, if the plan changes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.current_context.is_none()
+    }
+}
+
+/// SHA-256 of a file's content, or None if it doesn't exist
+pub fn fingerprint(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(Some(backup::hex(&Sha256::digest(&content))))
+}
+
+fn collect<T: PartialEq + Clone>(
+    before: &[T],
+    after: &[T],
+    name: impl Fn(&T) -> &str,
+    entry: impl Fn(T) -> Entry,
+    changes: &mut Vec<PlannedChange>,
+) {
+    for item in after {
+        let action = match before.iter().find(|b| name(b) == name(item)) {
+            None => Action::Add,
+            Some(existing) if existing != item => Action::Update,
+            Some(_) => continue,
+        };
+        changes.push(PlannedChange {
+            action,
+            entry: entry(item.clone()),
+        });
+    }
+}
+
+/// Entries added or changed going from `before` to `after`
+pub fn changes(before: &KubeConfig, after: &KubeConfig) -> Vec<PlannedChange> {
+    let mut changes = Vec::new();
+    collect(
+        &before.clusters,
+        &after.clusters,
+        |c| &c.name,
+        Entry::Cluster,
+        &mut changes,
+    );
+    collect(
+        &before.contexts,
+        &after.contexts,
+        |c| &c.name,
+        Entry::Context,
+        &mut changes,
+    );
+    collect(
+        &before.users,
+        &after.users,
+        |u| &u.name,
+        Entry::User,
+        &mut changes,
+    );
+    changes
+}
+
+fn upsert<T>(entries: &mut Vec<T>, entry: T, name: impl Fn(&T) -> &str) {
+    match entries.iter().position(|e| name(e) == name(&entry)) {
+        Some(i) => entries[i] = entry,
+        None => entries.push(entry),
+    }
+}
+
+/// Apply exactly the planned changes to `config`
+pub fn apply(config: &mut KubeConfig, plan: &Plan) {
+    for change in &plan.changes {
+        match change.entry.clone() {
+            Entry::Cluster(c) => upsert(&mut config.clusters, c, |c| &c.name),
+            Entry::Context(c) => upsert(&mut config.contexts, c, |c| &c.name),
+            Entry::User(u) => upsert(&mut config.users, u, |u| &u.name),
+        }
+    }
+    if let Some(current) = &plan.current_context {
+        config.current_context = Some(current.clone());
+    }
+}
+
+/// Write a plan as JSON. Plans carry full entries, credentials included, so
+/// the file is readable by the owner only.
+pub fn save(path: &Path, plan: &Plan) -> Result<()> {
+    let content = serde_json::to_string_pretty(plan)?;
+    write_private(path, content.as_bytes()).context("Failed to save the plan")
+}
+
+pub fn load(path: &Path) -> Result<Plan> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read plan: {:?}", path))?;
+    let plan: Plan = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse plan: {:?}", path))?;
+    if plan.version != PLAN_VERSION {
+        anyhow::bail!(
+            "Plan {:?} has format version {}, but this kconf reads version {}",
+            path,
+            plan.version,
+            PLAN_VERSION
+        );
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_plan_round_trip_reproduces_merge() {
+        let before = create_test_kubeconfig("a");
+        let mut after = before.clone();
+        after.clusters[0].cluster.server = "https://moved:6443".to_string();
+        let other = create_test_kubeconfig("b");
+        after.clusters.extend(other.clusters);
+        after.contexts.extend(other.contexts);
+        after.users.extend(other.users);
+
+        let plan = Plan {
+            version: PLAN_VERSION,
+            created_at: String::new(),
+            destination: PathBuf::from("config"),
+            destination_sha256: None,
+            sources: vec!["b.yaml".to_string()],
+            changes: changes(&before, &after),
+            current_context: Some("b-context".to_string()),
+        };
+        let summary: Vec<_> = plan
+            .changes
+            .iter()
+            .map(|c| (c.action, c.entry.kind(), c.entry.name()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Action::Update, "cluster", "a-cluster"),
+                (Action::Add, "cluster", "b-cluster"),
+                (Action::Add, "context", "b-context"),
+                (Action::Add, "user", "b-user"),
+            ]
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("plan.json");
+        // An existing, world-readable file is tightened
+        fs::write(&path, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        save(&path, &plan).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.changes, plan.changes);
+
+        let mut applied = before.clone();
+        apply(&mut applied, &loaded);
+        after.current_context = Some("b-context".to_string());
+        assert_eq!(applied, after);
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config");
+        assert_eq!(fingerprint(&path).unwrap(), None);
+        fs::write(&path, "a").unwrap();
+        let first = fingerprint(&path).unwrap();
+        fs::write(&path, "b").unwrap();
+        assert_ne!(fingerprint(&path).unwrap(), first);
+    }
+}
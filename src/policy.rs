@@ -0,0 +1,171 @@
+use crate::{matches_pattern, KubeConfig, Policies};
+
+/// A single policy rule violated by a source kubeconfig
+#[derive(Debug)]
+pub struct Violation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+/// Evaluate the configured policies against a source kubeconfig.
+/// Insecure TLS is handled separately since it is enforced via `--allow-insecure`.
+pub fn evaluate(policies: &Policies, source: &KubeConfig) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    if policies.deny_plaintext_passwords {
+        for user in &source.users {
+            if user.user.password.is_some() {
+                violations.push(Violation {
+                    rule: "deny_plaintext_passwords",
+                    message: format!("user '{}' has a plaintext password", user.name),
+                });
+            }
+        }
+    }
+
+    if policies.deny_http_servers {
+        for cluster in &source.clusters {
+            if cluster.cluster.server.starts_with("http://") {
+                violations.push(Violation {
+                    rule: "deny_http_servers",
+                    message: format!(
+                        "cluster '{}' uses an unencrypted server URL: {}",
+                        cluster.name, cluster.cluster.server
+                    ),
+                });
+            }
+        }
+    }
+
+    if !policies.require_exec_for_servers.is_empty() {
+        for context in &source.contexts {
+            let server = match source
+                .clusters
+                .iter()
+                .find(|c| c.name == context.context.cluster)
+            {
+                Some(c) => &c.cluster.server,
+                None => continue,
+            };
+            if !policies
+                .require_exec_for_servers
+                .iter()
+                .any(|p| matches_pattern(p, server))
+            {
+                continue;
+            }
+            let uses_exec = source
+                .users
+                .iter()
+                .find(|u| u.name == context.context.user)
+                .is_some_and(|u| u.user.exec.is_some());
+            if !uses_exec {
+                violations.push(Violation {
+                    rule: "require_exec_for_servers",
+                    message: format!(
+                        "context '{}' targets {} but its user does not use exec authentication",
+                        context.name, server
+                    ),
+                });
+            }
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ClusterInfo, ContextInfo, ExecConfig, NamedCluster, NamedContext, NamedUser, UserInfo,
+    };
+
+    fn source(server: &str, user: UserInfo) -> KubeConfig {
+        let mut config = crate::create_empty_kubeconfig();
+        config.clusters.push(NamedCluster {
+            name: "c".to_string(),
+            cluster: ClusterInfo {
+                server: server.to_string(),
+                certificate_authority_data: None,
+                certificate_authority: None,
+                insecure_skip_tls_verify: None,
+                proxy_url: None,
+                tls_server_name: None,
+            },
+        });
+        config.contexts.push(NamedContext {
+            name: "ctx".to_string(),
+            context: ContextInfo {
+                cluster: "c".to_string(),
+                user: "u".to_string(),
+                namespace: None,
+                extensions: None,
+            },
+        });
+        config.users.push(NamedUser {
+            name: "u".to_string(),
+            user,
+        });
+        config
+    }
+
+    fn basic_auth_user() -> UserInfo {
+        UserInfo {
+            client_certificate_data: None,
+            client_key_data: None,
+            client_certificate: None,
+            client_key: None,
+            token: None,
+            username: Some("admin".to_string()),
+            password: Some("hunter2".to_string()),
+            auth_provider: None,
+            exec: None,
+        }
+    }
+
+    #[test]
+    fn test_no_policies_no_violations() {
+        let config = source("http://insecure.example.com", basic_auth_user());
+        assert!(evaluate(&Policies::default(), &config).is_empty());
+    }
+
+    #[test]
+    fn test_deny_plaintext_passwords_and_http() {
+        let policies = Policies {
+            deny_plaintext_passwords: true,
+            deny_http_servers: true,
+            ..Default::default()
+        };
+        let config = source("http://insecure.example.com", basic_auth_user());
+        let violations = evaluate(&policies, &config);
+        let rules: Vec<_> = violations.iter().map(|v| v.rule).collect();
+        assert_eq!(rules, vec!["deny_plaintext_passwords", "deny_http_servers"]);
+    }
+
+    #[test]
+    fn test_require_exec_for_matching_servers() {
+        let policies = Policies {
+            require_exec_for_servers: vec!["https://*.prod.example.com*".to_string()],
+            ..Default::default()
+        };
+        let config = source("https://api.prod.example.com:6443", basic_auth_user());
+        assert_eq!(evaluate(&policies, &config).len(), 1);
+
+        let mut exec_user = basic_auth_user();
+        exec_user.exec = Some(ExecConfig {
+            api_version: "client.authentication.k8s.io/v1beta1".to_string(),
+            command: "aws".to_string(),
+            args: None,
+            env: None,
+            install_hint: None,
+            provide_cluster_info: None,
+            interactive_mode: None,
+        });
+        let config = source("https://api.prod.example.com:6443", exec_user);
+        assert!(evaluate(&policies, &config).is_empty());
+
+        let config = source("https://dev.example.com", basic_auth_user());
+        assert!(evaluate(&policies, &config).is_empty());
+    }
+}
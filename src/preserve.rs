@@ -0,0 +1,164 @@
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::KubeConfig;
+
+/// Split `cluster.proxy-url` into the entry kind and the path below it
+fn parse(field: &str) -> Result<(&str, Vec<&str>)> {
+    match field.split_once('.') {
+        Some((kind @ ("cluster" | "context" | "user"), path)) if !path.is_empty() => {
+            Ok((kind, path.split('.').collect()))
+        }
+        _ => bail!(
+            "Invalid preserve_fields entry '{}' (expected cluster.*, context.* or user.*)",
+            field
+        ),
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |v, key| v.get(key))
+}
+
+/// Set `path` in `value`, creating the mappings above it
+fn insert(value: &mut Value, path: &[&str], new: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut current = value;
+    for key in parents {
+        let Value::Mapping(map) = current else {
+            return;
+        };
+        current = map
+            .entry(Value::from(*key))
+            .or_insert_with(|| Value::Mapping(Default::default()));
+    }
+    if let Value::Mapping(map) = current {
+        map.insert(Value::from(*last), new);
+    }
+}
+
+/// Copy the fields at `paths` from `existing` into `incoming`, where the
+/// existing entry sets them. Returns the fields that differed.
+fn keep<T: Serialize + DeserializeOwned>(
+    existing: &T,
+    incoming: &mut T,
+    paths: &[Vec<&str>],
+) -> Vec<String> {
+    let (Ok(existing), Ok(mut value)) = (
+        serde_yaml::to_value(existing),
+        serde_yaml::to_value(&*incoming),
+    ) else {
+        return Vec::new();
+    };
+    let mut kept = Vec::new();
+    for path in paths {
+        let Some(local) = lookup(&existing, path) else {
+            continue;
+        };
+        if lookup(&value, path) != Some(local) {
+            insert(&mut value, path, local.clone());
+            kept.push(path.join("."));
+        }
+    }
+    if !kept.is_empty() {
+        if let Ok(updated) = serde_yaml::from_value(value) {
+            *incoming = updated;
+        }
+    }
+    kept
+}
+
+/// Keep the destination's value of each `fields` entry (e.g.
+/// `context.namespace`, `cluster.proxy-url`) on same-named source entries,
+/// so updates never overwrite local customizations. Returns (kind, name,
+/// field) for each value kept that the source would have changed.
+pub fn carry_over(
+    dest: &KubeConfig,
+    source: &mut KubeConfig,
+    fields: &[String],
+) -> Result<Vec<(&'static str, String, String)>> {
+    let mut paths: [Vec<Vec<&str>>; 3] = Default::default();
+    for field in fields {
+        let (kind, path) = parse(field)?;
+        let index = match kind {
+            "cluster" => 0,
+            "context" => 1,
+            _ => 2,
+        };
+        paths[index].push(path);
+    }
+
+    let mut kept = Vec::new();
+    if !paths[0].is_empty() {
+        for cluster in &mut source.clusters {
+            if let Some(existing) = dest.clusters.iter().find(|c| c.name == cluster.name) {
+                for field in keep(&existing.cluster, &mut cluster.cluster, &paths[0]) {
+                    kept.push(("cluster", cluster.name.clone(), field));
+                }
+            }
+        }
+    }
+    if !paths[1].is_empty() {
+        for context in &mut source.contexts {
+            if let Some(existing) = dest.contexts.iter().find(|c| c.name == context.name) {
+                for field in keep(&existing.context, &mut context.context, &paths[1]) {
+                    kept.push(("context", context.name.clone(), field));
+                }
+            }
+        }
+    }
+    if !paths[2].is_empty() {
+        for user in &mut source.users {
+            if let Some(existing) = dest.users.iter().find(|u| u.name == user.name) {
+                for field in keep(&existing.user, &mut user.user, &paths[2]) {
+                    kept.push(("user", user.name.clone(), field));
+                }
+            }
+        }
+    }
+    Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_preserve_fields() {
+        let mut dest = create_test_kubeconfig("a");
+        dest.contexts[0].context.namespace = Some("mine".to_string());
+        dest.clusters[0].cluster.proxy_url = Some("http://proxy:3128".to_string());
+        let mut source = create_test_kubeconfig("a");
+        source.contexts[0].context.namespace = Some("vendor".to_string());
+        source.clusters[0].cluster.server = "https://new:6443".to_string();
+
+        let fields = vec![
+            "context.namespace".to_string(),
+            "cluster.proxy-url".to_string(),
+            "user.exec.command".to_string(),
+        ];
+        let kept = carry_over(&dest, &mut source, &fields).unwrap();
+        assert_eq!(
+            kept,
+            vec![
+                ("cluster", "a-cluster".to_string(), "proxy-url".to_string()),
+                ("context", "a-context".to_string(), "namespace".to_string()),
+            ]
+        );
+        assert_eq!(
+            source.contexts[0].context.namespace.as_deref(),
+            Some("mine")
+        );
+        let cluster = &source.clusters[0].cluster;
+        assert_eq!(cluster.proxy_url.as_deref(), Some("http://proxy:3128"));
+        // Fields not listed still update
+        assert_eq!(cluster.server, "https://new:6443");
+
+        assert!(carry_over(&dest, &mut source, &["namespace".to_string()]).is_err());
+    }
+}
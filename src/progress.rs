@@ -0,0 +1,99 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, IsTerminal};
+
+/// Progress over a batch of items: an indicatif bar when stderr is a terminal
+/// and there is more than one item, plain numbered lines otherwise
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    total: usize,
+    done: usize,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        let bar = (total > 1 && io::stderr().is_terminal()).then(|| {
+            let bar = ProgressBar::new(total as u64);
+            bar.set_style(
+                ProgressStyle::with_template("{bar:30.cyan/blue} {pos}/{len} {wide_msg}")
+                    .expect("static template is valid"),
+            );
+            bar
+        });
+        Self {
+            bar,
+            total,
+            done: 0,
+        }
+    }
+
+    /// Status line for the next item, numbered when there are several
+    fn header(&self, verb: &str, item: &str) -> String {
+        if self.total > 1 {
+            format!("{} [{}/{}]: {}", verb, self.done + 1, self.total, item)
+        } else {
+            format!("{}: {}", verb, item)
+        }
+    }
+
+    /// Run `work` for `item`, keeping whatever it prints above the bar
+    pub fn run<T>(&mut self, verb: &str, item: &str, work: impl FnOnce() -> T) -> T {
+        let header = self.header(verb, item);
+        self.done += 1;
+        match &self.bar {
+            Some(bar) => {
+                bar.set_message(item.to_string());
+                let result = bar.suspend(|| {
+                    println!("{}", header);
+                    work()
+                });
+                bar.inc(1);
+                result
+            }
+            None => {
+                println!("{}", header);
+                work()
+            }
+        }
+    }
+
+    /// Mark one item finished; usable from several threads at once. Prints
+    /// nothing without a bar, since callers report results afterwards.
+    pub fn tick(&self, item: &str) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(item.to_string());
+            bar.inc(1);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_progress_numbers_items() {
+        // Tests don't run on a terminal, so this takes the plain-line path
+        let mut progress = Progress::new(2);
+        assert!(progress.bar.is_none());
+        assert_eq!(
+            progress.header("Processing", "a.yaml"),
+            "Processing [1/2]: a.yaml"
+        );
+        assert_eq!(progress.run("Processing", "a.yaml", || 7), 7);
+        assert_eq!(
+            progress.header("Processing", "b.yaml"),
+            "Processing [2/2]: b.yaml"
+        );
+
+        let single = Progress::new(1);
+        assert_eq!(single.header("Processing", "a.yaml"), "Processing: a.yaml");
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{matches_pattern, KconfError, KubeConfig, MergeResult};
+
+/// Whether context `name` matches one of the `protected` patterns
+pub fn is_protected(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|p| matches_pattern(p, name))
+}
+
+/// Fail when `name` is protected, unless `unprotect` was given
+pub fn guard(patterns: &[String], name: &str, unprotect: bool) -> Result<(), KconfError> {
+    if !unprotect && is_protected(patterns, name) {
+        return Err(KconfError::Protected(format!("context '{}'", name)));
+    }
+    Ok(())
+}
+
+/// Entries an update would change that belong to protected contexts of
+/// `dest`: the contexts themselves and the clusters and users they use
+pub fn touched(dest: &KubeConfig, patterns: &[String], result: &MergeResult) -> Vec<String> {
+    let protected: Vec<_> = dest
+        .contexts
+        .iter()
+        .filter(|c| is_protected(patterns, &c.name))
+        .collect();
+    let mut touched = Vec::new();
+    for context in &result.contexts_to_update {
+        if protected.iter().any(|p| p.name == context.name) {
+            touched.push(format!("context '{}'", context.name));
+        }
+    }
+    for cluster in &result.clusters_to_update {
+        if let Some(owner) = protected.iter().find(|p| p.context.cluster == cluster.name) {
+            touched.push(format!(
+                "cluster '{}' (used by '{}')",
+                cluster.name, owner.name
+            ));
+        }
+    }
+    for user in &result.users_to_update {
+        if let Some(owner) = protected.iter().find(|p| p.context.user == user.name) {
+            touched.push(format!("user '{}' (used by '{}')", user.name, owner.name));
+        }
+    }
+    touched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use crate::{filter_duplicates, UpdateMode};
+
+    #[test]
+    fn test_protected_updates() {
+        let dest = create_test_kubeconfig("prod");
+        let patterns = vec!["prod-*".to_string()];
+        assert!(guard(&patterns, "prod-context", false).is_err());
+        assert!(guard(&patterns, "prod-context", true).is_ok());
+        assert!(guard(&patterns, "dev-context", false).is_ok());
+
+        let mut source = create_test_kubeconfig("prod");
+        source.users[0].user.token = Some("rotated".to_string());
+        let result = filter_duplicates(&dest, source, UpdateMode::Replace);
+        assert_eq!(
+            touched(&dest, &patterns, &result),
+            vec!["user 'prod-user' (used by 'prod-context')"]
+        );
+        assert!(touched(&dest, &["staging-*".to_string()], &result).is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{ContextInfo, KubeConfig, NamedContext, NamedExtension};
+
+/// Name of the context extension holding kconf's provenance record
+pub const EXTENSION_NAME: &str = "kconf/provenance";
+
+/// Where and when a context was imported
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Provenance {
+    /// Source file path or URL
+    pub source: String,
+    /// RFC 3339 timestamp of the import
+    pub imported_at: String,
+    /// kconf version that performed the import
+    pub kconf_version: String,
+    /// RFC 3339 timestamp after which `kconf clean` removes the context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+impl Provenance {
+    /// Provenance for an import from `source` happening now
+    pub fn now(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            imported_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            kconf_version: env!("CARGO_PKG_VERSION").to_string(),
+            expires_at: None,
+        }
+    }
+}
+
+/// Provenance recorded on a context, if any
+pub fn get(context: &ContextInfo) -> Option<Provenance> {
+    context
+        .extensions
+        .as_ref()?
+        .iter()
+        .find(|e| e.name == EXTENSION_NAME)
+        .and_then(|e| serde_yaml::from_value(e.extension.clone()).ok())
+}
+
+/// Record provenance on a context, replacing any earlier record
+pub fn stamp(context: &mut ContextInfo, provenance: &Provenance) {
+    let extension = NamedExtension {
+        name: EXTENSION_NAME.to_string(),
+        extension: serde_yaml::to_value(provenance).expect("provenance serializes"),
+    };
+    let extensions = context.extensions.get_or_insert_with(Vec::new);
+    match extensions.iter_mut().find(|e| e.name == EXTENSION_NAME) {
+        Some(existing) => *existing = extension,
+        None => extensions.push(extension),
+    }
+}
+
+/// Copy the destination's provenance onto same-named source contexts that
+/// don't carry one, so an otherwise identical context still compares equal
+pub fn carry_over(dest: &KubeConfig, source: &mut KubeConfig) {
+    for context in &mut source.contexts {
+        if get(&context.context).is_some() {
+            continue;
+        }
+        let existing = dest
+            .contexts
+            .iter()
+            .find(|c| c.name == context.name)
+            .and_then(|c| get(&c.context));
+        if let Some(provenance) = existing {
+            stamp(&mut context.context, &provenance);
+        }
+    }
+}
+
+/// Stamp every context in `contexts` with the same provenance
+pub fn stamp_all(contexts: &mut [NamedContext], provenance: &Provenance) {
+    for context in contexts {
+        stamp(&mut context.context, provenance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_stamp_and_get() {
+        let mut config = create_test_kubeconfig("test");
+        let context = &mut config.contexts[0].context;
+        assert!(get(context).is_none());
+
+        stamp(context, &Provenance::now("/tmp/old.yaml"));
+        let provenance = Provenance::now("/tmp/new.yaml");
+        stamp(context, &provenance);
+        assert_eq!(context.extensions.as_ref().unwrap().len(), 1);
+        assert_eq!(get(context), Some(provenance));
+    }
+
+    #[test]
+    fn test_carry_over_keeps_identical_contexts_equal() {
+        let mut dest = create_test_kubeconfig("test");
+        stamp_all(&mut dest.contexts, &Provenance::now("/tmp/test.yaml"));
+        let mut source = create_test_kubeconfig("test");
+        carry_over(&dest, &mut source);
+        assert_eq!(source.contexts, dest.contexts);
+    }
+}
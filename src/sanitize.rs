@@ -0,0 +1,109 @@
+use sha2::{Digest, Sha256};
+
+use crate::backup::hex;
+use crate::check::server_address;
+use crate::sync::strip_credentials;
+use crate::{provenance, KubeConfig};
+
+/// Stable stand-in for a hostname, so the same host always maps to the same
+/// placeholder and shared servers stay recognisable
+fn hash_host(host: &str) -> String {
+    format!("host-{}", &hex(&Sha256::digest(host.as_bytes()))[..12])
+}
+
+/// Replace the host of `url`, keeping the scheme, port and path
+fn hash_url_host(url: &str) -> String {
+    match server_address(url) {
+        Some((host, _)) if !host.is_empty() => url.replacen(&host, &hash_host(&host), 1),
+        _ => url.to_string(),
+    }
+}
+
+/// Drop `user:password@` from a URL
+pub fn strip_userinfo(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..authority_end].rfind('@') {
+        Some(at) if scheme.is_empty() => rest[at + 1..].to_string(),
+        Some(at) => format!("{}://{}", scheme, &rest[at + 1..]),
+        None => url.to_string(),
+    }
+}
+
+/// Copy of `config` that's safe to share: every user credential is removed
+/// (exec plugins included, as their arguments and environment can carry
+/// secrets) and proxy URLs lose their login. With `hash_hosts`, server,
+/// proxy and TLS server names are replaced by hashes, and CA data is dropped
+/// since its certificates name the hosts too. URL sources recorded in
+/// provenance get the same treatment, minus any query string.
+pub fn sanitize(config: &KubeConfig, hash_hosts: bool) -> KubeConfig {
+    let mut sanitized = strip_credentials(config);
+    for cluster in &mut sanitized.clusters {
+        let info = &mut cluster.cluster;
+        info.proxy_url = info.proxy_url.as_deref().map(strip_userinfo);
+        if hash_hosts {
+            info.server = hash_url_host(&info.server);
+            info.proxy_url = info.proxy_url.as_deref().map(hash_url_host);
+            info.tls_server_name = info.tls_server_name.as_deref().map(hash_host);
+            info.certificate_authority_data = None;
+        }
+    }
+    for context in &mut sanitized.contexts {
+        let Some(mut record) = provenance::get(&context.context) else {
+            continue;
+        };
+        if record.source.contains("://") {
+            let source = strip_userinfo(&record.source);
+            let source = source.split('?').next().unwrap_or_default();
+            record.source = if hash_hosts {
+                hash_url_host(source)
+            } else {
+                source.to_string()
+            };
+            provenance::stamp(&mut context.context, &record);
+        }
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_sanitize() {
+        let mut config = create_test_kubeconfig("a");
+        config.users[0].user.token = Some("secret".to_string());
+        let cluster = &mut config.clusters[0].cluster;
+        cluster.server = "https://api.example.com:6443".to_string();
+        cluster.proxy_url = Some("http://me:pw@proxy.example.com:3128".to_string());
+
+        let shared = sanitize(&config, false);
+        assert_eq!(shared.users[0].user.token, None);
+        assert_eq!(shared.users[0].user.client_key_data, None);
+        let cluster = &shared.clusters[0].cluster;
+        assert_eq!(cluster.server, "https://api.example.com:6443");
+        assert_eq!(
+            cluster.proxy_url.as_deref(),
+            Some("http://proxy.example.com:3128")
+        );
+        assert!(cluster.certificate_authority_data.is_some());
+
+        let hashed = sanitize(&config, true);
+        let cluster = &hashed.clusters[0].cluster;
+        let host = hash_host("api.example.com");
+        assert_eq!(cluster.server, format!("https://{}:6443", host));
+        assert!(!cluster.proxy_url.as_deref().unwrap().contains("example"));
+        assert_eq!(cluster.certificate_authority_data, None);
+        assert_eq!(hashed.contexts, config.contexts);
+
+        provenance::stamp(
+            &mut config.contexts[0].context,
+            &provenance::Provenance::now("https://ci:pw@files.example.com/a.yaml?token=t"),
+        );
+        let shared = sanitize(&config, false);
+        let record = provenance::get(&shared.contexts[0].context).unwrap();
+        assert_eq!(record.source, "https://files.example.com/a.yaml");
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+#[cfg(not(feature = "keyring"))]
+use crate::KconfError;
+
+/// Service name kconf's entries are stored under in the OS keyring
+#[cfg(feature = "keyring")]
+pub const KEYRING_SERVICE: &str = "kconf";
+
+/// Where a secret comes from: an environment variable, an entry in the OS
+/// keyring (see `kconf secret set`), or a literal for values that aren't
+/// actually secret. Exactly one field must be set.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SecretRef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyring: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl SecretRef {
+    /// Look the secret up
+    pub fn resolve(&self) -> Result<String> {
+        match (&self.env, &self.keyring, &self.value) {
+            (Some(name), None, None) => {
+                env::var(name).with_context(|| format!("Environment variable not set: {}", name))
+            }
+            (None, Some(name), None) => get(name),
+            (None, None, Some(value)) => Ok(value.clone()),
+            _ => anyhow::bail!("A secret must set exactly one of env, keyring or value"),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn entry(name: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .with_context(|| format!("Failed to open keyring entry '{}'", name))
+}
+
+/// Read a secret stored in the OS keyring
+#[cfg(feature = "keyring")]
+pub fn get(name: &str) -> Result<String> {
+    entry(name)?
+        .get_password()
+        .with_context(|| format!("Failed to read '{}' from the OS keyring", name))
+}
+
+/// Read a secret stored in the OS keyring, or None if there is no such entry
+#[cfg(feature = "keyring")]
+pub fn find(name: &str) -> Result<Option<String>> {
+    match entry(name)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(anyhow::Error::from(e)
+            .context(format!("Failed to read '{}' from the OS keyring", name))),
+    }
+}
+
+/// Store a secret in the OS keyring, replacing any previous value
+#[cfg(feature = "keyring")]
+pub fn set(name: &str, secret: &str) -> Result<()> {
+    entry(name)?
+        .set_password(secret)
+        .with_context(|| format!("Failed to store '{}' in the OS keyring", name))
+}
+
+/// Remove a secret from the OS keyring
+#[cfg(feature = "keyring")]
+pub fn delete(name: &str) -> Result<()> {
+    entry(name)?
+        .delete_credential()
+        .with_context(|| format!("Failed to delete '{}' from the OS keyring", name))
+}
+
+// Without the keyring feature, keyring secrets fail when they're used
+#[cfg(not(feature = "keyring"))]
+pub fn get(_name: &str) -> Result<String> {
+    Err(KconfError::FeatureDisabled("keyring").into())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn find(_name: &str) -> Result<Option<String>> {
+    Err(KconfError::FeatureDisabled("keyring").into())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn set(_name: &str, _secret: &str) -> Result<()> {
+    Err(KconfError::FeatureDisabled("keyring").into())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn delete(_name: &str) -> Result<()> {
+    Err(KconfError::FeatureDisabled("keyring").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_env_and_value() {
+        env::set_var("KCONF_TEST_SECRET_REF", "s3cret");
+        let from_env: SecretRef = serde_yaml::from_str("env: KCONF_TEST_SECRET_REF").unwrap();
+        assert_eq!(from_env.resolve().unwrap(), "s3cret");
+
+        let literal: SecretRef = serde_yaml::from_str("value: plain").unwrap();
+        assert_eq!(literal.resolve().unwrap(), "plain");
+
+        assert!(SecretRef::default().resolve().is_err());
+        let both: SecretRef = serde_yaml::from_str("env: A\nvalue: b").unwrap();
+        assert!(both.resolve().is_err());
+    }
+}
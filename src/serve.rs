@@ -0,0 +1,512 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::{
+    create_empty_kubeconfig, kubens, load_destination, merge_source, needs_write, parse_kubeconfig,
+    remove_context, save_destination, AppConfig, ConflictStrategy, KubeConfig, MergeOptions,
+    MergeTotals, ReuseClusters, UpdateMode,
+};
+
+/// HTTP status and JSON body of a response
+type Response = (u16, serde_json::Value);
+
+/// Largest request body accepted, well above any real kubeconfig
+const MAX_BODY_BYTES: u64 = 8 * 1024 * 1024;
+
+fn error(status: u16, message: impl std::fmt::Display) -> Response {
+    (status, json!({ "error": message.to_string() }))
+}
+
+fn load(dest_path: &Path) -> Result<KubeConfig> {
+    if dest_path.exists() {
+        load_destination(&dest_path.to_path_buf())
+    } else {
+        Ok(create_empty_kubeconfig())
+    }
+}
+
+fn list_contexts(config: &KubeConfig) -> Response {
+    let contexts: Vec<serde_json::Value> = config
+        .contexts
+        .iter()
+        .map(|c| {
+            json!({
+                "name": c.name,
+                "cluster": c.context.cluster,
+                "user": c.context.user,
+                "namespace": kubens::namespace(&c.context),
+                "current": config.current_context.as_deref() == Some(c.name.as_str()),
+            })
+        })
+        .collect();
+    (200, json!(contexts))
+}
+
+fn merge(app_config: &AppConfig, dest_path: &Path, query: &str, body: &str) -> Result<Response> {
+    let source = match parse_kubeconfig(body) {
+        Ok(source) => source,
+        Err(e) => {
+            return Ok(error(
+                400,
+                format!("Body is not a valid kubeconfig: {:#}", e),
+            ))
+        }
+    };
+    let update = query
+        .split('&')
+        .any(|p| p == "update=true" || p == "update");
+    let options = MergeOptions {
+        mode: if update {
+            UpdateMode::Replace
+        } else {
+            UpdateMode::Skip
+        },
+        conflict: ConflictStrategy::Skip,
+        allow_insecure: false,
+        enforce_policy: app_config.policies.deny_plaintext_passwords
+            || app_config.policies.deny_http_servers
+            || !app_config.policies.require_exec_for_servers.is_empty(),
+        bind_user: None,
+        include_desktop: false,
+        expires_at: None,
+        reuse_clusters: ReuseClusters::Never,
+        unprotect: false,
+    };
+
+    let dest_existed = dest_path.exists();
+    let mut config = load(dest_path)?;
+    let original = config.clone();
+    let mut totals = MergeTotals::default();
+    // Policy failures are the client's problem, not the server's
+    if let Err(e) = merge_source(
+        app_config,
+        &mut config,
+        source,
+        "kconf serve",
+        &options,
+        &mut totals,
+    ) {
+        return Ok(error(422, format!("{:#}", e)));
+    }
+    if needs_write(dest_existed, &original, &config) {
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        save_destination(
+            app_config,
+            dest_path,
+            &config,
+            &format!(
+                "merge via API ({} added, {} updated)",
+                totals.added, totals.updated
+            ),
+        )?;
+    }
+    Ok((
+        200,
+        json!({
+            "added": totals.added,
+            "updated": totals.updated,
+            "unchanged": totals.unchanged,
+            "skipped": totals.skipped,
+        }),
+    ))
+}
+
+fn delete_context(app_config: &AppConfig, dest_path: &Path, name: &str) -> Result<Response> {
+    if let Err(e) = crate::protect::guard(&app_config.protected, name, false) {
+        return Ok(error(403, e.to_string()));
+    }
+    let mut config = load(dest_path)?;
+    let removed = remove_context(&mut config, name);
+    if removed == 0 {
+        return Ok(error(404, format!("Context not found: {}", name)));
+    }
+    save_destination(
+        app_config,
+        dest_path,
+        &config,
+        &format!("remove context '{}' via API", name),
+    )?;
+    Ok((200, json!({ "removed": removed })))
+}
+
+/// Names a loopback listener answers to
+const LOOPBACK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "::1"];
+
+/// Split `host:port` (or `[v6]:port`) into the host, without brackets, and
+/// the port if one is given
+fn split_host(authority: &str) -> (&str, Option<&str>) {
+    if let Some(v6) = authority.strip_prefix('[') {
+        let (host, rest) = v6.split_once(']').unwrap_or((v6, ""));
+        return (host, rest.strip_prefix(':'));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    }
+}
+
+/// Whether `listen` is a loopback address, reachable from this machine only
+pub fn is_loopback(listen: &str) -> bool {
+    let (host, _) = split_host(listen);
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Who may use the API: requests must present `token` as a bearer token and
+/// name one of `hosts` with the listen `port` in their Host header
+pub struct Access {
+    pub token: String,
+    pub hosts: Vec<String>,
+    pub port: String,
+}
+
+impl Access {
+    /// A fresh random token for serving on `listen`. A loopback listener
+    /// accepts the usual loopback names; any other address (including a
+    /// wildcard like 0.0.0.0) only the `allowed_hosts` given explicitly.
+    pub fn generate(listen: &str, allowed_hosts: &[String]) -> Result<Self> {
+        let (_, port) = split_host(listen);
+        let port = port.with_context(|| format!("{} has no port", listen))?;
+        let mut hosts: Vec<String> = allowed_hosts
+            .iter()
+            .map(|h| split_host(h).0.to_ascii_lowercase())
+            .collect();
+        if is_loopback(listen) {
+            hosts.extend(LOOPBACK_HOSTS.iter().map(|h| h.to_string()));
+        } else if hosts.is_empty() {
+            anyhow::bail!(
+                "Listening on {} needs --allowed-host with the name clients use to reach it",
+                listen
+            );
+        }
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| anyhow::anyhow!("Failed to generate an API token: {}", e))?;
+        Ok(Self {
+            token: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            hosts,
+            port: port.to_string(),
+        })
+    }
+
+    /// Whether a Host header names this server
+    fn accepts_host(&self, host: Option<&str>) -> bool {
+        let Some((name, port)) = host.map(split_host) else {
+            return false;
+        };
+        // Clients leave out the default port
+        port.unwrap_or("80") == self.port && self.hosts.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether an Authorization header carries the token, compared in
+    /// constant time so response timing doesn't reveal how much of it matched
+    fn authorizes(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = authorization.and_then(|a| a.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        token.len() == self.token.len()
+            && token
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    /// The response refusing a request, or None when it may go through.
+    /// Browsers can reach a loopback address too, so requests they send
+    /// (which carry an Origin) and requests for another host name (DNS
+    /// rebinding) are refused even before the token is checked.
+    fn refuse(&self, method: &str, headers: &[(String, String)]) -> Option<Response> {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.trim())
+        };
+        if header("Origin").is_some() {
+            return Some(error(403, "Requests from web pages aren't accepted"));
+        }
+        if !self.accepts_host(header("Host")) {
+            return Some(error(
+                403,
+                format!(
+                    "Host must be one of {} on port {}",
+                    self.hosts.join(", "),
+                    self.port
+                ),
+            ));
+        }
+        if !self.authorizes(header("Authorization")) {
+            return Some(error(401, "Missing or wrong bearer token"));
+        }
+        if method == "POST" {
+            let content_type = header("Content-Type")
+                .and_then(|t| t.split(';').next())
+                .map(|t| t.trim().to_ascii_lowercase());
+            if !matches!(
+                content_type.as_deref(),
+                Some("application/yaml" | "application/json")
+            ) {
+                return Some(error(
+                    415,
+                    "Content-Type must be application/yaml or application/json",
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// Check a request against `access` and dispatch it
+pub fn handle(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    access: &Access,
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: &str,
+) -> Response {
+    match access.refuse(method, headers) {
+        Some(refused) => refused,
+        None => route(app_config, dest_path, method, url, body),
+    }
+}
+
+/// Dispatch one request against the destination at `dest_path`
+fn route(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    method: &str,
+    url: &str,
+    body: &str,
+) -> Response {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let result = match (method, path.trim_end_matches('/')) {
+        ("GET", "/contexts") => load(dest_path).map(|c| list_contexts(&c)),
+        ("POST", "/merge") => merge(app_config, dest_path, query, body),
+        ("DELETE", path) if path.starts_with("/contexts/") => {
+            match percent_encoding::percent_decode_str(&path["/contexts/".len()..]).decode_utf8() {
+                Ok(name) => delete_context(app_config, dest_path, &name),
+                Err(_) => Ok(error(400, "Context name is not valid UTF-8")),
+            }
+        }
+        _ => Ok(error(404, format!("No route for {} {}", method, path))),
+    };
+    result.unwrap_or_else(|e| error(500, format!("{:#}", e)))
+}
+
+/// Serve the REST API on `listen` until the process is stopped
+pub fn serve(
+    app_config: &AppConfig,
+    dest_path: &Path,
+    listen: &str,
+    allowed_hosts: &[String],
+) -> Result<()> {
+    let access = Access::generate(listen, allowed_hosts)?;
+    let server = tiny_http::Server::http(listen)
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", listen, e))?;
+    println!("Listening on http://{}", listen);
+    println!("Token: {}", access.token);
+
+    for mut request in server.incoming_requests() {
+        let headers: Vec<(String, String)> = request
+            .headers()
+            .iter()
+            .map(|h| (h.field.to_string(), h.value.to_string()))
+            .collect();
+        let mut body = String::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY_BYTES + 1)
+            .read_to_string(&mut body);
+        let (status, value) = match read {
+            Ok(_) if body.len() as u64 > MAX_BODY_BYTES => {
+                error(413, format!("Body is larger than {} bytes", MAX_BODY_BYTES))
+            }
+            Ok(_) => handle(
+                app_config,
+                dest_path,
+                &access,
+                request.method().as_str(),
+                request.url(),
+                &headers,
+                &body,
+            ),
+            Err(e) => error(400, format!("Failed to read body: {}", e)),
+        };
+        println!("{} {} -> {}", request.method(), request.url(), status);
+        let response = tiny_http::Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(
+                tiny_http::Header::from_bytes("Content-Type", "application/json")
+                    .expect("static header is valid"),
+            );
+        if let Err(e) = request.respond(response) {
+            eprintln!("Warning: failed to send response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_routes() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+        let app_config = AppConfig::default();
+        let body = serde_yaml::to_string(&create_test_kubeconfig("api")).unwrap();
+
+        let (status, value) = route(&app_config, &dest, "POST", "/merge", &body);
+        assert_eq!(status, 200);
+        assert_eq!(value["added"], 3);
+
+        let (status, value) = route(&app_config, &dest, "GET", "/contexts", "");
+        assert_eq!(status, 200);
+        assert_eq!(value[0]["name"], "api-context");
+        assert_eq!(value[0]["current"], true);
+
+        let (status, value) = route(&app_config, &dest, "POST", "/merge?update=true", &body);
+        assert_eq!((status, value["unchanged"].as_u64()), (200, Some(3)));
+
+        assert_eq!(
+            route(&app_config, &dest, "DELETE", "/contexts/api-context", "").0,
+            200
+        );
+        assert_eq!(
+            route(&app_config, &dest, "DELETE", "/contexts/api-context", "").0,
+            404
+        );
+        let mut encoded = create_test_kubeconfig("api");
+        encoded.contexts[0].name = "arn:aws:eks:eu-west-1:123:cluster/api".to_string();
+        let body = serde_yaml::to_string(&encoded).unwrap();
+        assert_eq!(route(&app_config, &dest, "POST", "/merge", &body).0, 200);
+        assert_eq!(
+            route(
+                &app_config,
+                &dest,
+                "DELETE",
+                "/contexts/arn%3Aaws%3Aeks%3Aeu-west-1%3A123%3Acluster%2Fapi",
+                ""
+            )
+            .0,
+            200
+        );
+        assert_eq!(route(&app_config, &dest, "POST", "/merge", "- nope").0, 400);
+        assert_eq!(route(&app_config, &dest, "GET", "/nope", "").0, 404);
+    }
+
+    #[test]
+    fn test_refused_requests() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("config");
+        let app_config = AppConfig::default();
+        let access = Access::generate("127.0.0.1:7878", &[]).unwrap();
+        assert_eq!(access.token.len(), 64);
+        let body = serde_yaml::to_string(&create_test_kubeconfig("api")).unwrap();
+        let request = |method: &str, url: &str, headers: &[(&str, &str)]| {
+            let headers: Vec<(String, String)> = headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect();
+            handle(&app_config, &dest, &access, method, url, &headers, &body).0
+        };
+        let bearer = format!("Bearer {}", access.token);
+        let host = ("Host", "127.0.0.1:7878");
+        let auth = ("authorization", bearer.as_str());
+        let yaml = ("Content-Type", "application/yaml; charset=utf-8");
+
+        // No token, or the wrong one
+        assert_eq!(request("GET", "/contexts", &[host]), 401);
+        assert_eq!(
+            request(
+                "GET",
+                "/contexts",
+                &[host, ("Authorization", "Bearer nope")]
+            ),
+            401
+        );
+        let truncated = format!("Bearer {}", &access.token[1..]);
+        assert_eq!(
+            request(
+                "GET",
+                "/contexts",
+                &[host, ("Authorization", truncated.as_str())]
+            ),
+            401
+        );
+        // Sent by a web page
+        assert_eq!(
+            request(
+                "POST",
+                "/merge",
+                &[host, auth, yaml, ("Origin", "https://evil.example")]
+            ),
+            403
+        );
+        // DNS rebinding, or no Host at all
+        assert_eq!(
+            request("GET", "/contexts", &[("Host", "evil.example:7878"), auth]),
+            403
+        );
+        assert_eq!(request("GET", "/contexts", &[auth]), 403);
+        // Other loopback names work, other ports don't
+        for loopback in ["localhost:7878", "LOCALHOST:7878", "[::1]:7878"] {
+            assert_eq!(
+                request("GET", "/contexts", &[("Host", loopback), auth]),
+                200
+            );
+        }
+        assert_eq!(
+            request("GET", "/contexts", &[("Host", "127.0.0.1:8080"), auth]),
+            403
+        );
+        assert_eq!(
+            request("GET", "/contexts", &[("Host", "127.0.0.1"), auth]),
+            403
+        );
+        // A "simple" cross-origin content type
+        assert_eq!(
+            request(
+                "POST",
+                "/merge",
+                &[host, auth, ("Content-Type", "text/plain")]
+            ),
+            415
+        );
+        assert_eq!(request("POST", "/merge", &[host, auth]), 415);
+        assert!(!dest.exists());
+
+        assert_eq!(request("POST", "/merge", &[host, auth, yaml]), 200);
+        assert_eq!(request("GET", "/contexts", &[host, auth]), 200);
+    }
+
+    #[test]
+    fn test_wildcard_listen_needs_allowed_hosts() {
+        assert!(Access::generate("0.0.0.0:7878", &[]).is_err());
+        assert!(Access::generate("192.168.1.5:7878", &[]).is_err());
+        let access = Access::generate("0.0.0.0:7878", &["kconf.lan".to_string()]).unwrap();
+        assert!(access.accepts_host(Some("kconf.lan:7878")));
+        assert!(!access.accepts_host(Some("localhost:7878")));
+        assert!(!access.accepts_host(Some("0.0.0.0:7878")));
+        assert!(!access.accepts_host(Some("kconf.lan:7879")));
+        assert!(!access.accepts_host(None));
+
+        let access = Access::generate("[::1]:80", &[]).unwrap();
+        assert!(access.accepts_host(Some("localhost")));
+        assert!(access.accepts_host(Some("[::1]:80")));
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use crate::KubeConfig;
+
+/// Canonical form of an API server URL, so spellings of the same endpoint
+/// compare equal: lowercase scheme and host, no port when it's the scheme's
+/// default (443 for https, 80 for http) and no trailing slash. A URL without
+/// a scheme is taken to be https, as kubectl does. Other ports such as 6443
+/// are kept, since they're a different endpoint than the default one.
+pub fn canonical(url: &str) -> String {
+    let url = url.trim();
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => ("https".to_string(), url),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let authority = authority.to_lowercase();
+    let (host, port) = match authority.rfind(':') {
+        // A colon inside brackets belongs to an IPv6 address, not a port
+        Some(i) if !authority[i..].contains(']') => (&authority[..i], Some(&authority[i + 1..])),
+        _ => (authority.as_str(), None),
+    };
+    let default_port = match scheme.as_str() {
+        "https" => Some("443"),
+        "http" => Some("80"),
+        _ => None,
+    };
+    let port = port.filter(|p| Some(*p) != default_port && !p.is_empty());
+    let mut canonical = format!("{}://{}", scheme, host);
+    if let Some(port) = port {
+        canonical.push(':');
+        canonical.push_str(port);
+    }
+    canonical.push_str(path.trim_end_matches('/'));
+    canonical
+}
+
+/// Domain of an API server for grouping: the last two labels of its host
+/// (`example.com` for `https://api.prod.example.com:6443`), or the whole
+/// host for IP addresses and single-label names
+pub fn domain(url: &str) -> String {
+    let canonical = canonical(url);
+    let authority = canonical.split_once("://").map_or("", |(_, rest)| rest);
+    let authority = authority.split('/').next().unwrap_or_default();
+    let host = match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => &authority[..i],
+        _ => authority,
+    };
+    if host
+        .trim_matches(['[', ']'])
+        .parse::<std::net::IpAddr>()
+        .is_ok()
+    {
+        return host.to_string();
+    }
+    let labels: Vec<&str> = host.split('.').collect();
+    labels[labels.len().saturating_sub(2)..].join(".")
+}
+
+/// Cluster names by canonical server URL, names in config order
+pub fn by_server(config: &KubeConfig) -> BTreeMap<String, Vec<String>> {
+    let mut servers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for cluster in &config.clusters {
+        servers
+            .entry(canonical(&cluster.cluster.server))
+            .or_default()
+            .push(cluster.name.clone());
+    }
+    servers
+}
+
+/// Servers that more than one cluster entry points at
+pub fn duplicates(config: &KubeConfig) -> BTreeMap<String, Vec<String>> {
+    let mut servers = by_server(config);
+    servers.retain(|_, names| names.len() > 1);
+    servers
+}
+
+/// Incoming clusters that are new by name but whose server a destination
+/// cluster of another name already points at, as (incoming, existing)
+pub fn near_duplicates(dest: &KubeConfig, source: &KubeConfig) -> Vec<(String, String)> {
+    source
+        .clusters
+        .iter()
+        .filter(|c| !dest.clusters.iter().any(|d| d.name == c.name))
+        .filter_map(|c| {
+            let server = canonical(&c.cluster.server);
+            dest.clusters
+                .iter()
+                .find(|d| canonical(&d.cluster.server) == server)
+                .map(|d| (c.name.clone(), d.name.clone()))
+        })
+        .collect()
+}
+
+/// Point the source's contexts using cluster `incoming` at the destination's
+/// `existing` cluster instead, and drop `incoming` from the source
+pub fn reuse(source: &mut KubeConfig, incoming: &str, existing: &str) {
+    source.clusters.retain(|c| c.name != incoming);
+    for context in &mut source.contexts {
+        if context.context.cluster == incoming {
+            context.context.cluster = existing.to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_near_duplicates_and_reuse() {
+        let dest = create_test_kubeconfig("a");
+        let mut source = create_test_kubeconfig("b");
+        source.clusters[0].cluster.server = "https://A.example.com:6443/".to_string();
+        source.clusters.extend(create_test_kubeconfig("a").clusters);
+        assert_eq!(
+            near_duplicates(&dest, &source),
+            vec![("b-cluster".to_string(), "a-cluster".to_string())]
+        );
+
+        reuse(&mut source, "b-cluster", "a-cluster");
+        assert_eq!(source.clusters.len(), 1);
+        assert_eq!(source.contexts[0].context.cluster, "a-cluster");
+        assert!(near_duplicates(&dest, &source).is_empty());
+    }
+
+    #[test]
+    fn test_domain() {
+        assert_eq!(domain("https://api.prod.example.com:6443"), "example.com");
+        assert_eq!(domain("https://10.0.0.1:6443"), "10.0.0.1");
+        assert_eq!(domain("https://[::1]:6443"), "[::1]");
+        assert_eq!(domain("https://localhost:6443"), "localhost");
+    }
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(
+            canonical("HTTPS://API.Example.com:443/"),
+            "https://api.example.com"
+        );
+        assert_eq!(canonical("api.example.com"), "https://api.example.com");
+        assert_eq!(
+            canonical("https://api.example.com:6443"),
+            "https://api.example.com:6443"
+        );
+        assert_eq!(canonical("http://10.0.0.1:80/k8s/"), "http://10.0.0.1/k8s");
+        assert_eq!(canonical("https://[FD00::1]:443"), "https://[fd00::1]");
+        assert_eq!(canonical("https://[fd00::1]"), "https://[fd00::1]");
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut config = create_test_kubeconfig("a");
+        let mut other = create_test_kubeconfig("b");
+        other.clusters[0].cluster.server = "https://A.example.com:6443/".to_string();
+        config.clusters.extend(other.clusters);
+        config.clusters.extend(create_test_kubeconfig("c").clusters);
+
+        let duplicates = duplicates(&config);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates["https://a.example.com:6443"],
+            ["a-cluster", "b-cluster"]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+const BASH: &str = r#"# kconf shell integration: eval "$(kconf shell-init bash)"
+kc() { kconf use "$@"; }
+kcl() { kconf last; }
+kns() { kconf ns "$@"; }
+kconf_prompt() { kconf current --short --porcelain; }
+# Add $(kconf_prompt) to PS1, e.g. PS1='[$(kconf_prompt)] \w \$ '
+"#;
+
+const ZSH: &str = r#"# kconf shell integration: eval "$(kconf shell-init zsh)"
+kc() { kconf use "$@"; }
+kcl() { kconf last; }
+kns() { kconf ns "$@"; }
+kconf_prompt() { kconf current --short --porcelain; }
+# Refresh $KCONF_PROMPT before each prompt; use it in PROMPT or RPROMPT,
+# e.g. RPROMPT='${KCONF_PROMPT}' with `setopt prompt_subst`
+_kconf_precmd() { KCONF_PROMPT="$(kconf_prompt)"; }
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd _kconf_precmd
+"#;
+
+const FISH: &str = r#"# kconf shell integration: kconf shell-init fish | source
+function kc; kconf use $argv; end
+function kcl; kconf last; end
+function kns; kconf ns $argv; end
+function kconf_prompt; kconf current --short --porcelain; end
+# Call kconf_prompt from fish_prompt or fish_right_prompt
+"#;
+
+const POWERSHELL: &str = r#"# kconf shell integration: kconf shell-init powershell | Out-String | Invoke-Expression
+function kc { kconf use @args }
+function kcl { kconf last }
+function kns { kconf ns @args }
+function kconf_prompt { kconf current --short --porcelain }
+# Call kconf_prompt from your prompt function
+"#;
+
+/// Helper functions for `shell`, meant to be evaluated from its rc file:
+/// `kc` switches context (fuzzy selector without a name), `kcl` switches
+/// back, `kns` switches namespace and `kconf_prompt` prints the current
+/// context for prompts
+pub fn init(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+        Shell::Powershell => POWERSHELL,
+    }
+}
+
+/// A command setting environment variable `name` to `value` in `shell`,
+/// quoted so the value is taken literally
+pub fn export(shell: Shell, name: &str, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            format!("export {}='{}'", name, value.replace('\'', "'\\''"))
+        }
+        Shell::Fish => format!(
+            "set -gx {} '{}'",
+            name,
+            value.replace('\\', "\\\\").replace('\'', "\\'")
+        ),
+        Shell::Powershell => format!("$env:{} = '{}'", name, value.replace('\'', "''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_shell_defines_helpers() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Powershell] {
+            let script = init(shell);
+            for helper in ["kc", "kcl", "kns", "kconf_prompt"] {
+                assert!(
+                    script
+                        .lines()
+                        .any(|l| l.starts_with(&format!("{}()", helper))
+                            || l.starts_with(&format!("function {};", helper))
+                            || l.starts_with(&format!("function {} {{", helper))),
+                    "{:?} is missing {}",
+                    shell,
+                    helper
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_quotes_values() {
+        let path = "/home/o'neil/.k8sconf/env/prod.yaml";
+        assert_eq!(
+            export(Shell::Bash, "KUBECONFIG", path),
+            r#"export KUBECONFIG='/home/o'\''neil/.k8sconf/env/prod.yaml'"#
+        );
+        assert_eq!(
+            export(Shell::Fish, "KUBECONFIG", path),
+            r#"set -gx KUBECONFIG '/home/o\'neil/.k8sconf/env/prod.yaml'"#
+        );
+        assert_eq!(
+            export(Shell::Powershell, "KUBECONFIG", r"C:\Users\kc"),
+            r"$env:KUBECONFIG = 'C:\Users\kc'"
+        );
+    }
+}
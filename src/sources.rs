@@ -0,0 +1,621 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use minisign_verify::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+#[cfg(feature = "remote")]
+use std::io::Read;
+use std::process::Command;
+#[cfg(feature = "remote")]
+use std::thread;
+#[cfg(feature = "remote")]
+use std::time::Duration;
+
+use crate::secrets::SecretRef;
+use crate::{
+    absolutize_source, backup, expand_tilde, load_source, parse_kubeconfig, KubeConfig, UpdateMode,
+};
+
+/// A kubeconfig source registered under `sources:` in the app config.
+/// Exactly one of `file`, `url` or `command` must be set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Source {
+    /// Name shown in `kconf refresh` output
+    pub name: String,
+    /// Local kubeconfig file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// URL serving a kubeconfig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Shell command printing a kubeconfig on stdout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// How entries that already exist in the destination are handled
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Expected SHA-256 (hex) of the fetched kubeconfig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Minisign public key (the base64 line of `minisign.pub`) that must have
+    /// signed the fetched kubeconfig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minisign_key: Option<String>,
+    /// URL or path of the detached signature; defaults to the file or URL
+    /// with `.minisig` appended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Credentials sent when fetching a URL source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<SourceAuth>,
+}
+
+/// How to authenticate to the server behind a URL source
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SourceAuth {
+    /// Sent as `Authorization: Bearer <token>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer: Option<SecretRef>,
+    /// Sent as HTTP basic auth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basic: Option<BasicAuth>,
+    /// Extra request headers, e.g. an API key for an SSO gateway
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, SecretRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: SecretRef,
+}
+
+impl SourceAuth {
+    /// Resolve every secret into request headers
+    fn headers(&self) -> Result<Vec<(String, String)>> {
+        if self.bearer.is_some() && self.basic.is_some() {
+            anyhow::bail!("auth can set `bearer` or `basic`, not both");
+        }
+        let mut headers = Vec::new();
+        if let Some(token) = &self.bearer {
+            let token = token.resolve().context("Failed to resolve bearer token")?;
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+        if let Some(basic) = &self.basic {
+            let password = basic
+                .password
+                .resolve()
+                .context("Failed to resolve basic auth password")?;
+            let credentials = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", basic.username, password));
+            headers.push((
+                "Authorization".to_string(),
+                format!("Basic {}", credentials),
+            ));
+        }
+        for (name, value) in &self.headers {
+            let value = value
+                .resolve()
+                .with_context(|| format!("Failed to resolve header {}", name))?;
+            headers.push((name.clone(), value));
+        }
+        Ok(headers)
+    }
+}
+
+/// Network settings for URL sources, stored under `fetch:` in the app config
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FetchConfig {
+    /// Seconds to wait for a connection
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// Seconds to wait for data once connected
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: u64,
+    /// Extra attempts after a failed download
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubled for each one after
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Proxy URL to use instead of HTTPS_PROXY/HTTP_PROXY/ALL_PROXY
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: default_connect_timeout(),
+            read_timeout: default_read_timeout(),
+            retries: default_retries(),
+            backoff_ms: default_backoff_ms(),
+            proxy: None,
+        }
+    }
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_read_timeout() -> u64 {
+    30
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+/// Merge strategy for a registered source
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Leave existing entries untouched
+    Skip,
+    /// Replace existing entries
+    #[default]
+    Update,
+    /// Overlay only the fields the source sets
+    MergeFields,
+    /// Only refresh credentials behind existing contexts
+    CredentialsOnly,
+}
+
+impl From<Strategy> for UpdateMode {
+    fn from(strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Skip => UpdateMode::Skip,
+            Strategy::Update => UpdateMode::Replace,
+            Strategy::MergeFields => UpdateMode::MergeFields,
+            Strategy::CredentialsOnly => UpdateMode::CredentialsOnly,
+        }
+    }
+}
+
+impl Source {
+    /// Where the source comes from, recorded as the contexts' provenance
+    pub fn location(&self) -> Result<String> {
+        match (&self.file, &self.url, &self.command) {
+            (Some(file), None, None) => Ok(expand_tilde(file).display().to_string()),
+            (None, Some(url), None) => Ok(url.clone()),
+            (None, None, Some(command)) => Ok(format!("command: {}", command)),
+            _ => anyhow::bail!(
+                "Source '{}' must set exactly one of file, url or command",
+                self.name
+            ),
+        }
+    }
+
+    /// Read the source's current kubeconfig, verifying its pin and signature
+    /// when configured
+    pub fn fetch(&self, settings: &FetchConfig) -> Result<KubeConfig> {
+        self.location()?;
+        let verified = self.sha256.is_some() || self.minisign_key.is_some();
+        let content = if let Some(file) = &self.file {
+            let path = expand_tilde(file);
+            if !verified {
+                return load_source(&path, false);
+            }
+            fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?
+        } else if let Some(url) = &self.url {
+            get(url, settings, &self.auth_headers(url)?)?
+        } else {
+            let command = self.command.as_deref().unwrap_or_default();
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .output()
+                .with_context(|| format!("Failed to run '{}'", command))?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "'{}' failed: {}",
+                    command,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            output.stdout
+        };
+        self.verify(&content, settings)?;
+        let content = String::from_utf8(content)
+            .with_context(|| format!("Source '{}' is not UTF-8", self.name))?;
+        let mut config = parse_kubeconfig(&content)
+            .with_context(|| format!("Source '{}' is not a valid kubeconfig", self.name))?;
+        if let Some(file) = &self.file {
+            absolutize_source(&mut config, &expand_tilde(file));
+        }
+        Ok(config)
+    }
+
+    /// Headers carrying the source's credentials, which are only ever sent
+    /// over HTTPS
+    fn auth_headers(&self, url: &str) -> Result<Vec<(String, String)>> {
+        let Some(auth) = &self.auth else {
+            return Ok(Vec::new());
+        };
+        if !url.starts_with("https://") {
+            anyhow::bail!(
+                "Source '{}' has auth configured; refusing to send credentials to {} over plain HTTP",
+                self.name,
+                url
+            );
+        }
+        auth.headers()
+            .with_context(|| format!("Source '{}'", self.name))
+    }
+
+    /// Check fetched content against the configured SHA-256 pin and minisign
+    /// signature, before anything in it is trusted
+    fn verify(&self, content: &[u8], settings: &FetchConfig) -> Result<()> {
+        if let Some(pin) = &self.sha256 {
+            let actual = backup::hex(&Sha256::digest(content));
+            if !actual.eq_ignore_ascii_case(pin.trim()) {
+                anyhow::bail!(
+                    "Source '{}' doesn't match its sha256 pin (got {})",
+                    self.name,
+                    actual
+                );
+            }
+        }
+        let Some(key) = &self.minisign_key else {
+            return Ok(());
+        };
+        let key = PublicKey::from_base64(key.trim()).map_err(|e| {
+            anyhow::anyhow!("Invalid minisign_key for source '{}': {}", self.name, e)
+        })?;
+        let location = match (&self.signature, &self.file, &self.url) {
+            (Some(signature), _, _) => signature.clone(),
+            (None, Some(file), _) => format!("{}.minisig", file),
+            (None, _, Some(url)) => format!("{}.minisig", url),
+            _ => anyhow::bail!(
+                "Source '{}' needs `signature` set to verify command output",
+                self.name
+            ),
+        };
+        let signature = if location.starts_with("https://") || location.starts_with("http://") {
+            // The signature may live on another server, which mustn't see
+            // the source's credentials
+            let headers = match &self.url {
+                Some(url) if same_origin(url, &location) => self.auth_headers(&location)?,
+                _ => Vec::new(),
+            };
+            String::from_utf8(get(&location, settings, &headers)?)
+                .context("Signature is not UTF-8")?
+        } else {
+            let path = expand_tilde(&location);
+            fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read signature {:?}", path))?
+        };
+        let signature = Signature::decode(&signature)
+            .map_err(|e| anyhow::anyhow!("Invalid signature {}: {}", location, e))?;
+        key.verify(content, &signature, true).map_err(|e| {
+            anyhow::anyhow!(
+                "Signature check failed for source '{}': {}; refusing to merge it",
+                self.name,
+                e
+            )
+        })
+    }
+}
+
+/// Whether two http(s) URLs share scheme, host and port
+fn same_origin(a: &str, b: &str) -> bool {
+    let origin = |url: &str| {
+        let (scheme, rest) = url.split_once("://")?;
+        let authority = rest.split(['/', '?', '#']).next()?;
+        let host = authority.rsplit('@').next()?;
+        Some(format!("{}://{}", scheme, host).to_ascii_lowercase())
+    };
+    origin(a).is_some_and(|a| Some(a) == origin(b))
+}
+
+/// Host part of an http(s) URL, without port or credentials
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    }
+}
+
+/// The proxy to use for `url`: the configured one, or the usual environment
+/// variables unless NO_PROXY exempts the host. `env` looks up a variable.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn proxy_for(
+    url: &str,
+    configured: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let lookup = |name: &str| {
+        env(name)
+            .or_else(|| env(&name.to_lowercase()))
+            .filter(|v| !v.is_empty())
+    };
+    let host = url_host(url).to_lowercase();
+    if let Some(no_proxy) = lookup("NO_PROXY") {
+        let exempt = no_proxy.split(',').map(str::trim).any(|entry| {
+            let entry = entry.split(':').next().unwrap_or_default().to_lowercase();
+            let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+            entry == "*"
+                || (!domain.is_empty()
+                    && (host == domain || host.ends_with(&format!(".{}", domain))))
+        });
+        if exempt {
+            return None;
+        }
+    }
+    if let Some(proxy) = configured {
+        return Some(proxy.to_string());
+    }
+    let scheme_var = if url.starts_with("https://") {
+        "HTTPS_PROXY"
+    } else {
+        "HTTP_PROXY"
+    };
+    lookup(scheme_var).or_else(|| lookup("ALL_PROXY"))
+}
+
+#[cfg(not(feature = "remote"))]
+fn get(_url: &str, _settings: &FetchConfig, _headers: &[(String, String)]) -> Result<Vec<u8>> {
+    Err(crate::KconfError::FeatureDisabled("remote").into())
+}
+
+/// Download a URL's body with the configured timeouts and proxy, retrying
+/// connection failures and server errors with exponential backoff
+#[cfg(feature = "remote")]
+fn get(url: &str, settings: &FetchConfig, headers: &[(String, String)]) -> Result<Vec<u8>> {
+    let mut agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(settings.connect_timeout))
+        .timeout_read(Duration::from_secs(settings.read_timeout));
+    if let Some(proxy) = proxy_for(url, settings.proxy.as_deref(), |name| {
+        std::env::var(name).ok()
+    }) {
+        let proxy =
+            ureq::Proxy::new(&proxy).with_context(|| format!("Invalid proxy: {}", proxy))?;
+        agent = agent.proxy(proxy);
+    }
+    let agent = agent.build();
+
+    let mut delay = Duration::from_millis(settings.backoff_ms);
+    let mut attempt = 0;
+    loop {
+        let mut request = agent.get(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let error = match request.call() {
+            Ok(response) => {
+                let mut content = Vec::new();
+                match response.into_reader().read_to_end(&mut content) {
+                    Ok(_) => return Ok(content),
+                    Err(e) => anyhow::Error::from(e)
+                        .context(format!("Failed to read response from {}", url)),
+                }
+            }
+            // Client errors won't go away by asking again
+            Err(ureq::Error::Status(code, _)) if code < 500 && code != 429 => {
+                anyhow::bail!("Failed to fetch {}: HTTP {}", url, code)
+            }
+            Err(e) => anyhow::Error::from(e).context(format!("Failed to fetch {}", url)),
+        };
+        if attempt >= settings.retries {
+            return Err(error);
+        }
+        attempt += 1;
+        eprintln!(
+            "  {:#}; retrying in {:.1}s ({}/{})",
+            error,
+            delay.as_secs_f64(),
+            attempt,
+            settings.retries
+        );
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use crate::write_kubeconfig;
+    use tempfile::TempDir;
+
+    fn source(yaml: &str) -> Source {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_fetch_file_and_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("team.yaml");
+        write_kubeconfig(&path, &create_test_kubeconfig("team")).unwrap();
+
+        let file = source(&format!("name: team\nfile: {}\n", path.display()));
+        assert_eq!(file.strategy, Strategy::Update);
+        assert_eq!(
+            file.fetch(&FetchConfig::default()).unwrap().contexts[0].name,
+            "team-context"
+        );
+
+        let command = source(&format!(
+            "name: team\ncommand: cat {}\nstrategy: credentials-only\n",
+            path.display()
+        ));
+        assert_eq!(
+            UpdateMode::from(command.strategy),
+            UpdateMode::CredentialsOnly
+        );
+        assert_eq!(
+            command.fetch(&FetchConfig::default()).unwrap().clusters[0].name,
+            "team-cluster"
+        );
+
+        assert!(source("name: bad\ncommand: exit 3\n")
+            .fetch(&FetchConfig::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify_pin_and_signature() {
+        // Test vector from the minisign-verify crate: a signature over "test"
+        let temp_dir = TempDir::new().unwrap();
+        let signature = temp_dir.path().join("test.minisig");
+        fs::write(
+            &signature,
+            "untrusted comment: signature from minisign secret key\n\
+             RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=\n\
+             trusted comment: timestamp:1555779966\tfile:test\n\
+             QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==\n",
+        )
+        .unwrap();
+        let signed = source(&format!(
+            "name: team\nurl: https://example.com/kubeconfig\n\
+             minisign_key: RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3\n\
+             signature: {}\n",
+            signature.display()
+        ));
+        assert!(signed.verify(b"test", &FetchConfig::default()).is_ok());
+        assert!(signed.verify(b"Test", &FetchConfig::default()).is_err());
+
+        let pinned = source(
+            "name: team\nurl: https://example.com/kubeconfig\n\
+             sha256: 9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08\n",
+        );
+        assert!(pinned.verify(b"test", &FetchConfig::default()).is_ok());
+        assert!(pinned.verify(b"tampered", &FetchConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_proxy_for_honors_no_proxy() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let vars = env(&[
+            ("https_proxy", "http://proxy:3128"),
+            ("NO_PROXY", "localhost,.corp.example.com"),
+        ]);
+        assert_eq!(
+            proxy_for("https://kube.example.com/config", None, vars).as_deref(),
+            Some("http://proxy:3128")
+        );
+        assert_eq!(
+            proxy_for("https://git.corp.example.com:8443/config", None, vars),
+            None
+        );
+        assert_eq!(proxy_for("https://localhost/config", None, vars), None);
+        // HTTPS_PROXY doesn't apply to plain http
+        assert_eq!(
+            proxy_for("http://kube.example.com/config", None, vars),
+            None
+        );
+        assert_eq!(
+            proxy_for(
+                "http://kube.example.com/config",
+                Some("http://mine:8080"),
+                vars
+            )
+            .as_deref(),
+            Some("http://mine:8080")
+        );
+        assert_eq!(
+            proxy_for("https://x.example.com", None, env(&[("NO_PROXY", "*")])),
+            None
+        );
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn test_get_gives_up_after_retries() {
+        let settings = FetchConfig {
+            connect_timeout: 1,
+            retries: 1,
+            backoff_ms: 1,
+            ..FetchConfig::default()
+        };
+        let error = get("http://127.0.0.1:1/kubeconfig", &settings, &[]).unwrap_err();
+        assert!(format!("{:#}", error).contains("127.0.0.1:1"));
+    }
+
+    #[test]
+    fn test_auth_headers() {
+        std::env::set_var("KCONF_TEST_SOURCE_TOKEN", "abc");
+        let authed = source(
+            "name: platform\nurl: https://platform.example.com/kubeconfig\nauth:\n  \
+             bearer: {env: KCONF_TEST_SOURCE_TOKEN}\n  \
+             headers:\n    X-Team: {value: infra}\n",
+        );
+        assert_eq!(
+            authed
+                .auth_headers("https://platform.example.com/kubeconfig")
+                .unwrap(),
+            vec![
+                ("Authorization".to_string(), "Bearer abc".to_string()),
+                ("X-Team".to_string(), "infra".to_string()),
+            ]
+        );
+        let basic = source(
+            "name: basic\nurl: https://platform.example.com/kubeconfig\nauth:\n  \
+             basic: {username: me, password: {value: pw}}\n",
+        );
+        assert_eq!(
+            basic
+                .auth_headers("https://platform.example.com/kubeconfig")
+                .unwrap(),
+            vec![("Authorization".to_string(), "Basic bWU6cHc=".to_string())]
+        );
+        let both = source(
+            "name: both\nurl: https://platform.example.com/kubeconfig\nauth:\n  \
+             bearer: {value: abc}\n  basic: {username: me, password: {value: pw}}\n",
+        );
+        assert!(both
+            .auth_headers("https://platform.example.com/kubeconfig")
+            .is_err());
+        assert!(authed.auth_headers("http://platform.example.com/").is_err());
+        assert!(source("name: open\nurl: http://example.com/\n")
+            .auth_headers("http://example.com/")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let url = "https://platform.example.com/kubeconfig";
+        assert!(same_origin(
+            url,
+            "https://Platform.example.com/kubeconfig.minisig"
+        ));
+        assert!(!same_origin(
+            url,
+            "https://sigs.example.com/kubeconfig.minisig"
+        ));
+        assert!(!same_origin(url, "https://platform.example.com:8443/sig"));
+        assert!(!same_origin(url, "http://platform.example.com/sig"));
+        assert!(!same_origin(url, "https://platform.example.com.evil/sig"));
+        assert!(!same_origin(
+            url,
+            "https://platform.example.com@evil.example/sig"
+        ));
+    }
+
+    #[test]
+    fn test_source_needs_one_location() {
+        assert!(source("name: none\n").location().is_err());
+        assert!(source("name: both\nfile: a\nurl: https://b\n")
+            .location()
+            .is_err());
+    }
+}
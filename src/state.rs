@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::NamedContext;
+
+/// Per-context data kconf keeps for the user rather than in the kubeconfig,
+/// stored in ~/.k8sconf/state.json
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppState {
+    /// Free-text notes by context name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub notes: BTreeMap<String, String>,
+    /// Tags by context name, for `kconf list --group-by tag`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// Pinned context names, in the order they're listed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<String>,
+    /// When each context was last switched to with `kconf use`, as RFC 3339
+    /// UTC timestamps
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub last_used: BTreeMap<String, String>,
+    /// Context that was current before the last switch, for `kconf last`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+}
+
+impl AppState {
+    /// Pin a context at `position` (0-based), or after the other pins.
+    /// Pinning an already pinned context moves it.
+    pub fn pin(&mut self, context: &str, position: Option<usize>) {
+        self.pinned.retain(|p| p != context);
+        let position = position.unwrap_or(self.pinned.len()).min(self.pinned.len());
+        self.pinned.insert(position, context.to_string());
+    }
+
+    /// Returns false if the context wasn't pinned
+    pub fn unpin(&mut self, context: &str) -> bool {
+        let before = self.pinned.len();
+        self.pinned.retain(|p| p != context);
+        self.pinned.len() != before
+    }
+
+    /// Contexts with the pinned ones first, in pin order, followed by the
+    /// rest in their original order
+    pub fn order<'a>(&self, contexts: &'a [NamedContext]) -> Vec<&'a NamedContext> {
+        let mut ordered: Vec<&NamedContext> = contexts.iter().collect();
+        ordered.sort_by_key(|c| {
+            self.pinned
+                .iter()
+                .position(|p| *p == c.name)
+                .unwrap_or(usize::MAX)
+        });
+        ordered
+    }
+
+    /// Record switching from `from` to `to` at `now`
+    pub fn record_switch(&mut self, from: Option<&str>, to: &str, now: &str) {
+        if let Some(from) = from.filter(|f| *f != to) {
+            self.previous = Some(from.to_string());
+        }
+        self.last_used.insert(to.to_string(), now.to_string());
+    }
+
+    /// Contexts for the selector: pinned ones first, then the most recently
+    /// used, then the rest in their original order
+    pub fn by_recency<'a>(&self, contexts: &'a [NamedContext]) -> Vec<&'a NamedContext> {
+        let mut ordered = self.order(contexts);
+        ordered.sort_by(|a, b| {
+            let pinned = |c: &NamedContext| self.pinned.contains(&c.name);
+            if pinned(a) || pinned(b) {
+                return pinned(b).cmp(&pinned(a));
+            }
+            // Timestamps share a format and offset, so they sort as strings
+            self.last_used
+                .get(&b.name)
+                .cmp(&self.last_used.get(&a.name))
+        });
+        ordered
+    }
+}
+
+/// Load the state, or an empty one if nothing was saved yet
+pub fn load(path: &Path) -> Result<AppState> {
+    if !path.exists() {
+        return Ok(AppState::default());
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse app state: {:?}", path))
+}
+
+pub fn save(path: &Path, state: &AppState) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("Failed to write app state: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_state_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.json");
+        assert_eq!(load(&path).unwrap(), AppState::default());
+
+        let mut state = AppState::default();
+        state
+            .notes
+            .insert("prod-eu".to_string(), "change freeze Fridays".to_string());
+        save(&path, &state).unwrap();
+        assert_eq!(load(&path).unwrap(), state);
+    }
+
+    #[test]
+    fn test_pinned_contexts_come_first() {
+        let mut contexts = crate::tests::create_test_kubeconfig("a").contexts;
+        for name in ["b", "c"] {
+            contexts.extend(crate::tests::create_test_kubeconfig(name).contexts);
+        }
+        let mut state = AppState::default();
+        state.pin("c-context", None);
+        state.pin("b-context", Some(0));
+        let names: Vec<&str> = state
+            .order(&contexts)
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, ["b-context", "c-context", "a-context"]);
+
+        state.pin("b-context", None);
+        assert_eq!(state.pinned, ["c-context", "b-context"]);
+        assert!(state.unpin("c-context"));
+        assert!(!state.unpin("c-context"));
+    }
+
+    #[test]
+    fn test_recently_used_contexts_follow_pins() {
+        let mut contexts = crate::tests::create_test_kubeconfig("a").contexts;
+        for name in ["b", "c", "d"] {
+            contexts.extend(crate::tests::create_test_kubeconfig(name).contexts);
+        }
+        let mut state = AppState::default();
+        state.pin("d-context", None);
+        state.record_switch(Some("a-context"), "b-context", "2026-01-01T00:00:00+00:00");
+        state.record_switch(Some("b-context"), "c-context", "2026-01-02T00:00:00+00:00");
+        let names: Vec<&str> = state
+            .by_recency(&contexts)
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, ["d-context", "c-context", "b-context", "a-context"]);
+        assert_eq!(state.previous.as_deref(), Some("b-context"));
+
+        state.record_switch(Some("c-context"), "c-context", "2026-01-03T00:00:00+00:00");
+        assert_eq!(state.previous.as_deref(), Some("b-context"));
+    }
+}
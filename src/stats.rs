@@ -0,0 +1,96 @@
+use serde::Serialize;
+
+use crate::paths;
+use crate::KubeConfig;
+
+/// `bytes` as B, KiB or MiB
+pub fn format_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+fn yaml_len<T: Serialize>(value: &T) -> usize {
+    serde_yaml::to_string(value).map_or(0, |s| s.len())
+}
+
+/// One line summary of the entry counts and total size
+pub fn summary(config: &KubeConfig) -> String {
+    let size = yaml_len(config);
+    format!(
+        "{} cluster(s), {} context(s), {} user(s), {} as YAML",
+        config.clusters.len(),
+        config.contexts.len(),
+        config.users.len(),
+        format_size(size)
+    )
+}
+
+/// Table rows of cluster and user entries, largest first, with their
+/// embedded data; data over `limit` bytes is marked. Starts with a header
+/// row.
+pub fn rows(config: &KubeConfig, limit: usize) -> Vec<Vec<String>> {
+    let embedded = paths::embedded(config);
+    let describe = |kind: &str, name: &str| -> String {
+        embedded
+            .iter()
+            .filter(|e| e.kind == kind && e.name == name)
+            .map(|e| {
+                let large = if e.bytes > limit { " (large)" } else { "" };
+                format!("{} {}{}", e.field, format_size(e.bytes), large)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut entries: Vec<(&str, &str, usize)> = config
+        .clusters
+        .iter()
+        .map(|c| ("cluster", c.name.as_str(), yaml_len(c)))
+        .chain(
+            config
+                .users
+                .iter()
+                .map(|u| ("user", u.name.as_str(), yaml_len(u))),
+        )
+        .collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.2));
+
+    let mut rows = vec![["KIND", "NAME", "SIZE", "EMBEDDED DATA"]
+        .into_iter()
+        .map(str::to_string)
+        .collect()];
+    for (kind, name, bytes) in entries {
+        rows.push(vec![
+            kind.to_string(),
+            name.to_string(),
+            format_size(bytes),
+            describe(kind, name),
+        ]);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_stats_rows() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 * 1024 * 1024 / 2), "1.5 MiB");
+
+        let mut config = create_test_kubeconfig("a");
+        config.users[0].user.client_certificate_data = Some("x".repeat(4096));
+        assert!(summary(&config).starts_with("1 cluster(s), 1 context(s), 1 user(s), "));
+        let rows = rows(&config, 1024);
+        assert_eq!(rows[1][..2], ["user", "a-user"]);
+        assert!(rows[1][3].contains("client-certificate-data 4.0 KiB (large)"));
+        assert_eq!(rows[2][0], "cluster");
+        assert!(!rows[2][3].contains("(large)"));
+    }
+}
@@ -0,0 +1,401 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::history::{commit_file, git, run_git};
+use crate::{
+    filter_duplicates, merge_kubeconfigs, protect, remove_context, write_private, KubeConfig,
+    UpdateMode, UserInfo,
+};
+
+/// Git sync settings stored under `sync:` in the app config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// Git remote URL shared between machines
+    pub remote: String,
+    /// Branch to sync with
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    /// File inside the repository holding the kubeconfig
+    #[serde(default = "default_file")]
+    pub file: String,
+    /// Strip user credentials before pushing; set to false to share them
+    /// through the remote too
+    #[serde(default = "default_sanitize")]
+    pub sanitize: bool,
+}
+
+fn default_sanitize() -> bool {
+    true
+}
+
+fn default_branch() -> String {
+    "main".to_string()
+}
+
+fn default_file() -> String {
+    "config.yaml".to_string()
+}
+
+/// Outcome of a sync run
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Entries merged into the local config from the remote
+    pub pulled: usize,
+    /// Local entries replaced by the remote's version
+    pub updated: usize,
+    /// Contexts removed locally because they were removed from the remote
+    pub removed: Vec<String>,
+    /// Users pulled without any credentials, e.g. from a sanitized remote
+    pub without_credentials: Vec<String>,
+    /// Whether a new commit was pushed
+    pub pushed: bool,
+}
+
+/// Copy of a kubeconfig with every user credential removed
+pub fn strip_credentials(config: &KubeConfig) -> KubeConfig {
+    let mut stripped = config.clone();
+    for user in &mut stripped.users {
+        user.user = crate::UserInfo {
+            client_certificate_data: None,
+            client_key_data: None,
+            client_certificate: None,
+            client_key: None,
+            token: None,
+            username: None,
+            password: None,
+            auth_provider: None,
+            exec: None,
+        };
+    }
+    stripped
+}
+
+/// Whether a user has no credentials at all, as after `strip_credentials`
+fn lacks_credentials(user: &UserInfo) -> bool {
+    matches!(serde_yaml::to_value(user), Ok(serde_yaml::Value::Mapping(m)) if m.is_empty())
+}
+
+fn context_names(config: &KubeConfig) -> Vec<String> {
+    config.contexts.iter().map(|c| c.name.clone()).collect()
+}
+
+fn remote_branch_exists(workdir: &Path, branch: &str) -> bool {
+    run_git(git(workdir).args([
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("origin/{}", branch),
+    ]))
+    .is_ok()
+}
+
+/// Pull the shared kubeconfig, merge it into `local`, then push the merged
+/// result back to the remote. With `UpdateMode::Skip` local entries win on
+/// conflicts; otherwise the remote's version replaces them. Removals since
+/// the last sync carry over both ways: contexts removed from the remote are
+/// removed locally (except `protected` ones), and contexts removed locally
+/// aren't pulled back in.
+pub fn sync(
+    local: &mut KubeConfig,
+    settings: &SyncConfig,
+    workdir: &Path,
+    machine: &str,
+    mode: UpdateMode,
+    protected: &[String],
+) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    let file = workdir.join(&settings.file);
+    // The remote as of the last sync, the common base of both sides. It's
+    // read from the remote-tracking branch, as a failed push leaves the
+    // work tree ahead of the remote.
+    let last_synced = if workdir.join(".git").exists() {
+        run_git(git(workdir).args([
+            "show",
+            &format!("origin/{}:{}", settings.branch, settings.file),
+        ]))
+        .ok()
+        .and_then(|content| crate::parse_kubeconfig(&content).ok())
+        .map(|config| context_names(&config))
+        .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    if !workdir.join(".git").exists() {
+        fs::create_dir_all(workdir)
+            .with_context(|| format!("Failed to create directory: {:?}", workdir))?;
+        run_git(git(workdir).args(["init", "--quiet"]))?;
+        run_git(git(workdir).args(["remote", "add", "origin", &settings.remote]))?;
+    } else {
+        run_git(git(workdir).args(["remote", "set-url", "origin", &settings.remote]))?;
+    }
+
+    run_git(git(workdir).args(["fetch", "--quiet", "origin"]))
+        .context("Failed to fetch from sync remote")?;
+    if remote_branch_exists(workdir, &settings.branch) {
+        run_git(git(workdir).args([
+            "checkout",
+            "--quiet",
+            "-B",
+            &settings.branch,
+            &format!("origin/{}", settings.branch),
+        ]))?;
+        run_git(git(workdir).args([
+            "reset",
+            "--quiet",
+            "--hard",
+            &format!("origin/{}", settings.branch),
+        ]))?;
+    } else {
+        run_git(git(workdir).args(["checkout", "--quiet", "-B", &settings.branch]))?;
+    }
+
+    // Merge remote entries into the local config using kconf's merge semantics
+    if file.exists() {
+        let mut remote = crate::load_kubeconfig(&file)?;
+        let local_names = context_names(local);
+        let remote_names = context_names(&remote);
+        for name in &last_synced {
+            if !local_names.contains(name) {
+                remove_context(&mut remote, name);
+            } else if !remote_names.contains(name) {
+                if protect::is_protected(protected, name) {
+                    println!(
+                        "Keeping protected context '{}' (removed from the remote)",
+                        name
+                    );
+                    continue;
+                }
+                remove_context(local, name);
+                report.removed.push(name.clone());
+            }
+        }
+        // A sanitized remote has no credentials to update local users with
+        for user in &mut remote.users {
+            if let Some(existing) = local.users.iter().find(|u| u.name == user.name) {
+                if lacks_credentials(&user.user) {
+                    user.user = existing.user.clone();
+                }
+            }
+        }
+
+        let merge_result = filter_duplicates(local, remote, mode);
+        report.without_credentials = merge_result
+            .users_to_add
+            .iter()
+            .filter(|u| lacks_credentials(&u.user))
+            .map(|u| u.name.clone())
+            .collect();
+        let (added, updated, _) = merge_kubeconfigs(local, merge_result, None);
+        report.pulled = added;
+        report.updated = updated;
+    }
+
+    // Publish the merged result in a machine-independent form: sorted, and
+    // without current-context
+    let mut published = if settings.sanitize {
+        strip_credentials(local)
+    } else {
+        local.clone()
+    };
+    published.current_context = None;
+    published.clusters.sort_by(|a, b| a.name.cmp(&b.name));
+    published.contexts.sort_by(|a, b| a.name.cmp(&b.name));
+    published.users.sort_by(|a, b| a.name.cmp(&b.name));
+    write_private(&file, serde_yaml::to_string(&published)?.as_bytes())?;
+    if commit_file(workdir, &file, &format!("kconf sync from {}", machine))? {
+        run_git(git(workdir).args([
+            "push",
+            "--quiet",
+            "origin",
+            &format!("HEAD:{}", settings.branch),
+        ]))
+        .context("Failed to push to sync remote (it may have changed; run sync again)")?;
+        report.pushed = true;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_empty_kubeconfig;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    fn settings(remote: &Path, sanitize: bool) -> SyncConfig {
+        SyncConfig {
+            remote: remote.display().to_string(),
+            branch: "main".to_string(),
+            file: "config.yaml".to_string(),
+            sanitize,
+        }
+    }
+
+    #[test]
+    fn test_sync_between_two_machines() {
+        let temp_dir = TempDir::new().unwrap();
+        let remote = temp_dir.path().join("remote.git");
+        run_git(git(temp_dir.path()).args(["init", "--quiet", "--bare", "remote.git"])).unwrap();
+
+        let mut laptop = create_test_kubeconfig("laptop");
+        let report = sync(
+            &mut laptop,
+            &settings(&remote, false),
+            &temp_dir.path().join("a"),
+            "laptop",
+            UpdateMode::Skip,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(report.pulled, 0);
+        assert!(report.pushed);
+
+        let mut desktop = create_test_kubeconfig("desktop");
+        let report = sync(
+            &mut desktop,
+            &settings(&remote, false),
+            &temp_dir.path().join("b"),
+            "desktop",
+            UpdateMode::Skip,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(report.pulled, 3);
+        assert!(report.pushed);
+        assert_eq!(desktop.contexts.len(), 2);
+
+        // The laptop now picks up the desktop's entries
+        let report = sync(
+            &mut laptop,
+            &settings(&remote, false),
+            &temp_dir.path().join("a"),
+            "laptop",
+            UpdateMode::Skip,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(report.pulled, 3);
+        assert!(!report.pushed);
+        assert_eq!(laptop.contexts.len(), 2);
+    }
+
+    fn combined(names: &[&str]) -> KubeConfig {
+        let mut config = create_empty_kubeconfig();
+        for name in names {
+            let other = create_test_kubeconfig(name);
+            config.clusters.extend(other.clusters);
+            config.contexts.extend(other.contexts);
+            config.users.extend(other.users);
+        }
+        config
+    }
+
+    #[test]
+    fn test_sync_updates_and_removals() {
+        let temp_dir = TempDir::new().unwrap();
+        let remote = temp_dir.path().join("remote.git");
+        run_git(git(temp_dir.path()).args(["init", "--quiet", "--bare", "remote.git"])).unwrap();
+        let workdir = |name: &str| temp_dir.path().join(name);
+        let run = |config: &mut KubeConfig, name: &str, mode: UpdateMode| {
+            let protected = ["prod-*".to_string()];
+            sync(
+                config,
+                &settings(&remote, false),
+                &workdir(name),
+                name,
+                mode,
+                &protected,
+            )
+            .unwrap()
+        };
+
+        let mut laptop = combined(&["dev", "old", "prod"]);
+        run(&mut laptop, "laptop", UpdateMode::Skip);
+        let mut desktop = create_empty_kubeconfig();
+        run(&mut desktop, "desktop", UpdateMode::Skip);
+        let mut server = create_empty_kubeconfig();
+        run(&mut server, "server", UpdateMode::Skip);
+        assert_eq!(server.contexts.len(), 3);
+
+        // The laptop rotates dev's server and removes two contexts, which
+        // the remote's copies don't bring back
+        laptop.clusters[0].cluster.server = "https://rotated:6443".to_string();
+        remove_context(&mut laptop, "old-context");
+        remove_context(&mut laptop, "prod-context");
+        run(&mut laptop, "laptop", UpdateMode::Skip);
+        assert_eq!(context_names(&laptop), vec!["dev-context"]);
+
+        // Updating takes the rotation and the removal, except of the
+        // protected context
+        let report = run(&mut desktop, "desktop", UpdateMode::Replace);
+        assert_eq!(report.removed, vec!["old-context"]);
+        assert_eq!(report.updated, 1);
+        assert_eq!(desktop.clusters[0].cluster.server, "https://rotated:6443");
+        assert_eq!(context_names(&desktop), vec!["dev-context", "prod-context"]);
+
+        // Without updating, local entries win, but the removal comes through
+        let report = run(&mut server, "server", UpdateMode::Skip);
+        assert_eq!(report.removed, vec!["old-context"]);
+        assert_eq!(context_names(&server), vec!["dev-context", "prod-context"]);
+        assert_ne!(server.clusters[0].cluster.server, "https://rotated:6443");
+    }
+
+    #[test]
+    fn test_sync_sanitized_remote() {
+        let temp_dir = TempDir::new().unwrap();
+        let remote = temp_dir.path().join("remote.git");
+        run_git(git(temp_dir.path()).args(["init", "--quiet", "--bare", "remote.git"])).unwrap();
+        let run = |config: &mut KubeConfig, name: &str| {
+            let workdir = temp_dir.path().join(name);
+            sync(
+                config,
+                &settings(&remote, true),
+                &workdir,
+                name,
+                UpdateMode::Replace,
+                &[],
+            )
+            .unwrap()
+        };
+
+        let mut laptop = create_test_kubeconfig("dev");
+        run(&mut laptop, "laptop");
+        let mut desktop = create_empty_kubeconfig();
+        let report = run(&mut desktop, "desktop");
+        assert_eq!(report.without_credentials, vec!["dev-user"]);
+
+        // The stripped users don't replace the laptop's credentials
+        let report = run(&mut laptop, "laptop");
+        assert_eq!(report.updated, 0);
+        assert_eq!(laptop, create_test_kubeconfig("dev"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let copy = temp_dir.path().join("laptop").join("config.yaml");
+            let mode = fs::metadata(copy).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_by_default() {
+        let settings: SyncConfig =
+            serde_yaml::from_str("remote: git@example.com:me/k.git").unwrap();
+        assert!(settings.sanitize);
+    }
+
+    #[test]
+    fn test_strip_credentials() {
+        let config = create_test_kubeconfig("test");
+        let stripped = strip_credentials(&config);
+        assert_eq!(stripped.users.len(), 1);
+        assert!(stripped.users[0].user.client_key_data.is_none());
+        assert!(stripped.users[0].user.client_certificate_data.is_none());
+        assert_eq!(stripped.clusters, config.clusters);
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{matches_pattern, KubeConfig};
+
+/// Team mode settings stored under `team:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TeamConfig {
+    /// Rules binding credential-less contexts to local users, first match wins
+    #[serde(default)]
+    pub bindings: Vec<UserBinding>,
+}
+
+/// Binds contexts whose name matches `pattern` to a locally configured user
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserBinding {
+    /// Context name pattern (`*` wildcards)
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Name of the local user to use
+    pub user: String,
+}
+
+/// Point contexts whose user isn't defined in the source at a local user,
+/// chosen by `user_override` or the first matching binding.
+/// Returns the (context, user) pairs that were bound.
+pub fn bind_users(
+    source: &mut KubeConfig,
+    bindings: &[UserBinding],
+    user_override: Option<&str>,
+) -> Vec<(String, String)> {
+    let source_users: Vec<String> = source.users.iter().map(|u| u.name.clone()).collect();
+    let mut bound = Vec::new();
+
+    for context in &mut source.contexts {
+        if source_users.contains(&context.context.user) {
+            continue;
+        }
+        let user = user_override.map(str::to_string).or_else(|| {
+            bindings
+                .iter()
+                .find(|b| matches_pattern(&b.pattern, &context.name))
+                .map(|b| b.user.clone())
+        });
+        if let Some(user) = user {
+            context.context.user = user.clone();
+            bound.push((context.name.clone(), user));
+        }
+    }
+
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    fn team_config() -> KubeConfig {
+        let mut config = create_test_kubeconfig("team-prod");
+        let staging = create_test_kubeconfig("team-staging");
+        config.clusters.extend(staging.clusters);
+        config.contexts.extend(staging.contexts);
+        config.users.clear();
+        config
+    }
+
+    #[test]
+    fn test_bind_users_by_pattern() {
+        let mut config = team_config();
+        let bindings = vec![
+            UserBinding {
+                pattern: "*-prod-*".to_string(),
+                user: "me-prod".to_string(),
+            },
+            UserBinding {
+                pattern: "*".to_string(),
+                user: "me-sso".to_string(),
+            },
+        ];
+        let bound = bind_users(&mut config, &bindings, None);
+        assert_eq!(bound.len(), 2);
+        assert_eq!(config.contexts[0].context.user, "me-prod");
+        assert_eq!(config.contexts[1].context.user, "me-sso");
+    }
+
+    #[test]
+    fn test_bind_users_override_and_existing_users() {
+        let mut config = team_config();
+        config.users = create_test_kubeconfig("team-prod").users;
+
+        let bound = bind_users(&mut config, &[], Some("me"));
+        // The prod context has its user in the source, so only staging is bound
+        assert_eq!(
+            bound,
+            vec![("team-staging-context".to_string(), "me".to_string())]
+        );
+        assert_eq!(config.contexts[0].context.user, "team-prod-user");
+    }
+
+    #[test]
+    fn test_bind_users_without_match() {
+        let mut config = team_config();
+        assert!(bind_users(&mut config, &[], None).is_empty());
+        assert_eq!(config.contexts[0].context.user, "team-prod-user");
+    }
+}
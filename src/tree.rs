@@ -0,0 +1,127 @@
+use crate::KubeConfig;
+
+/// How many contexts use the user `name`
+fn user_uses(config: &KubeConfig, name: &str) -> usize {
+    config
+        .contexts
+        .iter()
+        .filter(|c| c.context.user == name)
+        .count()
+}
+
+/// Clusters with their contexts underneath and each context's user, marking
+/// clusters and users shared by several contexts. Removing a context only
+/// removes its cluster and user when nothing else uses them, which is what
+/// the markers show. Contexts pointing at a missing cluster come after the
+/// rest, followed by the clusters and users no context uses.
+pub fn render(config: &KubeConfig) -> String {
+    let mut out = String::new();
+    let mut groups: Vec<(String, Option<&str>)> = config
+        .clusters
+        .iter()
+        .map(|c| (c.name.clone(), Some(c.cluster.server.as_str())))
+        .collect();
+    for context in &config.contexts {
+        if !groups
+            .iter()
+            .any(|(name, _)| *name == context.context.cluster)
+        {
+            groups.push((context.context.cluster.clone(), None));
+        }
+    }
+
+    let mut unused_clusters = Vec::new();
+    for (cluster, server) in &groups {
+        let contexts: Vec<_> = config
+            .contexts
+            .iter()
+            .filter(|c| c.context.cluster == *cluster)
+            .collect();
+        if contexts.is_empty() {
+            unused_clusters.push(cluster.as_str());
+            continue;
+        }
+        let mut line = match server {
+            Some(server) => format!("{} ({})", cluster, server),
+            None => format!("{} (missing cluster)", cluster),
+        };
+        if contexts.len() > 1 {
+            line.push_str(&format!(" [shared by {} contexts]", contexts.len()));
+        }
+        out.push_str(&line);
+        out.push('\n');
+
+        for (i, context) in contexts.iter().enumerate() {
+            let last = i + 1 == contexts.len();
+            let current = config.current_context.as_deref() == Some(context.name.as_str());
+            let user = &context.context.user;
+            let mut user_line = format!("user {}", user);
+            if !config.users.iter().any(|u| u.name == *user) {
+                user_line.push_str(" (missing)");
+            }
+            let shared = user_uses(config, user);
+            if shared > 1 {
+                user_line.push_str(&format!(" [shared by {} contexts]", shared));
+            }
+            out.push_str(&format!(
+                "{} {}{}\n{}    └── {}\n",
+                if last { "└──" } else { "├──" },
+                context.name,
+                if current { " *" } else { "" },
+                if last { " " } else { "│" },
+                user_line
+            ));
+        }
+    }
+
+    let unused_users: Vec<&str> = config
+        .users
+        .iter()
+        .filter(|u| user_uses(config, &u.name) == 0)
+        .map(|u| u.name.as_str())
+        .collect();
+    if !unused_clusters.is_empty() {
+        out.push_str(&format!(
+            "\nClusters without contexts: {}\n",
+            unused_clusters.join(", ")
+        ));
+    }
+    if !unused_users.is_empty() {
+        out.push_str(&format!(
+            "\nUsers without contexts: {}\n",
+            unused_users.join(", ")
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_render_shared_entries() {
+        let mut config = create_test_kubeconfig("prod");
+        let mut second = config.contexts[0].clone();
+        second.name = "prod-admin".to_string();
+        config.contexts.push(second);
+        config.current_context = Some("prod-admin".to_string());
+        config.users.extend(create_test_kubeconfig("old").users);
+
+        let tree = render(&config);
+        let cluster = &config.clusters[0];
+        assert_eq!(
+            tree,
+            format!(
+                "{} ({}) [shared by 2 contexts]\n\
+                 ├── prod-context\n\
+                 │    └── user prod-user [shared by 2 contexts]\n\
+                 └── prod-admin *\n     \
+                 └── user prod-user [shared by 2 contexts]\n\
+                 \nUsers without contexts: old-user\n",
+                cluster.name, cluster.cluster.server
+            )
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{provenance, KubeConfig};
+
+/// Parse a TTL such as `30m`, `12h`, `7d` or `2w`
+pub fn parse(ttl: &str) -> Result<Duration> {
+    let ttl = ttl.trim();
+    let split = ttl.len() - ttl.chars().last().map_or(0, char::len_utf8);
+    let (number, unit) = ttl.split_at(split);
+    let count: i64 = number
+        .parse()
+        .ok()
+        .filter(|n| *n > 0)
+        .with_context(|| format!("Invalid TTL '{}' (expected e.g. 12h, 7d or 2w)", ttl))?;
+    match unit {
+        "m" => Ok(Duration::minutes(count)),
+        "h" => Ok(Duration::hours(count)),
+        "d" => Ok(Duration::days(count)),
+        "w" => Ok(Duration::weeks(count)),
+        _ => anyhow::bail!("Invalid TTL unit in '{}' (use m, h, d or w)", ttl),
+    }
+}
+
+/// RFC 3339 expiry time for a TTL starting `now`
+pub fn expiry(now: DateTime<Utc>, ttl: Duration) -> String {
+    (now + ttl).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Contexts whose recorded expiry is at or before `now`
+pub fn expired(config: &KubeConfig, now: DateTime<Utc>) -> Vec<String> {
+    config
+        .contexts
+        .iter()
+        .filter(|c| {
+            provenance::get(&c.context)
+                .and_then(|p| p.expires_at)
+                .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+                .is_some_and(|at| at <= now)
+        })
+        .map(|c| c.name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::Provenance;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse("7d").unwrap(), Duration::days(7));
+        assert_eq!(parse("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse("2w").unwrap(), Duration::weeks(2));
+        assert!(parse("0d").is_err());
+        assert!(parse("7").is_err());
+        assert!(parse("d").is_err());
+        assert!(parse("7y").is_err());
+    }
+
+    #[test]
+    fn test_expired_contexts() {
+        let now = Utc::now();
+        let mut config = create_test_kubeconfig("old");
+        let sandbox = Provenance {
+            expires_at: Some(expiry(now, -Duration::days(1))),
+            ..Provenance::now("/tmp/sandbox.yaml")
+        };
+        provenance::stamp(&mut config.contexts[0].context, &sandbox);
+        for (name, expires_at) in [
+            ("fresh", Some(expiry(now, Duration::days(1)))),
+            ("kept", None),
+        ] {
+            let mut other = create_test_kubeconfig(name);
+            let stamp = Provenance {
+                expires_at,
+                ..Provenance::now("/tmp/other.yaml")
+            };
+            provenance::stamp(&mut other.contexts[0].context, &stamp);
+            config.contexts.extend(other.contexts);
+        }
+        assert_eq!(expired(&config, now), vec!["old-context"]);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+
+use crate::KubeConfig;
+
+/// Names that appear more than once in `names`, in order of first repeat
+fn repeated<'a>(names: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    let mut repeated = Vec::new();
+    for name in names {
+        if !seen.insert(name) && !repeated.contains(&name) {
+            repeated.push(name);
+        }
+    }
+    repeated
+}
+
+/// Everything that makes an incoming source unfit to merge: repeated names,
+/// clusters without a server, and contexts without a cluster or pointing at
+/// a cluster or user the source doesn't define. The users of `bound`
+/// contexts come from the destination (team bindings), so they're not
+/// looked up in the source.
+pub fn problems(config: &KubeConfig, bound: &[(String, String)]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (kind, name) in [
+        (
+            "cluster",
+            repeated(config.clusters.iter().map(|c| c.name.as_str())),
+        ),
+        (
+            "context",
+            repeated(config.contexts.iter().map(|c| c.name.as_str())),
+        ),
+        (
+            "user",
+            repeated(config.users.iter().map(|u| u.name.as_str())),
+        ),
+    ]
+    .into_iter()
+    .flat_map(|(kind, names)| names.into_iter().map(move |n| (kind, n)))
+    {
+        problems.push(format!("{} '{}' is defined more than once", kind, name));
+    }
+
+    for cluster in &config.clusters {
+        if cluster.cluster.server.trim().is_empty() {
+            problems.push(format!("cluster '{}' has no server", cluster.name));
+        }
+    }
+
+    for context in &config.contexts {
+        let info = &context.context;
+        if info.cluster.is_empty() {
+            problems.push(format!("context '{}' has no cluster", context.name));
+        } else if !config.clusters.iter().any(|c| c.name == info.cluster) {
+            problems.push(format!(
+                "context '{}' references cluster '{}', which the source doesn't define",
+                context.name, info.cluster
+            ));
+        }
+        let local = bound.iter().any(|(name, _)| *name == context.name);
+        // A context without a user is anonymous, which kubectl allows
+        if !local && !info.user.is_empty() && !config.users.iter().any(|u| u.name == info.user) {
+            problems.push(format!(
+                "context '{}' references user '{}', which the source doesn't define",
+                context.name, info.user
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_source_problems() {
+        let config = create_test_kubeconfig("a");
+        assert!(problems(&config, &[]).is_empty());
+
+        let mut config = create_test_kubeconfig("a");
+        config.clusters.clear();
+        config.clusters.extend(create_test_kubeconfig("b").clusters);
+        config.clusters[0].cluster.server = String::new();
+        config.users.push(config.users[0].clone());
+        let mut second = config.contexts[0].clone();
+        second.name = "b-context".to_string();
+        second.context.cluster = "b-cluster".to_string();
+        second.context.user = "local".to_string();
+        config.contexts.push(second);
+
+        assert_eq!(
+            problems(&config, &[]),
+            vec![
+                "user 'a-user' is defined more than once",
+                "cluster 'b-cluster' has no server",
+                "context 'a-context' references cluster 'a-cluster', which the source doesn't define",
+                "context 'b-context' references user 'local', which the source doesn't define",
+            ]
+        );
+        // Bound to a destination user by a team binding
+        let bound = vec![("b-context".to_string(), "local".to_string())];
+        assert_eq!(problems(&config, &bound).len(), 3);
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::credentials::exec_stanza;
+use crate::{KubeConfig, UserInfo};
+
+/// Vault settings stored under `credentials.vault:` in the app config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultConfig {
+    /// Vault server, e.g. https://vault.example.com:8200 (defaults to VAULT_ADDR)
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Where each user's token is kept, with `{user}` replaced by the user
+    /// name, e.g. `secret/kubeconfig/{user}`. Setting it turns the Vault
+    /// credential mode on.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Field of the secret holding the token
+    #[serde(default = "default_field")]
+    pub field: String,
+    /// Version of the KV secrets engine mounted at the path's first segment
+    #[serde(default = "default_kv_version")]
+    pub kv_version: u8,
+}
+
+fn default_field() -> String {
+    "token".to_string()
+}
+
+fn default_kv_version() -> u8 {
+    2
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        VaultConfig {
+            address: None,
+            path: None,
+            field: default_field(),
+            kv_version: default_kv_version(),
+        }
+    }
+}
+
+impl VaultConfig {
+    /// The secret path for a user, when the Vault mode is on
+    pub fn user_path(&self, user: &str) -> Option<String> {
+        self.path.as_ref().map(|p| p.replace("{user}", user))
+    }
+
+    /// API path for a secret: KV version 2 keeps data under `<mount>/data/`
+    fn api_path(&self, path: &str) -> String {
+        let path = path.trim_matches('/');
+        match (self.kv_version, path.split_once('/')) {
+            (2, Some((mount, rest))) if !rest.starts_with("data/") => {
+                format!("{}/data/{}", mount, rest)
+            }
+            _ => path.to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> Result<String> {
+        let address = match &self.address {
+            Some(address) => address.clone(),
+            None => std::env::var("VAULT_ADDR")
+                .context("No Vault address (set credentials.vault.address or VAULT_ADDR)")?,
+        };
+        Ok(format!(
+            "{}/v1/{}",
+            address.trim_end_matches('/'),
+            self.api_path(path)
+        ))
+    }
+
+    /// Read the token stored at `path`
+    pub fn read_token(&self, path: &str) -> Result<String> {
+        let body = request("GET", &self.url(path)?, None)
+            .with_context(|| format!("Failed to read {} from Vault", path))?;
+        extract(&body, &self.field)
+            .with_context(|| format!("Vault secret {} has no string field '{}'", path, self.field))
+    }
+
+    /// Store `token` at `path`, replacing the secret's current version
+    pub fn write_token(&self, path: &str, token: &str) -> Result<()> {
+        let data = serde_json::json!({ self.field.as_str(): token });
+        let body = match self.kv_version {
+            2 => serde_json::json!({ "data": data }),
+            _ => data,
+        };
+        request("POST", &self.url(path)?, Some(body))
+            .with_context(|| format!("Failed to write {} to Vault", path))?;
+        Ok(())
+    }
+}
+
+/// A field from a KV read response: version 2 nests the secret under
+/// `data.data`, version 1 puts it directly under `data`
+fn extract(body: &Value, field: &str) -> Option<String> {
+    let data = &body["data"];
+    data["data"][field]
+        .as_str()
+        .or_else(|| data[field].as_str())
+        .map(str::to_string)
+}
+
+/// The Vault token kconf itself uses, found the way the vault CLI finds it
+#[cfg(feature = "remote")]
+fn client_token() -> Result<String> {
+    if let Ok(token) = std::env::var("VAULT_TOKEN") {
+        return Ok(token);
+    }
+    let path = dirs::home_dir()
+        .context("Could not determine home directory")?
+        .join(".vault-token");
+    let token = std::fs::read_to_string(&path)
+        .context("No Vault token (set VAULT_TOKEN or run `vault login`)")?;
+    Ok(token.trim().to_string())
+}
+
+#[cfg(feature = "remote")]
+fn request(method: &str, url: &str, body: Option<Value>) -> Result<Value> {
+    let mut request = ureq::request(method, url).set("X-Vault-Token", &client_token()?);
+    if let Ok(namespace) = std::env::var("VAULT_NAMESPACE") {
+        request = request.set("X-Vault-Namespace", &namespace);
+    }
+    let result = match body {
+        Some(body) => request.send_string(&body.to_string()),
+        None => request.call(),
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(ureq::Error::Status(403, _)) => {
+            anyhow::bail!("permission denied (check the Vault token's policies)")
+        }
+        Err(ureq::Error::Status(404, _)) => anyhow::bail!("no secret at this path"),
+        Err(e) => return Err(e.into()),
+    };
+    let text = response.into_string()?;
+    // Writes may answer 204 with no body
+    if text.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(serde_json::from_str(&text)?)
+}
+
+#[cfg(not(feature = "remote"))]
+fn request(_method: &str, _url: &str, _body: Option<Value>) -> Result<Value> {
+    Err(crate::KconfError::FeatureDisabled("remote").into())
+}
+
+/// Secret path an exec stanza written by [`offload`] refers to
+pub fn referenced_path(user: &UserInfo) -> Option<&str> {
+    let args = user.exec.as_ref()?.args.as_ref()?;
+    match args.as_slice() {
+        [credential, vault, path] if credential == "credential" && vault == "vault" => {
+            Some(path.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Users holding nothing but a token, which an exec plugin can serve whole
+fn is_token_only(user: &UserInfo) -> bool {
+    user.token.is_some()
+        && user.exec.is_none()
+        && user.username.is_none()
+        && user.password.is_none()
+        && user.client_certificate_data.is_none()
+        && user.client_key_data.is_none()
+        && user.client_certificate.is_none()
+        && user.client_key.is_none()
+}
+
+/// Store every inline token in Vault and return the config to write to the
+/// destination, whose token users call `program credential vault <path>`
+pub fn offload(config: &KubeConfig, vault: &VaultConfig, program: &str) -> Result<KubeConfig> {
+    let mut public = config.clone();
+    for user in &mut public.users {
+        if !is_token_only(&user.user) {
+            continue;
+        }
+        let Some(path) = vault.user_path(&user.name) else {
+            continue;
+        };
+        if let Some(token) = &user.user.token {
+            vault.write_token(&path, token)?;
+        }
+        user.user = exec_stanza(program, &["credential", "vault", &path]);
+    }
+    Ok(public)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+
+    #[test]
+    fn test_api_paths_and_responses() {
+        let mut vault = VaultConfig {
+            address: Some("https://vault.example.com:8200/".to_string()),
+            path: Some("secret/kube/{user}".to_string()),
+            ..VaultConfig::default()
+        };
+        let path = vault.user_path("prod-user").unwrap();
+        assert_eq!(
+            vault.url(&path).unwrap(),
+            "https://vault.example.com:8200/v1/secret/data/kube/prod-user"
+        );
+        vault.kv_version = 1;
+        assert_eq!(
+            vault.url(&path).unwrap(),
+            "https://vault.example.com:8200/v1/secret/kube/prod-user"
+        );
+
+        let v2 = serde_json::json!({ "data": { "data": { "token": "abc" } } });
+        let v1 = serde_json::json!({ "data": { "token": "abc" } });
+        assert_eq!(extract(&v2, "token").as_deref(), Some("abc"));
+        assert_eq!(extract(&v1, "token").as_deref(), Some("abc"));
+        assert_eq!(extract(&v1, "password"), None);
+    }
+
+    #[test]
+    fn test_referenced_path() {
+        let mut user = create_test_kubeconfig("a").users.remove(0).user;
+        assert!(!is_token_only(&user));
+        assert_eq!(referenced_path(&user), None);
+
+        user = exec_stanza("kconf", &["credential", "vault", "secret/kube/a-user"]);
+        assert_eq!(referenced_path(&user), Some("secret/kube/a-user"));
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::secrets::SecretRef;
+
+/// Body sent to the webhook
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The summary as a JSON object
+    #[default]
+    Json,
+    /// A Slack incoming-webhook message
+    Slack,
+}
+
+/// Webhook settings stored under `webhook:` in the app config
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct WebhookConfig {
+    /// URL to POST to after merges and removals; leave unset to disable.
+    /// Slack webhook URLs are credentials, so keep them in `env` or `keyring`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<SecretRef>,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+/// Operations that change which credentials are distributed; context and
+/// namespace switches don't
+const EVENTS: &[&str] = &[
+    "merge", "import", "refresh", "apply", "sync", "remove", "clean",
+];
+
+/// The event a destination write is reported as, if it's reported at all
+pub fn event(operation: &str) -> Option<&'static str> {
+    let first = operation.split_whitespace().next()?;
+    EVENTS.iter().copied().find(|e| *e == first)
+}
+
+/// The request body for a write of `destination`
+pub fn payload(
+    format: WebhookFormat,
+    event: &str,
+    operation: &str,
+    destination: &Path,
+    host: &str,
+    user: &str,
+    at: &str,
+) -> Value {
+    match format {
+        WebhookFormat::Json => json!({
+            "event": event,
+            "summary": operation,
+            "destination": destination.display().to_string(),
+            "host": host,
+            "user": user,
+            "timestamp": at,
+            "kconf_version": env!("CARGO_PKG_VERSION"),
+        }),
+        WebhookFormat::Slack => json!({
+            "text": format!("kconf on {} ({}): {} → {}", host, user, operation, destination.display()),
+        }),
+    }
+}
+
+/// POST a summary of a successful destination write, when a webhook is set
+/// and the operation is one that's reported
+pub fn notify(config: &WebhookConfig, operation: &str, destination: &Path) -> Result<()> {
+    let (Some(url), Some(event)) = (&config.url, event(operation)) else {
+        return Ok(());
+    };
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    let body = payload(
+        config.format,
+        event,
+        operation,
+        destination,
+        &crate::machine_name(),
+        &user,
+        &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    );
+    post(&url.resolve()?, &body)
+}
+
+#[cfg(feature = "remote")]
+fn post(url: &str, body: &Value) -> Result<()> {
+    use anyhow::Context;
+    ureq::post(url)
+        .timeout(std::time::Duration::from_secs(10))
+        .set("content-type", "application/json")
+        .send_string(&body.to_string())
+        .context("Webhook request failed")?;
+    Ok(())
+}
+
+#[cfg(not(feature = "remote"))]
+fn post(_url: &str, _body: &Value) -> Result<()> {
+    Err(crate::KconfError::FeatureDisabled("remote").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_and_payloads() {
+        assert_eq!(event("merge a.yaml (1 added, 0 updated)"), Some("merge"));
+        assert_eq!(event("remove context 'prod'"), Some("remove"));
+        assert_eq!(event("use context 'prod'"), None);
+
+        let destination = Path::new("/home/me/.kube/config");
+        let at = "2026-01-01T00:00:00Z";
+        let body = payload(
+            WebhookFormat::Json,
+            "merge",
+            "merge a.yaml (1 added, 0 updated)",
+            destination,
+            "laptop",
+            "me",
+            at,
+        );
+        assert_eq!(body["event"], "merge");
+        assert_eq!(body["destination"], "/home/me/.kube/config");
+        assert_eq!(body["timestamp"], at);
+
+        let slack = payload(
+            WebhookFormat::Slack,
+            "remove",
+            "remove context 'prod'",
+            destination,
+            "laptop",
+            "me",
+            at,
+        );
+        assert_eq!(
+            slack["text"],
+            "kconf on laptop (me): remove context 'prod' → /home/me/.kube/config"
+        );
+
+        // No URL configured, so nothing is sent
+        assert!(notify(&WebhookConfig::default(), "merge", destination).is_ok());
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{
+    create_empty_kubeconfig, filter_duplicates, load_kubeconfig, merge_kubeconfigs, write_private,
+    KubeConfig, UpdateMode,
+};
+
+/// Merge kubeconfig files in memory. As with a KUBECONFIG list, the first
+/// file to define a name wins; later definitions that differ are reported
+/// on stderr so the command's own output stays clean.
+pub fn merge(paths: &[PathBuf]) -> Result<KubeConfig> {
+    let mut config = create_empty_kubeconfig();
+    for path in paths {
+        let source = load_kubeconfig(path)?;
+        let current = source.current_context.clone();
+        let mut differing = Vec::new();
+        for cluster in &source.clusters {
+            if config
+                .clusters
+                .iter()
+                .any(|c| c.name == cluster.name && c != cluster)
+            {
+                differing.push(("cluster", cluster.name.clone()));
+            }
+        }
+        for context in &source.contexts {
+            if config
+                .contexts
+                .iter()
+                .any(|c| c.name == context.name && c != context)
+            {
+                differing.push(("context", context.name.clone()));
+            }
+        }
+        for user in &source.users {
+            if config
+                .users
+                .iter()
+                .any(|u| u.name == user.name && u != user)
+            {
+                differing.push(("user", user.name.clone()));
+            }
+        }
+        for (kind, name) in differing {
+            eprintln!(
+                "Warning: ignoring {} '{}' from {:?} (defined differently by an earlier file)",
+                kind, name, path
+            );
+        }
+        let result = filter_duplicates(&config, source, UpdateMode::Skip);
+        merge_kubeconfigs(&mut config, result, current);
+    }
+    Ok(config)
+}
+
+/// Create a fresh directory under the system temp dir, accessible by the
+/// owner only. Names are never reused, so a path another user created first
+/// (as a directory or a symlink) is skipped rather than written through.
+fn private_temp_dir() -> Result<PathBuf> {
+    let base = std::env::temp_dir();
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    for attempt in 0..100 {
+        let dir = base.join(format!("kconf-with-{}-{}", std::process::id(), attempt));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create directory: {:?}", dir))
+            }
+        }
+    }
+    anyhow::bail!("Failed to create a private directory in {:?}", base)
+}
+
+/// Write `config` to a file in a private temporary directory, run `command`
+/// with KUBECONFIG pointing at it and remove the directory again.
+/// Returns the command's exit code.
+pub fn run(config: &KubeConfig, command: &[String]) -> Result<i32> {
+    let (program, args) = command.split_first().context("No command given")?;
+    let dir = private_temp_dir()?;
+    let path = dir.join("config.yaml");
+    let status = serde_yaml::to_string(config)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| write_private(&path, content.as_bytes()))
+        .and_then(|_| {
+            Command::new(program)
+                .args(args)
+                .env("KUBECONFIG", &path)
+                .status()
+                .with_context(|| format!("Failed to run {}", program))
+        });
+    let _ = fs::remove_dir_all(&dir);
+    // A command killed by a signal has no exit code
+    Ok(status?.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_kubeconfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_merge_keeps_first_definition() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = temp_dir.path().join("first.yaml");
+        let second = temp_dir.path().join("second.yaml");
+        let mut other = create_test_kubeconfig("a");
+        other.clusters[0].cluster.server = "https://elsewhere.example.com".to_string();
+        other.clusters.extend(create_test_kubeconfig("b").clusters);
+        fs::write(
+            &first,
+            serde_yaml::to_string(&create_test_kubeconfig("a")).unwrap(),
+        )
+        .unwrap();
+        fs::write(&second, serde_yaml::to_string(&other).unwrap()).unwrap();
+
+        let merged = merge(&[first, second]).unwrap();
+        assert_eq!(merged.clusters.len(), 2);
+        assert_eq!(
+            merged.clusters[0].cluster.server,
+            "https://a.example.com:6443"
+        );
+        assert_eq!(merged.current_context.as_deref(), Some("a-context"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_sets_kubeconfig_and_cleans_up() {
+        let config = create_test_kubeconfig("a");
+        let command =
+            ["sh", "-c", "grep -q a-context \"$KUBECONFIG\" && exit 3"].map(str::to_string);
+        // A name taken by someone else is skipped, not written through
+        let taken = std::env::temp_dir().join(format!("kconf-with-{}-0", std::process::id()));
+        fs::write(&taken, "").unwrap();
+        assert_eq!(run(&config, &command).unwrap(), 3);
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "");
+        fs::remove_file(&taken).unwrap();
+        let dir = std::env::temp_dir().join(format!("kconf-with-{}-1", std::process::id()));
+        assert!(!dir.exists());
+    }
+}